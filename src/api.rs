@@ -0,0 +1,337 @@
+//! The daemon's localhost HTTP API, for editors and internal tools.
+//!
+//! Every request needs `Authorization: Bearer <token>`, with the token from
+//! `$CONNECT_DB_API_TOKEN` or the one the daemon generates into
+//! `daemon-token` in the data directory. Answers are JSON:
+//!
+//! - `GET /v1/databases`: the names `list` shows
+//! - `GET /v1/databases/NAME/connection`: parameters to connect with, the
+//!   tunnel's local endpoint for tunneled profiles (`?redact=true` masks
+//!   the password)
+//! - `GET /v1/databases/NAME/health`: state and detail like `status`
+//! - `GET /v1/tunnels`: the tunnels the daemon holds open
+//! - `POST /v1/databases/NAME/query` with `{"sql": ..., "limit": ...}`:
+//!   rows of a read-only query, with the limits of [`crate::sandbox`]
+//!
+//! `?cwd=DIR` resolves in another project than the daemon's. Connection
+//! parameters and queries are only served for profiles exposed with
+//! `"mcp": true` (see [`crate::sandbox::exposed`]).
+
+use crate::{
+    config::{self, Profile},
+    daemon::Handlers,
+    engines::ConnectionParams,
+    random, sandbox, url,
+};
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+pub const TOKEN_VARIABLE: &str = "CONNECT_DB_API_TOKEN";
+
+/// Largest request body read, for queries.
+const MAX_BODY: usize = 1 << 20;
+
+/// How long a client gets to send its whole request. Requests are served one
+/// at a time on the daemon's loop (handlers change the working directory), so
+/// a client that connects and stalls holds up everything else until then.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct Api {
+    token: String,
+    /// Where requests without `cwd` resolve
+    cwd: PathBuf,
+}
+
+/// Bind `address` (`:7170` for port 7170 on 127.0.0.1), which must be a
+/// loopback one, and set up the token.
+pub fn listen(address: &str) -> Result<(TcpListener, Api)> {
+    let address = match address.strip_prefix(':') {
+        Some(port) => format!("127.0.0.1:{}", port),
+        None => address.to_string(),
+    };
+    let listener =
+        TcpListener::bind(&address).with_context(|| format!("Failed to listen on {}", address))?;
+    if !listener.local_addr()?.ip().is_loopback() {
+        return Err(anyhow::anyhow!(
+            "The HTTP API only listens on localhost, not {}",
+            address
+        ));
+    }
+    listener.set_nonblocking(true)?;
+    let token = match env::var(TOKEN_VARIABLE) {
+        Ok(token) if !token.is_empty() => {
+            eprintln!(
+                "HTTP API on http://{} (token from ${})",
+                address, TOKEN_VARIABLE
+            );
+            token
+        }
+        _ => {
+            let token = random::hex(32)?;
+            let path = config::data_dir()?.join("daemon-token");
+            // `mode` only applies when the file is created, so tighten an
+            // existing one before the new token goes in
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o600)
+                .open(&path)
+                .and_then(|mut file| {
+                    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+                    file.write_all(token.as_bytes())
+                })
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            eprintln!(
+                "HTTP API on http://{} (token in {})",
+                address,
+                path.display()
+            );
+            token
+        }
+    };
+    let cwd = env::current_dir()?;
+    Ok((listener, Api { token, cwd }))
+}
+
+/// Reads from a stream that fail once `until` has passed, however slowly
+/// the bytes trickle in.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    until: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let too_slow = || {
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the client took too long to send its request",
+            )
+        };
+        let left = self.until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(too_slow());
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.read(buf).map_err(|err| match err.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => too_slow(),
+            _ => err,
+        })
+    }
+}
+
+impl Api {
+    pub fn serve(&self, mut stream: TcpStream, handlers: &Handlers) -> Result<()> {
+        stream.set_nonblocking(false)?;
+        let mut reader = BufReader::new(Deadline {
+            stream: &stream,
+            until: Instant::now() + REQUEST_TIMEOUT,
+        });
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut authorized = false;
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+            let Some((name, value)) = header.split_once(':') else {
+                continue;
+            };
+            if name.eq_ignore_ascii_case("authorization")
+                && let Some(token) = value.trim().strip_prefix("Bearer ")
+            {
+                authorized = same(token.trim(), &self.token);
+            } else if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or(0);
+            }
+        }
+        let mut body = Vec::new();
+        if authorized && length <= MAX_BODY {
+            reader.take(length as u64).read_to_end(&mut body)?;
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default();
+        let target = parts.next().unwrap_or("/");
+        let (status, body) = if !authorized {
+            (
+                "401 Unauthorized",
+                json!({"error": "missing or wrong token"}),
+            )
+        } else if length > MAX_BODY {
+            (
+                "413 Payload Too Large",
+                json!({"error": "the request body is too large"}),
+            )
+        } else {
+            match self.route(method, target, &body, handlers) {
+                Ok(Some(body)) => ("200 OK", body),
+                Ok(None) => ("404 Not Found", json!({"error": "no such endpoint"})),
+                Err(err) => (
+                    "500 Internal Server Error",
+                    json!({"error": format!("{:#}", err)}),
+                ),
+            }
+        };
+        let body = body.to_string();
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )?;
+        Ok(())
+    }
+
+    fn route(
+        &self,
+        method: &str,
+        target: &str,
+        body: &[u8],
+        handlers: &Handlers,
+    ) -> Result<Option<Value>> {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let query = url::parse_query(query);
+        let parameter = |key: &str| {
+            query
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.as_str())
+        };
+        let cwd = parameter("cwd").map(PathBuf::from);
+        let cwd = cwd.as_ref().unwrap_or(&self.cwd);
+        env::set_current_dir(cwd).with_context(|| format!("Failed to enter {}", cwd.display()))?;
+
+        if method == "POST" {
+            let Some(name) = path
+                .strip_prefix("/v1/databases/")
+                .and_then(|rest| rest.strip_suffix("/query"))
+            else {
+                return Ok(None);
+            };
+            return self.query(&url::decode(name), body, handlers).map(Some);
+        }
+        if method != "GET" {
+            return Ok(None);
+        }
+        if path == "/v1/databases" {
+            return Ok(Some(json!({"databases": (handlers.names)()?})));
+        }
+        if path == "/v1/tunnels" {
+            let tunnels: Vec<Value> = crate::daemon::tunnels()
+                .into_iter()
+                .map(|(tunnel, port)| json!({"tunnel": tunnel, "port": port}))
+                .collect();
+            return Ok(Some(json!({"tunnels": tunnels})));
+        }
+        // Names may be hierarchical, like payments/staging/orders
+        let Some((name, action)) = path
+            .strip_prefix("/v1/databases/")
+            .and_then(|rest| rest.rsplit_once('/'))
+        else {
+            return Ok(None);
+        };
+        let name = url::decode(name);
+        match action {
+            "connection" => {
+                exposed_profile(&name)?;
+                let params = (handlers.connection)(&name)?;
+                let redact = parameter("redact").is_some_and(|value| value != "false");
+                Ok(Some(describe(&name, &params, redact)))
+            }
+            "health" => {
+                let (state, detail) = (handlers.health)(&name)?;
+                Ok(Some(
+                    json!({"name": name, "state": state, "detail": detail}),
+                ))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn query(&self, name: &str, body: &[u8], handlers: &Handlers) -> Result<Value> {
+        let request: Value =
+            serde_json::from_slice(body).context("The body must be a JSON object")?;
+        let sql = request["sql"].as_str().context("`sql` is required")?;
+        let limit = request["limit"]
+            .as_u64()
+            .map_or(usize::MAX, |limit| limit as usize);
+        let profile = exposed_profile(name)?;
+        let params = (handlers.session)(name)?;
+        let result = sandbox::run("api", name, &profile, &params, sql, &[], limit)?;
+        Ok(sandbox::rows_json(&result))
+    }
+}
+
+/// The profile of `name` when it is exposed to the API, checked before any
+/// secret is fetched, approval asked for or tunnel opened for it.
+fn exposed_profile(name: &str) -> Result<Profile> {
+    let profile = config::load_profile(name)?;
+    if !sandbox::exposed(&profile) {
+        return Err(anyhow::anyhow!("{} is not exposed to the HTTP API", name));
+    }
+    Ok(profile)
+}
+
+fn describe(name: &str, params: &ConnectionParams, redact: bool) -> Value {
+    let password = match redact {
+        true => "****",
+        false => params.password.as_str(),
+    };
+    let options: serde_json::Map<String, Value> = params
+        .options
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone().into()))
+        .collect();
+    json!({
+        "name": name,
+        "engine": params.engine,
+        "host": params.host,
+        "port": params.port,
+        "username": params.username,
+        "password": password,
+        "database": params.database,
+        "options": options,
+        "url": params.to_url(redact),
+    })
+}
+
+/// Compare tokens without giving away how much of a guess was right.
+fn same(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_tokens() {
+        assert!(same("3f9a0c", "3f9a0c"));
+        assert!(!same("3f9a0d", "3f9a0c"));
+        assert!(!same("xf9a0c", "3f9a0c"));
+        // A prefix of the token, or the token with more after it, is wrong
+        assert!(!same("3f9a", "3f9a0c"));
+        assert!(!same("3f9a0c0", "3f9a0c"));
+        assert!(!same("", "3f9a0c"));
+        assert!(same("", ""));
+    }
+}
@@ -0,0 +1,157 @@
+//! External approval before connecting to a profile: a request is POSTed to
+//! an approval endpoint, which is then polled until someone approves or
+//! denies it.
+//!
+//! The endpoint answers both the POST and the polls with JSON like
+//! `{"status": "pending" | "approved" | "denied", "poll_url": "...",
+//! "approver": "...", "message": "..."}`. `poll_url` is only needed in the
+//! first answer; without it the request's `id` is appended to the URL.
+
+use crate::{audit, config::Profile, dry_run, http, time};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+    collections::HashSet,
+    env,
+    sync::{Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// `approval` settings of a profile.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ApprovalConfig {
+    /// Endpoint the approval request is POSTed to
+    pub url: String,
+    /// How long to wait for a decision, like `10m` (default 10 minutes)
+    pub timeout: Option<String>,
+    /// Time between polls, like `5s` (default 5 seconds)
+    pub poll_interval: Option<String>,
+    /// Environment variable holding a bearer token for the endpoint
+    pub token_env: Option<String>,
+}
+
+/// Profiles already approved in this process.
+fn approved() -> &'static Mutex<HashSet<String>> {
+    static APPROVED: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+    APPROVED.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+/// Wait for approval to connect to `name` when its profile asks for it.
+pub fn require(name: &str, profile: &Profile) -> Result<()> {
+    let Some(config) = &profile.approval else {
+        return Ok(());
+    };
+    if approved().lock().unwrap().contains(name) {
+        return Ok(());
+    }
+    if dry_run::enabled() {
+        dry_run::report(format!(
+            "would ask {} to approve connecting to {}",
+            config.url, name
+        ));
+        return Ok(());
+    }
+    let timeout = time::parse_duration(config.timeout.as_deref().unwrap_or("10m"))?;
+    let interval = time::parse_duration(config.poll_interval.as_deref().unwrap_or("5s"))?.max(1);
+    let token = match &config.token_env {
+        Some(variable) => Some(env::var(variable).with_context(|| {
+            format!(
+                "{} is not set; it holds the approval endpoint token",
+                variable
+            )
+        })?),
+        None => None,
+    };
+    let authorization = token.map(|token| format!("Bearer {}", token));
+    let mut headers = vec![("Content-Type", "application/json")];
+    if let Some(authorization) = &authorization {
+        headers.push(("Authorization", authorization));
+    }
+
+    let user = env::var("USER").unwrap_or_default();
+    let request = json!({
+        "database": name,
+        "user": user,
+        "command": env::args().collect::<Vec<_>>().join(" "),
+        "production": profile.production,
+        "time": time::now_rfc3339(),
+    });
+    let response = http::post(&config.url, &headers, &request.to_string())?;
+    if !response.is_success() {
+        return Err(anyhow::anyhow!(
+            "Approval endpoint returned HTTP {}",
+            response.status
+        ));
+    }
+    let mut answer = response.json()?;
+    let base = config.url.trim_end_matches('/');
+    let poll_url = match (
+        answer.get("poll_url").and_then(Value::as_str),
+        answer.get("id"),
+    ) {
+        (Some(url), _) => url.to_string(),
+        (None, Some(Value::String(id))) => format!("{}/{}", base, id),
+        (None, Some(id)) => format!("{}/{}", base, id),
+        (None, None) => config.url.clone(),
+    };
+
+    eprintln!("Waiting for approval to connect to {}...", name);
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    loop {
+        let field = |key: &str| answer.get(key).and_then(Value::as_str).unwrap_or_default();
+        match field("status") {
+            "approved" => {
+                let approver = field("approver").to_string();
+                eprintln!(
+                    "Approved{}",
+                    if approver.is_empty() {
+                        String::new()
+                    } else {
+                        format!(" by {}", approver)
+                    }
+                );
+                audit::record("approval_granted", name, json!({ "approver": approver }))?;
+                approved().lock().unwrap().insert(name.to_string());
+                return Ok(());
+            }
+            "denied" => {
+                let message = field("message").to_string();
+                audit::record("approval_denied", name, json!({ "message": message }))?;
+                return Err(anyhow::anyhow!(
+                    "Connecting to {} was denied{}",
+                    name,
+                    if message.is_empty() {
+                        String::new()
+                    } else {
+                        format!(": {}", message)
+                    }
+                ));
+            }
+            _ => {}
+        }
+        if Instant::now() >= deadline {
+            audit::record("approval_timeout", name, json!({}))?;
+            return Err(anyhow::anyhow!(
+                "No approval to connect to {} within {}s",
+                name,
+                timeout
+            ));
+        }
+        thread::sleep(Duration::from_secs(interval));
+        let headers: Vec<(&str, &str)> = headers
+            .iter()
+            .filter(|(header, _)| *header != "Content-Type")
+            .copied()
+            .collect();
+        let response = http::get(&poll_url, &headers)?;
+        if !response.is_success() {
+            return Err(anyhow::anyhow!(
+                "Approval endpoint returned HTTP {}",
+                response.status
+            ));
+        }
+        answer = response.json()?;
+    }
+}
@@ -0,0 +1,35 @@
+//! Append-only log of privileged actions in
+//! `~/.config/connect-db/audit.jsonl`.
+
+use crate::{config, time};
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::Write,
+};
+
+/// Append `event` on `database` with its `details` (an object), stamped with
+/// the local user and the time.
+pub fn record(event: &str, database: &str, details: Value) -> Result<()> {
+    let mut entry = json!({
+        "time": time::now_rfc3339(),
+        "event": event,
+        "database": database,
+        "user": env::var("USER").unwrap_or_default(),
+    });
+    if let (Some(entry), Value::Object(details)) = (entry.as_object_mut(), details) {
+        entry.extend(details);
+    }
+    let path = config::config_dir()?.join("audit.jsonl");
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", entry).with_context(|| format!("Failed to write {}", path.display()))
+}
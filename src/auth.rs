@@ -0,0 +1,86 @@
+use crate::{config::Profile, keychain, prompt};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::process::{Command, Stdio};
+
+/// How a profile authenticates against the database.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMode {
+    /// Password from the secrets files, passed via PGPASSWORD
+    #[default]
+    Password,
+    /// Kerberos ticket from the credential cache; no password is resolved
+    Gssapi,
+    /// Directory password prompted for interactively
+    Ldap,
+    /// Credential obtained through an OIDC device-code sign-in
+    Oidc,
+    /// Temporary Redshift credentials minted through the AWS CLI
+    #[serde(rename = "redshift-iam")]
+    RedshiftIam,
+    /// Secrets from a `connect-db-secret-<name>` plugin
+    Plugin,
+}
+
+/// Prompt for the directory password of `username`, reusing the keychain copy
+/// when the profile enables `cache_password`.
+pub fn ldap_password(profile_name: &str, username: &str, profile: &Profile) -> Result<String> {
+    let account = format!("{}:{}", profile_name, username);
+    if profile.cache_password
+        && let Some(password) = keychain::get(&account)
+    {
+        return Ok(password);
+    }
+
+    let password = prompt::hidden(&format!("LDAP password for {}: ", username))?;
+    if profile.cache_password
+        && let Err(err) = keychain::set(&account, &password)
+    {
+        eprintln!("Warning: could not cache password: {}", err);
+    }
+    Ok(password)
+}
+
+/// Drop a cached directory password, e.g. after it was rotated.
+pub fn forget_ldap_password(profile_name: &str, username: &str) -> Result<()> {
+    keychain::delete(&format!("{}:{}", profile_name, username))
+}
+
+/// Make sure a valid Kerberos ticket is available, running `kinit` for the
+/// profile's principal when one is configured.
+pub fn ensure_kerberos_ticket(profile: &Profile) -> Result<()> {
+    if has_kerberos_ticket()? {
+        return Ok(());
+    }
+
+    let Some(principal) = &profile.kinit_principal else {
+        return Err(anyhow::anyhow!(
+            "No valid Kerberos ticket found; run `kinit` first or set kinit_principal in the profile"
+        ));
+    };
+
+    println!("No valid Kerberos ticket, running kinit for {}", principal);
+    let status = Command::new("kinit")
+        .arg(principal)
+        .status()
+        .context("Failed to run kinit")?;
+    if !status.success() || !has_kerberos_ticket()? {
+        return Err(anyhow::anyhow!(
+            "kinit did not produce a valid ticket for {}",
+            principal
+        ));
+    }
+    Ok(())
+}
+
+fn has_kerberos_ticket() -> Result<bool> {
+    // `klist -s` is silent and exits non-zero when there is no valid ticket
+    let status = Command::new("klist")
+        .arg("-s")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context("Failed to run klist (is Kerberos installed?)")?;
+    Ok(status.success())
+}
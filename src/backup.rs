@@ -0,0 +1,232 @@
+use crate::{
+    backups,
+    codec::{Compression, Encryption},
+    config::Profile,
+    cron,
+    dump::{self, DumpOptions, Format},
+    engines::ConnectionParams,
+    http, notify,
+    object_store::{self, Location},
+    time,
+};
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::{cmp::Reverse, fmt, fs, path::PathBuf, thread, time::Duration};
+
+/// Longest single sleep while waiting for the next run, so suspend/resume
+/// and clock changes delay a backup by at most this much.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+pub struct BackupOptions {
+    /// Directory or object storage prefix receiving `<name>-<time>.dump`
+    pub dest: String,
+    pub format: Format,
+    pub compression: Option<Compression>,
+    pub encryption: Option<Encryption>,
+    /// Keep only the newest N backups
+    pub keep: Option<usize>,
+    /// Remove backups older than this many seconds
+    pub max_age: Option<u64>,
+    /// URL receiving a JSON POST when a scheduled backup fails
+    pub webhook: Option<String>,
+}
+
+enum Destination {
+    Local(PathBuf),
+    Remote(Location),
+}
+
+impl Destination {
+    fn parse(dest: &str) -> Destination {
+        match object_store::parse(dest) {
+            Some(location) => Destination::Remote(location),
+            None => Destination::Local(PathBuf::from(dest.strip_prefix("file://").unwrap_or(dest))),
+        }
+    }
+
+    /// Where pg_dump writes the file `name`.
+    fn output(&self, name: &str) -> PathBuf {
+        match self {
+            Destination::Local(dir) => dir.join(name),
+            Destination::Remote(location) => PathBuf::from(location.join(name).to_string()),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        match self {
+            Destination::Local(dir) => {
+                let mut names = Vec::new();
+                for entry in fs::read_dir(dir)? {
+                    names.push(entry?.file_name().to_string_lossy().to_string());
+                }
+                Ok(names)
+            }
+            Destination::Remote(location) => Ok(location
+                .list()?
+                .into_iter()
+                .map(|object| object.name)
+                .collect()),
+        }
+    }
+
+    fn remove(&self, name: &str) -> Result<()> {
+        match self {
+            Destination::Local(dir) => {
+                let path = dir.join(name);
+                if path.is_dir() {
+                    fs::remove_dir_all(path)?;
+                } else {
+                    fs::remove_file(path)?;
+                }
+                Ok(())
+            }
+            Destination::Remote(location) => location.join(name).delete(),
+        }
+    }
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Destination::Local(dir) => write!(f, "{}", dir.display()),
+            Destination::Remote(location) => write!(f, "{}", location),
+        }
+    }
+}
+
+/// Check options up front so a schedule doesn't fail at every run.
+fn validate(options: &BackupOptions) -> Result<Destination> {
+    let destination = Destination::parse(&options.dest);
+    if matches!(destination, Destination::Remote(_)) && options.format == Format::Directory {
+        return Err(anyhow::anyhow!(
+            "Directory backups need a local destination; use --format custom or plain"
+        ));
+    }
+    Ok(destination)
+}
+
+/// Take one backup into the destination and apply the retention policy.
+pub fn run_once(
+    name: &str,
+    params: &ConnectionParams,
+    profile: &Profile,
+    options: &BackupOptions,
+) -> Result<PathBuf> {
+    let destination = validate(options)?;
+    if let Destination::Local(dir) = &destination {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut dump_options = DumpOptions {
+        output: None,
+        format: options.format,
+        jobs: 1,
+        schemas: Vec::new(),
+        tables: Vec::new(),
+        exclude_tables: Vec::new(),
+        compression: options.compression,
+        encryption: options.encryption.clone(),
+    };
+    dump_options.output = Some(destination.output(&dump_options.default_file_name(name)));
+    let dumped = dump::dump(name, params, profile, &dump_options)?;
+    if let Err(err) = backups::record(name, &dumped, &dump_options, &[]) {
+        eprintln!(
+            "Warning: failed to record the backup in the catalog: {:#}",
+            err
+        );
+    }
+    apply_retention(&destination, name, options)?;
+    Ok(dumped.path)
+}
+
+/// When a file of the destination is a backup of `name`, the time in its
+/// name. The timestamp must follow the name directly, so `orders` doesn't
+/// claim `orders-archive-...` backups.
+fn backup_time(name: &str, file_name: &str) -> Option<u64> {
+    let stamp = file_name.strip_prefix(name)?.strip_prefix('-')?.get(..20)?;
+    let (date, time) = stamp.split_once('T')?;
+    time::parse_rfc3339(&format!("{}T{}", date, time.replace('-', ":")))
+}
+
+/// Remove backups of `name` beyond `keep` or older than `max_age`. The
+/// newest backup is never removed.
+fn apply_retention(destination: &Destination, name: &str, options: &BackupOptions) -> Result<()> {
+    if options.keep.is_none() && options.max_age.is_none() {
+        return Ok(());
+    }
+    let mut backups: Vec<(String, u64)> = destination
+        .list()?
+        .into_iter()
+        .filter_map(|file_name| {
+            let taken = backup_time(name, &file_name)?;
+            Some((file_name, taken))
+        })
+        .collect();
+    backups.sort_by_key(|(_, taken)| Reverse(*taken));
+
+    let now = time::unix_now();
+    for (index, (file_name, taken)) in backups.iter().enumerate().skip(1) {
+        let beyond_keep = options.keep.is_some_and(|keep| index >= keep);
+        let too_old = options
+            .max_age
+            .is_some_and(|max_age| now.saturating_sub(*taken) > max_age);
+        if beyond_keep || too_old {
+            destination.remove(file_name)?;
+            let removed = destination.output(file_name);
+            eprintln!("Removed old backup {}", removed.display());
+            backups::forget(&removed)?;
+        }
+    }
+    Ok(())
+}
+
+/// Back up `name` whenever the cron expression fires, until interrupted.
+/// Credentials are resolved again for every run, so rotated secrets are
+/// picked up.
+pub fn schedule(
+    name: &str,
+    cron_expression: &str,
+    options: &BackupOptions,
+    resolve: &dyn Fn() -> Result<(Profile, ConnectionParams)>,
+) -> Result<()> {
+    let schedule = cron::Schedule::parse(cron_expression)?;
+    validate(options)?;
+    loop {
+        let next = schedule
+            .next_after(time::unix_now())
+            .with_context(|| format!("{} never fires", cron_expression))?;
+        eprintln!("Next backup of {} at {}", name, time::rfc3339(next));
+        while time::unix_now() < next {
+            let remaining = Duration::from_secs(next - time::unix_now());
+            thread::sleep(remaining.min(MAX_SLEEP));
+        }
+
+        let result =
+            resolve().and_then(|(profile, params)| run_once(name, &params, &profile, options));
+        if let Err(err) = result {
+            report_failure(name, &err, options);
+        }
+    }
+}
+
+fn report_failure(name: &str, err: &anyhow::Error, options: &BackupOptions) {
+    eprintln!("Backup of {} failed: {:#}", name, err);
+    notify::send(&format!("Backup of {} failed", name), &err.to_string());
+    let Some(webhook) = &options.webhook else {
+        return;
+    };
+    let body = json!({
+        "text": format!("connect-db: backup of {} failed: {:#}", name, err),
+        "database": name,
+        "error": format!("{:#}", err),
+        "time": time::now_rfc3339(),
+    });
+    match http::post(
+        webhook,
+        &[("Content-Type", "application/json")],
+        &body.to_string(),
+    ) {
+        Ok(response) if response.is_success() => {}
+        Ok(response) => eprintln!("Failure webhook returned HTTP {}", response.status),
+        Err(err) => eprintln!("Failure webhook failed: {}", err),
+    }
+}
@@ -0,0 +1,488 @@
+//! Catalog of the dumps taken by connect-db, with labels and checksums.
+
+use crate::{
+    config,
+    dump::{DumpOptions, Dumped},
+    object_store,
+    progress::{Progress, Unit},
+    random, report, sha256, storage, time,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    fs,
+    path::{self, Path, PathBuf},
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Entry {
+    pub id: String,
+    /// Absolute path or object storage URL
+    pub location: String,
+    pub database: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+    pub format: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<String>,
+    #[serde(default)]
+    pub encrypted: bool,
+    pub size: u64,
+    pub sha256: String,
+    pub created_at: u64,
+}
+
+fn catalog_path() -> Result<PathBuf> {
+    Ok(config::data_dir()?.join("backups.json"))
+}
+
+/// Recorded dumps, oldest first.
+pub fn load() -> Result<Vec<Entry>> {
+    let path = catalog_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+fn save(entries: &[Entry]) -> Result<()> {
+    let path = catalog_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Write and rename so a crash never leaves half a catalog
+    let temporary = path.with_extension(format!("json.{}", std::process::id()));
+    fs::write(&temporary, serde_json::to_string_pretty(entries)?)
+        .with_context(|| format!("Failed to write {}", temporary.display()))?;
+    fs::rename(&temporary, &path).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Key a location the same way however it was spelled on the command line.
+fn normalize(location: &Path) -> String {
+    let text = location.to_string_lossy();
+    if object_store::parse(&text).is_some() {
+        return text.to_string();
+    }
+    path::absolute(location)
+        .unwrap_or_else(|_| location.to_path_buf())
+        .display()
+        .to_string()
+}
+
+/// Add a finished dump to the catalog, hashing it when it was written
+/// locally by pg_dump.
+pub fn record(
+    database: &str,
+    dumped: &Dumped,
+    options: &DumpOptions,
+    labels: &[String],
+) -> Result<Entry> {
+    let (size, sha256) = match &dumped.streamed {
+        Some((size, sha256)) => (*size, sha256.clone()),
+        None => (
+            disk_size(&dumped.path)?,
+            sha256::path(&dumped.path)
+                .with_context(|| format!("Failed to hash {}", dumped.path.display()))?,
+        ),
+    };
+    let entry = Entry {
+        id: random::hex(4)?,
+        location: normalize(&dumped.path),
+        database: database.to_string(),
+        labels: labels.to_vec(),
+        format: format!("{:?}", options.format).to_lowercase(),
+        compression: options
+            .compression
+            .map(|compression| format!("{:?}", compression).to_lowercase()),
+        encrypted: options.encryption.is_some(),
+        size,
+        sha256,
+        created_at: time::unix_now(),
+    };
+    let mut entries = load()?;
+    entries.retain(|existing| existing.location != entry.location);
+    entries.push(entry.clone());
+    save(&entries)?;
+    Ok(entry)
+}
+
+/// Drop the entry of a dump that was deleted elsewhere (backup retention).
+pub fn forget(location: &Path) -> Result<()> {
+    let location = normalize(location);
+    let mut entries = load()?;
+    let before = entries.len();
+    entries.retain(|entry| entry.location != location);
+    if entries.len() != before {
+        save(&entries)?;
+    }
+    Ok(())
+}
+
+fn disk_size(path: &Path) -> Result<u64> {
+    if !path.is_dir() {
+        return Ok(fs::metadata(path)?.len());
+    }
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        total += entry?.metadata()?.len();
+    }
+    Ok(total)
+}
+
+/// Whether `selector` names the entry by id, label, database or location.
+fn selected(entry: &Entry, selector: &str) -> bool {
+    entry.id == selector
+        || entry.database == selector
+        || entry.location == selector
+        || entry.labels.iter().any(|label| label == selector)
+}
+
+pub fn list(database: Option<&str>, label: Option<&str>) -> Result<()> {
+    let mut entries = load()?;
+    entries.retain(|entry| {
+        database.is_none_or(|database| entry.database == database)
+            && label.is_none_or(|label| entry.labels.iter().any(|l| l == label))
+    });
+    entries.sort_by_key(|entry| Reverse(entry.created_at));
+    let rows: Vec<Vec<String>> = entries
+        .into_iter()
+        .map(|entry| {
+            let mut kind = entry.format;
+            if let Some(compression) = entry.compression {
+                kind.push_str(&format!("+{}", compression));
+            }
+            if entry.encrypted {
+                kind.push_str("+age");
+            }
+            vec![
+                entry.id,
+                time::rfc3339(entry.created_at),
+                entry.database,
+                kind,
+                storage::format_size(entry.size),
+                entry.labels.join(","),
+                entry.location,
+            ]
+        })
+        .collect();
+    report::print_table(
+        &[
+            "ID", "CREATED", "DATABASE", "FORMAT", "SIZE", "LABELS", "LOCATION",
+        ],
+        &rows,
+    );
+    Ok(())
+}
+
+/// Recompute the checksums of the selected dumps (all when empty).
+pub fn verify(selectors: &[String]) -> Result<()> {
+    let entries: Vec<Entry> = load()?
+        .into_iter()
+        .filter(|entry| {
+            selectors.is_empty() || selectors.iter().any(|selector| selected(entry, selector))
+        })
+        .collect();
+    if entries.is_empty() {
+        return Err(anyhow::anyhow!("No recorded dumps match"));
+    }
+
+    let mut failed = 0;
+    for entry in &entries {
+        let state = match checksum(entry) {
+            Ok(Some(sha256)) if sha256 == entry.sha256 => "ok".to_string(),
+            Ok(Some(_)) => "checksum mismatch".to_string(),
+            Ok(None) => "missing".to_string(),
+            Err(err) => format!("error: {}", err),
+        };
+        if state != "ok" {
+            failed += 1;
+        }
+        println!("{}  {}  {}", entry.id, entry.location, state);
+    }
+    if failed > 0 {
+        return Err(anyhow::anyhow!(
+            "{} of {} dumps failed verification",
+            failed,
+            entries.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Current checksum of a dump, or `None` when it no longer exists.
+fn checksum(entry: &Entry) -> Result<Option<String>> {
+    let Some(location) = object_store::parse(&entry.location) else {
+        let path = Path::new(&entry.location);
+        if !path.exists() {
+            return Ok(None);
+        }
+        return Ok(Some(sha256::path(path)?));
+    };
+    if location.size()?.is_none() {
+        return Ok(None);
+    }
+    let mut download = location.download()?;
+    let stdout = download.stdout.take().expect("stdout is piped");
+    let mut progress = Progress::new(&format!("verify {}", entry.id), entry.size, Unit::Bytes);
+    let sha256 = sha256::reader(stdout, |read| progress.set(read, ""))?;
+    if !download.wait()?.success() {
+        return Err(anyhow::anyhow!("Failed to download {}", location));
+    }
+    progress.finish();
+    Ok(Some(sha256))
+}
+
+pub struct PruneOptions {
+    pub database: Option<String>,
+    /// Unlabeled dumps to keep per database
+    pub keep: Option<usize>,
+    /// Seconds after which unlabeled dumps are removed
+    pub older_than: Option<u64>,
+    /// Forget entries whose dump no longer exists
+    pub missing: bool,
+    pub dry_run: bool,
+}
+
+/// What `prune` does with an entry.
+#[derive(Debug, PartialEq)]
+enum Pruning {
+    /// The dump is gone; only drop the entry
+    Forget,
+    /// Delete the dump and its entry
+    Remove,
+}
+
+/// Delete old unlabeled dumps and their entries; labeled dumps are
+/// snapshots and never pruned.
+pub fn prune(options: &PruneOptions) -> Result<()> {
+    if options.keep.is_none() && options.older_than.is_none() && !options.missing {
+        return Err(anyhow::anyhow!(
+            "Nothing to prune; pass --keep, --older-than or --missing"
+        ));
+    }
+    let entries = load()?;
+    let mut removed = Vec::new();
+    for (entry, pruning) in to_prune(&entries, options, time::unix_now(), is_missing)? {
+        match pruning {
+            Pruning::Forget => {
+                println!("Forgetting missing {} ({})", entry.id, entry.location);
+            }
+            Pruning::Remove => {
+                println!("Removing {} ({})", entry.id, entry.location);
+                if !options.dry_run {
+                    delete(entry)?;
+                }
+            }
+        }
+        removed.push(entry.id.clone());
+    }
+
+    if options.dry_run {
+        println!("Dry run; nothing was removed");
+        return Ok(());
+    }
+    if !removed.is_empty() {
+        let remaining: Vec<Entry> = entries
+            .into_iter()
+            .filter(|entry| !removed.contains(&entry.id))
+            .collect();
+        save(&remaining)?;
+    }
+    println!("Pruned {} dumps", removed.len());
+    Ok(())
+}
+
+/// The entries `options` prunes at `now`, newest first per database.
+fn to_prune<'a>(
+    entries: &'a [Entry],
+    options: &PruneOptions,
+    now: u64,
+    is_missing: impl Fn(&Entry) -> Result<bool>,
+) -> Result<Vec<(&'a Entry, Pruning)>> {
+    let mut by_database: BTreeMap<&str, Vec<&Entry>> = BTreeMap::new();
+    for entry in entries {
+        if options
+            .database
+            .as_deref()
+            .is_none_or(|database| entry.database == database)
+        {
+            by_database.entry(&entry.database).or_default().push(entry);
+        }
+    }
+
+    let mut pruned = Vec::new();
+    for dumps in by_database.values_mut() {
+        dumps.sort_by_key(|entry| Reverse(entry.created_at));
+        let mut unlabeled = 0;
+        for entry in dumps.iter() {
+            if options.missing && is_missing(entry)? {
+                pruned.push((*entry, Pruning::Forget));
+                continue;
+            }
+            if !entry.labels.is_empty() {
+                continue;
+            }
+            unlabeled += 1;
+            let beyond_keep = options.keep.is_some_and(|keep| unlabeled > keep);
+            let too_old = options
+                .older_than
+                .is_some_and(|age| now.saturating_sub(entry.created_at) > age);
+            if beyond_keep || too_old {
+                pruned.push((*entry, Pruning::Remove));
+            }
+        }
+    }
+    Ok(pruned)
+}
+
+fn is_missing(entry: &Entry) -> Result<bool> {
+    Ok(match object_store::parse(&entry.location) {
+        Some(location) => location.size()?.is_none(),
+        None => !Path::new(&entry.location).exists(),
+    })
+}
+
+fn delete(entry: &Entry) -> Result<()> {
+    if let Some(location) = object_store::parse(&entry.location) {
+        return location.delete();
+    }
+    let path = Path::new(&entry.location);
+    if path.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+    const NOW: u64 = 100 * DAY;
+
+    fn entry(id: &str, database: &str, age_days: u64, labels: &[&str]) -> Entry {
+        Entry {
+            id: id.to_string(),
+            location: format!("/backups/{}.dump", id),
+            database: database.to_string(),
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            format: "custom".to_string(),
+            compression: None,
+            encrypted: false,
+            size: 1,
+            sha256: String::new(),
+            created_at: NOW - age_days * DAY,
+        }
+    }
+
+    fn defaults() -> PruneOptions {
+        PruneOptions {
+            database: None,
+            keep: None,
+            older_than: None,
+            missing: false,
+            dry_run: false,
+        }
+    }
+
+    fn pruned(entries: &[Entry], options: &PruneOptions, missing: &[&str]) -> Vec<String> {
+        to_prune(entries, options, NOW, |entry| {
+            Ok(missing.contains(&entry.id.as_str()))
+        })
+        .unwrap()
+        .into_iter()
+        .map(|(entry, pruning)| match pruning {
+            Pruning::Remove => entry.id.clone(),
+            Pruning::Forget => format!("forget {}", entry.id),
+        })
+        .collect()
+    }
+
+    fn catalog() -> Vec<Entry> {
+        vec![
+            entry("a1", "orders", 10, &[]),
+            entry("a2", "orders", 1, &[]),
+            entry("a3", "orders", 5, &["release-1.0"]),
+            entry("a4", "orders", 3, &[]),
+            entry("a5", "orders", 20, &[]),
+            entry("b1", "billing", 30, &[]),
+            entry("b2", "billing", 2, &[]),
+        ]
+    }
+
+    #[test]
+    fn keeps_the_newest_unlabeled_dumps_per_database() {
+        let options = PruneOptions {
+            keep: Some(2),
+            ..defaults()
+        };
+        // orders keeps a2 and a4, and the labeled a3 doesn't count
+        assert_eq!(pruned(&catalog(), &options, &[]), ["a1", "a5"]);
+        let options = PruneOptions {
+            keep: Some(1),
+            ..defaults()
+        };
+        assert_eq!(pruned(&catalog(), &options, &[]), ["b1", "a4", "a1", "a5"]);
+    }
+
+    #[test]
+    fn removes_dumps_older_than_the_limit() {
+        let options = PruneOptions {
+            older_than: Some(7 * DAY),
+            ..defaults()
+        };
+        assert_eq!(pruned(&catalog(), &options, &[]), ["b1", "a1", "a5"]);
+        // Either limit is enough to prune
+        let options = PruneOptions {
+            older_than: Some(25 * DAY),
+            keep: Some(3),
+            ..defaults()
+        };
+        assert_eq!(pruned(&catalog(), &options, &[]), ["b1", "a5"]);
+    }
+
+    #[test]
+    fn only_the_given_database() {
+        let options = PruneOptions {
+            database: Some("billing".to_string()),
+            keep: Some(0),
+            ..defaults()
+        };
+        assert_eq!(pruned(&catalog(), &options, &[]), ["b2", "b1"]);
+    }
+
+    #[test]
+    fn labeled_dumps_are_only_forgotten_when_missing() {
+        let options = PruneOptions {
+            keep: Some(0),
+            ..defaults()
+        };
+        let all = pruned(&catalog(), &options, &[]);
+        assert!(!all.contains(&"a3".to_string()));
+
+        let options = PruneOptions {
+            missing: true,
+            ..defaults()
+        };
+        assert_eq!(
+            pruned(&catalog(), &options, &["a3", "b2"]),
+            ["forget b2", "forget a3"]
+        );
+        // A missing dump doesn't take one of the places --keep leaves
+        let options = PruneOptions {
+            missing: true,
+            keep: Some(1),
+            ..defaults()
+        };
+        assert_eq!(
+            pruned(&catalog(), &options, &["a2"]),
+            ["b1", "forget a2", "a1", "a5"]
+        );
+    }
+}
@@ -0,0 +1,552 @@
+//! `connect-db batch plan.yaml`: run a runbook of steps (connect, exec,
+//! script, dump, restore, any connect-db command, shell) in order and
+//! summarize how each went.
+//!
+//! Each step runs as its own connect-db process, so every step gets the
+//! approvals, hooks and confirmations it would get when run by hand. Plans
+//! take `{{name}}` parameters and may stop at `confirm` gates; runs, answers
+//! and step results are recorded in the audit log.
+
+use crate::{audit, dry_run, progress, prompt, report, yaml};
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Instant,
+};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Plan {
+    /// What the runbook is for, shown when it starts
+    description: Option<String>,
+    /// Parameters the steps refer to as `{{name}}`
+    #[serde(default)]
+    params: BTreeMap<String, Param>,
+    /// Run the remaining steps after one fails (steps may override it)
+    #[serde(default)]
+    continue_on_error: bool,
+    /// Steps, parsed once their parameters are filled in
+    steps: Vec<Value>,
+}
+
+/// A parameter given as its default (`null` for none) or with details.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Param {
+    Details(ParamDetails),
+    Default(Value),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ParamDetails {
+    /// Asked for when no value is given
+    description: Option<String>,
+    default: Option<Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Step {
+    /// Label used in the summary instead of the generated one
+    name: Option<String>,
+    /// Database for this step, instead of the last `connect`
+    database: Option<String>,
+    continue_on_error: Option<bool>,
+    /// Check the database is reachable and use it for later steps
+    connect: Option<String>,
+    /// SQL to run
+    exec: Option<String>,
+    /// SQL file to run
+    script: Option<PathBuf>,
+    /// File to dump into
+    dump: Option<PathBuf>,
+    /// Dump to restore
+    restore: Option<PathBuf>,
+    /// connect-db arguments, e.g. `[verify, staging, --tables, users]`
+    run: Option<Vec<String>>,
+    /// Command run with `sh -c`
+    shell: Option<String>,
+    /// Question that must be answered yes to go on
+    confirm: Option<String>,
+    /// Tables to dump (`--table`)
+    #[serde(default)]
+    tables: Vec<String>,
+    /// Extra arguments for exec, script, dump and restore steps
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+enum Action {
+    /// Arguments for a connect-db process
+    ConnectDb(Vec<OsString>),
+    Shell(String),
+    Confirm(String),
+}
+
+struct Planned {
+    label: String,
+    /// Database the step works on, for the audit log
+    database: Option<String>,
+    action: Action,
+    continue_on_error: bool,
+}
+
+enum Outcome {
+    Ok,
+    Failed(String),
+    Declined,
+    Skipped,
+}
+
+pub struct BatchOptions {
+    /// `--param` values
+    pub params: Vec<(String, String)>,
+    /// Answer yes to every `confirm` step
+    pub yes: bool,
+}
+
+/// `NAME=VALUE` for `--param`.
+pub fn parse_param(argument: &str) -> Result<(String, String), String> {
+    argument
+        .split_once('=')
+        .map(|(name, value)| (name.trim().to_string(), value.to_string()))
+        .filter(|(name, _)| !name.is_empty())
+        .ok_or_else(|| format!("expected NAME=VALUE, got '{}'", argument))
+}
+
+/// Run the steps of the plan in `path`. `forwarded` are the global options
+/// (like `--env`) each connect-db step is started with.
+pub fn run(path: &Path, options: &BatchOptions, forwarded: &[OsString]) -> Result<()> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let value = yaml::parse(&text).with_context(|| format!("Invalid plan {}", path.display()))?;
+    let plan: Plan = serde_json::from_value(value)
+        .with_context(|| format!("Invalid plan {}", path.display()))?;
+    if let Some(description) = &plan.description {
+        eprintln!("{}", description.trim());
+    }
+    let values = param_values(&plan.params, &options.params)?;
+    // Check every step up front so a typo doesn't stop a runbook halfway
+    let steps =
+        plan_steps(&plan, &values).with_context(|| format!("Invalid plan {}", path.display()))?;
+    let program = env::current_exe().context("Failed to locate the connect-db binary")?;
+
+    let plan_name = path.display().to_string();
+    if !dry_run::enabled() {
+        audit::record(
+            "batch_start",
+            "",
+            json!({ "plan": plan_name, "params": values }),
+        )?;
+    }
+
+    let count = steps.len();
+    let mut results = Vec::new();
+    let mut stopped = false;
+    for (index, step) in steps.iter().enumerate() {
+        if stopped {
+            results.push((Outcome::Skipped, None));
+            continue;
+        }
+        eprintln!("==> [{}/{}] {}", index + 1, count, step.label);
+        let started = Instant::now();
+        let outcome = match &step.action {
+            Action::Confirm(question) => {
+                let answer = confirm(question, options.yes);
+                record(
+                    "batch_confirm",
+                    step,
+                    json!({
+                        "plan": plan_name,
+                        "step": index + 1,
+                        "question": question,
+                        "answer": match &answer {
+                            Ok(Some(answer)) => answer.to_string(),
+                            Ok(None) => "no".to_string(),
+                            Err(err) => format!("{:#}", err),
+                        },
+                    }),
+                );
+                // A gate stops the run whatever `continue_on_error` says
+                match answer {
+                    Ok(Some(_)) => Outcome::Ok,
+                    Ok(None) => {
+                        stopped = true;
+                        Outcome::Declined
+                    }
+                    Err(err) => {
+                        eprintln!("Step {} failed: {:#}", index + 1, err);
+                        stopped = true;
+                        Outcome::Failed(format!("{:#}", err))
+                    }
+                }
+            }
+            action => match run_step(&program, forwarded, action) {
+                Ok(()) => Outcome::Ok,
+                Err(err) => {
+                    eprintln!("Step {} failed: {:#}", index + 1, err);
+                    stopped = !step.continue_on_error;
+                    Outcome::Failed(format!("{:#}", err))
+                }
+            },
+        };
+        let seconds = started.elapsed().as_secs();
+        if !matches!(step.action, Action::Confirm(_)) {
+            record(
+                "batch_step",
+                step,
+                json!({
+                    "plan": plan_name,
+                    "step": index + 1,
+                    "label": step.label,
+                    "result": outcome_text(&outcome),
+                    "seconds": seconds,
+                }),
+            );
+        }
+        results.push((outcome, Some(seconds)));
+    }
+
+    let rows: Vec<Vec<String>> = steps
+        .iter()
+        .zip(&results)
+        .enumerate()
+        .map(|(index, (step, (outcome, seconds)))| {
+            vec![
+                (index + 1).to_string(),
+                step.label.clone(),
+                seconds.map(progress::format_duration).unwrap_or_default(),
+                outcome_text(outcome),
+            ]
+        })
+        .collect();
+    println!();
+    report::print_table(&["#", "STEP", "TIME", "RESULT"], &rows);
+
+    let failed = results
+        .iter()
+        .filter(|(outcome, _)| matches!(outcome, Outcome::Failed(_)))
+        .count();
+    let skipped = results
+        .iter()
+        .filter(|(outcome, _)| matches!(outcome, Outcome::Skipped))
+        .count();
+    let declined = results
+        .iter()
+        .position(|(outcome, _)| matches!(outcome, Outcome::Declined));
+    if !dry_run::enabled()
+        && let Err(err) = audit::record(
+            "batch_end",
+            "",
+            json!({ "plan": plan_name, "failed": failed, "skipped": skipped }),
+        )
+    {
+        eprintln!("Warning: failed to write the audit log: {:#}", err);
+    }
+    if let Some(index) = declined {
+        bail!(
+            "Stopped at step {}: not confirmed ({} skipped)",
+            index + 1,
+            skipped
+        );
+    }
+    if failed > 0 {
+        bail!(
+            "{} of {} step{} failed{}",
+            failed,
+            count,
+            if count == 1 { "" } else { "s" },
+            if skipped > 0 {
+                format!(", {} skipped", skipped)
+            } else {
+                String::new()
+            }
+        );
+    }
+    Ok(())
+}
+
+fn outcome_text(outcome: &Outcome) -> String {
+    match outcome {
+        Outcome::Ok => "ok".to_string(),
+        Outcome::Failed(reason) => format!("failed: {}", reason),
+        Outcome::Declined => "declined".to_string(),
+        Outcome::Skipped => "skipped".to_string(),
+    }
+}
+
+/// Audit `event` for `step`, outside dry runs. A log that can't be written
+/// mid-run is a warning, not a reason to abandon the runbook halfway.
+fn record(event: &str, step: &Planned, details: Value) {
+    if dry_run::enabled() {
+        return;
+    }
+    if let Err(err) = audit::record(event, step.database.as_deref().unwrap_or(""), details) {
+        eprintln!("Warning: failed to write the audit log: {:#}", err);
+    }
+}
+
+/// How `question` was answered: `Some` with who said yes, `None` for no.
+fn confirm(question: &str, yes: bool) -> Result<Option<&'static str>> {
+    if dry_run::enabled() {
+        dry_run::report(format!("would ask: {}", question));
+        return Ok(Some("dry-run"));
+    }
+    if yes {
+        eprintln!("{} (yes, --yes given)", question);
+        return Ok(Some("--yes"));
+    }
+    Ok(prompt::confirm(question)?.then_some("yes"))
+}
+
+/// The value of every parameter: from `--param`, the default, or asked for.
+fn param_values(
+    declared: &BTreeMap<String, Param>,
+    given: &[(String, String)],
+) -> Result<BTreeMap<String, String>> {
+    if let Some((name, _)) = given.iter().find(|(name, _)| !declared.contains_key(name)) {
+        bail!("The plan has no parameter '{}'", name);
+    }
+    let mut values = BTreeMap::new();
+    for (name, param) in declared {
+        let (description, default) = match param {
+            Param::Details(details) => (details.description.as_deref(), details.default.as_ref()),
+            Param::Default(default) => (None, Some(default)),
+        };
+        let default = match default {
+            None | Some(Value::Null) => None,
+            Some(Value::String(text)) => Some(text.clone()),
+            Some(value @ (Value::Number(_) | Value::Bool(_))) => Some(value.to_string()),
+            Some(_) => bail!("Parameter '{}': the default must be a single value", name),
+        };
+        let value = match given.iter().rev().find(|(given, _)| given == name) {
+            Some((_, value)) => value.clone(),
+            None => match default {
+                Some(default) => default,
+                None => {
+                    let question = match description {
+                        Some(description) => format!("{} ({})", description, name),
+                        None => name.clone(),
+                    };
+                    let value = prompt::line(&question, None).with_context(|| {
+                        format!(
+                            "Parameter '{}' has no value; pass --param {}=VALUE",
+                            name, name
+                        )
+                    })?;
+                    if value.is_empty() {
+                        bail!("Parameter '{}' needs a value", name);
+                    }
+                    value
+                }
+            },
+        };
+        values.insert(name.clone(), value);
+    }
+    Ok(values)
+}
+
+/// `text` with `{{name}}` replaced by parameter values. Braces around
+/// anything but a name (like Go templates in shell steps) are kept.
+fn render(text: &str, values: &BTreeMap<String, String>) -> Result<String> {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + 2 + length].trim();
+        let end = start + 2 + length + 2;
+        result.push_str(&rest[..start]);
+        if !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            let value = values
+                .get(name)
+                .with_context(|| format!("unknown parameter {{{{{}}}}}", name))?;
+            result.push_str(value);
+        } else {
+            result.push_str(&rest[start..end]);
+        }
+        rest = &rest[end..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn render_value(value: &Value, values: &BTreeMap<String, String>) -> Result<Value> {
+    Ok(match value {
+        Value::String(text) => Value::String(render(text, values)?),
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| render_value(item, values))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, item)| Ok((key.clone(), render_value(item, values)?)))
+                .collect::<Result<_>>()?,
+        ),
+        other => other.clone(),
+    })
+}
+
+fn plan_steps(plan: &Plan, values: &BTreeMap<String, String>) -> Result<Vec<Planned>> {
+    let mut database: Option<String> = None;
+    let mut planned = Vec::new();
+    for (index, step) in plan.steps.iter().enumerate() {
+        let number = index + 1;
+        let step: Step = render_value(step, values)
+            .and_then(|step| Ok(serde_json::from_value(step)?))
+            .with_context(|| format!("Step {}", number))?;
+        let actions = [
+            step.connect.is_some(),
+            step.exec.is_some(),
+            step.script.is_some(),
+            step.dump.is_some(),
+            step.restore.is_some(),
+            step.run.is_some(),
+            step.shell.is_some(),
+            step.confirm.is_some(),
+        ];
+        if actions.iter().filter(|set| **set).count() != 1 {
+            bail!(
+                "Step {}: give exactly one of connect, exec, script, dump, restore, run, shell or confirm",
+                number
+            );
+        }
+        if let Some(name) = &step.connect {
+            database = Some(name.clone());
+        }
+        let target = || {
+            step.database
+                .clone()
+                .or_else(|| database.clone())
+                .with_context(|| {
+                    format!(
+                        "Step {}: no database; add `database:` or an earlier `connect` step",
+                        number
+                    )
+                })
+        };
+        let args = |mut args: Vec<OsString>| {
+            args.extend(step.args.iter().map(OsString::from));
+            Action::ConnectDb(args)
+        };
+
+        let (label, action) = if let Some(question) = &step.confirm {
+            (
+                format!("confirm {}", question.trim()),
+                Action::Confirm(question.trim().to_string()),
+            )
+        } else if let Some(name) = &step.connect {
+            (
+                format!("connect {}", name),
+                Action::ConnectDb(vec!["exec".into(), name.into(), "SELECT 1".into()]),
+            )
+        } else if let Some(sql) = &step.exec {
+            let name = target()?;
+            (
+                format!("exec on {}", name),
+                args(vec!["exec".into(), name.into(), sql.into()]),
+            )
+        } else if let Some(file) = &step.script {
+            let name = target()?;
+            (
+                format!("script {} on {}", file.display(), name),
+                args(vec![
+                    "exec".into(),
+                    name.into(),
+                    "--file".into(),
+                    file.into(),
+                ]),
+            )
+        } else if let Some(file) = &step.dump {
+            let name = target()?;
+            let mut dump = vec![
+                "dump".into(),
+                name.clone().into(),
+                "--output".into(),
+                file.into(),
+            ];
+            for table in &step.tables {
+                dump.push("--table".into());
+                dump.push(table.into());
+            }
+            let what = if step.tables.is_empty() {
+                name
+            } else {
+                format!("{} ({})", name, step.tables.join(", "))
+            };
+            (format!("dump {} to {}", what, file.display()), args(dump))
+        } else if let Some(file) = &step.restore {
+            let name = target()?;
+            (
+                format!("restore {} into {}", file.display(), name),
+                args(vec!["restore".into(), name.into(), file.into()]),
+            )
+        } else if let Some(run) = &step.run {
+            if run.is_empty() {
+                bail!("Step {}: `run` needs connect-db arguments", number);
+            }
+            (
+                format!("run {}", run.join(" ")),
+                Action::ConnectDb(run.iter().map(OsString::from).collect()),
+            )
+        } else {
+            let command = step.shell.clone().unwrap_or_default();
+            // Multi-line scripts are labeled by their first line
+            let first = command.trim().lines().next().unwrap_or_default();
+            (format!("shell {}", first), Action::Shell(command))
+        };
+        planned.push(Planned {
+            label: step.name.clone().unwrap_or(label),
+            database: step.database.clone().or_else(|| database.clone()),
+            action,
+            continue_on_error: step.continue_on_error.unwrap_or(plan.continue_on_error),
+        });
+    }
+    if planned.is_empty() {
+        bail!("The plan has no steps");
+    }
+    Ok(planned)
+}
+
+fn run_step(program: &Path, forwarded: &[OsString], action: &Action) -> Result<()> {
+    let status = match action {
+        Action::ConnectDb(args) => Command::new(program)
+            .args(forwarded)
+            .args(args)
+            .status()
+            .context("Failed to run connect-db")?,
+        Action::Shell(command) => {
+            if dry_run::enabled() {
+                dry_run::report(format!("would run `{}`", command));
+                return Ok(());
+            }
+            Command::new("sh")
+                .args(["-c", command])
+                .status()
+                .context("Failed to run sh")?
+        }
+        Action::Confirm(_) => unreachable!("confirm steps are answered by `run`"),
+    };
+    if !status.success() {
+        match status.code() {
+            Some(code) => bail!("exit code {}", code),
+            None => bail!("killed by a signal"),
+        }
+    }
+    Ok(())
+}
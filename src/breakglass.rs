@@ -0,0 +1,246 @@
+//! Temporary elevated access: credentials leased from Vault for one session,
+//! recorded in the audit log and revoked when the time is up.
+
+use crate::{
+    audit,
+    config::Profile,
+    dry_run,
+    engines::{self, ConnectionParams},
+    time, webhook,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+/// `breakglass` settings of a profile.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct BreakglassConfig {
+    /// Vault path issuing the elevated credentials, e.g. `database/creds/orders-admin`
+    pub vault_path: String,
+    /// Webhook told when access starts and ends
+    pub webhook: Option<String>,
+}
+
+struct Lease {
+    id: String,
+    /// Seconds Vault will keep the credentials alive on its own
+    duration: u64,
+    username: String,
+    password: String,
+}
+
+/// Open a session on `name` with elevated credentials for at most
+/// `duration` seconds, then revoke them.
+pub fn run(
+    name: &str,
+    params: &ConnectionParams,
+    profile: &Profile,
+    reason: &str,
+    duration: u64,
+) -> Result<()> {
+    let config = profile.breakglass.as_ref().with_context(|| {
+        format!(
+            "{} has no breakglass settings; add \"breakglass\": {{\"vault_path\": ...}} to its profile",
+            name
+        )
+    })?;
+    if reason.trim().is_empty() {
+        return Err(anyhow::anyhow!("--reason must say why access is needed"));
+    }
+
+    // Reading the path mints credentials and starts a lease, asked to last
+    // no longer than the session so Vault ends it even if this process dies
+    if dry_run::enabled() {
+        dry_run::stop(&format!(
+            "vault read -format=json {} ttl={}s",
+            config.vault_path, duration
+        ));
+    }
+    let mut lease = read_credentials(&config.vault_path, duration)?;
+    if lease.duration == 0 || lease.duration > duration {
+        lease.duration = shorten(&lease.id, duration).unwrap_or(lease.duration);
+    }
+    let duration = if lease.duration > 0 && lease.duration < duration {
+        eprintln!(
+            "Warning: Vault leases these credentials for {}s only",
+            lease.duration
+        );
+        lease.duration
+    } else {
+        if lease.duration > duration {
+            eprintln!(
+                "Warning: Vault keeps these credentials for {}s unless this session revokes them",
+                lease.duration
+            );
+        }
+        duration
+    };
+    let details = json!({
+        "reason": reason,
+        "vault_path": config.vault_path,
+        "lease_id": lease.id,
+        "duration_seconds": duration,
+    });
+    audit::record("breakglass_start", name, details.clone())?;
+    notify(
+        config,
+        &format!(
+            "{} started break-glass access to {} for {}s: {}",
+            user(),
+            name,
+            duration,
+            reason
+        ),
+        name,
+        &details,
+    );
+
+    let mut params = params.clone();
+    params.username = lease.username.clone();
+    params.password = lease.password.clone();
+    eprintln!(
+        "Break-glass access to {} as {} until {}",
+        name,
+        lease.username,
+        time::rfc3339(time::unix_now() + duration)
+    );
+    let started = time::unix_now();
+    let session = engines::spawn_session(&params, profile).and_then(|mut child| {
+        // Ends the session when the time is up, unless it ended first
+        let (done, expired) = mpsc::channel::<()>();
+        let pid = child.id() as libc::pid_t;
+        let timer = thread::spawn(move || {
+            if expired.recv_timeout(Duration::from_secs(duration)).is_err() {
+                eprintln!("\nBreak-glass access has expired; ending the session");
+                unsafe {
+                    libc::kill(pid, libc::SIGTERM);
+                }
+            }
+        });
+        // The timer is stopped before the client is reaped, so it never
+        // signals a pid that was already reused
+        engines::wait_session_then(&mut child, || {
+            let _ = done.send(());
+            let _ = timer.join();
+        })
+    });
+
+    let revoked = revoke(&lease.id);
+    let details = json!({
+        "reason": reason,
+        "lease_id": lease.id,
+        "session_seconds": time::unix_now() - started,
+        "revoked": revoked.is_ok(),
+    });
+    if let Err(err) = audit::record("breakglass_end", name, details.clone()) {
+        eprintln!("Warning: failed to write the audit log: {:#}", err);
+    }
+    notify(
+        config,
+        &format!(
+            "{} ended break-glass access to {}{}",
+            user(),
+            name,
+            if revoked.is_ok() {
+                ""
+            } else {
+                " (revoking the lease FAILED)"
+            }
+        ),
+        name,
+        &details,
+    );
+    session?;
+    revoked.with_context(|| {
+        format!(
+            "Revoke lease {} with `vault lease revoke` before it expires",
+            lease.id
+        )
+    })?;
+    eprintln!("Revoked the break-glass credentials for {}", name);
+    Ok(())
+}
+
+fn read_credentials(path: &str, ttl: u64) -> Result<Lease> {
+    let output = Command::new("vault")
+        .args(["read", "-format=json", path])
+        .arg(format!("ttl={}s", ttl))
+        .stderr(Stdio::inherit())
+        .output()
+        .context("Failed to run the vault CLI")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("vault read {} failed", path));
+    }
+    let response: Value =
+        serde_json::from_slice(&output.stdout).context("Failed to parse vault output")?;
+    let field = |pointer: &str| {
+        response
+            .pointer(pointer)
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .with_context(|| format!("vault read {} returned no {}", path, pointer))
+    };
+    Ok(Lease {
+        id: field("/lease_id")?,
+        duration: response
+            .get("lease_duration")
+            .and_then(Value::as_u64)
+            .unwrap_or(0),
+        username: field("/data/username")?,
+        password: field("/data/password")?,
+    })
+}
+
+/// Ask Vault to end the lease `ttl` seconds from now, for secrets engines
+/// that ignore a `ttl` on read; returns the lease's new duration.
+fn shorten(lease_id: &str, ttl: u64) -> Result<u64> {
+    let output = Command::new("vault")
+        .args(["lease", "renew", "-format=json"])
+        .arg(format!("-increment={}s", ttl))
+        .arg(lease_id)
+        .stderr(Stdio::inherit())
+        .output()
+        .context("Failed to run the vault CLI")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("vault lease renew {} failed", lease_id));
+    }
+    let response: Value =
+        serde_json::from_slice(&output.stdout).context("Failed to parse vault output")?;
+    response
+        .get("lease_duration")
+        .and_then(Value::as_u64)
+        .context("vault lease renew returned no lease_duration")
+}
+
+fn revoke(lease_id: &str) -> Result<()> {
+    let status = Command::new("vault")
+        .args(["lease", "revoke", lease_id])
+        .stdout(Stdio::null())
+        .status()
+        .context("Failed to run the vault CLI")?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("vault lease revoke {} failed", lease_id));
+    }
+    Ok(())
+}
+
+fn user() -> String {
+    std::env::var("USER").unwrap_or_else(|_| "someone".to_string())
+}
+
+fn notify(config: &BreakglassConfig, text: &str, name: &str, details: &Value) {
+    let Some(url) = &config.webhook else {
+        return;
+    };
+    let mut body = json!({ "text": text, "database": name, "user": user() });
+    if let (Some(body), Some(details)) = (body.as_object_mut(), details.as_object()) {
+        body.extend(details.clone());
+    }
+    webhook::post(url, &body);
+}
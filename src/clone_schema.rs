@@ -0,0 +1,108 @@
+use crate::{
+    config::Profile,
+    engines::{ConnectionParams, Engine, client, postgres},
+    prompt,
+};
+use anyhow::{Context, Result};
+use std::process::{Command, Stdio};
+
+pub struct CloneOptions {
+    /// Schemas to include (`pg_dump -n`)
+    pub schemas: Vec<String>,
+    /// Schemas to leave out (`pg_dump -N`)
+    pub exclude_schemas: Vec<String>,
+    /// Tables to leave out (`pg_dump -T`)
+    pub exclude_tables: Vec<String>,
+    /// Keep CREATE EXTENSION statements
+    pub include_extensions: bool,
+    /// Keep ownership and GRANT/REVOKE statements
+    pub include_owners: bool,
+    /// Skip the production confirmation
+    pub yes: bool,
+}
+
+/// A database taking part in the clone.
+pub struct Side<'a> {
+    pub name: &'a str,
+    pub params: &'a ConnectionParams,
+    pub profile: &'a Profile,
+}
+
+/// Dump the schema of `source` with pg_dump and restore it into `target`.
+pub fn clone_schema(source: &Side, target: &Side, options: &CloneOptions) -> Result<()> {
+    for side in [source, target] {
+        if side.params.engine != Engine::Postgres {
+            return Err(anyhow::anyhow!(
+                "{} is not a PostgreSQL database; clone-schema uses pg_dump",
+                side.name
+            ));
+        }
+    }
+    if !options.yes {
+        if source.profile.production
+            && !prompt::confirm(&format!(
+                "{} is a production database. Dump its schema?",
+                source.name
+            ))?
+        {
+            return Err(anyhow::anyhow!("Aborted"));
+        }
+        if target.profile.production
+            && !prompt::confirm(&format!(
+                "{} is a production database. Restore a schema into it?",
+                target.name
+            ))?
+        {
+            return Err(anyhow::anyhow!("Aborted"));
+        }
+    }
+
+    let engine = source.params.engine.implementation();
+    let pg_dump = client::ensure(engine, "pg_dump")?;
+    let mut cmd = Command::new(pg_dump);
+    cmd.args(postgres::psql_args(source.params))
+        .args(["--schema-only", "--no-password"])
+        .envs(engine.client_env(source.params, source.profile));
+    if !options.include_owners {
+        cmd.args(["--no-owner", "--no-privileges"]);
+    }
+    for schema in &options.schemas {
+        cmd.arg("--schema").arg(schema);
+    }
+    for schema in &options.exclude_schemas {
+        cmd.arg("--exclude-schema").arg(schema);
+    }
+    for table in &options.exclude_tables {
+        cmd.arg("--exclude-table").arg(table);
+    }
+
+    println!("Dumping schema of {}", source.name);
+    let output = cmd
+        .stderr(Stdio::inherit())
+        .output()
+        .context("Failed to run pg_dump")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("pg_dump failed for {}", source.name));
+    }
+
+    let dump = String::from_utf8_lossy(&output.stdout);
+    let script = filter_dump(&dump, options);
+    println!("Restoring into {}", target.name);
+    postgres::run_script(target.params, target.profile, &script)?;
+    println!("Cloned schema of {} into {}", source.name, target.name);
+    Ok(())
+}
+
+/// Drop statements the options exclude. pg_dump writes these on one line.
+fn filter_dump(dump: &str, options: &CloneOptions) -> String {
+    dump.lines()
+        .filter(|line| {
+            let extension =
+                line.starts_with("CREATE EXTENSION") || line.starts_with("COMMENT ON EXTENSION");
+            options.include_extensions || !extension
+        })
+        // Session settings newer pg_dump versions emit that older servers reject
+        .filter(|line| !line.starts_with("SET transaction_timeout"))
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
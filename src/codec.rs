@@ -0,0 +1,129 @@
+//! Compression and encryption layers around dump streams, applied with the
+//! zstd, gzip and age CLIs.
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::{path::Path, process::Command};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Zstd,
+    Gzip,
+}
+
+/// `age:<recipient>`; the recipient is a public key (`age1...`, `ssh-...`)
+/// or a file of recipients.
+#[derive(Debug, Clone)]
+pub struct Encryption {
+    recipient: String,
+}
+
+/// Parser for `--encrypt`.
+pub fn parse_encryption(value: &str) -> Result<Encryption, String> {
+    match value.split_once(':') {
+        Some(("age", recipient)) if !recipient.is_empty() => Ok(Encryption {
+            recipient: recipient.to_string(),
+        }),
+        _ => Err("expected age:<recipient>".to_string()),
+    }
+}
+
+/// Filters turning pg_dump output into the stored form, in order.
+pub fn encoders(
+    compression: Option<Compression>,
+    encryption: Option<&Encryption>,
+) -> Vec<(&'static str, Command)> {
+    let mut filters = Vec::new();
+    match compression {
+        Some(Compression::Zstd) => filters.push(("zstd", command("zstd", &["-q", "-c", "-T0"]))),
+        Some(Compression::Gzip) => filters.push(("gzip", command("gzip", &["-c"]))),
+        None => {}
+    }
+    if let Some(encryption) = encryption {
+        let flag = if encryption.recipient.starts_with("age1")
+            || encryption.recipient.starts_with("ssh-")
+        {
+            "-r"
+        } else {
+            "-R"
+        };
+        filters.push(("age", command("age", &[flag, &encryption.recipient])));
+    }
+    filters
+}
+
+/// File name suffix for the layers, e.g. `.zst.age`.
+pub fn extension(compression: Option<Compression>, encryption: Option<&Encryption>) -> String {
+    let mut extension = String::new();
+    match compression {
+        Some(Compression::Zstd) => extension.push_str(".zst"),
+        Some(Compression::Gzip) => extension.push_str(".gz"),
+        None => {}
+    }
+    if encryption.is_some() {
+        extension.push_str(".age");
+    }
+    extension
+}
+
+/// A layer recognised by its leading bytes when restoring.
+#[derive(Debug, Clone, Copy)]
+pub enum Layer {
+    Zstd,
+    Gzip,
+    Age,
+}
+
+impl Layer {
+    pub fn detect(head: &[u8]) -> Option<Layer> {
+        if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Layer::Zstd)
+        } else if head.starts_with(&[0x1f, 0x8b]) {
+            Some(Layer::Gzip)
+        } else if head.starts_with(b"age-encryption.org/")
+            || head.starts_with(b"-----BEGIN AGE ENCRYPTED FILE-----")
+        {
+            Some(Layer::Age)
+        } else {
+            None
+        }
+    }
+
+    /// What the decoder does, for error messages.
+    pub fn action(self) -> &'static str {
+        match self {
+            Layer::Zstd | Layer::Gzip => "decompressing",
+            Layer::Age => "decrypting",
+        }
+    }
+
+    pub fn tool(self) -> &'static str {
+        match self {
+            Layer::Zstd => "zstd",
+            Layer::Gzip => "gzip",
+            Layer::Age => "age",
+        }
+    }
+
+    /// Filter removing this layer; age needs an identity file.
+    pub fn decoder(self, identity: Option<&Path>) -> Result<Command> {
+        Ok(match self {
+            Layer::Zstd => command("zstd", &["-q", "-d", "-c"]),
+            Layer::Gzip => command("gzip", &["-d", "-c"]),
+            Layer::Age => {
+                let identity = identity.context(
+                    "The dump is encrypted with age; pass --identity or set CONNECT_DB_AGE_IDENTITY",
+                )?;
+                let mut cmd = command("age", &["-d", "-i"]);
+                cmd.arg(identity);
+                cmd
+            }
+        })
+    }
+}
+
+fn command(program: &str, args: &[&str]) -> Command {
+    let mut cmd = Command::new(program);
+    cmd.args(args);
+    cmd
+}
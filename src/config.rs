@@ -0,0 +1,799 @@
+use crate::{
+    approval::ApprovalConfig,
+    auth::AuthMode,
+    breakglass::BreakglassConfig,
+    dns::DnsConfig,
+    dry_run,
+    engines::{Engine, redshift::RedshiftConfig},
+    libpq::EnvLayer,
+    oidc::OidcConfig,
+    prerequisites::Prerequisite,
+    toml,
+    wireguard::WireguardConfig,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{collections::BTreeMap, env, fs, path::Path, path::PathBuf, sync::OnceLock};
+
+/// Non-secret settings for a database alias. Profiles come from the synced
+/// team catalog and from the local `config.json`, which overrides it.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Profile {
+    /// Name of the `.vault/secrets` entry (or stored profile) with the credentials
+    pub secret: Option<String>,
+    /// Values for the `secret_path` template, like `{"team": "payments"}`
+    pub secret_vars: BTreeMap<String, String>,
+    pub description: Option<String>,
+    pub tunnel: Option<TunnelConfig>,
+    /// Resolver and static host addresses to connect with
+    pub dns: Option<DnsConfig>,
+    /// Ephemeral WireGuard interface to bring up while connected
+    pub wireguard: Option<WireguardConfig>,
+    /// SOCKS5 or HTTP proxy to connect through, like `socks5://proxy:1080`
+    pub proxy: Option<String>,
+    /// `host[:port]` of the database in each region, like read replicas
+    pub endpoints: BTreeMap<String, String>,
+    pub auth: AuthMode,
+    /// Connection URL without credentials, for auth modes that need no secrets
+    pub db_url: Option<String>,
+    /// Database to use when the URL names none
+    pub default_database: Option<String>,
+    /// Kerberos service name for `auth = "gssapi"` (defaults to `postgres`)
+    pub krbsrvname: Option<String>,
+    /// Principal to `kinit` when no valid ticket is cached
+    pub kinit_principal: Option<String>,
+    /// Directory user for `auth = "ldap"` (defaults to `$USER`), or the user
+    /// whose password is in the keychain with `password_in_keychain`
+    pub username: Option<String>,
+    /// Cache the prompted LDAP password in the OS keychain
+    pub cache_password: bool,
+    /// Read the password for `username` from the OS keychain instead of the
+    /// secrets files
+    pub password_in_keychain: bool,
+    /// Device-code sign-in settings for `auth = "oidc"`
+    pub oidc: Option<OidcConfig>,
+    /// Cluster settings for `auth = "redshift-iam"`
+    pub redshift: RedshiftConfig,
+    /// Secret plugin for `auth = "plugin"`: `connect-db-secret-<plugin>`
+    pub plugin: Option<String>,
+    /// Engine to assume instead of detecting it from the URL
+    pub engine: Option<Engine>,
+    /// `pg_service.conf` entry with the host, port, database and TLS
+    /// settings; credentials still come from the secrets
+    pub pg_service: Option<String>,
+    /// Whether `PGHOST`, `PGUSER`, `PGSERVICE` and the like fill in
+    /// (`merge`) or replace (`prefer`) the resolved parameters
+    pub libpq_env: Option<EnvLayer>,
+    /// CockroachDB Serverless routing id, sent as `--cluster=<id>`
+    pub cluster: Option<String>,
+    /// CockroachDB certs dir with `ca.crt` and `client.<user>.crt/.key`
+    pub certs_dir: Option<String>,
+    /// Production database: risky commands ask for confirmation first
+    pub production: bool,
+    /// Environment the database belongs to, like `staging`; defaults to the
+    /// selected one when an `environments` overlay applies
+    pub environment: Option<String>,
+    /// Refuse commands that write to the database
+    pub read_only: bool,
+    /// Let `mcp` and the HTTP API's query endpoint run read-only queries here
+    pub mcp: bool,
+    /// Disk size available to the server, e.g. `500GB`, for `storage`
+    pub storage_limit: Option<String>,
+    /// Vault path and webhook for `breakglass`
+    pub breakglass: Option<BreakglassConfig>,
+    /// Endpoint that has to approve each connection
+    pub approval: Option<ApprovalConfig>,
+    /// Seconds of inactivity before TCP keepalives are sent
+    pub keepalives_idle: Option<u32>,
+    /// Seconds between unanswered keepalives
+    pub keepalives_interval: Option<u32>,
+    /// Unanswered keepalives before the connection counts as dead
+    pub keepalives_count: Option<u32>,
+    /// Milliseconds sent data may stay unacknowledged before the connection
+    /// is dropped (Linux)
+    pub tcp_user_timeout: Option<u32>,
+    /// Concurrent sessions allowed from this machine
+    pub max_sessions: Option<u32>,
+    /// Network conditions, like a VPN route, checked before connecting
+    pub prerequisites: Vec<Prerequisite>,
+    /// Shell commands run before connecting, e.g. to bring up a VPN
+    pub pre_connect: Vec<String>,
+    /// Shell commands run after an interactive session ends
+    pub post_disconnect: Vec<String>,
+    /// How long each `pre_connect`/`post_disconnect` command may take, like
+    /// `30s` (the default)
+    pub command_timeout: Option<String>,
+}
+
+/// SSH jump host the connection is forwarded through.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TunnelConfig {
+    /// Destination passed to `ssh`, e.g. `user@bastion.example.com`
+    pub ssh: String,
+    /// Local port to listen on (a free port is picked when unset)
+    pub local_port: Option<u16>,
+}
+
+/// Directory holding connect-db's own state (`$XDG_CONFIG_HOME/connect-db`
+/// or `~/.config/connect-db`).
+pub fn config_dir() -> Result<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home_dir()?.join(".config"),
+    };
+    Ok(base.join("connect-db"))
+}
+
+/// `$XDG_DATA_HOME/connect-db`, for state that isn't configuration.
+pub fn data_dir() -> Result<PathBuf> {
+    let base = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home_dir()?.join(".local").join("share"),
+    };
+    Ok(base.join("connect-db"))
+}
+
+pub fn home_dir() -> Result<PathBuf> {
+    env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+        .context("HOME is not set")
+}
+
+/// Project configuration files, looked up from the working directory
+/// upwards; the TOML one wins when a directory has both.
+pub const PROJECT_FILES: [&str; 2] = [".connect-db.toml", ".connect-db.json"];
+
+/// Nearest project file in the working directory or one of its parents.
+pub fn project_file() -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    cwd.ancestors()
+        .flat_map(|dir| PROJECT_FILES.map(|name| dir.join(name)))
+        .find(|path| path.is_file())
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "toml")
+}
+
+/// Directory with the `.db.json` / `.db-role.json` secrets files: the
+/// project's `secrets_dir` (relative to the project file) or `.vault/secrets`.
+pub fn secrets_dir() -> Result<PathBuf> {
+    let Some(path) = project_file() else {
+        return Ok(PathBuf::from(".vault/secrets"));
+    };
+    let value = read_config_file(&path)?;
+    let root = path.parent().unwrap_or(Path::new("."));
+    match value.get("secrets_dir") {
+        None => Ok(root.join(".vault/secrets")),
+        Some(Value::String(dir)) => Ok(root.join(expand_env(dir)?)),
+        Some(_) => Err(anyhow::anyhow!(
+            "'secrets_dir' must be a string in {}",
+            path.display()
+        )),
+    }
+}
+
+/// Layout version written by `config migrate`; files from newer releases are
+/// rejected instead of being half understood.
+pub const CONFIG_VERSION: u64 = 1;
+
+/// Profile definitions from one config file.
+pub struct Layer {
+    pub path: PathBuf,
+    pub profiles: Map<String, Value>,
+}
+
+/// Where each value of a resolved profile came from, by dotted key path.
+pub type Provenance = BTreeMap<String, String>;
+
+/// Top-level settings from the local `config.json`.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Settings {
+    /// Desktop notifications when long operations finish or connections drop
+    pub notifications: bool,
+    /// Rows `exec` reads before truncating when `--limit` isn't given
+    pub exec_limit: Option<usize>,
+    /// Webhook told when sessions to production profiles start and end
+    pub session_webhook: Option<String>,
+    /// How long resolved secrets are reused, like `5m` (`0` turns caching off)
+    pub secret_cache_ttl: Option<String>,
+    /// Most requests per second sent to secret backends
+    pub secret_requests_per_second: Option<f64>,
+    /// Release endpoint `self-update` asks for the latest version
+    pub update_url: Option<String>,
+    /// minisign public key release binaries are signed with
+    pub update_public_key: Option<String>,
+    /// Endpoint anonymized usage events are sent to (opt-in)
+    pub usage_stats_url: Option<String>,
+    /// Layout of the secrets directory, like `{team}/{env}/{name}.db.json`
+    pub secret_path: Option<String>,
+    /// Values for the `secret_path` template shared by every profile
+    pub secret_vars: BTreeMap<String, String>,
+    /// Default `libpq_env` for profiles that don't set one
+    pub libpq_env: Option<EnvLayer>,
+    /// Most rows a query from `mcp` or the HTTP API returns (default 1000)
+    pub sandbox_max_rows: Option<usize>,
+    /// How long a query from `mcp` or the HTTP API may run, like `30s`
+    /// (the default)
+    pub sandbox_timeout: Option<String>,
+}
+
+pub fn settings() -> Result<Settings> {
+    let Ok(dir) = config_dir() else {
+        return Ok(Settings::default());
+    };
+    let path = dir.join("config.json");
+    if !path.exists() {
+        return Ok(Settings::default());
+    }
+    serde_json::from_value(read_config_file(&path)?)
+        .with_context(|| format!("Invalid settings in {}", path.display()))
+}
+
+/// Config files that may hold profiles, in the order they are layered
+/// (later entries win): the synced catalog, the project file, the local config.
+/// Without a config directory (no HOME, as in some CI jobs) only the project
+/// file is read.
+pub fn layer_paths() -> Result<Vec<PathBuf>> {
+    let dir = config_dir().ok();
+    let mut paths: Vec<PathBuf> = dir.iter().map(|dir| dir.join("catalog.json")).collect();
+    paths.extend(project_file());
+    paths.extend(dir.map(|dir| dir.join("config.json")));
+    Ok(paths)
+}
+
+pub fn profile_layers() -> Result<Vec<Layer>> {
+    layer_paths()?
+        .into_iter()
+        .map(|path| {
+            let profiles = read_profiles(&path)?;
+            Ok(Layer { path, profiles })
+        })
+        .collect()
+}
+
+static ENVIRONMENT: OnceLock<String> = OnceLock::new();
+
+/// Select the `environments` overlay applied by [`load_profile`].
+pub fn select_environment(environment: &str) {
+    let _ = ENVIRONMENT.set(environment.to_string());
+}
+
+pub fn selected_environment() -> Option<&'static str> {
+    ENVIRONMENT.get().map(String::as_str)
+}
+
+/// The environment commands are held to: the selected one, else
+/// `CONNECT_DB_DEFAULT_ENV`, along with which of the two it is.
+pub fn active_environment() -> Option<(String, &'static str)> {
+    if let Some(environment) = selected_environment() {
+        return Some((environment.to_string(), "the selected environment"));
+    }
+    env::var("CONNECT_DB_DEFAULT_ENV")
+        .ok()
+        .filter(|environment| !environment.is_empty())
+        .map(|environment| (environment, "CONNECT_DB_DEFAULT_ENV"))
+}
+
+/// Whether `profile` is tagged with the active environment; every profile
+/// is when none is active.
+pub fn in_active_environment(profile: &Profile) -> bool {
+    match active_environment() {
+        Some((active, _)) => profile.environment.as_deref() == Some(active.as_str()),
+        None => true,
+    }
+}
+
+/// Refuse `name` when it is tagged with another environment than the
+/// active one. Untagged profiles are allowed.
+pub fn check_environment(name: &str, profile: &Profile) -> Result<()> {
+    if let Some(tag) = &profile.environment
+        && let Some((active, source)) = active_environment()
+        && *tag != active
+    {
+        return Err(anyhow::anyhow!(
+            "{} is a {} database but {} is {}; pass --env {} to use it",
+            name,
+            tag,
+            source,
+            active,
+            tag
+        ));
+    }
+    Ok(())
+}
+
+/// Whether `profile` is a production database, by flag or by an environment
+/// named `prod` or `production`.
+pub fn is_production(profile: &Profile) -> bool {
+    profile.production
+        || profile.environment.as_deref().is_some_and(|environment| {
+            matches!(environment.to_lowercase().as_str(), "prod" | "production")
+        })
+}
+
+/// Resolve the merged profile for `name`; unknown names get the defaults.
+///
+/// Profiles may name a template to start from with `extends`, and carry
+/// per-environment overrides under `environments.<env>`, applied when an
+/// environment is selected.
+pub fn load_profile(name: &str) -> Result<Profile> {
+    let (merged, _) = resolve_profile(name)?;
+    serde_json::from_value(merged).with_context(|| format!("Invalid profile definition: {}", name))
+}
+
+/// The merged profile definition for `name` and where each value came from.
+pub fn resolve_profile(name: &str) -> Result<(Value, Provenance)> {
+    resolve_in(name, &profile_layers()?, selected_environment())
+}
+
+/// [`resolve_profile`] over `layers`, with the overrides of `environment`.
+fn resolve_in(
+    name: &str,
+    layers: &[Layer],
+    environment: Option<&str>,
+) -> Result<(Value, Provenance)> {
+    let mut provenance = Provenance::new();
+    let mut merged = resolve_extends(name, name, layers, &mut Vec::new(), &mut provenance)?;
+
+    let environments = merged
+        .as_object_mut()
+        .and_then(|profile| profile.remove("environments"));
+    let environment_provenance: Provenance = provenance
+        .iter()
+        .filter_map(|(path, source)| {
+            let rest = path.strip_prefix("environments.")?;
+            Some((rest.to_string(), source.clone()))
+        })
+        .collect();
+    provenance.retain(|path, _| !path.starts_with("environments."));
+
+    if let Some(environment) = environment
+        && let Some(mut environments) = environments
+    {
+        let overlay = environments
+            .get_mut(environment)
+            .map(Value::take)
+            .with_context(|| {
+                let known: Vec<&str> = environments
+                    .as_object()
+                    .map(|known| known.keys().map(String::as_str).collect())
+                    .unwrap_or_default();
+                format!(
+                    "Profile {} has no environment '{}' (known: {})",
+                    name,
+                    environment,
+                    known.join(", ")
+                )
+            })?;
+        let source = |path: &str| {
+            let origin = environment_provenance
+                .get(&format!("{}.{}", environment, path))
+                .map(String::as_str)
+                .unwrap_or_default();
+            format!("{}, environment {}", origin, environment)
+        };
+        merge_tracked(&mut merged, overlay, "", &source, &mut provenance);
+        // A profile varying per environment is in the one it was resolved for
+        if let Some(profile) = merged.as_object_mut()
+            && !profile.contains_key("environment")
+        {
+            profile.insert("environment".to_string(), environment.into());
+            provenance.insert(
+                "environment".to_string(),
+                format!("selected environment {}", environment),
+            );
+        }
+    }
+
+    Ok((merged, provenance))
+}
+
+/// Merge the layers' definitions of `name` on top of the profile it extends.
+fn resolve_extends(
+    requested: &str,
+    name: &str,
+    layers: &[Layer],
+    chain: &mut Vec<String>,
+    provenance: &mut Provenance,
+) -> Result<Value> {
+    if chain.iter().any(|seen| seen == name) {
+        chain.push(name.to_string());
+        return Err(anyhow::anyhow!(
+            "Profile inheritance cycle: {}",
+            chain.join(" -> ")
+        ));
+    }
+    chain.push(name.to_string());
+
+    let base = match layers
+        .iter()
+        .filter_map(|layer| layer.profiles.get(name))
+        .filter_map(|profile| profile.get("extends"))
+        .next_back()
+    {
+        None => None,
+        Some(Value::String(base)) => Some(base.clone()),
+        Some(_) => {
+            return Err(anyhow::anyhow!(
+                "'extends' must be a profile name in {}",
+                name
+            ));
+        }
+    };
+    let mut merged = match &base {
+        Some(base) => {
+            if !layers.iter().any(|layer| layer.profiles.contains_key(base)) {
+                return Err(anyhow::anyhow!(
+                    "Profile {} extends unknown profile {}",
+                    name,
+                    base
+                ));
+            }
+            resolve_extends(requested, base, layers, chain, provenance)?
+        }
+        None => Value::Object(Map::new()),
+    };
+
+    for layer in layers {
+        let Some(profile) = layer.profiles.get(name) else {
+            continue;
+        };
+        let mut profile = profile.clone();
+        if let Some(profile) = profile.as_object_mut() {
+            profile.remove("extends");
+        }
+        let source = |_: &str| {
+            if name == requested {
+                layer.path.display().to_string()
+            } else {
+                format!("{} via extends {}", layer.path.display(), name)
+            }
+        };
+        merge_tracked(&mut merged, profile, "", &source, provenance);
+    }
+    Ok(merged)
+}
+
+/// Names of every profile defined in any layer.
+pub fn profile_names() -> Result<Vec<String>> {
+    let mut names: Vec<String> = profile_layers()?
+        .into_iter()
+        .flat_map(|layer| layer.profiles.into_iter().map(|(name, _)| name))
+        .collect();
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Parse a config file, refusing layouts newer than this release understands.
+pub fn read_config_file(path: &Path) -> Result<Value> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+    let value: Value = match is_toml(path) {
+        true => toml::parse(&content),
+        false => serde_json::from_str(&content).map_err(anyhow::Error::from),
+    }
+    .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+    let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version > CONFIG_VERSION {
+        return Err(anyhow::anyhow!(
+            "{} uses config version {}, but this connect-db only understands up to {}; upgrade connect-db",
+            path.display(),
+            version,
+            CONFIG_VERSION
+        ));
+    }
+    Ok(value)
+}
+
+fn read_profiles(path: &Path) -> Result<Map<String, Value>> {
+    if !path.exists() {
+        return Ok(Map::new());
+    }
+    let mut value = read_config_file(path)?;
+    match value.get_mut("profiles").map(Value::take) {
+        Some(Value::Object(profiles)) => Ok(profiles),
+        Some(_) => Err(anyhow::anyhow!(
+            "'profiles' must be an object in {}",
+            path.display()
+        )),
+        None => Ok(Map::new()),
+    }
+}
+
+/// Deep-merge `overlay` into `base`: objects are merged key by key, anything
+/// else in the overlay replaces the base value. Every replaced leaf is
+/// attributed to `source(path)` in `provenance`.
+fn merge_tracked(
+    base: &mut Value,
+    overlay: Value,
+    path: &str,
+    source: &dyn Fn(&str) -> String,
+    provenance: &mut Provenance,
+) {
+    let child_path = |key: &str| match path {
+        "" => key.to_string(),
+        _ => format!("{}.{}", path, key),
+    };
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                let path = child_path(&key);
+                merge_tracked(
+                    base.entry(key).or_insert(Value::Null),
+                    value,
+                    &path,
+                    source,
+                    provenance,
+                );
+            }
+        }
+        (base, overlay) => {
+            let prefix = format!("{}.", path);
+            provenance.retain(|key, _| key != path && !key.starts_with(&prefix));
+            for (leaf, _) in leaves(&overlay, path) {
+                let origin = source(&leaf);
+                provenance.insert(leaf, origin);
+            }
+            *base = overlay;
+        }
+    }
+}
+
+/// Non-object values in `value` with their dotted paths.
+pub fn leaves<'a>(value: &'a Value, path: &str) -> Vec<(String, &'a Value)> {
+    match value {
+        Value::Object(map) if !map.is_empty() => map
+            .iter()
+            .flat_map(|(key, value)| {
+                let path = match path {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", path, key),
+                };
+                leaves(value, &path)
+            })
+            .collect(),
+        _ => vec![(path.to_string(), value)],
+    }
+}
+
+/// Upgrade steps, indexed by the version they start from.
+const MIGRATIONS: [fn(&mut Map<String, Value>); CONFIG_VERSION as usize] = [
+    // Unversioned files already use the version 1 layout
+    |_| {},
+];
+
+/// Upgrade the local and project config files to [`CONFIG_VERSION`] in
+/// place, keeping a `.bak` copy of each file changed. The catalog is left
+/// alone; it is replaced on the next sync.
+pub fn migrate() -> Result<()> {
+    let mut paths = vec![config_dir()?.join("config.json")];
+    paths.extend(project_file());
+    for path in paths.into_iter().filter(|path| path.exists()) {
+        let mut value = read_config_file(&path)?;
+        let config = value
+            .as_object_mut()
+            .with_context(|| format!("{} must contain a JSON object", path.display()))?;
+        let version = config.get("version").and_then(Value::as_u64).unwrap_or(0);
+        if version == CONFIG_VERSION {
+            println!("{}: up to date (version {})", path.display(), version);
+            continue;
+        }
+        // Rewriting would lose the comments and layout of a TOML file
+        if is_toml(&path) {
+            println!(
+                "{}: version {}; TOML files are not rewritten, upgrade it by hand to version {}",
+                path.display(),
+                version,
+                CONFIG_VERSION
+            );
+            continue;
+        }
+        for migration in &MIGRATIONS[version as usize..] {
+            migration(config);
+        }
+        config.insert("version".to_string(), Value::from(CONFIG_VERSION));
+
+        dry_run::stop_before_writing(&path);
+        let backup = path.with_extension("json.bak");
+        fs::copy(&path, &backup)
+            .with_context(|| format!("Failed to back up {}", path.display()))?;
+        fs::write(&path, serde_json::to_string_pretty(&value)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        println!(
+            "{}: migrated from version {} to {} (backup in {})",
+            path.display(),
+            version,
+            CONFIG_VERSION,
+            backup.display()
+        );
+    }
+    Ok(())
+}
+
+/// Expand `~/`, `$VAR` and `${VAR}` in a path template.
+pub fn expand_env(template: &str) -> Result<String> {
+    let template = match template.strip_prefix("~/") {
+        Some(rest) => format!("{}/{}", home_dir()?.display(), rest),
+        None => template.to_string(),
+    };
+
+    let mut expanded = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
+        let name: String = if chars.peek() == Some(&'{') {
+            chars.next();
+            let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+            name
+        } else {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            name
+        };
+        if name.is_empty() {
+            expanded.push('$');
+            continue;
+        }
+        let value =
+            env::var(&name).with_context(|| format!("Environment variable {} is not set", name))?;
+        expanded.push_str(&value);
+    }
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_variables() {
+        // Names no other test uses, since tests share the environment
+        unsafe {
+            env::set_var("CONNECT_DB_TEST_DIR", "/srv/secrets");
+            env::set_var("CONNECT_DB_TEST_NAME", "orders");
+        }
+        assert_eq!(
+            expand_env("$CONNECT_DB_TEST_DIR/${CONNECT_DB_TEST_NAME}.json").unwrap(),
+            "/srv/secrets/orders.json"
+        );
+        assert_eq!(
+            expand_env("${CONNECT_DB_TEST_NAME}_ro").unwrap(),
+            "orders_ro"
+        );
+        // Without braces the name ends at the first other character
+        assert_eq!(expand_env("$CONNECT_DB_TEST_NAME-ro").unwrap(), "orders-ro");
+    }
+
+    #[test]
+    fn keeps_a_lone_dollar() {
+        assert_eq!(expand_env("price$").unwrap(), "price$");
+        assert_eq!(expand_env("a $ b").unwrap(), "a $ b");
+        assert_eq!(expand_env("/plain/path").unwrap(), "/plain/path");
+    }
+
+    #[test]
+    fn unset_variables_are_an_error() {
+        let err = expand_env("$CONNECT_DB_TEST_UNSET/x").unwrap_err();
+        assert!(err.to_string().contains("CONNECT_DB_TEST_UNSET"));
+    }
+
+    #[test]
+    fn reads_toml_and_json_config_files() {
+        let dir = env::temp_dir().join(format!("connect-db-config-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let toml = dir.join(".connect-db.toml");
+        fs::write(
+            &toml,
+            "secrets_dir = \"deploy\"\n[profiles.app]\nextends = \"base\"\n\
+             [profiles.app.environments.staging]\nsecret = \"app-staging\"\n",
+        )
+        .unwrap();
+        let value = read_config_file(&toml).unwrap();
+        assert_eq!(value["secrets_dir"], "deploy");
+        assert_eq!(value["profiles"]["app"]["extends"], "base");
+        assert_eq!(
+            value["profiles"]["app"]["environments"]["staging"]["secret"],
+            "app-staging"
+        );
+
+        let json = dir.join(".connect-db.json");
+        fs::write(&json, r#"{"version": 99}"#).unwrap();
+        let err = read_config_file(&json).unwrap_err();
+        assert!(err.to_string().contains("config version 99"), "{}", err);
+        fs::write(&json, "version = 1").unwrap();
+        assert!(read_config_file(&json).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn layer(path: &str, toml: &str) -> Layer {
+        let Value::Object(mut file) = toml::parse(toml).unwrap() else {
+            unreachable!("TOML documents are tables");
+        };
+        let Some(Value::Object(profiles)) = file.remove("profiles") else {
+            panic!("{} has no profiles", path);
+        };
+        Layer {
+            path: PathBuf::from(path),
+            profiles,
+        }
+    }
+
+    #[test]
+    fn resolves_extends_and_environments_from_toml() {
+        let layers = [
+            layer(
+                "catalog.toml",
+                r#"
+[profiles.base-postgres]
+tunnel.ssh = "me@bastion.example.com"
+environments.prod.production = true
+
+[profiles.orders]
+extends = "base-postgres"
+secret = "orders-dev"
+
+[profiles.orders.environments.staging]
+secret = "orders-staging"
+
+[profiles.orders.environments.prod]
+secret = "orders-prod"
+"#,
+            ),
+            layer(
+                "config.toml",
+                "[profiles.orders]\ndescription = \"Orders\"\n",
+            ),
+        ];
+        let (orders, _) = resolve_in("orders", &layers, None).unwrap();
+        assert_eq!(orders["secret"], "orders-dev");
+        assert_eq!(orders["tunnel"]["ssh"], "me@bastion.example.com");
+        assert_eq!(orders["description"], "Orders");
+        assert!(orders.get("environments").is_none());
+
+        let (staging, _) = resolve_in("orders", &layers, Some("staging")).unwrap();
+        assert_eq!(staging["secret"], "orders-staging");
+        assert_eq!(staging["environment"], "staging");
+        assert!(staging.get("production").is_none());
+
+        // Overrides of the extended profile apply too
+        let (prod, provenance) = resolve_in("orders", &layers, Some("prod")).unwrap();
+        assert_eq!(prod["secret"], "orders-prod");
+        assert_eq!(prod["production"], true);
+        assert_eq!(provenance["description"], "config.toml");
+
+        let err = resolve_in("orders", &layers, Some("qa")).unwrap_err();
+        assert!(
+            err.to_string().contains("has no environment 'qa'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn expands_a_leading_tilde() {
+        let home = home_dir().unwrap();
+        assert_eq!(
+            expand_env("~/.vault/x.json").unwrap(),
+            format!("{}/.vault/x.json", home.display())
+        );
+        // Only at the start, and only as a directory
+        assert_eq!(expand_env("a/~/b").unwrap(), "a/~/b");
+        assert_eq!(expand_env("~user/b").unwrap(), "~user/b");
+    }
+}
@@ -0,0 +1,54 @@
+use crate::{
+    config::Profile,
+    engines::{self, ConnectionParams, Engine, postgres},
+    report,
+};
+use anyhow::Result;
+
+const BY_CLIENT: &str = "\
+select coalesce(usename, '-'), coalesce(nullif(application_name, ''), '-'), coalesce(state, '-'), count(*) \
+from pg_stat_activity where backend_type = 'client backend' \
+group by 1, 2, 3 order by 4 desc, 1, 2, 3";
+
+const TOTALS: &str = "\
+select count(*), current_setting('max_connections'), current_setting('superuser_reserved_connections') \
+from pg_stat_activity where backend_type = 'client backend'";
+
+/// Show client connections grouped by user, application and state against
+/// `max_connections`, or pool statistics on a PgBouncer admin console.
+pub fn report(params: &ConnectionParams, profile: &Profile) -> Result<()> {
+    if params.engine != Engine::Postgres {
+        return Err(anyhow::anyhow!("connections needs a PostgreSQL database"));
+    }
+    // PgBouncer's admin console is the virtual "pgbouncer" database
+    if params.database == "pgbouncer" {
+        println!("PgBouncer pools");
+        postgres::print_query(params, profile, "SHOW POOLS")?;
+        println!("PgBouncer stats");
+        return postgres::print_query(params, profile, "SHOW STATS");
+    }
+
+    let totals = report::rows(&engines::run_query(params, profile, TOTALS)?);
+    if let Some([used, max, reserved]) = totals.first().map(Vec::as_slice) {
+        let used: f64 = used.parse().unwrap_or(0.0);
+        let max: f64 = max.parse().unwrap_or(0.0);
+        let reserved: f64 = reserved.parse().unwrap_or(0.0);
+        let available = max - reserved;
+        println!(
+            "{} of {} connections in use ({:.0}%), {} reserved for superusers",
+            used,
+            available,
+            if available > 0.0 {
+                used / available * 100.0
+            } else {
+                0.0
+            },
+            reserved
+        );
+        println!();
+    }
+
+    let rows = report::rows(&engines::run_query(params, profile, BY_CLIENT)?);
+    report::print_table(&["USER", "APPLICATION", "STATE", "COUNT"], &rows);
+    Ok(())
+}
@@ -0,0 +1,384 @@
+//! Connection strings that aren't URLs: libpq's `host=db dbname=app`
+//! conninfo and the ADO.NET-style `Host=db;Username=app;Password=...` strings
+//! some vault files hold.
+
+use crate::engines::{ConnectionParams, Engine, cockroach};
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+
+/// Whether `text` is a `Key=Value;...` keyword string rather than a URL.
+pub fn is_keyword_string(text: &str) -> bool {
+    !text.contains("://") && text.contains('=') && has_unquoted_semicolon(text)
+}
+
+/// Whether `text` is a libpq `keyword=value ...` conninfo string.
+pub fn is_conninfo(text: &str) -> bool {
+    !text.contains("://") && text.contains('=') && !has_unquoted_semicolon(text)
+}
+
+/// A `;` outside libpq's single quotes separates ADO.NET keywords; inside
+/// them it's part of a conninfo value.
+fn has_unquoted_semicolon(text: &str) -> bool {
+    let mut quoted = false;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\'' => quoted = !quoted,
+            ';' if !quoted => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Parse a libpq conninfo string. Values may be single-quoted, and `\'` and
+/// `\\` escape a quote or backslash, as in `password='it\'s secret'`.
+pub fn parse_conninfo(text: &str) -> Result<ConnectionParams> {
+    let mut params = ConnectionParams {
+        engine: Engine::Postgres,
+        host: "localhost".to_string(),
+        port: "5432".to_string(),
+        username: String::new(),
+        password: String::new(),
+        database: String::new(),
+        options: Vec::new(),
+        failover_hosts: Vec::new(),
+        extra: BTreeMap::new(),
+    };
+    for (key, value) in split_conninfo(text)? {
+        match key.as_str() {
+            "host" | "hostaddr" => params.host = value,
+            "port" => params.port = value,
+            "dbname" => params.database = value,
+            "user" => params.username = value,
+            "password" => params.password = value,
+            _ => params.options.push((key, value)),
+        }
+    }
+    // libpq takes `host=a,b port=5432,5433`, or one port for every host
+    if params.host.contains(',') || params.port.contains(',') {
+        let hosts: Vec<&str> = params.host.split(',').collect();
+        let ports: Vec<&str> = params.port.split(',').collect();
+        if ports.len() != 1 && ports.len() != hosts.len() {
+            return Err(anyhow::anyhow!(
+                "Connection string names {} hosts but {} ports",
+                hosts.len(),
+                ports.len()
+            ));
+        }
+        let mut pairs: Vec<(String, String)> = hosts
+            .iter()
+            .enumerate()
+            .map(|(index, host)| {
+                let port = ports.get(index).unwrap_or(&ports[0]);
+                (host.to_string(), port.to_string())
+            })
+            .collect();
+        (params.host, params.port) = pairs.remove(0);
+        params.failover_hosts = pairs;
+    }
+    if params.host.starts_with('/') {
+        return Err(anyhow::anyhow!(
+            "Connection string uses a socket directory, which isn't supported"
+        ));
+    }
+    finish(params)
+}
+
+fn split_conninfo(text: &str) -> Result<Vec<(String, String)>> {
+    let mut pairs = Vec::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+        let mut key = String::new();
+        while let Some(&c) = chars.peek()
+            && c != '='
+            && !c.is_whitespace()
+        {
+            key.push(c);
+            chars.next();
+        }
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        if chars.next() != Some('=') {
+            return Err(anyhow::anyhow!(
+                "Connection string: missing \"=\" after \"{}\"",
+                key
+            ));
+        }
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let mut value = String::new();
+        if chars.peek() == Some(&'\'') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('\'') => break,
+                    Some('\\') => value.extend(chars.next()),
+                    Some(c) => value.push(c),
+                    None => {
+                        return Err(anyhow::anyhow!(
+                            "Unterminated quote in the value of {}",
+                            key
+                        ));
+                    }
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek()
+                && !c.is_whitespace()
+            {
+                chars.next();
+                if c == '\\' {
+                    value.extend(chars.next());
+                } else {
+                    value.push(c);
+                }
+            }
+        }
+        pairs.push((key, value));
+    }
+    Ok(pairs)
+}
+
+/// Parse an ADO.NET (Npgsql) keyword string into PostgreSQL parameters.
+/// Keywords are case-insensitive and values may be quoted with `"` or `'`,
+/// doubling the quote to include it.
+pub fn parse_keyword_string(text: &str) -> Result<ConnectionParams> {
+    let mut params = ConnectionParams {
+        engine: Engine::Postgres,
+        host: String::new(),
+        port: "5432".to_string(),
+        username: String::new(),
+        password: String::new(),
+        database: String::new(),
+        options: Vec::new(),
+        failover_hosts: Vec::new(),
+        extra: BTreeMap::new(),
+    };
+    for (key, value) in split_keywords(text)? {
+        let normalized: String = key
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '_')
+            .collect::<String>()
+            .to_lowercase();
+        match normalized.as_str() {
+            "host" | "server" | "datasource" | "address" | "addr" | "networkaddress" => {
+                // SQL Server style `host,port` or `host:port`
+                match value.rsplit_once([',', ':']) {
+                    Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => {
+                        params.host = host.to_string();
+                        params.port = port.to_string();
+                    }
+                    _ => params.host = value,
+                }
+            }
+            "port" => params.port = value,
+            "database" | "initialcatalog" | "db" => params.database = value,
+            "username" | "userid" | "user" | "uid" => params.username = value,
+            "password" | "pwd" => params.password = value,
+            "sslmode" => params
+                .options
+                .push(("sslmode".to_string(), libpq_ssl_mode(&value))),
+            "applicationname" => params.options.push(("application_name".to_string(), value)),
+            _ => params.options.push((normalized, value)),
+        }
+    }
+    if params.host.is_empty() {
+        return Err(anyhow::anyhow!("Connection string has no Host"));
+    }
+    finish(params)
+}
+
+/// CockroachDB is recognized by its endpoint in both formats.
+fn finish(mut params: ConnectionParams) -> Result<ConnectionParams> {
+    if cockroach::is_cockroach_endpoint(&params.host, &params.port) {
+        params.engine = Engine::CockroachDb;
+    }
+    Ok(params)
+}
+
+/// libpq's names for Npgsql `SSL Mode` values.
+fn libpq_ssl_mode(mode: &str) -> String {
+    match mode.to_lowercase().as_str() {
+        "verifyca" | "verify-ca" => "verify-ca".to_string(),
+        "verifyfull" | "verify-full" => "verify-full".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Split `Key=Value;...` into pairs, honoring quoted values.
+fn split_keywords(text: &str) -> Result<Vec<(String, String)>> {
+    let mut pairs = Vec::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        let key: String = chars.by_ref().take_while(|c| *c != '=').collect();
+        if key.trim().is_empty() {
+            // Only separators and whitespace are left
+            if chars.peek().is_none() {
+                break;
+            }
+            return Err(anyhow::anyhow!(
+                "Connection string has a value without a keyword"
+            ));
+        }
+        let key = key.trim().trim_start_matches(';').trim().to_string();
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let mut value = String::new();
+        match chars.peek().copied() {
+            Some(quote @ ('"' | '\'')) => {
+                chars.next();
+                loop {
+                    let c = chars
+                        .next()
+                        .with_context(|| format!("Unterminated quote in the value of {}", key))?;
+                    if c == quote {
+                        if chars.peek() == Some(&quote) {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    value.push(c);
+                }
+                // Skip to the separator
+                for c in chars.by_ref() {
+                    if c == ';' {
+                        break;
+                    }
+                }
+            }
+            _ => {
+                value = chars.by_ref().take_while(|c| *c != ';').collect();
+                value = value.trim().to_string();
+            }
+        }
+        pairs.push((key, value));
+        while chars.peek().is_some_and(|c| c.is_whitespace() || *c == ';') {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+    }
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn option<'a>(params: &'a ConnectionParams, key: &str) -> Option<&'a str> {
+        params
+            .options
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn tells_the_formats_apart() {
+        assert!(is_conninfo("host=db dbname=app"));
+        assert!(is_conninfo("host=db password='a;b'"));
+        assert!(is_keyword_string("Host=db;Username=app"));
+        assert!(!is_conninfo("postgres://u@db/app?sslmode=require"));
+        assert!(!is_keyword_string("postgres://u@db/app?a=1;b"));
+        assert!(!is_conninfo("just-a-name"));
+    }
+
+    #[test]
+    fn parses_conninfo() {
+        let params = parse_conninfo(
+            "host=db.corp port = 5433 dbname=orders user=app password='it\\'s a \\\\secret' sslmode=require",
+        )
+        .unwrap();
+        assert_eq!(params.host, "db.corp");
+        assert_eq!(params.port, "5433");
+        assert_eq!(params.database, "orders");
+        assert_eq!(params.username, "app");
+        assert_eq!(params.password, "it's a \\secret");
+        assert_eq!(option(&params, "sslmode"), Some("require"));
+        assert_eq!(params.engine, Engine::Postgres);
+    }
+
+    #[test]
+    fn conninfo_defaults_and_errors() {
+        let params = parse_conninfo("dbname=app").unwrap();
+        assert_eq!(params.host, "localhost");
+        assert_eq!(params.port, "5432");
+        assert!(parse_conninfo("host").is_err());
+        assert!(parse_conninfo("password='open").is_err());
+        assert!(parse_conninfo("host=/var/run/postgresql").is_err());
+    }
+
+    #[test]
+    fn conninfo_pairs_hosts_with_ports() {
+        let params = parse_conninfo("host=a,b,c port=5432,5433,5434").unwrap();
+        assert_eq!((params.host.as_str(), params.port.as_str()), ("a", "5432"));
+        assert_eq!(
+            params.failover_hosts,
+            [
+                ("b".to_string(), "5433".to_string()),
+                ("c".to_string(), "5434".to_string())
+            ]
+        );
+        let params = parse_conninfo("host=a,b port=6432").unwrap();
+        assert_eq!(
+            params.failover_hosts,
+            [("b".to_string(), "6432".to_string())]
+        );
+        assert!(parse_conninfo("host=a,b,c port=1,2").is_err());
+    }
+
+    #[test]
+    fn parses_keyword_strings() {
+        let params = parse_keyword_string(
+            "Server=db.corp,5433; Initial Catalog=orders;User ID=app;Password=\"a;\"\"b\"\"\";SSL Mode=VerifyFull;Application Name=etl;",
+        )
+        .unwrap();
+        assert_eq!(params.host, "db.corp");
+        assert_eq!(params.port, "5433");
+        assert_eq!(params.database, "orders");
+        assert_eq!(params.username, "app");
+        assert_eq!(params.password, "a;\"b\"");
+        assert_eq!(option(&params, "sslmode"), Some("verify-full"));
+        assert_eq!(option(&params, "application_name"), Some("etl"));
+
+        let params = parse_keyword_string("host=db:6432;pwd='it''s'").unwrap();
+        assert_eq!(params.port, "6432");
+        assert_eq!(params.password, "it's");
+    }
+
+    #[test]
+    fn keyword_string_errors() {
+        assert!(parse_keyword_string("Database=app;User=x").is_err());
+        assert!(parse_keyword_string("Host=db;Password='open").is_err());
+        assert!(parse_keyword_string("Host=db;;=value").is_err());
+    }
+
+    #[test]
+    fn recognizes_cockroach_endpoints() {
+        assert_eq!(
+            parse_conninfo("host=db port=26257").unwrap().engine,
+            Engine::CockroachDb
+        );
+        assert_eq!(
+            parse_keyword_string("Host=free-tier.aws-us-east-1.cockroachlabs.cloud;Port=5432")
+                .unwrap()
+                .engine,
+            Engine::CockroachDb
+        );
+    }
+}
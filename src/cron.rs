@@ -0,0 +1,223 @@
+//! Five-field cron expressions (`minute hour day-of-month month day-of-week`)
+//! evaluated in local time.
+
+use anyhow::{Context, Result};
+
+/// Longest gap searched for the next match (a bit over four years, so
+/// `0 0 29 2 *` still fires).
+const MAX_SEARCH_MINUTES: u64 = 4 * 366 * 24 * 60 + 1;
+
+pub struct Schedule {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    /// Whether day-of-month / day-of-week were restricted; cron fires when
+    /// either matches if both are
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl Schedule {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields.as_slice() else {
+            return Err(anyhow::anyhow!(
+                "Cron expression needs 5 fields (minute hour day month weekday): {}",
+                expression
+            ));
+        };
+        let mut weekdays = parse_field(weekday, 0, 7)
+            .with_context(|| format!("Invalid day-of-week in {}", expression))?;
+        // Both 0 and 7 mean Sunday
+        if weekdays[7] {
+            weekdays[0] = true;
+        }
+        Ok(Schedule {
+            minutes: parse_field(minute, 0, 59)
+                .with_context(|| format!("Invalid minute in {}", expression))?,
+            hours: parse_field(hour, 0, 23)
+                .with_context(|| format!("Invalid hour in {}", expression))?,
+            days: parse_field(day, 1, 31)
+                .with_context(|| format!("Invalid day-of-month in {}", expression))?,
+            months: parse_field(month, 1, 12)
+                .with_context(|| format!("Invalid month in {}", expression))?,
+            weekdays,
+            days_restricted: *day != "*",
+            weekdays_restricted: *weekday != "*",
+        })
+    }
+
+    /// First matching minute strictly after `after` (Unix seconds).
+    pub fn next_after(&self, after: u64) -> Option<u64> {
+        let mut candidate = (after / 60 + 1) * 60;
+        for _ in 0..MAX_SEARCH_MINUTES {
+            if self.matches(candidate) {
+                return Some(candidate);
+            }
+            candidate += 60;
+        }
+        None
+    }
+
+    fn matches(&self, unix_seconds: u64) -> bool {
+        let Some(local) = local_time(unix_seconds) else {
+            return false;
+        };
+        let day = self.days[local.tm_mday as usize];
+        let weekday = self.weekdays[local.tm_wday as usize];
+        let day_matches = match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            _ => day && weekday,
+        };
+        self.minutes[local.tm_min as usize]
+            && self.hours[local.tm_hour as usize]
+            && self.months[local.tm_mon as usize + 1]
+            && day_matches
+    }
+}
+
+/// Parse `*`, `5`, `1-5`, `*/15`, `0-30/10` and comma lists into a table
+/// indexed by value.
+fn parse_field(field: &str, min: usize, max: usize) -> Result<Vec<bool>> {
+    let mut allowed = vec![false; max + 1];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<usize>().context("invalid step")?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(anyhow::anyhow!("step must be positive"));
+        }
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (start.parse()?, end.parse()?),
+                // `5/10` means from 5 to the end in steps of 10
+                None if part.contains('/') => (range.parse()?, max),
+                None => {
+                    let value = range.parse()?;
+                    (value, value)
+                }
+            },
+        };
+        if start < min || end > max || start > end {
+            return Err(anyhow::anyhow!("{} is outside {}-{}", part, min, max));
+        }
+        for value in (start..=end).step_by(step) {
+            allowed[value] = true;
+        }
+    }
+    Ok(allowed)
+}
+
+fn local_time(unix_seconds: u64) -> Option<libc::tm> {
+    let time = unix_seconds as libc::time_t;
+    let mut local: libc::tm = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::localtime_r(&time, &mut local) };
+    (!result.is_null()).then_some(local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-01-15 10:07:00 UTC; checks below go through `local_time`, so they
+    /// hold in any time zone.
+    const START: u64 = 1_705_313_220;
+
+    fn values(field: &str, min: usize, max: usize) -> Vec<usize> {
+        let allowed = parse_field(field, min, max).unwrap();
+        (0..allowed.len()).filter(|&value| allowed[value]).collect()
+    }
+
+    #[test]
+    fn parses_fields() {
+        assert_eq!(values("*", 1, 12), (1..=12).collect::<Vec<_>>());
+        assert_eq!(values("5", 0, 59), [5]);
+        assert_eq!(values("1-10/3", 0, 59), [1, 4, 7, 10]);
+        assert_eq!(values("5/20", 0, 59), [5, 25, 45]);
+        assert_eq!(values("*/15", 0, 59), [0, 15, 30, 45]);
+        assert_eq!(values("1,3-4,30", 1, 31), [1, 3, 4, 30]);
+    }
+
+    #[test]
+    fn rejects_bad_expressions() {
+        for expression in [
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "10-5 * * * *",
+            "a * * * *",
+        ] {
+            assert!(Schedule::parse(expression).is_err(), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn sunday_is_0_and_7() {
+        let schedule = Schedule::parse("0 0 * * 7").unwrap();
+        assert!(schedule.weekdays[0] && schedule.weekdays[7]);
+    }
+
+    #[test]
+    fn finds_the_next_matching_minute() {
+        let schedule = Schedule::parse("*/15 * * * *").unwrap();
+        let next = schedule.next_after(START).unwrap();
+        assert!(next > START && next - START <= 15 * 60);
+        assert_eq!(local_time(next).unwrap().tm_min % 15, 0);
+        // Strictly after: a matching minute is not returned again
+        assert!(schedule.next_after(next).unwrap() > next);
+
+        let every_minute = Schedule::parse("* * * * *").unwrap();
+        assert_eq!(every_minute.next_after(START), Some(START + 60));
+        assert_eq!(every_minute.next_after(START + 59), Some(START + 60));
+    }
+
+    #[test]
+    fn finds_a_daily_time() {
+        let next = Schedule::parse("30 3 * * *")
+            .unwrap()
+            .next_after(START)
+            .unwrap();
+        let local = local_time(next).unwrap();
+        assert_eq!((local.tm_hour, local.tm_min), (3, 30));
+        assert!(next - START <= 2 * 24 * 60 * 60);
+    }
+
+    #[test]
+    fn day_of_month_or_weekday_when_both_are_set() {
+        let schedule = Schedule::parse("0 12 1 * 1").unwrap();
+        let mut after = START;
+        for _ in 0..10 {
+            after = schedule.next_after(after).unwrap();
+            let local = local_time(after).unwrap();
+            assert!(local.tm_mday == 1 || local.tm_wday == 1);
+        }
+        // With only one of them set, that one decides
+        let mondays = Schedule::parse("0 12 * * 1").unwrap();
+        let local = local_time(mondays.next_after(START).unwrap()).unwrap();
+        assert_eq!(local.tm_wday, 1);
+    }
+
+    #[test]
+    fn leap_days_are_found() {
+        let next = Schedule::parse("0 0 29 2 *")
+            .unwrap()
+            .next_after(START)
+            .unwrap();
+        let local = local_time(next).unwrap();
+        assert_eq!((local.tm_mon, local.tm_mday), (1, 29));
+        assert_eq!(local.tm_year + 1900, 2024);
+        assert_eq!(
+            Schedule::parse("0 0 31 2 *").unwrap().next_after(START),
+            None
+        );
+    }
+}
@@ -0,0 +1,89 @@
+//! Minimal RFC 4180 CSV, for query results printed by the clients and
+//! written to files.
+
+use std::io::{self, BufRead, Write};
+
+/// Records of `text`; quoted fields may contain commas, quotes and newlines.
+pub fn parse(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' => quoted = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+/// Records read one at a time from `input`, so a large result can be cut
+/// short without reading the rest.
+pub struct Reader<R> {
+    input: R,
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(input: R) -> Self {
+        Reader { input }
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = io::Result<Vec<String>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut text = String::new();
+        // A record ends at a newline outside quotes
+        loop {
+            match self.input.read_line(&mut text) {
+                Ok(0) => break,
+                Ok(_) if text.matches('"').count().is_multiple_of(2) => break,
+                Ok(_) => {}
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        if text.is_empty() {
+            return None;
+        }
+        Some(Ok(parse(&text).into_iter().next().unwrap_or_default()))
+    }
+}
+
+/// Write one record, quoting fields that need it.
+pub fn write_record(output: &mut impl Write, record: &[String]) -> io::Result<()> {
+    let fields: Vec<String> = record
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect();
+    writeln!(output, "{}", fields.join(","))
+}
@@ -0,0 +1,381 @@
+//! `connect-db daemon`: keeps resolved credentials and SSH tunnels warm for
+//! other invocations, which ask it over a unix socket before resolving
+//! secrets or dialing a tunnel themselves.
+//!
+//! Requests are served one at a time, since credentials are resolved in the
+//! working directory of the invocation asking for them. A daemon started
+//! with other global options (`--env`, `--secret-var`, ...) than the
+//! invocation is not used.
+//!
+//! With `--http`, the same daemon answers editors and other tools over a
+//! token-protected localhost HTTP API (see [`crate::api`]).
+
+use crate::{
+    api,
+    auth::AuthMode,
+    config::{self, TunnelConfig},
+    dry_run,
+    engines::{self, ConnectionParams},
+    time, tunnel,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::TcpListener,
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    process::Child,
+    sync::{
+        Mutex, MutexGuard, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "request", rename_all = "lowercase")]
+enum Request {
+    Credentials {
+        name: String,
+        cwd: PathBuf,
+        options: Vec<String>,
+        refresh: bool,
+    },
+    Tunnel {
+        tunnel: TunnelConfig,
+        host: String,
+        port: String,
+        proxy: Option<String>,
+    },
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Response {
+    Params(Box<ConnectionParams>),
+    Port(u16),
+    Error(String),
+    /// The daemon can't serve this request; the invocation does it itself
+    Unsupported(String),
+}
+
+/// Global options of this invocation that change how secrets resolve.
+static OPTIONS: OnceLock<Vec<String>> = OnceLock::new();
+static SERVING: AtomicBool = AtomicBool::new(false);
+static STOP: AtomicBool = AtomicBool::new(false);
+
+pub fn set_cli_options(options: Vec<String>) {
+    let _ = OPTIONS.set(options);
+}
+
+fn options() -> Vec<String> {
+    OPTIONS.get().cloned().unwrap_or_default()
+}
+
+fn socket_path() -> Result<PathBuf> {
+    Ok(config::data_dir()?.join("daemon.sock"))
+}
+
+extern "C" fn on_stop(_: libc::c_int) {
+    STOP.store(true, Ordering::Relaxed);
+}
+
+/// What the daemon does for requests, provided by the command line.
+pub struct Handlers<'a> {
+    /// The credentials of a profile, bypassing caches when asked to refresh
+    pub resolve: &'a dyn Fn(&str, bool) -> Result<ConnectionParams>,
+    /// The database names `list` shows
+    pub names: &'a dyn Fn() -> Result<Vec<String>>,
+    /// The parameters to connect with, through the profile's tunnel
+    pub connection: &'a dyn Fn(&str) -> Result<ConnectionParams>,
+    /// Like `connection`, for a query the daemon runs itself, so after the
+    /// profile's approval and session limit
+    pub session: &'a dyn Fn(&str) -> Result<ConnectionParams>,
+    /// State and detail like `status` prints them
+    pub health: &'a dyn Fn(&str) -> Result<(&'static str, String)>,
+}
+
+struct State {
+    ttl: Duration,
+    /// By working directory and profile name
+    credentials: HashMap<(PathBuf, String), (Instant, ConnectionParams)>,
+    /// ssh processes and their local ports, by jump host and target
+    tunnels: HashMap<String, (Child, u16)>,
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+fn state() -> MutexGuard<'static, Option<State>> {
+    STATE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Serve requests until interrupted, on the unix socket and, with `http`
+/// (like `127.0.0.1:7170`), over HTTP.
+pub fn run(ttl: &str, http: Option<&str>, handlers: &Handlers) -> Result<()> {
+    let ttl = Duration::from_secs(time::parse_duration(ttl)?);
+    let path = socket_path()?;
+    if UnixStream::connect(&path).is_ok() {
+        return Err(anyhow::anyhow!(
+            "A daemon is already listening on {}",
+            path.display()
+        ));
+    }
+    let http = match http {
+        Some(address) => Some(api::listen(address)?),
+        None => None,
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let _ = fs::remove_file(&path);
+    // Only the user may connect; the socket hands out passwords
+    let listener = unsafe {
+        let mask = libc::umask(0o077);
+        let listener = UnixListener::bind(&path);
+        libc::umask(mask);
+        listener
+    }
+    .with_context(|| format!("Failed to listen on {}", path.display()))?;
+    listener.set_nonblocking(true)?;
+    unsafe {
+        libc::signal(libc::SIGINT, on_stop as *const () as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, on_stop as *const () as libc::sighandler_t);
+    }
+    SERVING.store(true, Ordering::Relaxed);
+    *state() = Some(State {
+        ttl,
+        credentials: HashMap::new(),
+        tunnels: HashMap::new(),
+    });
+    eprintln!("connect-db daemon listening on {}", path.display());
+    let result = serve_all(&listener, http.as_ref(), handlers);
+    if let Some(state) = state().take() {
+        for (_, (mut child, _)) in state.tunnels {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+    let _ = fs::remove_file(&path);
+    eprintln!("connect-db daemon stopped");
+    result
+}
+
+fn serve_all(
+    listener: &UnixListener,
+    http: Option<&(TcpListener, api::Api)>,
+    handlers: &Handlers,
+) -> Result<()> {
+    while !STOP.load(Ordering::Relaxed) {
+        let mut idle = true;
+        match listener.accept() {
+            Ok((stream, _)) => {
+                idle = false;
+                if let Err(err) = serve(stream, handlers) {
+                    eprintln!("Warning: {:#}", err);
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+            Err(err) => return Err(err.into()),
+        }
+        if let Some((listener, api)) = http {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    idle = false;
+                    if let Err(err) = api.serve(stream, handlers) {
+                        eprintln!("Warning: {:#}", err);
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        if idle {
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+    Ok(())
+}
+
+fn serve(stream: UnixStream, handlers: &Handlers) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let request: Request = serde_json::from_str(&line).context("Invalid request")?;
+    let response = match request {
+        Request::Credentials {
+            name,
+            cwd,
+            options: requested,
+            refresh,
+        } => {
+            if requested != options() {
+                Response::Unsupported("the daemon runs with other global options".to_string())
+            } else {
+                serve_credentials(&name, cwd, refresh, handlers.resolve)
+            }
+        }
+        Request::Tunnel {
+            tunnel,
+            host,
+            port,
+            proxy,
+        } => match serve_tunnel(&tunnel, &host, &port, proxy.as_deref()) {
+            Ok(port) => Response::Port(port),
+            Err(err) => Response::Error(format!("{:#}", err)),
+        },
+    };
+    let mut content = serde_json::to_string(&response)?;
+    content.push('\n');
+    (&stream).write_all(content.as_bytes())?;
+    Ok(())
+}
+
+fn serve_credentials(
+    name: &str,
+    cwd: PathBuf,
+    refresh: bool,
+    resolve: &dyn Fn(&str, bool) -> Result<ConnectionParams>,
+) -> Response {
+    let key = (cwd, name.to_string());
+    if !refresh
+        && let Some(state) = state().as_ref()
+        && let Some((fetched, params)) = state.credentials.get(&key)
+        && fetched.elapsed() < state.ttl
+    {
+        return Response::Params(Box::new(params.clone()));
+    }
+    let profile = env::set_current_dir(&key.0)
+        .with_context(|| format!("Failed to enter {}", key.0.display()))
+        .and_then(|_| config::load_profile(name));
+    match profile {
+        Ok(profile) if matches!(profile.auth, AuthMode::Ldap | AuthMode::Oidc) => {
+            return Response::Unsupported("the profile authenticates interactively".to_string());
+        }
+        Ok(_) => {}
+        Err(err) => return Response::Error(format!("{:#}", err)),
+    }
+    match resolve(name, refresh) {
+        Ok(params) => {
+            eprintln!("Resolved {} for {}", name, key.0.display());
+            if let Some(state) = state().as_mut() {
+                state
+                    .credentials
+                    .insert(key, (Instant::now(), params.clone()));
+            }
+            Response::Params(Box::new(params))
+        }
+        Err(err) => Response::Error(format!("{:#}", err)),
+    }
+}
+
+fn serve_tunnel(tunnel: &TunnelConfig, host: &str, port: &str, proxy: Option<&str>) -> Result<u16> {
+    let key = format!("{} {}:{} {}", tunnel.ssh, host, port, proxy.unwrap_or(""));
+    let mut state = state();
+    let state = state.as_mut().context("The daemon is stopping")?;
+    if let Some((child, local_port)) = state.tunnels.get_mut(&key) {
+        if child.try_wait()?.is_none() {
+            return Ok(*local_port);
+        }
+        state.tunnels.remove(&key);
+    }
+    eprintln!("Opening SSH tunnel via {} to {}:{}", tunnel.ssh, host, port);
+    let (child, local_port) = tunnel::open_persistent_tunnel(tunnel, host, port, proxy)?;
+    state.tunnels.insert(key, (child, local_port));
+    Ok(local_port)
+}
+
+/// Local ports of the tunnels the daemon holds open, by jump host and target.
+pub fn tunnels() -> Vec<(String, u16)> {
+    let state = state();
+    let Some(state) = state.as_ref() else {
+        return Vec::new();
+    };
+    let mut tunnels: Vec<_> = state
+        .tunnels
+        .iter()
+        .map(|(key, (_, port))| (key.trim_end().to_string(), *port))
+        .collect();
+    tunnels.sort();
+    tunnels
+}
+
+/// Ask a running daemon. None when there is none, or it can't serve this.
+fn ask(request: &Request) -> Option<Result<Response>> {
+    if SERVING.load(Ordering::Relaxed) {
+        return None;
+    }
+    let stream = UnixStream::connect(socket_path().ok()?).ok()?;
+    let exchange = || -> Result<Response> {
+        // Resolving or dialing on a cold daemon can take a while
+        stream.set_read_timeout(Some(Duration::from_secs(120)))?;
+        let mut content = serde_json::to_string(request)?;
+        content.push('\n');
+        (&stream).write_all(content.as_bytes())?;
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        if line.is_empty() {
+            return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+        }
+        Ok(serde_json::from_str(&line)?)
+    };
+    match exchange() {
+        Ok(Response::Unsupported(reason)) => {
+            dry_run::report(format!("not using the daemon: {}", reason));
+            None
+        }
+        Ok(response) => Some(Ok(response)),
+        Err(err) => Some(Err(err.context("The connect-db daemon did not answer"))),
+    }
+}
+
+/// The credentials of `name` from a running daemon.
+pub fn credentials(name: &str, refresh: bool) -> Option<Result<ConnectionParams>> {
+    let request = Request::Credentials {
+        name: name.to_string(),
+        cwd: env::current_dir().ok()?,
+        options: options(),
+        refresh,
+    };
+    let response = ask(&request)?;
+    dry_run::report(format!("credentials of {} from the daemon", name));
+    Some(response.and_then(|response| match response {
+        Response::Params(params) => Ok(*params),
+        Response::Error(err) => Err(anyhow::anyhow!(err)),
+        _ => Err(anyhow::anyhow!("Unexpected answer from the daemon")),
+    }))
+}
+
+/// The local port of a tunnel to `host:port` the daemon holds open.
+pub fn tunnel(
+    tunnel: &TunnelConfig,
+    host: &str,
+    port: &str,
+    proxy: Option<&str>,
+) -> Option<Result<u16>> {
+    // Connections the daemon makes for the HTTP API share its tunnels
+    if SERVING.load(Ordering::Relaxed) {
+        return Some(serve_tunnel(tunnel, host, port, proxy));
+    }
+    let request = Request::Tunnel {
+        tunnel: tunnel.clone(),
+        host: host.to_string(),
+        port: port.to_string(),
+        proxy: proxy.map(str::to_string),
+    };
+    Some(ask(&request)?.and_then(|response| match response {
+        Response::Port(port) if engines::is_reachable(&format!("127.0.0.1:{}", port)) => Ok(port),
+        Response::Port(port) => Err(anyhow::anyhow!(
+            "The daemon's tunnel on port {} is not accepting connections",
+            port
+        )),
+        Response::Error(err) => Err(anyhow::anyhow!(err)),
+        _ => Err(anyhow::anyhow!("Unexpected answer from the daemon")),
+    }))
+}
@@ -0,0 +1,295 @@
+//! Row-level comparison of a table across two PostgreSQL databases, by
+//! checksumming key-ordered chunks and drilling into the ones that differ.
+
+use crate::{
+    clone_schema::Side,
+    engines::{self, Engine, postgres::quote_literal},
+    progress::{Progress, Unit},
+    report,
+};
+use anyhow::{Context, Result};
+use std::{
+    collections::{HashMap, HashSet},
+    thread,
+};
+
+/// Separates key columns inside the hex-encoded key text.
+const KEY_SEPARATOR: char = '\x1f';
+
+pub struct DiffOptions {
+    pub table: String,
+    /// Key columns, in order; together they must be unique
+    pub keys: Vec<String>,
+    /// Rows per checksummed chunk
+    pub chunk_size: usize,
+    /// Differing rows to print before only counting
+    pub max_rows: usize,
+}
+
+#[derive(Default)]
+struct Counts {
+    rows: u64,
+    differ: u64,
+    only_a: u64,
+    only_b: u64,
+}
+
+/// Compare `options.table` in `a` and `b`, printing the keys of differing
+/// and missing rows. Fails when the tables differ.
+pub fn diff(a: &Side, b: &Side, options: &DiffOptions) -> Result<()> {
+    for side in [a, b] {
+        if side.params.engine != Engine::Postgres {
+            return Err(anyhow::anyhow!(
+                "{} is not a PostgreSQL database; data-diff only supports PostgreSQL",
+                side.name
+            ));
+        }
+    }
+    let types = key_types(a, options)?;
+    let boundaries = chunk_boundaries(a, options)?;
+    let mut chunks = Vec::new();
+    let mut lower = None;
+    for boundary in boundaries {
+        chunks.push(range_condition(
+            options,
+            &types,
+            lower.as_deref(),
+            Some(&boundary),
+        ));
+        lower = Some(boundary);
+    }
+    chunks.push(range_condition(options, &types, lower.as_deref(), None));
+
+    let mut counts = Counts::default();
+    let mut printed = 0;
+    let mut progress = Progress::new("diff", chunks.len() as u64, Unit::Chunks);
+    for (index, condition) in chunks.iter().enumerate() {
+        let sql = format!(
+            "select count(*), coalesce(md5(string_agg(md5(diff_row::text), '' order by {keys})), '') \
+             from {table} diff_row where {condition}",
+            keys = options.keys.join(", "),
+            table = options.table,
+        );
+        let (summary_a, summary_b) = on_both(a, b, &sql)?;
+        let rows_a = summary_a
+            .split('|')
+            .next()
+            .and_then(|count| count.parse::<u64>().ok())
+            .unwrap_or(0);
+        counts.rows += rows_a;
+        if summary_a != summary_b {
+            for line in compare_rows(a, b, options, condition, &mut counts)? {
+                if printed < options.max_rows {
+                    progress.message(&line);
+                }
+                printed += 1;
+            }
+        }
+        progress.set(index as u64 + 1, "");
+    }
+    progress.finish();
+
+    let different = counts.differ + counts.only_a + counts.only_b;
+    if printed > options.max_rows {
+        println!("... {} more", printed - options.max_rows);
+    }
+    println!(
+        "{}: {} rows in {} compared in {} chunks; {} differ, {} only in {}, {} only in {}",
+        options.table,
+        counts.rows,
+        a.name,
+        chunks.len(),
+        counts.differ,
+        counts.only_a,
+        a.name,
+        counts.only_b,
+        b.name
+    );
+    if different > 0 {
+        return Err(anyhow::anyhow!(
+            "{} differs between {} and {}",
+            options.table,
+            a.name,
+            b.name
+        ));
+    }
+    Ok(())
+}
+
+/// SQL type of every key column, used to cast boundary literals.
+fn key_types(side: &Side, options: &DiffOptions) -> Result<Vec<String>> {
+    let mut types = Vec::new();
+    for key in &options.keys {
+        let sql = format!(
+            "select format_type(atttypid, atttypmod) from pg_attribute \
+             where attrelid = {}::regclass and attname = {} and not attisdropped",
+            quote_literal(&options.table),
+            quote_literal(key)
+        );
+        let output = engines::run_query(side.params, side.profile, &sql)
+            .with_context(|| format!("Failed to look up {} in {}", options.table, side.name))?;
+        if output.is_empty() {
+            return Err(anyhow::anyhow!(
+                "{} has no column {} in {}",
+                options.table,
+                key,
+                side.name
+            ));
+        }
+        types.push(output);
+    }
+    Ok(types)
+}
+
+/// Key text of every `chunk_size`-th row of `side`, hex-encoded so any key
+/// value survives psql's output.
+fn chunk_boundaries(side: &Side, options: &DiffOptions) -> Result<Vec<String>> {
+    let sql = format!(
+        "select key from (select {key} as key, row_number() over (order by {keys}) as rn \
+         from {table}) numbered where rn % {chunk} = 0 order by rn",
+        key = hex_key(options),
+        keys = options.keys.join(", "),
+        table = options.table,
+        chunk = options.chunk_size.max(1),
+    );
+    let output = engines::run_query(side.params, side.profile, &sql)
+        .with_context(|| format!("Failed to read keys of {} in {}", options.table, side.name))?;
+    Ok(output.lines().map(str::to_string).collect())
+}
+
+fn hex_key(options: &DiffOptions) -> String {
+    let columns: Vec<String> = options
+        .keys
+        .iter()
+        .map(|key| format!("{}::text", key))
+        .collect();
+    format!(
+        "encode(convert_to(concat_ws(E'\\x1f', {}), 'UTF8'), 'hex')",
+        columns.join(", ")
+    )
+}
+
+/// Key values of a hex-encoded key.
+fn decode_key(hex: &str) -> Vec<String> {
+    let bytes: Vec<u8> = (0..hex.len())
+        .step_by(2)
+        .filter_map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+        .collect();
+    String::from_utf8_lossy(&bytes)
+        .split(KEY_SEPARATOR)
+        .map(str::to_string)
+        .collect()
+}
+
+/// `lower < (keys) <= upper`, either bound optional.
+fn range_condition(
+    options: &DiffOptions,
+    types: &[String],
+    lower: Option<&str>,
+    upper: Option<&str>,
+) -> String {
+    let keys = format!("({})", options.keys.join(", "));
+    let literal = |hex: &str| {
+        let values: Vec<String> = decode_key(hex)
+            .iter()
+            .zip(types)
+            .map(|(value, kind)| format!("{}::{}", quote_literal(value), kind))
+            .collect();
+        format!("({})", values.join(", "))
+    };
+    let mut conditions = Vec::new();
+    if let Some(lower) = lower {
+        conditions.push(format!("{} > {}", keys, literal(lower)));
+    }
+    if let Some(upper) = upper {
+        conditions.push(format!("{} <= {}", keys, literal(upper)));
+    }
+    if conditions.is_empty() {
+        conditions.push("true".to_string());
+    }
+    conditions.join(" and ")
+}
+
+fn on_both(a: &Side, b: &Side, sql: &str) -> Result<(String, String)> {
+    let (result_a, result_b) = thread::scope(|scope| {
+        let other = scope.spawn(|| engines::run_query(b.params, b.profile, sql));
+        let result_a = engines::run_query(a.params, a.profile, sql);
+        let result_b = other
+            .join()
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("query panicked")));
+        (result_a, result_b)
+    });
+    let result_a = result_a.with_context(|| format!("Query failed on {}", a.name))?;
+    let result_b = result_b.with_context(|| format!("Query failed on {}", b.name))?;
+    Ok((result_a, result_b))
+}
+
+/// Row checksums of one chunk on both sides, as report lines.
+fn compare_rows(
+    a: &Side,
+    b: &Side,
+    options: &DiffOptions,
+    condition: &str,
+    counts: &mut Counts,
+) -> Result<Vec<String>> {
+    let sql = format!(
+        "select {key}, md5(diff_row::text) from {table} diff_row where {condition} order by {keys}",
+        key = hex_key(options),
+        table = options.table,
+        keys = options.keys.join(", "),
+    );
+    let (rows_a, rows_b) = on_both(a, b, &sql)?;
+    let rows_a = report::rows(&rows_a);
+    let rows_b = report::rows(&rows_b);
+    let hashes_b: HashMap<&str, &str> = rows_b
+        .iter()
+        .filter_map(|row| Some((row.first()?.as_str(), row.get(1)?.as_str())))
+        .collect();
+    let keys_a: HashSet<&str> = rows_a
+        .iter()
+        .filter_map(|row| Some(row.first()?.as_str()))
+        .collect();
+
+    let mut lines = Vec::new();
+    for row in &rows_a {
+        let [key, hash] = row.as_slice() else {
+            continue;
+        };
+        match hashes_b.get(key.as_str()) {
+            Some(other) if other == hash => {}
+            Some(_) => {
+                counts.differ += 1;
+                lines.push(format!("~ {} differs", describe(options, key)));
+            }
+            None => {
+                counts.only_a += 1;
+                lines.push(format!("- {} only in {}", describe(options, key), a.name));
+            }
+        }
+    }
+    for row in &rows_b {
+        let Some(key) = row.first() else {
+            continue;
+        };
+        if !keys_a.contains(key.as_str()) {
+            counts.only_b += 1;
+            lines.push(format!("+ {} only in {}", describe(options, key), b.name));
+        }
+    }
+    Ok(lines)
+}
+
+/// `id=5` or `(tenant=a, id=5)`.
+fn describe(options: &DiffOptions, hex: &str) -> String {
+    let pairs: Vec<String> = options
+        .keys
+        .iter()
+        .zip(decode_key(hex))
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    if pairs.len() == 1 {
+        pairs.into_iter().next().unwrap_or_default()
+    } else {
+        format!("({})", pairs.join(", "))
+    }
+}
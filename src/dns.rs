@@ -0,0 +1,126 @@
+//! Per-profile name resolution for split-horizon DNS: static host
+//! overrides, a resolver to ask instead of the system's, and curl-style
+//! `--resolve host:port:addr`.
+//!
+//! PostgreSQL-protocol clients get the address as `hostaddr`, so TLS still
+//! verifies the host name; other clients connect to the address directly.
+
+use crate::{
+    config::Profile,
+    dry_run,
+    engines::{ConnectionParams, Engine},
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, net::IpAddr, process::Command, sync::OnceLock};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct DnsConfig {
+    /// DNS server to look host names up with, like the corporate one
+    /// reachable over the VPN
+    pub resolver: Option<String>,
+    /// Addresses for host names, like entries in `/etc/hosts`
+    pub hosts: BTreeMap<String, String>,
+}
+
+/// `--resolve` entries: host, port (`*` for any) and address.
+static RESOLVE: OnceLock<Vec<(String, String, String)>> = OnceLock::new();
+
+pub fn set_cli_overrides(entries: Vec<(String, String, String)>) {
+    let _ = RESOLVE.set(entries);
+}
+
+/// `HOST:PORT:ADDR` for `--resolve`; IPv6 addresses may be in brackets.
+pub fn parse_resolve(argument: &str) -> Result<(String, String, String), String> {
+    let invalid = || format!("expected HOST:PORT:ADDR, got '{}'", argument);
+    let (host, rest) = argument.split_once(':').ok_or_else(invalid)?;
+    let (port, address) = rest.split_once(':').ok_or_else(invalid)?;
+    let address = address.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() || port.is_empty() || address.parse::<IpAddr>().is_err() {
+        return Err(invalid());
+    }
+    Ok((host.to_string(), port.to_string(), address.to_string()))
+}
+
+/// Point `params` at the addresses `--resolve` and the profile's `dns`
+/// settings give for its hosts. The resolver is only asked when `local`,
+/// since behind an SSH tunnel the bastion resolves names itself.
+pub fn apply(params: &mut ConnectionParams, profile: &Profile, local: bool) -> Result<()> {
+    let libpq = matches!(
+        params.engine,
+        Engine::Postgres | Engine::Redshift | Engine::CockroachDb
+    );
+    if let Some(address) = lookup(&params.host, &params.port, profile, local)? {
+        if libpq {
+            params.options.retain(|(key, _)| key != "hostaddr");
+            params.options.push(("hostaddr".to_string(), address));
+        } else {
+            params.host = address;
+        }
+    }
+    for index in 0..params.failover_hosts.len() {
+        let (host, port) = &params.failover_hosts[index];
+        if let Some(address) = lookup(host, port, profile, local)? {
+            params.failover_hosts[index].0 = address;
+        }
+    }
+    Ok(())
+}
+
+/// The address the overrides (or, when `local`, the resolver) give `host`.
+pub fn lookup(host: &str, port: &str, profile: &Profile, local: bool) -> Result<Option<String>> {
+    if host.parse::<IpAddr>().is_ok() {
+        return Ok(None);
+    }
+    let cli = RESOLVE
+        .get()
+        .into_iter()
+        .flatten()
+        .find(|(name, entry_port, _)| {
+            name.eq_ignore_ascii_case(host) && (entry_port == "*" || entry_port == port)
+        });
+    let dns = profile.dns.as_ref();
+    let address = match cli {
+        Some((_, _, address)) => Some(address.clone()),
+        None => dns
+            .and_then(|dns| {
+                dns.hosts
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(host))
+            })
+            .map(|(_, address)| address.clone()),
+    };
+    let address = match (address, dns.and_then(|dns| dns.resolver.as_deref())) {
+        (Some(address), _) => address,
+        (None, Some(resolver)) if local => query(resolver, host)?,
+        _ => return Ok(None),
+    };
+    dry_run::report(format!("resolved {} to {}", host, address));
+    Ok(Some(address))
+}
+
+/// Ask `resolver` for an address of `host` with `dig`.
+fn query(resolver: &str, host: &str) -> Result<String> {
+    let output = Command::new("dig")
+        .args(["+short", "+time=2", "+tries=1"])
+        .arg(format!("@{}", resolver))
+        .arg(host)
+        .output()
+        .context("Failed to run dig (install bind-utils or dnsutils)")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "dig @{} {} failed: {}",
+            resolver,
+            host,
+            String::from_utf8_lossy(&output.stdout).trim()
+        ));
+    }
+    // The chain may start with CNAME targets
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .find(|line| line.parse::<IpAddr>().is_ok())
+        .map(str::to_string)
+        .with_context(|| format!("Resolver {} has no address for {}", resolver, host))
+}
@@ -0,0 +1,71 @@
+//! `--dry-run`: resolve secrets as usual, reporting every file read, request
+//! sent and tunnel that would start, then stop before the first command with
+//! side effects and show it (secrets masked) instead.
+
+use crate::engines::shell_quote;
+use std::{fmt::Display, path::Path, process::Command, sync::OnceLock};
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+pub fn enable() {
+    let _ = ENABLED.set(true);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.get().copied().unwrap_or(false)
+}
+
+/// Note a step on stderr when dry-running.
+pub fn report(step: impl Display) {
+    if enabled() {
+        eprintln!("[dry-run] {}", step);
+    }
+}
+
+/// Show the command that would run next and exit without running it.
+pub fn stop(command: &str) -> ! {
+    eprintln!("[dry-run] would run:");
+    println!("{}", command);
+    crate::telemetry::flush();
+    crate::usage::finish(true, 0);
+    std::process::exit(0)
+}
+
+/// When dry-running, show `command` and exit instead of running it.
+pub fn stop_before(command: &Command) {
+    if enabled() {
+        stop(&command_line(command));
+    }
+}
+
+/// When dry-running, exit instead of writing `path`.
+pub fn stop_before_writing(path: &Path) {
+    if enabled() {
+        stop(&format!("write {}", path.display()));
+    }
+}
+
+/// `command` as a shell command line, with the environment it sets and
+/// secret-looking variables masked.
+pub fn command_line(command: &Command) -> String {
+    let mut words = Vec::new();
+    for (key, value) in command.get_envs() {
+        let Some(value) = value else {
+            continue;
+        };
+        let upper = key.to_string_lossy().to_uppercase();
+        let value = match ["PASSWORD", "PWD", "SECRET", "TOKEN"]
+            .iter()
+            .any(|word| upper.contains(word))
+        {
+            true => "****".to_string(),
+            false => shell_quote(&value.to_string_lossy()),
+        };
+        words.push(format!("{}={}", key.to_string_lossy(), value));
+    }
+    words.push(shell_quote(&command.get_program().to_string_lossy()));
+    for arg in command.get_args() {
+        words.push(shell_quote(&arg.to_string_lossy()));
+    }
+    words.join(" ")
+}
@@ -0,0 +1,987 @@
+use crate::{
+    clone_schema::Side,
+    codec::{self, Compression, Encryption},
+    config::Profile,
+    dry_run,
+    engines::{self, ConnectionParams, Engine, client, postgres},
+    object_store::{self, Location},
+    progress::{Progress, Unit},
+    prompt, report,
+    sha256::Sha256,
+    time,
+};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::{
+    collections::{HashMap, HashSet},
+    env, fmt, fs,
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// Heap size of every user table, the weights for dump progress.
+const TABLE_SIZES: &str = "\
+select n.nspname || '.' || c.relname, pg_table_size(c.oid) \
+from pg_class c join pg_namespace n on n.oid = c.relnamespace \
+where c.relkind in ('r', 'm') \
+and n.nspname not in ('pg_catalog', 'information_schema') \
+and n.nspname not like 'pg_toast%'";
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// pg_dump custom archive, restorable with pg_restore
+    Custom,
+    /// One file per table; required for --jobs
+    Directory,
+    /// Plain SQL script
+    Plain,
+}
+
+pub struct DumpOptions {
+    /// File or directory to write (defaults to `<name>-<time>.<ext>`)
+    pub output: Option<PathBuf>,
+    pub format: Format,
+    /// Parallel pg_dump workers
+    pub jobs: usize,
+    /// Schemas to include (`pg_dump -n`)
+    pub schemas: Vec<String>,
+    /// Tables to include (`pg_dump -t`)
+    pub tables: Vec<String>,
+    /// Tables to leave out (`pg_dump -T`)
+    pub exclude_tables: Vec<String>,
+    pub compression: Option<Compression>,
+    pub encryption: Option<Encryption>,
+}
+
+pub struct RestoreOptions {
+    /// Parallel pg_restore workers
+    pub jobs: usize,
+    /// Drop objects before recreating them
+    pub clean: bool,
+    /// Don't restore ownership
+    pub no_owner: bool,
+    /// Skip the production confirmation
+    pub yes: bool,
+    /// age identity file for encrypted dumps
+    pub identity: Option<PathBuf>,
+}
+
+fn ensure_postgres(name: &str, params: &ConnectionParams) -> Result<()> {
+    if params.engine != Engine::Postgres {
+        return Err(anyhow::anyhow!(
+            "{} is not a PostgreSQL database; this command uses pg_dump",
+            name
+        ));
+    }
+    Ok(())
+}
+
+fn ensure_writable(name: &str, profile: &Profile, yes: bool) -> Result<()> {
+    if profile.read_only {
+        return Err(anyhow::anyhow!(
+            "{} is read-only; restoring is not allowed",
+            name
+        ));
+    }
+    if profile.production
+        && !yes
+        && !prompt::confirm(&format!(
+            "{} is a production database. Restore into it?",
+            name
+        ))?
+    {
+        return Err(anyhow::anyhow!("Aborted"));
+    }
+    Ok(())
+}
+
+impl Format {
+    fn pg_dump_name(self) -> &'static str {
+        match self {
+            Format::Custom => "custom",
+            Format::Directory => "directory",
+            Format::Plain => "plain",
+        }
+    }
+}
+
+impl DumpOptions {
+    /// `<name>-<time>` with the extensions of the format and layers.
+    pub fn default_file_name(&self, name: &str) -> String {
+        let extension = match self.format {
+            Format::Custom => ".dump",
+            Format::Directory => "",
+            Format::Plain => ".sql",
+        };
+        let stamp = time::now_rfc3339().replace(':', "-");
+        // Hierarchical names like `payments/orders` stay one file
+        format!(
+            "{}-{}{}{}",
+            name.replace('/', "-"),
+            stamp,
+            extension,
+            codec::extension(self.compression, self.encryption.as_ref())
+        )
+    }
+
+    fn layered(&self) -> bool {
+        self.compression.is_some() || self.encryption.is_some()
+    }
+}
+
+/// A finished dump.
+pub struct Dumped {
+    /// File, directory or object storage URL
+    pub path: PathBuf,
+    /// Size and checksum, known when the dump was streamed
+    pub streamed: Option<(u64, String)>,
+}
+
+/// Hashes what passes through to the sink.
+struct Hashed<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for Hashed<W> {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buffer)?;
+        self.hasher.update(&buffer[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Where a streamed dump ends up.
+enum Sink {
+    File(PathBuf),
+    Remote(Location),
+}
+
+impl fmt::Display for Sink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sink::File(path) => write!(f, "{}", path.display()),
+            Sink::Remote(location) => write!(f, "{}", location),
+        }
+    }
+}
+
+/// Dump a database with pg_dump, showing progress by table size. Outputs
+/// that are object storage URLs are streamed there.
+pub fn dump(
+    name: &str,
+    params: &ConnectionParams,
+    profile: &Profile,
+    options: &DumpOptions,
+) -> Result<Dumped> {
+    ensure_postgres(name, params)?;
+    if options.jobs > 1 && options.format != Format::Directory {
+        return Err(anyhow::anyhow!("--jobs needs --format directory"));
+    }
+    if options.layered() && options.format == Format::Directory {
+        return Err(anyhow::anyhow!(
+            "--compress and --encrypt need --format custom or plain"
+        ));
+    }
+    let output = match &options.output {
+        Some(output) => output.clone(),
+        None => PathBuf::from(options.default_file_name(name)),
+    };
+    if let Some(mut location) = output.to_str().and_then(object_store::parse) {
+        if location.is_prefix() {
+            location = location.join(&options.default_file_name(name));
+        }
+        return dump_streamed(name, params, profile, options, &Sink::Remote(location));
+    }
+    if output.exists() {
+        return Err(anyhow::anyhow!("{} already exists", output.display()));
+    }
+    if options.layered() {
+        return dump_streamed(name, params, profile, options, &Sink::File(output));
+    }
+
+    let weights = table_sizes(params, profile)?;
+    let mut cmd = pg_dump_command(params, profile, options)?;
+    cmd.arg("--file").arg(&output);
+    cmd.args(["--format", options.format.pg_dump_name()]);
+
+    dry_run::stop_before(&cmd);
+    eprintln!("Dumping {} to {}", name, output.display());
+    let mut child = cmd
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run pg_dump")?;
+    let mut tracker = Tracker::new("pg_dump", "dump", weights, Unit::Bytes, options.jobs > 1);
+    tracker.follow(&mut child)?;
+    if !child.wait()?.success() {
+        return Err(anyhow::anyhow!("pg_dump failed for {}", name));
+    }
+    tracker.progress.finish();
+    eprintln!("Wrote {}", output.display());
+    Ok(Dumped {
+        path: output,
+        streamed: None,
+    })
+}
+
+/// Stream pg_dump output through the compression and encryption filters
+/// into a file or an upload. The data passes through this process so a
+/// failed stage can abort the upload before it completes.
+fn dump_streamed(
+    name: &str,
+    params: &ConnectionParams,
+    profile: &Profile,
+    options: &DumpOptions,
+    sink: &Sink,
+) -> Result<Dumped> {
+    if options.format == Format::Directory {
+        return Err(anyhow::anyhow!(
+            "Directory dumps can't be streamed to {}; use --format custom or plain",
+            sink
+        ));
+    }
+    let weights = table_sizes(params, profile)?;
+    let mut cmd = pg_dump_command(params, profile, options)?;
+    cmd.args(["--format", options.format.pg_dump_name()]);
+    if options.compression.is_some() && options.format == Format::Custom {
+        // Compressing twice only costs time
+        cmd.args(["--compress", "0"]);
+    }
+
+    if dry_run::enabled() {
+        dry_run::stop(&format!("{} > {}", dry_run::command_line(&cmd), sink));
+    }
+    eprintln!("Dumping {} to {}", name, sink);
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run pg_dump")?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut filters: Vec<(&str, Child)> = Vec::new();
+    for (tool, mut cmd) in codec::encoders(options.compression, options.encryption.as_ref()) {
+        // Spawning fails before any input is consumed; later stages see EOF
+        let spawned = cmd
+            .stdin(stdout)
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", tool));
+        let mut filter = match spawned {
+            Ok(filter) => filter,
+            Err(err) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(err);
+            }
+        };
+        stdout = filter.stdout.take().expect("stdout is piped");
+        filters.push((tool, filter));
+    }
+    let opened = match sink {
+        Sink::File(path) => fs::File::create(path)
+            .with_context(|| format!("Failed to create {}", path.display()))
+            .map(|file| (Box::new(file) as Box<dyn Write + Send>, None)),
+        Sink::Remote(location) => location.upload().map(|mut upload| {
+            let stdin = upload.stdin.take().expect("stdin is piped");
+            (Box::new(stdin) as Box<dyn Write + Send>, Some(upload))
+        }),
+    };
+    let (writer, mut upload) = match opened {
+        Ok(opened) => opened,
+        Err(err) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(err);
+        }
+    };
+
+    let mut writer = Hashed {
+        inner: writer,
+        hasher: Sha256::default(),
+    };
+    let mut tracker = Tracker::new("pg_dump", "dump", weights, Unit::Bytes, false);
+    let sent = thread::scope(|scope| {
+        let following = scope.spawn(|| tracker.follow(&mut child));
+        // Progress comes from the table sizes on stderr, not the bytes
+        let sent = pump(stdout, &mut writer, |_| {});
+        following
+            .join()
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("progress tracking panicked")))?;
+        sent
+    })?;
+
+    let mut failure = None;
+    if !child.wait()?.success() {
+        failure = Some(format!("pg_dump failed for {}", name));
+    }
+    for (tool, filter) in &mut filters {
+        if !filter.wait()?.success() && failure.is_none() {
+            failure = Some(format!("{} failed for {}", tool, name));
+        }
+    }
+    if let Some(failure) = failure {
+        // Kill the upload while its input is still open so no partial
+        // object is written
+        if let Some(upload) = &mut upload {
+            let _ = upload.kill();
+            let _ = upload.wait();
+        }
+        drop(writer);
+        if let Sink::File(path) = sink {
+            let _ = fs::remove_file(path);
+        }
+        return Err(anyhow::anyhow!(failure));
+    }
+    writer.flush()?;
+    let checksum = writer.hasher.finish();
+    drop(writer.inner);
+    if let (Some(upload), Sink::Remote(location)) = (&mut upload, sink)
+        && !upload.wait()?.success()
+    {
+        return Err(anyhow::anyhow!("Upload to {} failed", location));
+    }
+    tracker.progress.finish();
+    eprintln!("Wrote {} ({})", sink, crate::storage::format_size(sent));
+    Ok(Dumped {
+        path: PathBuf::from(sink.to_string()),
+        streamed: Some((sent, checksum)),
+    })
+}
+
+/// Copy `reader` into `writer`, reporting the bytes sent so far. Stops
+/// early when the writer goes away; the caller checks exit statuses.
+fn pump(
+    mut reader: impl Read,
+    writer: &mut impl Write,
+    mut sent_so_far: impl FnMut(u64),
+) -> Result<u64> {
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut sent = 0;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        if writer.write_all(&buffer[..read]).is_err() {
+            break;
+        }
+        sent += read as u64;
+        sent_so_far(sent);
+    }
+    Ok(sent)
+}
+
+fn pg_dump_command(
+    params: &ConnectionParams,
+    profile: &Profile,
+    options: &DumpOptions,
+) -> Result<Command> {
+    let engine = params.engine.implementation();
+    let pg_dump = client::ensure(engine, "pg_dump")?;
+    let mut cmd = Command::new(pg_dump);
+    cmd.args(postgres::psql_args(params))
+        .args(["--verbose", "--no-password"])
+        .envs(engine.client_env(params, profile));
+    if options.jobs > 1 {
+        cmd.arg("--jobs").arg(options.jobs.to_string());
+    }
+    for schema in &options.schemas {
+        cmd.arg("--schema").arg(schema);
+    }
+    for table in &options.tables {
+        cmd.arg("--table").arg(table);
+    }
+    for table in &options.exclude_tables {
+        cmd.arg("--exclude-table").arg(table);
+    }
+    Ok(cmd)
+}
+
+fn table_sizes(params: &ConnectionParams, profile: &Profile) -> Result<HashMap<String, u64>> {
+    let rows = report::rows(&engines::run_query(params, profile, TABLE_SIZES)?);
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| match row.as_slice() {
+            [table, size] => Some((table.clone(), size.parse().ok()?)),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Restore a pg_dump archive, directory or plain SQL file.
+pub fn restore(
+    name: &str,
+    params: &ConnectionParams,
+    profile: &Profile,
+    input: &Path,
+    options: &RestoreOptions,
+) -> Result<()> {
+    ensure_postgres(name, params)?;
+    ensure_writable(name, profile, options.yes)?;
+    if let Some(location) = input.to_str().and_then(object_store::parse) {
+        if dry_run::enabled() {
+            dry_run::stop(&format!("restore {} into {}", location, name));
+        }
+        // A listing failure only costs the progress total
+        let total = location.size().ok().flatten().unwrap_or(0);
+        let mut download = location.download()?;
+        let stdout = download.stdout.take().expect("stdout is piped");
+        let stages = vec![(format!("Failed to download {}", location), download)];
+        let source = Source {
+            label: location.to_string(),
+            reader: Box::new(stdout),
+            total,
+            stages,
+        };
+        return restore_stream(name, params, profile, source, options);
+    }
+
+    if !input.exists() {
+        return Err(anyhow::anyhow!("{} does not exist", input.display()));
+    }
+    let directory = input.join("toc.dat").is_file();
+    let mut head = Vec::new();
+    if !directory {
+        let file =
+            fs::File::open(input).with_context(|| format!("Failed to open {}", input.display()))?;
+        file.take(64).read_to_end(&mut head)?;
+    }
+    if codec::Layer::detect(&head).is_some() {
+        if dry_run::enabled() {
+            dry_run::stop(&format!("restore {} into {}", input.display(), name));
+        }
+        let file = fs::File::open(input)?;
+        let source = Source {
+            label: input.display().to_string(),
+            total: file.metadata()?.len(),
+            reader: Box::new(file),
+            stages: Vec::new(),
+        };
+        return restore_stream(name, params, profile, source, options);
+    }
+    let archive = directory || head.starts_with(b"PGDMP");
+    if !archive {
+        if options.clean || options.no_owner {
+            return Err(anyhow::anyhow!(
+                "--clean and --no-owner only apply to pg_dump archives, not SQL scripts"
+            ));
+        }
+        if options.jobs > 1 {
+            eprintln!("Plain SQL dumps restore with a single connection; ignoring --jobs");
+        }
+        return restore_script(name, params, profile, input);
+    }
+
+    let engine = params.engine.implementation();
+    let pg_restore = client::ensure(engine, "pg_restore")?;
+    let (weights, unit) = archive_tables(&pg_restore, input, directory)?;
+
+    let mut cmd = Command::new(&pg_restore);
+    cmd.args(postgres::psql_args(params))
+        .args(["--verbose", "--no-password"])
+        .envs(engine.client_env(params, profile));
+    if options.jobs > 1 {
+        cmd.arg("--jobs").arg(options.jobs.to_string());
+    }
+    if options.clean {
+        cmd.args(["--clean", "--if-exists"]);
+    }
+    if options.no_owner {
+        cmd.args(["--no-owner", "--no-privileges"]);
+    }
+    cmd.arg(input);
+
+    dry_run::stop_before(&cmd);
+    eprintln!("Restoring {} into {}", input.display(), name);
+    let mut child = cmd
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run pg_restore")?;
+    let mut tracker = Tracker::new("pg_restore", "restore", weights, unit, options.jobs > 1);
+    tracker.follow(&mut child)?;
+    if !child.wait()?.success() {
+        return Err(anyhow::anyhow!("pg_restore failed for {}", name));
+    }
+    tracker.progress.finish();
+    eprintln!("Restored into {}", name);
+    Ok(())
+}
+
+/// Table data entries of an archive from `pg_restore -l`, weighted by their
+/// data file size in directory archives and counted otherwise.
+fn archive_tables(
+    pg_restore: &Path,
+    input: &Path,
+    directory: bool,
+) -> Result<(HashMap<String, u64>, Unit)> {
+    let output = Command::new(pg_restore)
+        .arg("--list")
+        .arg(input)
+        .output()
+        .context("Failed to run pg_restore")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let mut weights = HashMap::new();
+    // e.g. `3367; 0 16390 TABLE DATA public orders postgres`
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Some((id, rest)) = line.split_once(';') else {
+            continue;
+        };
+        let words: Vec<&str> = rest.split_whitespace().collect();
+        let [_, _, "TABLE", "DATA", schema, table, ..] = words.as_slice() else {
+            continue;
+        };
+        let weight = if directory {
+            ["dat.gz", "dat"]
+                .iter()
+                .find_map(|extension| {
+                    fs::metadata(input.join(format!("{}.{}", id, extension))).ok()
+                })
+                .map(|metadata| metadata.len())
+                .unwrap_or(0)
+        } else {
+            1
+        };
+        weights.insert(format!("{}.{}", schema, table), weight);
+    }
+    let unit = if directory { Unit::Bytes } else { Unit::Tables };
+    Ok((weights, unit))
+}
+
+/// Feed a plain SQL file to psql, showing progress by bytes read.
+fn restore_script(
+    name: &str,
+    params: &ConnectionParams,
+    profile: &Profile,
+    input: &Path,
+) -> Result<()> {
+    let file =
+        fs::File::open(input).with_context(|| format!("Failed to open {}", input.display()))?;
+    let total = file.metadata()?.len();
+
+    let mut cmd = script_command(params, profile)?;
+    if dry_run::enabled() {
+        dry_run::stop(&format!(
+            "{} < {}",
+            dry_run::command_line(&cmd),
+            input.display()
+        ));
+    }
+    eprintln!("Restoring {} into {}", input.display(), name);
+    let mut child = cmd.spawn().context("Failed to run psql")?;
+
+    let mut progress = Progress::new("restore", total, Unit::Bytes);
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // psql exits early on the first error; its own message explains why
+    pump(file, &mut stdin, |sent| progress.set(sent, ""))?;
+    drop(stdin);
+    if !child.wait()?.success() {
+        return Err(anyhow::anyhow!("psql failed restoring {}", input.display()));
+    }
+    progress.finish();
+    eprintln!("Restored into {}", name);
+    Ok(())
+}
+
+/// psql running a script from stdin, stopping at the first error.
+fn script_command(params: &ConnectionParams, profile: &Profile) -> Result<Command> {
+    let engine = params.engine.implementation();
+    let psql = client::ensure(engine, "psql")?;
+    let mut cmd = Command::new(psql);
+    cmd.args(postgres::psql_args(params))
+        .args(["-X", "-q", "-v", "ON_ERROR_STOP=1", "-f", "-"])
+        .envs(engine.client_env(params, profile))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null());
+    Ok(cmd)
+}
+
+/// Input of a streamed restore.
+struct Source {
+    /// Path or URL, for messages
+    label: String,
+    reader: Box<dyn Read + Send>,
+    /// Input size for progress, or 0 when unknown
+    total: u64,
+    /// Processes producing the input, with the error reported if one fails
+    stages: Vec<(String, Child)>,
+}
+
+/// Counts bytes read from the input into the shared progress bar.
+struct Counted {
+    inner: Box<dyn Read + Send>,
+    progress: Arc<Mutex<Progress>>,
+    read: u64,
+}
+
+impl Read for Counted {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buffer)?;
+        self.read += read as u64;
+        if let Ok(mut progress) = self.progress.lock() {
+            progress.set(self.read, "");
+        }
+        Ok(read)
+    }
+}
+
+/// Look at the first bytes of a stream without losing them.
+fn peek(mut reader: Box<dyn Read + Send>) -> Result<(Vec<u8>, Box<dyn Read + Send>)> {
+    let mut head = Vec::new();
+    (&mut reader).take(64).read_to_end(&mut head)?;
+    let rest = Box::new(std::io::Cursor::new(head.clone()).chain(reader));
+    Ok((head, rest))
+}
+
+/// Restore a custom archive or SQL script from a stream, removing
+/// compression and encryption layers on the way, with progress by bytes
+/// of input read.
+fn restore_stream(
+    name: &str,
+    params: &ConnectionParams,
+    profile: &Profile,
+    source: Source,
+    options: &RestoreOptions,
+) -> Result<()> {
+    let Source {
+        label,
+        reader,
+        total,
+        mut stages,
+    } = source;
+    if options.jobs > 1 {
+        kill_all(&mut stages);
+        return Err(anyhow::anyhow!(
+            "--jobs needs a plain local archive; {} is restored as a stream",
+            label
+        ));
+    }
+    let progress = Arc::new(Mutex::new(Progress::new("restore", total, Unit::Bytes)));
+    let mut reader: Box<dyn Read + Send> = Box::new(Counted {
+        inner: reader,
+        progress: Arc::clone(&progress),
+        read: 0,
+    });
+
+    let mut feeders = Vec::new();
+    let head = loop {
+        let (head, rest) = peek(reader)?;
+        let Some(layer) = codec::Layer::detect(&head) else {
+            reader = rest;
+            break head;
+        };
+        let spawned = layer
+            .decoder(options.identity.as_deref())
+            .and_then(|mut cmd| {
+                cmd.stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .spawn()
+                    .with_context(|| format!("Failed to run {}", layer.tool()))
+            });
+        let mut decoder = match spawned {
+            Ok(decoder) => decoder,
+            Err(err) => {
+                kill_all(&mut stages);
+                return Err(err);
+            }
+        };
+        let mut stdin = decoder.stdin.take().expect("stdin is piped");
+        feeders.push(thread::spawn(move || pump(rest, &mut stdin, |_| {})));
+        reader = Box::new(decoder.stdout.take().expect("stdout is piped"));
+        stages.push((
+            format!("{} failed {} {}", layer.tool(), layer.action(), label),
+            decoder,
+        ));
+    };
+    if head.is_empty() {
+        let failure = wait_all(&mut stages)?;
+        return Err(failure.unwrap_or_else(|| anyhow::anyhow!("{} is empty", label)));
+    }
+
+    let archive = head.starts_with(b"PGDMP");
+    let mut cmd = if archive {
+        let engine = params.engine.implementation();
+        let mut cmd = Command::new(client::ensure(engine, "pg_restore")?);
+        cmd.args(postgres::psql_args(params))
+            .arg("--no-password")
+            .envs(engine.client_env(params, profile))
+            .stdin(Stdio::piped());
+        if options.clean {
+            cmd.args(["--clean", "--if-exists"]);
+        }
+        if options.no_owner {
+            cmd.args(["--no-owner", "--no-privileges"]);
+        }
+        cmd
+    } else {
+        if options.clean || options.no_owner {
+            kill_all(&mut stages);
+            return Err(anyhow::anyhow!(
+                "--clean and --no-owner only apply to pg_dump archives, not SQL scripts"
+            ));
+        }
+        script_command(params, profile)?
+    };
+    let tool = if archive { "pg_restore" } else { "psql" };
+
+    eprintln!("Restoring {} into {}", label, name);
+    let mut child = match cmd
+        .spawn()
+        .with_context(|| format!("Failed to run {}", tool))
+    {
+        Ok(child) => child,
+        Err(err) => {
+            kill_all(&mut stages);
+            return Err(err);
+        }
+    };
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    pump(reader, &mut stdin, |_| {})?;
+    drop(stdin);
+    let restored = child.wait()?.success();
+    if !restored {
+        // Earlier stages may be blocked writing into closed pipes
+        kill_all(&mut stages);
+    }
+    let failure = wait_all(&mut stages)?;
+    for feeder in feeders {
+        let _ = feeder.join();
+    }
+    if !restored {
+        return Err(anyhow::anyhow!("{} failed restoring {}", tool, label));
+    }
+    if let Some(failure) = failure {
+        return Err(failure);
+    }
+    if let Ok(mut progress) = progress.lock() {
+        progress.finish();
+    }
+    eprintln!("Restored into {}", name);
+    Ok(())
+}
+
+fn kill_all(stages: &mut [(String, Child)]) {
+    for (_, child) in stages {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// Wait for every stage, returning the first failure.
+fn wait_all(stages: &mut [(String, Child)]) -> Result<Option<anyhow::Error>> {
+    let mut failure = None;
+    for (message, child) in stages {
+        if !child.wait()?.success() && failure.is_none() {
+            failure = Some(anyhow::anyhow!("{}", message));
+        }
+    }
+    Ok(failure)
+}
+
+pub struct CopyOptions {
+    /// Parallel workers; above 1 the dump goes through a temporary directory
+    pub jobs: usize,
+    pub schemas: Vec<String>,
+    pub exclude_tables: Vec<String>,
+    pub clean: bool,
+    pub no_owner: bool,
+    pub yes: bool,
+}
+
+/// Copy schema and data from `source` into `target`.
+pub fn copy(source: &Side, target: &Side, options: &CopyOptions) -> Result<()> {
+    ensure_postgres(source.name, source.params)?;
+    ensure_postgres(target.name, target.params)?;
+    ensure_writable(target.name, target.profile, options.yes)?;
+    let restore_options = RestoreOptions {
+        jobs: options.jobs,
+        clean: options.clean,
+        no_owner: options.no_owner,
+        yes: true,
+        identity: None,
+    };
+
+    if options.jobs > 1 {
+        let staging = env::temp_dir().join(format!("connect-db-copy-{}", std::process::id()));
+        let dump_options = DumpOptions {
+            output: Some(staging.clone()),
+            format: Format::Directory,
+            jobs: options.jobs,
+            schemas: options.schemas.clone(),
+            tables: Vec::new(),
+            exclude_tables: options.exclude_tables.clone(),
+            compression: None,
+            encryption: None,
+        };
+        let result =
+            dump(source.name, source.params, source.profile, &dump_options).and_then(|staged| {
+                restore(
+                    target.name,
+                    target.params,
+                    target.profile,
+                    &staged.path,
+                    &restore_options,
+                )
+            });
+        let _ = fs::remove_dir_all(&staging);
+        return result;
+    }
+
+    // Stream pg_dump straight into pg_restore, tracking progress on the dump side
+    let weights = table_sizes(source.params, source.profile)?;
+    let dump_options = DumpOptions {
+        output: None,
+        format: Format::Custom,
+        jobs: 1,
+        schemas: options.schemas.clone(),
+        tables: Vec::new(),
+        exclude_tables: options.exclude_tables.clone(),
+        compression: None,
+        encryption: None,
+    };
+    let mut dump_cmd = pg_dump_command(source.params, source.profile, &dump_options)?;
+    dump_cmd.args(["--format", "custom"]);
+    let engine = target.params.engine.implementation();
+    let pg_restore = client::ensure(engine, "pg_restore")?;
+    let mut restore_cmd = Command::new(pg_restore);
+    restore_cmd
+        .args(postgres::psql_args(target.params))
+        .arg("--no-password")
+        .envs(engine.client_env(target.params, target.profile));
+    if options.clean {
+        restore_cmd.args(["--clean", "--if-exists"]);
+    }
+    if options.no_owner {
+        restore_cmd.args(["--no-owner", "--no-privileges"]);
+    }
+    if dry_run::enabled() {
+        dry_run::stop(&format!(
+            "{} | {}",
+            dry_run::command_line(&dump_cmd),
+            dry_run::command_line(&restore_cmd)
+        ));
+    }
+
+    let mut dump_child = dump_cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run pg_dump")?;
+    let mut restore_child = restore_cmd
+        .stdin(dump_child.stdout.take().expect("stdout is piped"))
+        .spawn()
+        .context("Failed to run pg_restore")?;
+
+    eprintln!("Copying {} into {}", source.name, target.name);
+    let mut tracker = Tracker::new("pg_dump", "copy", weights, Unit::Bytes, false);
+    tracker.follow(&mut dump_child)?;
+    let dumped = dump_child.wait()?.success();
+    let restored = restore_child.wait()?.success();
+    if !dumped {
+        return Err(anyhow::anyhow!("pg_dump failed for {}", source.name));
+    }
+    if !restored {
+        return Err(anyhow::anyhow!("pg_restore failed for {}", target.name));
+    }
+    tracker.progress.finish();
+    eprintln!("Copied {} into {}", source.name, target.name);
+    Ok(())
+}
+
+/// Turns pg_dump/pg_restore `--verbose` output into progress.
+struct Tracker {
+    tool: &'static str,
+    weights: HashMap<String, u64>,
+    /// Tables whose data has started, in order
+    started: Vec<String>,
+    done: HashSet<String>,
+    /// Workers report finished items; a sequential run only reports starts
+    parallel: bool,
+    progress: Progress,
+}
+
+impl Tracker {
+    fn new(
+        tool: &'static str,
+        label: &str,
+        weights: HashMap<String, u64>,
+        unit: Unit,
+        parallel: bool,
+    ) -> Self {
+        let total = weights.values().sum();
+        Tracker {
+            tool,
+            weights,
+            started: Vec::new(),
+            done: HashSet::new(),
+            parallel,
+            progress: Progress::new(label, total, unit),
+        }
+    }
+
+    fn follow(&mut self, child: &mut Child) -> Result<()> {
+        let stderr = child.stderr.take().expect("stderr is piped");
+        for line in BufReader::new(stderr).lines() {
+            self.line(&line?);
+        }
+        Ok(())
+    }
+
+    fn line(&mut self, line: &str) {
+        let Some(message) = line.strip_prefix(&format!("{}: ", self.tool)) else {
+            self.progress.message(line);
+            return;
+        };
+        if message.starts_with("error:") || message.starts_with("warning:") {
+            self.progress.message(line);
+            return;
+        }
+
+        let started = message
+            .strip_prefix("dumping contents of table \"")
+            .or_else(|| message.strip_prefix("processing data for table \""))
+            .and_then(|rest| rest.strip_suffix('"'));
+        if let Some(table) = started {
+            if !self.parallel {
+                self.done.extend(self.started.drain(..));
+            }
+            self.started.push(table.to_string());
+        } else if let Some(rest) = message.strip_prefix("finished item ")
+            && let Some((_, tag)) = rest.split_once(" TABLE DATA ")
+        {
+            // Workers name the table without its schema
+            let tag = tag.rsplit(' ').next().unwrap_or(tag);
+            let suffix = format!(".{}", tag);
+            if let Some(table) = self
+                .started
+                .iter()
+                .find(|table| table.ends_with(&suffix) && !self.done.contains(*table))
+                .cloned()
+            {
+                self.done.insert(table);
+            }
+        } else {
+            return;
+        }
+
+        let done: u64 = self
+            .done
+            .iter()
+            .filter_map(|table| self.weights.get(table))
+            .sum();
+        let current = self.started.last().cloned().unwrap_or_default();
+        self.progress.set(done, &current);
+    }
+}
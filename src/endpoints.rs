@@ -0,0 +1,134 @@
+//! Regional endpoints: a profile can list the same database (read replicas,
+//! in practice) in several regions. connect-db measures the round trip to
+//! each and connects to the closest, keeping the others as fallbacks, unless
+//! `--region` picks one.
+
+use crate::{config::Profile, dns, dry_run, engines::ConnectionParams};
+use anyhow::Result;
+use std::{
+    net::{TcpStream, ToSocketAddrs},
+    sync::OnceLock,
+    thread,
+    time::{Duration, Instant},
+};
+
+static REGION: OnceLock<Option<String>> = OnceLock::new();
+
+pub fn set_cli_region(region: Option<String>) {
+    let _ = REGION.set(region);
+}
+
+/// Point `params` at one of the profile's endpoints. Round trips are only
+/// measured when `local`; behind a tunnel or proxy only `--region` applies.
+pub fn select(params: &mut ConnectionParams, profile: &Profile, local: bool) -> Result<()> {
+    if profile.endpoints.is_empty() {
+        return Ok(());
+    }
+    let endpoints: Vec<(String, String, String)> = profile
+        .endpoints
+        .iter()
+        .map(|(region, endpoint)| {
+            let (host, port) = split_endpoint(endpoint, &params.port);
+            (region.clone(), host, port)
+        })
+        .collect();
+
+    if let Some(region) = REGION.get().cloned().flatten() {
+        let Some((_, host, port)) = endpoints.iter().find(|(name, _, _)| *name == region) else {
+            return Err(anyhow::anyhow!(
+                "No endpoint for region '{}'; the profile has {}",
+                region,
+                profile
+                    .endpoints
+                    .keys()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        };
+        eprintln!("Using region {} ({}:{})", region, host, port);
+        use_endpoint(params, host, port, Vec::new());
+        return Ok(());
+    }
+    if !local {
+        dry_run::report("not measuring endpoint latency through a tunnel or proxy");
+        return Ok(());
+    }
+
+    let probes: Vec<_> = endpoints
+        .iter()
+        .map(|(_, host, port)| {
+            let address = dns::lookup(host, port, profile, true)
+                .ok()
+                .flatten()
+                .unwrap_or_else(|| host.clone());
+            let port = port.clone();
+            thread::spawn(move || round_trip(&address, &port))
+        })
+        .collect();
+    let mut measured: Vec<_> = endpoints
+        .into_iter()
+        .zip(probes)
+        .map(|(endpoint, probe)| (endpoint, probe.join().unwrap_or(None)))
+        .collect();
+    let summary: Vec<String> = measured
+        .iter()
+        .map(|((region, _, _), rtt)| match rtt {
+            Some(rtt) => format!("{} {:.1} ms", region, rtt.as_secs_f64() * 1000.0),
+            None => format!("{} unreachable", region),
+        })
+        .collect();
+    eprintln!("Endpoint latency: {}", summary.join(", "));
+    measured.sort_by_key(|(_, rtt)| rtt.unwrap_or(Duration::MAX));
+    let mut ranked = measured.into_iter().map(|(endpoint, _)| endpoint);
+    let Some((region, host, port)) = ranked.next() else {
+        return Ok(());
+    };
+    eprintln!("Using region {} ({}:{})", region, host, port);
+    let fallbacks = ranked.map(|(_, host, port)| (host, port)).collect();
+    use_endpoint(params, &host, &port, fallbacks);
+    Ok(())
+}
+
+/// `host[:port]`, with IPv6 addresses in brackets.
+fn split_endpoint(endpoint: &str, default_port: &str) -> (String, String) {
+    if let Some(rest) = endpoint.strip_prefix('[')
+        && let Some((host, port)) = rest.split_once(']')
+    {
+        let port = port.strip_prefix(':').unwrap_or(default_port);
+        return (host.to_string(), port.to_string());
+    }
+    match endpoint.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => (host.to_string(), port.to_string()),
+        _ => (endpoint.to_string(), default_port.to_string()),
+    }
+}
+
+fn use_endpoint(
+    params: &mut ConnectionParams,
+    host: &str,
+    port: &str,
+    fallbacks: Vec<(String, String)>,
+) {
+    // An address for the secret's host no longer applies
+    params.options.retain(|(key, _)| key != "hostaddr");
+    params.host = host.to_string();
+    params.port = port.to_string();
+    params.failover_hosts = fallbacks;
+}
+
+/// The quickest of three TCP handshakes with `host:port`.
+fn round_trip(host: &str, port: &str) -> Option<Duration> {
+    let address = match host.contains(':') {
+        true => format!("[{}]:{}", host, port),
+        false => format!("{}:{}", host, port),
+    };
+    let target = address.to_socket_addrs().ok()?.next()?;
+    (0..3)
+        .filter_map(|_| {
+            let started = Instant::now();
+            TcpStream::connect_timeout(&target, Duration::from_secs(2)).ok()?;
+            Some(started.elapsed())
+        })
+        .min()
+}
@@ -0,0 +1,103 @@
+use super::DatabaseEngine;
+use crate::error::ConnectDbError;
+use anyhow::Result;
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// How to install a client on one operating system.
+pub struct InstallHint {
+    /// Value of `std::env::consts::OS` the hint applies to
+    pub os: &'static str,
+    pub platform: &'static str,
+    pub command: &'static str,
+}
+
+/// Find `binary` on PATH (or use it as is when it contains a path separator).
+pub fn locate(binary: &str) -> Option<PathBuf> {
+    if binary.contains('/') {
+        let path = PathBuf::from(binary);
+        return is_executable(&path).then_some(path);
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(binary))
+        .find(|path| is_executable(path))
+}
+
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+/// First dotted number in the client's `--version` output, e.g. `16.2`.
+pub fn version(path: &Path) -> Option<Vec<u32>> {
+    let output = Command::new(path)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))
+        .map(parse_version)
+}
+
+fn parse_version(version: &str) -> Vec<u32> {
+    version
+        .split('.')
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+/// Make sure the client exists and is recent enough, explaining how to
+/// install it otherwise.
+pub fn ensure(engine: &dyn DatabaseEngine, binary: &str) -> Result<PathBuf> {
+    let Some(path) = locate(binary) else {
+        return Err(ConnectDbError::ClientMissing(format!(
+            "{} was not found on PATH.{}",
+            binary,
+            install_guidance(engine)
+        ))
+        .into());
+    };
+
+    if let Some(minimum) = engine.minimum_client_version()
+        && let Some(found) = version(&path)
+        && found < parse_version(minimum)
+    {
+        let found = found
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(".");
+        return Err(ConnectDbError::ClientMissing(format!(
+            "{} {} is too old; version {} or newer is required.{}",
+            binary,
+            found,
+            minimum,
+            install_guidance(engine)
+        ))
+        .into());
+    }
+    Ok(path)
+}
+
+fn install_guidance(engine: &dyn DatabaseEngine) -> String {
+    let hints: Vec<&InstallHint> = engine
+        .install_hints()
+        .iter()
+        .filter(|hint| hint.os == env::consts::OS)
+        .collect();
+    if hints.is_empty() {
+        return String::new();
+    }
+    let mut guidance = String::from("\nInstall it with:");
+    for hint in hints {
+        guidance.push_str(&format!("\n  {:<14} {}", hint.platform, hint.command));
+    }
+    guidance
+}
@@ -0,0 +1,74 @@
+//! Private files handing credentials to clients that would otherwise take
+//! them as arguments, where anyone running `ps` could read them.
+//!
+//! Files live in a 0700 directory under the data dir, are 0600 themselves and
+//! are named after the process that wrote them. A session replaces this
+//! process with the client, so nothing is left to delete its own file;
+//! instead the files of exited processes are removed on the next write.
+
+use super::ConnectionParams;
+use crate::{config, sha256::Sha256};
+use anyhow::{Context, Result};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Where this process keeps the file with `extension` for the login in
+/// `params`, so parallel runs against other servers don't share one.
+pub fn path(params: &ConnectionParams, extension: &str) -> Result<PathBuf> {
+    let mut hasher = Sha256::default();
+    hasher.update(format!("{}@{}:{}", params.username, params.host, params.port).as_bytes());
+    let login = hasher.finish();
+    Ok(config::data_dir()?.join("clients").join(format!(
+        "{}-{}.{}",
+        std::process::id(),
+        &login[..12],
+        extension
+    )))
+}
+
+/// Write `contents` to `path` (from [`path`]) with mode 0600.
+pub fn write(path: &Path, contents: &str) -> Result<()> {
+    let dir = path.parent().expect("client files have a directory");
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    remove_stale(dir);
+
+    // Write and rename so a client starting in parallel never reads half a file
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+    let temporary = path.with_extension(format!("tmp{}", WRITES.fetch_add(1, Ordering::Relaxed)));
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&temporary)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .with_context(|| format!("Failed to write {}", temporary.display()))?;
+    fs::rename(&temporary, path).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Remove the files of processes that have exited.
+fn remove_stale(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let pid = name
+            .to_str()
+            .and_then(|name| name.split_once('-'))
+            .and_then(|(pid, _)| pid.parse::<libc::pid_t>().ok());
+        if let Some(pid) = pid
+            && unsafe { libc::kill(pid, 0) } != 0
+            && io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH)
+        {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use super::{ConnectionParams, client};
+use crate::config::Profile;
+use anyhow::{Context, Result};
+use std::{
+    env,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// Run the engine's client with `docker run` instead of a local install.
+///
+/// Credentials reach the container as `-e NAME` pass-through variables so
+/// their values stay out of the docker command line; the only mounts are
+/// read-only client files such as cqlsh's private cqlshrc.
+pub fn connect(params: &ConnectionParams, profile: &Profile, image: Option<&str>) -> Result<()> {
+    let engine = params.engine.implementation();
+    let image = match image {
+        Some(image) => image.to_string(),
+        None => engine
+            .client_image(params)
+            .context("No client container image is known for this engine; pass one with --client-container=<image>")?,
+    };
+    if client::locate("docker").is_none() {
+        return Err(anyhow::anyhow!("docker was not found on PATH"));
+    }
+
+    let binary = engine.client_binary(params);
+    println!("{}", engine.banner(params));
+    println!("Running {} in {}", binary, image);
+
+    let mut cmd = exec::Command::new("docker");
+    cmd.args(&["run", "-it", "--rm"]);
+    // A loopback host (e.g. an SSH tunnel) is only reachable from the host network
+    if matches!(params.host.as_str(), "127.0.0.1" | "localhost" | "::1") {
+        cmd.args(&["--network", "host"]);
+    }
+    for (key, value) in engine.client_env(params, profile) {
+        cmd.args(&["-e", &key]);
+        unsafe {
+            env::set_var(key, value);
+        }
+    }
+    let args = engine.client_args(params, profile)?;
+    engine.write_client_files(params)?;
+    for file in engine.client_files(params) {
+        cmd.args(&["-v", &format!("{0}:{0}:ro", file.display())]);
+    }
+    cmd.arg(&image).arg(&binary);
+    cmd.args(&args);
+
+    crate::session_limit::hand_over();
+    let err = cmd.exec();
+    Err(anyhow::anyhow!("Failed to exec docker: {}", err))
+}
+
+/// Where `shell` runs the client.
+pub enum ExecTarget {
+    /// `[namespace/]pod`, via `kubectl exec`
+    Pod(String),
+    /// Running container name or id, via `docker exec`
+    Container(String),
+}
+
+/// Run the engine's client inside an existing pod or container, for
+/// databases only reachable from the cluster network.
+pub fn exec_in(params: &ConnectionParams, profile: &Profile, target: &ExecTarget) -> Result<()> {
+    let engine = params.engine.implementation();
+    let binary = engine.client_binary(params);
+    let vars = engine.client_env(params, profile);
+    if !engine.client_files(params).is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} reads its credentials from a local file, which can't reach an existing pod or container; use --client-container instead",
+            binary
+        ));
+    }
+    let args = engine.client_args(params, profile)?;
+    println!("{}", engine.banner(params));
+
+    match target {
+        ExecTarget::Container(name) => {
+            println!("Running {} in container {}", binary, name);
+            let mut cmd = exec::Command::new("docker");
+            cmd.args(&["exec", "-it"]);
+            for (key, value) in vars {
+                cmd.args(&["-e", &key]);
+                unsafe {
+                    env::set_var(key, value);
+                }
+            }
+            cmd.arg(name).arg(&binary).args(&args);
+
+            crate::session_limit::hand_over();
+            let err = cmd.exec();
+            Err(anyhow::anyhow!("Failed to exec docker: {}", err))
+        }
+        ExecTarget::Pod(pod) => {
+            println!("Running {} in pod {}", binary, pod);
+            let kubectl_target = match pod.split_once('/') {
+                Some((namespace, pod)) => {
+                    vec!["-n".to_string(), namespace.to_string(), pod.to_string()]
+                }
+                None => vec![pod.clone()],
+            };
+
+            // kubectl exec can't pass environment variables, so stage them in
+            // a private file the interactive shell sources and deletes
+            let env_file = format!("/tmp/.connect-db-{}", std::process::id());
+            if !vars.is_empty() {
+                stage_env_file(&kubectl_target, &env_file, &vars)?;
+            }
+            let script = if vars.is_empty() {
+                "exec \"$0\" \"$@\"".to_string()
+            } else {
+                format!(". {0} && rm -f {0} && exec \"$0\" \"$@\"", env_file)
+            };
+
+            let mut cmd = exec::Command::new("kubectl");
+            cmd.args(&["exec", "-it"])
+                .args(&kubectl_target)
+                .args(&["--", "sh", "-c", &script, &binary])
+                .args(&args);
+
+            crate::session_limit::hand_over();
+            let err = cmd.exec();
+            Err(anyhow::anyhow!("Failed to exec kubectl: {}", err))
+        }
+    }
+}
+
+fn stage_env_file(kubectl_target: &[String], path: &str, vars: &[(String, String)]) -> Result<()> {
+    let contents: String = vars
+        .iter()
+        .map(|(key, value)| format!("export {}='{}'\n", key, value.replace('\'', "'\\''")))
+        .collect();
+    let mut child = Command::new("kubectl")
+        .args(["exec", "-i"])
+        .args(kubectl_target)
+        .args(["--", "sh", "-c", &format!("umask 077 && cat > {}", path)])
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("Failed to run kubectl")?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(contents.as_bytes())?;
+    if !child.wait()?.success() {
+        return Err(anyhow::anyhow!("Failed to pass credentials to the pod"));
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use super::{ConnectionParams, DatabaseEngine, Engine, InstallHint};
+use crate::{config, config::Profile, url};
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+
+/// SQLite and DuckDB: a file on disk opened with the engine's shell.
+pub struct LocalFile {
+    engine: Engine,
+    binary: &'static str,
+    schemes: &'static [&'static str],
+    install_hints: &'static [InstallHint],
+}
+
+pub const SQLITE: LocalFile = LocalFile {
+    engine: Engine::Sqlite,
+    binary: "sqlite3",
+    schemes: &["sqlite"],
+    install_hints: &[
+        InstallHint {
+            os: "macos",
+            platform: "Homebrew",
+            command: "brew install sqlite",
+        },
+        InstallHint {
+            os: "linux",
+            platform: "Debian/Ubuntu",
+            command: "sudo apt install sqlite3",
+        },
+        InstallHint {
+            os: "linux",
+            platform: "Fedora/RHEL",
+            command: "sudo dnf install sqlite",
+        },
+    ],
+};
+
+pub const DUCKDB: LocalFile = LocalFile {
+    engine: Engine::DuckDb,
+    binary: "duckdb",
+    schemes: &["duckdb"],
+    install_hints: &[
+        InstallHint {
+            os: "macos",
+            platform: "Homebrew",
+            command: "brew install duckdb",
+        },
+        InstallHint {
+            os: "linux",
+            platform: "Installer",
+            command: "curl https://install.duckdb.org | sh",
+        },
+    ],
+};
+
+impl DatabaseEngine for LocalFile {
+    fn schemes(&self) -> &'static [&'static str] {
+        self.schemes
+    }
+
+    fn default_port(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn client_binary(&self, _params: &ConnectionParams) -> String {
+        self.binary.to_string()
+    }
+
+    fn install_hints(&self) -> &'static [InstallHint] {
+        self.install_hints
+    }
+
+    fn parse_url(&self, db_url: &str) -> Result<ConnectionParams> {
+        // Parse URL like: sqlite:///absolute/path.db, duckdb://relative.duckdb or duckdb://$DATA_DIR/x.duckdb
+        let (_, rest) = db_url
+            .split_once("://")
+            .with_context(|| format!("Invalid URL format: {}", db_url))?;
+        let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+        if path.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Invalid URL: missing file path: {}",
+                db_url
+            ));
+        }
+        let options = url::split_url(&format!("file://?{}", query))?.query;
+
+        Ok(ConnectionParams {
+            engine: self.engine,
+            host: String::new(),
+            port: String::new(),
+            username: String::new(),
+            password: String::new(),
+            database: config::expand_env(&url::decode(path))?,
+            options,
+            failover_hosts: Vec::new(),
+            extra: BTreeMap::new(),
+        })
+    }
+
+    fn banner(&self, params: &ConnectionParams) -> String {
+        format!("Opening {} with {}", params.database, self.binary)
+    }
+
+    fn client_args(&self, params: &ConnectionParams, _profile: &Profile) -> Result<Vec<String>> {
+        let mut args = Vec::new();
+        if params.flag("readonly") {
+            args.push("-readonly".to_string());
+        }
+        args.push(params.database.clone());
+        Ok(args)
+    }
+
+    fn client_env(&self, _params: &ConnectionParams, _profile: &Profile) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    fn health_check_query(&self) -> Option<&'static str> {
+        match self.engine {
+            Engine::Sqlite => Some("select sqlite_version()"),
+            _ => Some("select version()"),
+        }
+    }
+
+    fn query_args(
+        &self,
+        params: &ConnectionParams,
+        profile: &Profile,
+        sql: &str,
+    ) -> Option<Vec<String>> {
+        let mut args = self.client_args(params, profile).ok()?;
+        args.insert(0, "-noheader".to_string());
+        args.insert(0, "-list".to_string());
+        args.push(sql.to_string());
+        Some(args)
+    }
+
+    fn csv_query_args(
+        &self,
+        params: &ConnectionParams,
+        profile: &Profile,
+        sql: &str,
+    ) -> Option<Vec<String>> {
+        let mut args = self.client_args(params, profile).ok()?;
+        args.insert(0, "-header".to_string());
+        args.insert(0, "-csv".to_string());
+        args.push(sql.to_string());
+        Some(args)
+    }
+}
@@ -0,0 +1,755 @@
+//! Engine-specific URL parsing and client invocation.
+//!
+//! Each supported database lives in its own module implementing
+//! [`DatabaseEngine`]; [`Engine`] names them in profiles and picks the
+//! implementation for a URL scheme.
+
+mod cassandra;
+mod clickhouse;
+pub mod client;
+mod client_file;
+pub mod cockroach;
+pub mod container;
+mod local_file;
+mod oracle;
+pub mod postgres;
+pub mod redshift;
+mod snowflake;
+mod trino;
+
+pub use client::InstallHint;
+
+use crate::{
+    config::Profile,
+    conninfo, csv, dry_run,
+    error::{self, ConnectDbError},
+    http, progress,
+    sql_params::{self, Binding},
+    telemetry, url, usage,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env,
+    io::{self, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    process::{Child, ChildStdout, Command, ExitStatus, Stdio},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    Postgres,
+    Snowflake,
+    ClickHouse,
+    Cassandra,
+    Sqlite,
+    DuckDb,
+    Oracle,
+    Trino,
+    Redshift,
+    CockroachDb,
+}
+
+impl Engine {
+    const ALL: [Engine; 10] = [
+        Engine::Postgres,
+        Engine::Snowflake,
+        Engine::ClickHouse,
+        Engine::Cassandra,
+        Engine::Sqlite,
+        Engine::DuckDb,
+        Engine::Oracle,
+        Engine::Trino,
+        Engine::Redshift,
+        Engine::CockroachDb,
+    ];
+
+    pub fn implementation(self) -> &'static dyn DatabaseEngine {
+        match self {
+            Engine::Postgres => &postgres::Postgres,
+            Engine::Snowflake => &snowflake::Snowflake,
+            Engine::ClickHouse => &clickhouse::ClickHouse,
+            Engine::Cassandra => &cassandra::Cassandra,
+            Engine::Sqlite => &local_file::SQLITE,
+            Engine::DuckDb => &local_file::DUCKDB,
+            Engine::Oracle => &oracle::Oracle,
+            Engine::Trino => &trino::Trino,
+            Engine::Redshift => &redshift::Redshift,
+            Engine::CockroachDb => &cockroach::CockroachDb,
+        }
+    }
+
+    fn for_scheme(scheme: &str) -> Option<Engine> {
+        Engine::ALL
+            .into_iter()
+            .find(|engine| engine.implementation().schemes().contains(&scheme))
+    }
+}
+
+/// Everything the tool knows about one database engine.
+pub trait DatabaseEngine: Sync {
+    /// URL schemes handled by the engine, e.g. `["postgresql", "postgres"]`
+    fn schemes(&self) -> &'static [&'static str];
+
+    /// Port used when the URL does not name one
+    fn default_port(&self) -> Option<&'static str>;
+
+    /// Client executable to launch for these parameters
+    fn client_binary(&self, params: &ConnectionParams) -> String;
+
+    /// Oldest client release known to work, compared against `--version`
+    fn minimum_client_version(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Container image shipping the client, for `--client-container`
+    fn client_image(&self, _params: &ConnectionParams) -> Option<String> {
+        None
+    }
+
+    /// Per-OS instructions printed when the client is missing
+    fn install_hints(&self) -> &'static [InstallHint] {
+        &[]
+    }
+
+    fn parse_url(&self, db_url: &str) -> Result<ConnectionParams>;
+
+    /// Line printed before handing over to the client
+    fn banner(&self, params: &ConnectionParams) -> String;
+
+    /// Arguments for an interactive client session
+    fn client_args(&self, params: &ConnectionParams, profile: &Profile) -> Result<Vec<String>>;
+
+    /// Environment for the client (passwords, TLS settings, ...)
+    fn client_env(&self, params: &ConnectionParams, profile: &Profile) -> Vec<(String, String)>;
+
+    /// Local files the client arguments point at, mounted into `docker run`
+    fn client_files(&self, _params: &ConnectionParams) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    /// Write the [`client_files`](Self::client_files), right before a client
+    /// is started, so printing or dry-running a command leaves nothing behind
+    fn write_client_files(&self, _params: &ConnectionParams) -> Result<()> {
+        Ok(())
+    }
+
+    /// Cheap query proving the connection works and identifying the server
+    fn health_check_query(&self) -> Option<&'static str>;
+
+    /// Arguments to run `sql` non-interactively, if the client supports it
+    fn query_args(
+        &self,
+        params: &ConnectionParams,
+        profile: &Profile,
+        sql: &str,
+    ) -> Option<Vec<String>>;
+
+    /// Arguments to run `sql` printing CSV with a header row
+    fn csv_query_args(
+        &self,
+        _params: &ConnectionParams,
+        _profile: &Profile,
+        _sql: &str,
+    ) -> Option<Vec<String>> {
+        None
+    }
+
+    /// Arguments printing CSV for a script read from stdin, and the script:
+    /// `sql` with its placeholders bound by the client rather than inlined.
+    /// Preferred over `csv_query_args` when available
+    fn csv_script_query(
+        &self,
+        _params: &ConnectionParams,
+        _profile: &Profile,
+        _sql: &str,
+        _bindings: &[Binding],
+    ) -> Option<(Vec<String>, String)> {
+        None
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ConnectionParams {
+    pub engine: Engine,
+    pub host: String,
+    pub port: String,
+    pub username: String,
+    pub password: String,
+    pub database: String,
+    /// Query parameters from the URL
+    pub options: Vec<(String, String)>,
+    /// Further `(host, port)` pairs of a multi-host URL, in failover order
+    pub failover_hosts: Vec<(String, String)>,
+    /// Extra secret fields from the credentials file
+    pub extra: BTreeMap<String, String>,
+}
+
+impl ConnectionParams {
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether a boolean URL option is set to something other than false/0.
+    pub fn flag(&self, name: &str) -> bool {
+        self.option(name)
+            .is_some_and(|value| value != "false" && value != "0")
+    }
+
+    /// Fill in a database the URL didn't name: the profile's
+    /// `default_database`, or the one the engine's client would pick.
+    pub fn fill_default_database(&mut self, profile_default: Option<&str>) {
+        if !self.database.is_empty() {
+            return;
+        }
+        self.database = match (profile_default, self.engine) {
+            (Some(database), _) => database.to_string(),
+            // libpq's default is a database named after the user
+            (None, Engine::Postgres) if !self.username.is_empty() => self.username.clone(),
+            (None, Engine::Postgres) => "postgres".to_string(),
+            (None, Engine::CockroachDb) => "defaultdb".to_string(),
+            (None, Engine::Redshift) => "dev".to_string(),
+            (None, Engine::ClickHouse) => "default".to_string(),
+            (None, _) => return,
+        };
+    }
+
+    /// Switch to the first reachable host of a multi-host URL. When none
+    /// answers, the first one is kept so the client reports the failure.
+    pub fn select_reachable_host(&mut self) {
+        // A `hostaddr` from DNS settings is what the client dials
+        let primary = self.option("hostaddr").unwrap_or(&self.host);
+        if self.failover_hosts.is_empty() || is_reachable(&format!("{}:{}", primary, self.port)) {
+            return;
+        }
+        let Some(index) = self
+            .failover_hosts
+            .iter()
+            .position(|(host, port)| is_reachable(&format!("{}:{}", host, port)))
+        else {
+            return;
+        };
+        let (host, port) = self.failover_hosts.remove(index);
+        eprintln!(
+            "{}:{} is unreachable; failing over to {}:{}",
+            self.host, self.port, host, port
+        );
+        self.options.retain(|(key, _)| key != "hostaddr");
+        // The others stay behind it as fallbacks
+        let primary = (
+            std::mem::replace(&mut self.host, host),
+            std::mem::replace(&mut self.port, port),
+        );
+        self.failover_hosts.insert(0, primary);
+    }
+
+    /// Format the parameters back into a URL, optionally masking the password.
+    pub fn to_url(&self, redact: bool) -> String {
+        let scheme = self.engine.implementation().schemes()[0];
+        let mut url = format!("{}://", scheme);
+        if !self.username.is_empty() {
+            url.push_str(&http::url_encode(&self.username));
+            if !self.password.is_empty() {
+                url.push(':');
+                if redact {
+                    url.push_str("****");
+                } else {
+                    url.push_str(&http::url_encode(&self.password));
+                }
+            }
+            url.push('@');
+        }
+        let hosts = std::iter::once((&self.host, &self.port))
+            .chain(self.failover_hosts.iter().map(|(host, port)| (host, port)));
+        for (index, (host, port)) in hosts.enumerate() {
+            if index > 0 {
+                url.push(',');
+            }
+            if host.contains(':') {
+                url.push_str(&format!("[{}]", host));
+            } else {
+                url.push_str(host);
+            }
+            if !port.is_empty() {
+                url.push_str(&format!(":{}", port));
+            }
+        }
+        // Local file engines keep the file path right after the scheme
+        if !self.host.is_empty() && !self.database.is_empty() {
+            url.push('/');
+        }
+        url.push_str(&self.database);
+        if !self.options.is_empty() {
+            let query: Vec<(&str, &str)> = self
+                .options
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+                .collect();
+            url.push('?');
+            url.push_str(&http::form(&query));
+        }
+        url
+    }
+
+    /// Build parameters from generic URL parts, applying the engine's default port.
+    fn from_url_parts(engine: Engine, parts: url::UrlParts) -> Self {
+        let default_port = engine.implementation().default_port().unwrap_or_default();
+        ConnectionParams {
+            engine,
+            host: parts.host,
+            port: parts.port.unwrap_or_else(|| default_port.to_string()),
+            username: parts.username.unwrap_or_default(),
+            password: parts.password.unwrap_or_default(),
+            database: parts.path,
+            options: parts.query,
+            failover_hosts: Vec::new(),
+            extra: BTreeMap::new(),
+        }
+    }
+}
+
+/// Parse a connection URL for any supported engine, based on its scheme.
+pub fn parse_url(db_url: &str) -> Result<ConnectionParams> {
+    parse_any_url(db_url).map_err(|err| ConnectDbError::UrlParse(format!("{:#}", err)).into())
+}
+
+fn parse_any_url(db_url: &str) -> Result<ConnectionParams> {
+    if conninfo::is_keyword_string(db_url) {
+        return conninfo::parse_keyword_string(db_url);
+    }
+    if conninfo::is_conninfo(db_url) {
+        return conninfo::parse_conninfo(db_url);
+    }
+    let engine = url::scheme(db_url)
+        .and_then(Engine::for_scheme)
+        .unwrap_or(Engine::Postgres);
+    engine.implementation().parse_url(db_url)
+}
+
+/// Connection parameters given on the command line, layered on top of what
+/// the secret backend resolved.
+#[derive(Debug, Default)]
+pub struct Overrides {
+    pub host: Option<String>,
+    pub port: Option<String>,
+    pub username: Option<String>,
+    pub database: Option<String>,
+    pub sslmode: Option<String>,
+}
+
+impl Overrides {
+    pub fn apply(&self, params: &mut ConnectionParams) {
+        let fields = [
+            (&self.host, &mut params.host),
+            (&self.port, &mut params.port),
+            (&self.username, &mut params.username),
+            (&self.database, &mut params.database),
+        ];
+        for (value, field) in fields {
+            if let Some(value) = value {
+                *field = value.clone();
+            }
+        }
+        if let Some(sslmode) = &self.sslmode {
+            params.options.retain(|(key, _)| key != "sslmode");
+            params
+                .options
+                .push(("sslmode".to_string(), sslmode.clone()));
+        }
+    }
+}
+
+/// Print the client command `connect` would run, with secrets masked.
+pub fn print_command(params: &ConnectionParams, profile: &Profile) -> Result<()> {
+    println!("{}", command_line(params, profile)?);
+    Ok(())
+}
+
+/// The client command `connect` would run, with secrets masked.
+pub fn command_line(params: &ConnectionParams, profile: &Profile) -> Result<String> {
+    let args = params
+        .engine
+        .implementation()
+        .client_args(params, profile)?;
+    Ok(redacted_command(params, profile, &args))
+}
+
+/// `args` for the engine's client as a shell command line, with its
+/// environment, and with the password and secret-looking variables masked.
+fn redacted_command(params: &ConnectionParams, profile: &Profile, args: &[String]) -> String {
+    let engine = params.engine.implementation();
+    let mask = |value: &str| {
+        if params.password.is_empty() {
+            value.to_string()
+        } else {
+            value.replace(&params.password, "****")
+        }
+    };
+    let mut words = Vec::new();
+    for (key, value) in engine.client_env(params, profile) {
+        let upper = key.to_uppercase();
+        let value = if ["PASSWORD", "PWD", "SECRET", "TOKEN"]
+            .iter()
+            .any(|word| upper.contains(word))
+        {
+            "****".to_string()
+        } else {
+            mask(&value)
+        };
+        words.push(format!("{}={}", key, shell_quote(&value)));
+    }
+    words.push(shell_quote(&engine.client_binary(params)));
+    for arg in args {
+        words.push(shell_quote(&mask(arg)));
+    }
+    words.join(" ")
+}
+
+/// Quote `value` for a POSIX shell when it needs it.
+pub fn shell_quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:@,+%*".contains(c));
+    if plain {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+/// Replace the current process with the engine's interactive client.
+pub fn connect(params: &ConnectionParams, profile: &Profile) -> Result<()> {
+    let engine = params.engine.implementation();
+    let binary = engine.client_binary(params);
+    let span = telemetry::span("connect");
+    span.attribute("db.system", format!("{:?}", params.engine).to_lowercase());
+    span.attribute("client", &binary);
+    let client = span.record(client::ensure(engine, &binary))?;
+    println!("{}", engine.banner(params));
+
+    let mut cmd = exec::Command::new(&client);
+    cmd.args(&engine.client_args(params, profile)?);
+    engine.write_client_files(params)?;
+    for (key, value) in engine.client_env(params, profile) {
+        unsafe {
+            env::set_var(key, value);
+        }
+    }
+
+    // Spans can't be sent once the client has replaced this process
+    drop(span);
+    telemetry::flush();
+    usage::finish(true, 0);
+
+    // This will replace the current process with the client
+    // If successful, this function will never return
+    crate::session_limit::hand_over();
+    let err = cmd.exec();
+
+    // If we reach this point, exec failed
+    Err(anyhow::anyhow!("Failed to exec {}: {}", binary, err))
+}
+
+/// Start the engine's interactive client as a child process, for sessions
+/// that need something done when they end.
+pub fn spawn_session(params: &ConnectionParams, profile: &Profile) -> Result<Child> {
+    let binary = params.engine.implementation().client_binary(params);
+    session_command(params, profile)?
+        .spawn()
+        .with_context(|| format!("Failed to run {}", binary))
+}
+
+/// The client command of an interactive session, after printing its banner.
+pub fn session_command(params: &ConnectionParams, profile: &Profile) -> Result<Command> {
+    let engine = params.engine.implementation();
+    let client = client::ensure(engine, &engine.client_binary(params))?;
+    println!("{}", engine.banner(params));
+    engine.write_client_files(params)?;
+    let mut command = Command::new(&client);
+    command
+        .args(engine.client_args(params, profile)?)
+        .envs(engine.client_env(params, profile));
+    Ok(command)
+}
+
+/// Wait for a session from [`spawn_session`], leaving Ctrl-C to the client.
+pub fn wait_session(child: &mut Child) -> Result<ExitStatus> {
+    wait_session_then(child, || {})
+}
+
+/// Like [`wait_session`], calling `exited` once the client has exited but
+/// before it is reaped, so anything still signalling its pid can't hit a
+/// process that reused it.
+pub fn wait_session_then(child: &mut Child, exited: impl FnOnce()) -> Result<ExitStatus> {
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_IGN);
+    }
+    let pid = child.id() as libc::id_t;
+    let waited = loop {
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let result =
+            unsafe { libc::waitid(libc::P_PID, pid, &mut info, libc::WEXITED | libc::WNOWAIT) };
+        let err = io::Error::last_os_error();
+        if result == 0 || err.kind() != io::ErrorKind::Interrupted {
+            break if result == 0 { Ok(()) } else { Err(err) };
+        }
+    };
+    exited();
+    let status = waited.and_then(|()| child.wait());
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+    Ok(status?)
+}
+
+/// Run `sql` through the engine's client and return its trimmed output.
+pub fn run_query(params: &ConnectionParams, profile: &Profile, sql: &str) -> Result<String> {
+    let engine = params.engine.implementation();
+    let args = engine
+        .query_args(params, profile, sql)
+        .context("This engine's client cannot run queries non-interactively")?;
+    Ok(run_client(params, profile, args)?.trim().to_string())
+}
+
+/// Rows of a query result under their column names.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct ResultSet {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    /// More rows followed the limit and were not read
+    pub truncated: bool,
+}
+
+/// Run `sql` and parse the client's CSV output into columns and rows,
+/// reading at most `limit` rows before stopping the client.
+pub fn run_result_query(
+    params: &ConnectionParams,
+    profile: &Profile,
+    sql: &str,
+    bindings: &[Binding],
+    limit: Option<usize>,
+) -> Result<ResultSet> {
+    let mut stream = stream_result_query(params, profile, sql, bindings)?;
+    let result = read_rows(&mut stream, limit)?;
+    stream.finish(result.truncated)?;
+    Ok(result)
+}
+
+/// [`run_result_query`], stopping the client once `timeout` has passed.
+pub fn run_result_query_within(
+    params: &ConnectionParams,
+    profile: &Profile,
+    sql: &str,
+    bindings: &[Binding],
+    limit: Option<usize>,
+    timeout: Duration,
+) -> Result<ResultSet> {
+    let child = spawn_result_query(params, profile, sql, bindings)?;
+    // The child is only reaped after the watchdog is done, so its pid can't
+    // be reused while the watchdog may still signal it
+    let pid = child.id() as libc::pid_t;
+    let (done, finished) = mpsc::channel::<()>();
+    let watchdog = thread::spawn(move || {
+        let expired = finished.recv_timeout(timeout) == Err(mpsc::RecvTimeoutError::Timeout);
+        if expired {
+            unsafe { libc::kill(pid, libc::SIGKILL) };
+        }
+        expired
+    });
+    let outcome = read_columns(child).and_then(|mut stream| {
+        let result = read_rows(&mut stream, limit)?;
+        Ok((stream, result))
+    });
+    drop(done);
+    if watchdog.join().unwrap_or(false) {
+        if let Ok((stream, _)) = outcome {
+            let _ = stream.finish(true);
+        }
+        return Err(anyhow::anyhow!(
+            "The query was stopped after {}",
+            progress::format_duration(timeout.as_secs())
+        ));
+    }
+    let (stream, result) = outcome?;
+    stream.finish(result.truncated)?;
+    Ok(result)
+}
+
+fn read_rows(stream: &mut ResultStream, limit: Option<usize>) -> Result<ResultSet> {
+    let mut result = ResultSet {
+        columns: stream.columns.clone(),
+        ..ResultSet::default()
+    };
+    for row in stream.by_ref() {
+        if limit.is_some_and(|limit| result.rows.len() == limit) {
+            result.truncated = true;
+            break;
+        }
+        result.rows.push(row?);
+    }
+    Ok(result)
+}
+
+/// Rows of a running query, read from the client as they arrive.
+pub struct ResultStream {
+    pub columns: Vec<String>,
+    child: Child,
+    records: csv::Reader<BufReader<ChildStdout>>,
+    errors: thread::JoinHandle<String>,
+}
+
+impl Iterator for ResultStream {
+    type Item = io::Result<Vec<String>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records.next()
+    }
+}
+
+impl ResultStream {
+    /// Wait for the client, or stop it when the rest is `abandoned`, and
+    /// fail with its error output if the query failed.
+    pub fn finish(mut self, abandoned: bool) -> Result<()> {
+        if abandoned {
+            let _ = self.child.kill();
+        }
+        let status = self.child.wait()?;
+        let errors = self.errors.join().unwrap_or_default();
+        if !status.success() && !abandoned {
+            return Err(error::client_error(errors.trim()));
+        }
+        Ok(())
+    }
+}
+
+/// Start `sql` and read its column names; the rows follow from the stream.
+///
+/// Placeholders are bound by the client where it supports that, and
+/// replaced with literals otherwise.
+pub fn stream_result_query(
+    params: &ConnectionParams,
+    profile: &Profile,
+    sql: &str,
+    bindings: &[Binding],
+) -> Result<ResultStream> {
+    read_columns(spawn_result_query(params, profile, sql, bindings)?)
+}
+
+fn spawn_result_query(
+    params: &ConnectionParams,
+    profile: &Profile,
+    sql: &str,
+    bindings: &[Binding],
+) -> Result<Child> {
+    sql_params::check(sql, bindings)?;
+    let engine = params.engine.implementation();
+    match engine.csv_script_query(params, profile, sql, bindings) {
+        Some((args, script)) => spawn_client(params, profile, args, Some(script)),
+        None => {
+            let sql = sql_params::substitute(sql, bindings, sql_params::literal);
+            let args = engine
+                .csv_query_args(params, profile, &sql)
+                .context("This engine's client cannot print query results as CSV")?;
+            spawn_client(params, profile, args, None)
+        }
+    }
+}
+
+fn read_columns(mut child: Child) -> Result<ResultStream> {
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let errors = thread::spawn(move || {
+        let mut errors = String::new();
+        let _ = stderr.read_to_string(&mut errors);
+        errors
+    });
+    let mut records = csv::Reader::new(BufReader::new(
+        child.stdout.take().expect("stdout is piped"),
+    ));
+    let columns = match records.next().transpose() {
+        Ok(columns) => columns.unwrap_or_default(),
+        Err(err) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(err.into());
+        }
+    };
+    Ok(ResultStream {
+        columns,
+        child,
+        records,
+        errors,
+    })
+}
+
+fn spawn_client(
+    params: &ConnectionParams,
+    profile: &Profile,
+    args: Vec<String>,
+    input: Option<String>,
+) -> Result<Child> {
+    let engine = params.engine.implementation();
+    let binary = engine.client_binary(params);
+    if dry_run::enabled() {
+        let mut command = redacted_command(params, profile, &args);
+        if let Some(input) = &input {
+            command.push_str(&format!(" <<'EOF'\n{}\nEOF", input.trim_end()));
+        }
+        dry_run::stop(&command);
+    }
+    let client = client::ensure(engine, &binary)?;
+    engine.write_client_files(params)?;
+
+    let mut child = Command::new(&client)
+        .args(args)
+        .envs(engine.client_env(params, profile))
+        // Don't let libpq-based clients hang on an unreachable host
+        .env("PGCONNECT_TIMEOUT", "5")
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", binary))?;
+    if let Some(input) = input {
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        // Written from a thread so a large result can't block the script
+        thread::spawn(move || stdin.write_all(input.as_bytes()));
+    }
+    Ok(child)
+}
+
+fn run_client(params: &ConnectionParams, profile: &Profile, args: Vec<String>) -> Result<String> {
+    let binary = params.engine.implementation().client_binary(params);
+    let output = spawn_client(params, profile, args, None)?
+        .wait_with_output()
+        .with_context(|| format!("Failed to run {}", binary))?;
+    if !output.status.success() {
+        return Err(error::client_error(
+            String::from_utf8_lossy(&output.stderr).trim(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Quick TCP probe of a `host:port` address.
+pub fn is_reachable(address: &str) -> bool {
+    let Ok(addrs) = address.to_socket_addrs() else {
+        return false;
+    };
+    addrs
+        .into_iter()
+        .any(|addr| TcpStream::connect_timeout(&addr, Duration::from_secs(2)).is_ok())
+}
@@ -0,0 +1,87 @@
+//! Failure kinds wrapper scripts can branch on, each with its own exit code.
+//!
+//! Errors stay `anyhow` errors; the places that can tell what went wrong
+//! attach a [`ConnectDbError`] as context, and `main` exits with the code of
+//! the outermost one in the chain.
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ConnectDbError {
+    /// A secrets file, stored profile or keychain entry doesn't exist
+    SecretNotFound(String),
+    /// A secrets file isn't valid JSON of the expected shape
+    SecretParse(String),
+    /// A connection URL or string can't be parsed
+    UrlParse(String),
+    /// The database server doesn't answer
+    Unreachable(String),
+    /// The server rejected the credentials
+    AuthFailed(String),
+    /// The engine's client binary isn't installed (or is too old)
+    ClientMissing(String),
+    /// The SSH tunnel couldn't be opened
+    TunnelFailed(String),
+}
+
+impl ConnectDbError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ConnectDbError::SecretNotFound(_) => 10,
+            ConnectDbError::SecretParse(_) => 11,
+            ConnectDbError::UrlParse(_) => 12,
+            ConnectDbError::Unreachable(_) => 13,
+            ConnectDbError::AuthFailed(_) => 14,
+            ConnectDbError::ClientMissing(_) => 15,
+            ConnectDbError::TunnelFailed(_) => 16,
+        }
+    }
+}
+
+impl fmt::Display for ConnectDbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (ConnectDbError::SecretNotFound(message)
+        | ConnectDbError::SecretParse(message)
+        | ConnectDbError::UrlParse(message)
+        | ConnectDbError::Unreachable(message)
+        | ConnectDbError::AuthFailed(message)
+        | ConnectDbError::ClientMissing(message)
+        | ConnectDbError::TunnelFailed(message)) = self;
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for ConnectDbError {}
+
+/// Exit code for `err`: its kind's code, or 1 for untyped errors.
+pub fn exit_code(err: &anyhow::Error) -> u8 {
+    err.downcast_ref::<ConnectDbError>()
+        .map_or(1, ConnectDbError::exit_code)
+}
+
+/// A client's error output, tagged as a connection or login failure when its
+/// wording says so.
+pub fn client_error(stderr: &str) -> anyhow::Error {
+    const AUTH_FAILED: [&str; 4] = [
+        "password authentication failed",
+        "no password supplied",
+        "Authentication failed",
+        "Access denied",
+    ];
+    const UNREACHABLE: [&str; 6] = [
+        "could not connect to server",
+        "Connection refused",
+        "could not translate host name",
+        "timeout expired",
+        "No route to host",
+        "Network is unreachable",
+    ];
+    let message = stderr.to_string();
+    if AUTH_FAILED.iter().any(|text| stderr.contains(text)) {
+        ConnectDbError::AuthFailed(message).into()
+    } else if UNREACHABLE.iter().any(|text| stderr.contains(text)) {
+        ConnectDbError::Unreachable(message).into()
+    } else {
+        anyhow::anyhow!(message)
+    }
+}
@@ -0,0 +1,386 @@
+//! Running ad-hoc queries on one database, or on several and merging their
+//! results.
+
+use crate::{
+    clone_schema::Side,
+    config,
+    engines::{self, ResultSet},
+    notebook,
+    output::{self, OutputFormat},
+    report, result_cache, sql_lint,
+    sql_params::Binding,
+    time,
+};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+/// Rows printed when neither `--limit` nor `exec_limit` is set.
+const DEFAULT_LIMIT: usize = 1000;
+
+/// Column added in front of merged rows naming the database they came from.
+const SOURCE_COLUMN: &str = "source";
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Merge {
+    /// One table of every row, matching columns by name
+    Union,
+    /// One table per database
+    Separate,
+}
+
+/// The SQL given on the command line, or read from `file` (`-` for stdin).
+pub fn read_sql(sql: Option<String>, file: Option<&Path>) -> Result<String> {
+    match (sql, file) {
+        (Some(_), Some(_)) => Err(anyhow::anyhow!("Pass either SQL or --file, not both")),
+        (Some(sql), None) => Ok(sql),
+        (None, Some(path)) if path == Path::new("-") => {
+            let mut sql = String::new();
+            io::stdin().read_to_string(&mut sql)?;
+            Ok(sql)
+        }
+        (None, Some(path)) => {
+            fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
+        }
+        (None, None) => Err(anyhow::anyhow!("Pass the SQL to run or --file")),
+    }
+}
+
+pub struct ExecOptions {
+    pub bindings: Vec<Binding>,
+    pub allow_destructive: bool,
+    /// Rows to read per database before stopping; `None` reads everything
+    pub limit: Option<usize>,
+    /// Write the rows to this file instead of printing them
+    pub output: Option<PathBuf>,
+    /// Format of `output`; guessed from its extension when unset
+    pub output_format: Option<OutputFormat>,
+    /// Append the queries and their results to this session notebook
+    pub notebook: Option<PathBuf>,
+    /// Reuse the result of the same query fetched within this many seconds
+    pub cache: Option<u64>,
+}
+
+/// `--limit`, falling back to `exec_limit` in config.json; 0 means no limit.
+/// Results written to a file are only limited by an explicit `--limit`.
+pub fn row_limit(flag: Option<usize>, to_file: bool) -> Result<Option<usize>> {
+    let limit = match flag {
+        Some(limit) => limit,
+        None if to_file => 0,
+        None => config::settings()?.exec_limit.unwrap_or(DEFAULT_LIMIT),
+    };
+    Ok((limit > 0).then_some(limit))
+}
+
+/// Run `sql` on one database and print its result as a table, or write it
+/// to `options.output`.
+pub fn exec(side: &Side, sql: &str, options: &ExecOptions) -> Result<()> {
+    sql_lint::guard(side.name, side.profile, sql, options.allow_destructive)?;
+    if let Some(path) = &options.output {
+        let format = match options.output_format {
+            Some(format) => format,
+            None => OutputFormat::from_path(path)?,
+        };
+        let mut stream =
+            engines::stream_result_query(side.params, side.profile, sql, &options.bindings)?;
+        if stream.columns.is_empty() {
+            // Surface the client's error, if any, before complaining
+            stream.finish(false)?;
+            return Err(anyhow::anyhow!("The query returned no rows to write"));
+        }
+        let columns = stream.columns.clone();
+        let limit = options.limit.unwrap_or(usize::MAX);
+        let written = output::write(&columns, stream.by_ref().take(limit), path, format);
+        // Like other command line tools, stop quietly when a pipe reader like
+        // `head` has seen enough
+        if output::is_stdout(path)
+            && let Err(err) = &written
+            && err
+                .downcast_ref::<io::Error>()
+                .is_some_and(|err| err.kind() == io::ErrorKind::BrokenPipe)
+        {
+            return stream.finish(true);
+        }
+        let truncated = written.is_ok() && options.limit.is_some() && stream.next().is_some();
+        let finished = stream.finish(written.is_err() || truncated);
+        let written = written?;
+        finished?;
+        let target = match output::is_stdout(path) {
+            true => "stdout".to_string(),
+            false => path.display().to_string(),
+        };
+        eprintln!("Wrote {} to {}", row_count(written as usize), target);
+        if truncated {
+            eprintln!("Warning: stopped at --limit {}", written);
+        }
+        return Ok(());
+    }
+    let result = match options.cache {
+        Some(ttl) => cached_result(side, sql, options, ttl),
+        None => engines::run_result_query(
+            side.params,
+            side.profile,
+            sql,
+            &options.bindings,
+            options.limit,
+        ),
+    };
+    record(options, side.name, sql, result.as_ref())?;
+    let result = result?;
+    if !result.columns.is_empty() {
+        let headers: Vec<&str> = result.columns.iter().map(String::as_str).collect();
+        report::print_table(&headers, &result.rows);
+        eprintln!("({})", row_count(result.rows.len()));
+    }
+    warn_truncated(side.name, &result);
+    Ok(())
+}
+
+/// The result of `sql` from the result cache when fetched less than `ttl`
+/// seconds ago, or else from the database, caching it.
+fn cached_result(side: &Side, sql: &str, options: &ExecOptions, ttl: u64) -> Result<ResultSet> {
+    let key = result_cache::key(side, sql, &options.bindings, options.limit)?;
+    if let Some((cached_at, result)) = result_cache::load(&key, ttl)? {
+        eprintln!("Cached result from {}", time::rfc3339(cached_at));
+        return Ok(result);
+    }
+    let result = engines::run_result_query(
+        side.params,
+        side.profile,
+        sql,
+        &options.bindings,
+        options.limit,
+    )?;
+    if let Err(err) = result_cache::store(&key, &result) {
+        eprintln!("Warning: {:#}", err);
+    }
+    Ok(result)
+}
+
+/// Rerun `sql` every `interval` seconds until interrupted, redrawing the
+/// result and highlighting cells that changed since the previous run.
+pub fn watch(side: &Side, sql: &str, options: &ExecOptions, interval: u64) -> Result<()> {
+    sql_lint::guard(side.name, side.profile, sql, options.allow_destructive)?;
+    let interval = interval.max(1);
+    let title = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut previous: Option<ResultSet> = None;
+    loop {
+        let mut screen = format!(
+            "\x1b[H\x1b[2JEvery {}s on {}: {}\n{}\n\n",
+            interval,
+            side.name,
+            title,
+            time::now_rfc3339()
+        );
+        match engines::run_result_query(
+            side.params,
+            side.profile,
+            sql,
+            &options.bindings,
+            options.limit,
+        ) {
+            Ok(result) => {
+                let headers: Vec<&str> = result.columns.iter().map(String::as_str).collect();
+                // Nothing is highlighted on the first run or when the columns changed
+                let before = previous
+                    .as_ref()
+                    .filter(|before| before.columns == result.columns);
+                let changed = |row: usize, column: usize| {
+                    before.is_some_and(|before| {
+                        before.rows.get(row).and_then(|cells| cells.get(column))
+                            != result.rows.get(row).and_then(|cells| cells.get(column))
+                    })
+                };
+                for line in report::table_lines(&headers, &result.rows, changed) {
+                    screen.push_str(&line);
+                    screen.push('\n');
+                }
+                screen.push_str(&format!("({})\n", row_count(result.rows.len())));
+                if result.truncated {
+                    screen.push_str("(stopped at the row limit; pass --limit for more)\n");
+                }
+                previous = Some(result);
+            }
+            Err(err) => screen.push_str(&format!("Error: {:#}\n", err)),
+        }
+        print!("{}", screen);
+        io::stdout().flush()?;
+        thread::sleep(Duration::from_secs(interval));
+    }
+}
+
+/// Add a query's result, or its error, to `options.notebook`.
+fn record(
+    options: &ExecOptions,
+    database: &str,
+    sql: &str,
+    result: Result<&ResultSet, &anyhow::Error>,
+) -> Result<()> {
+    let Some(path) = &options.notebook else {
+        return Ok(());
+    };
+    let empty = ResultSet::default();
+    let (set, error) = match result {
+        Ok(set) => (set, None),
+        Err(err) => (&empty, Some(format!("{:#}", err))),
+    };
+    notebook::record(
+        path,
+        &notebook::Cell {
+            database,
+            sql,
+            columns: &set.columns,
+            rows: &set.rows,
+            error,
+            truncated: set.truncated,
+        },
+    )
+}
+
+fn warn_truncated(name: &str, result: &ResultSet) {
+    if result.truncated {
+        eprintln!(
+            "Warning: stopped reading {} after {}; pass --limit N for more, or --limit 0 for all",
+            name,
+            row_count(result.rows.len())
+        );
+    }
+}
+
+/// Run `sql` on every database in parallel and print the merged results.
+/// Fails when any database failed, after printing the others.
+pub fn multi_exec(sides: &[Side], sql: &str, merge: Merge, options: &ExecOptions) -> Result<()> {
+    for side in sides {
+        sql_lint::guard(side.name, side.profile, sql, options.allow_destructive)?;
+    }
+    let results: Vec<Result<ResultSet>> = thread::scope(|scope| {
+        let handles: Vec<_> = sides
+            .iter()
+            .map(|side| {
+                scope.spawn(move || {
+                    engines::run_result_query(
+                        side.params,
+                        side.profile,
+                        sql,
+                        &options.bindings,
+                        options.limit,
+                    )
+                    .with_context(|| format!("Query failed on {}", side.name))
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("query panicked")))
+            })
+            .collect()
+    });
+
+    let mut succeeded = Vec::new();
+    let mut failed = 0;
+    for (side, result) in sides.iter().zip(results) {
+        if merge == Merge::Separate || result.is_err() {
+            record(options, side.name, sql, result.as_ref())?;
+        }
+        match result {
+            Ok(result) => {
+                warn_truncated(side.name, &result);
+                succeeded.push((side.name, result));
+            }
+            Err(err) => {
+                eprintln!("{:#}", err);
+                failed += 1;
+            }
+        }
+    }
+
+    match merge {
+        _ if succeeded.is_empty() => {}
+        Merge::Union => {
+            let (columns, rows) = union(&succeeded);
+            let names: Vec<&str> = succeeded.iter().map(|(name, _)| *name).collect();
+            let merged = ResultSet {
+                columns,
+                rows,
+                truncated: succeeded.iter().any(|(_, result)| result.truncated),
+            };
+            record(options, &names.join(", "), sql, Ok(&merged))?;
+            let ResultSet { columns, rows, .. } = merged;
+            let headers: Vec<&str> = columns.iter().map(String::as_str).collect();
+            report::print_table(&headers, &rows);
+            eprintln!("{} rows from {} databases", rows.len(), succeeded.len());
+        }
+        Merge::Separate => {
+            for (index, (name, result)) in succeeded.iter().enumerate() {
+                if index > 0 {
+                    println!();
+                }
+                println!("== {} ({})", name, row_count(result.rows.len()));
+                let headers: Vec<&str> = result.columns.iter().map(String::as_str).collect();
+                report::print_table(&headers, &result.rows);
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(anyhow::anyhow!(
+            "{} of {} databases failed",
+            failed,
+            sides.len()
+        ));
+    }
+    Ok(())
+}
+
+fn row_count(rows: usize) -> String {
+    if rows == 1 {
+        "1 row".to_string()
+    } else {
+        format!("{} rows", rows)
+    }
+}
+
+/// Every row under the union of all column names, tagged with its source;
+/// columns a database didn't return are left empty.
+fn union(results: &[(&str, ResultSet)]) -> (Vec<String>, Vec<Vec<String>>) {
+    let mut columns = vec![SOURCE_COLUMN.to_string()];
+    for (_, result) in results {
+        for column in &result.columns {
+            if !columns[1..].contains(column) {
+                columns.push(column.clone());
+            }
+        }
+    }
+
+    let mut rows = Vec::new();
+    for (name, result) in results {
+        // Identical column lists map by position, which keeps duplicate names
+        let positions: Vec<Option<usize>> = if result.columns == columns[1..] {
+            (0..result.columns.len()).map(Some).collect()
+        } else {
+            columns[1..]
+                .iter()
+                .map(|column| result.columns.iter().position(|c| c == column))
+                .collect()
+        };
+        for row in &result.rows {
+            let mut merged = vec![name.to_string()];
+            merged.extend(positions.iter().map(|position| {
+                position
+                    .and_then(|index| row.get(index).cloned())
+                    .unwrap_or_default()
+            }));
+            rows.push(merged);
+        }
+    }
+    (columns, rows)
+}
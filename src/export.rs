@@ -0,0 +1,244 @@
+//! Write profiles as connection definitions for other tools: libpq's
+//! `pg_service.conf`, DBeaver's `data-sources.json` and DataGrip's
+//! `dataSources.xml`.
+
+use crate::{
+    connection_string,
+    engines::{ConnectionParams, Engine},
+    sha256::Sha256,
+};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde_json::{Map, Value, json};
+use std::{
+    fmt::Write as _, fs::OpenOptions, io::Write as _, os::unix::fs::OpenOptionsExt, path::Path,
+};
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum Format {
+    /// libpq service file (PostgreSQL-protocol databases only)
+    PgService,
+    /// DBeaver `data-sources.json`
+    Dbeaver,
+    /// DataGrip / IntelliJ `dataSources.xml`
+    Datagrip,
+}
+
+/// Resolves a database name to its connection parameters.
+pub type Resolver<'a> = dyn Fn(&str) -> Result<ConnectionParams> + 'a;
+
+/// Export `names` in `format` to `output` (stdout when unset). Passwords are
+/// left out unless `with_secrets` is set. Databases that fail to resolve, or
+/// that the format can't describe, are skipped with a warning.
+pub fn run(
+    format: Format,
+    names: &[String],
+    resolve: &Resolver,
+    with_secrets: bool,
+    output: Option<&Path>,
+) -> Result<()> {
+    let mut databases = Vec::new();
+    for name in names {
+        match resolve(name) {
+            Ok(mut params) => {
+                if !with_secrets {
+                    params.password.clear();
+                }
+                databases.push((name.as_str(), params));
+            }
+            Err(err) => eprintln!(
+                "Skipping {}: {}",
+                name,
+                err.to_string().lines().next().unwrap_or_default()
+            ),
+        }
+    }
+
+    let text = match format {
+        Format::PgService => pg_service(&databases),
+        Format::Dbeaver => dbeaver(&databases)?,
+        Format::Datagrip => datagrip(&databases),
+    };
+    match output {
+        Some(path) => {
+            // Only the user may read files with passwords in them
+            let mode = if with_secrets { 0o600 } else { 0o644 };
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(mode)
+                .open(path)
+                .and_then(|mut file| file.write_all(text.as_bytes()))
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            eprintln!("Wrote {}", path.display());
+        }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+fn is_postgres_protocol(engine: Engine) -> bool {
+    matches!(
+        engine,
+        Engine::Postgres | Engine::CockroachDb | Engine::Redshift
+    )
+}
+
+fn pg_service(databases: &[(&str, ConnectionParams)]) -> String {
+    let mut text = String::from("# Generated by connect-db export\n");
+    for (name, params) in databases {
+        if !is_postgres_protocol(params.engine) {
+            eprintln!("Skipping {}: not a PostgreSQL-protocol database", name);
+            continue;
+        }
+        // libpq fails over between comma-separated hosts itself
+        let mut hosts = vec![params.host.as_str()];
+        let mut ports = vec![params.port.as_str()];
+        for (host, port) in &params.failover_hosts {
+            hosts.push(host);
+            ports.push(port);
+        }
+        let _ = write!(
+            text,
+            "\n[{}]\nhost={}\nport={}\ndbname={}\n",
+            name,
+            hosts.join(","),
+            ports.join(","),
+            params.database
+        );
+        if !params.username.is_empty() {
+            let _ = writeln!(text, "user={}", params.username);
+        }
+        if !params.password.is_empty() {
+            let _ = writeln!(text, "password={}", params.password);
+        }
+        for (key, value) in &params.options {
+            if connection_string::LIBPQ_OPTIONS.contains(&key.as_str()) {
+                let _ = writeln!(text, "{}={}", key, value);
+            }
+        }
+    }
+    text
+}
+
+/// DBeaver provider and driver ids for an engine.
+fn dbeaver_driver(engine: Engine) -> Option<(&'static str, &'static str)> {
+    Some(match engine {
+        Engine::Postgres => ("postgresql", "postgres-jdbc"),
+        Engine::CockroachDb => ("postgresql", "cockroachdb"),
+        Engine::Redshift => ("postgresql", "redshift"),
+        Engine::ClickHouse => ("generic", "com_clickhouse"),
+        Engine::Snowflake => ("snowflake", "snowflake"),
+        Engine::Oracle => ("oracle", "oracle_thin"),
+        Engine::Trino => ("generic", "trino_jdbc"),
+        Engine::Sqlite => ("generic", "sqlite_jdbc"),
+        Engine::DuckDb => ("duckdb", "duckdb_jdbc"),
+        Engine::Cassandra => return None,
+    })
+}
+
+fn dbeaver(databases: &[(&str, ConnectionParams)]) -> Result<String> {
+    let mut connections = Map::new();
+    for (name, params) in databases {
+        let (Some((provider, driver)), Some(url)) = (
+            dbeaver_driver(params.engine),
+            connection_string::jdbc(params, false),
+        ) else {
+            eprintln!("Skipping {}: DBeaver has no driver for it", name);
+            continue;
+        };
+        let mut configuration = json!({
+            "host": params.host,
+            "port": params.port,
+            "database": params.database,
+            "url": url,
+            "type": "dev",
+            "auth-model": "native",
+        });
+        if !params.username.is_empty() {
+            configuration["user"] = params.username.clone().into();
+        }
+        if !params.password.is_empty() {
+            configuration["password"] = params.password.clone().into();
+        }
+        connections.insert(
+            format!("connect-db-{}", name),
+            json!({
+                "provider": provider,
+                "driver": driver,
+                "name": name,
+                "save-password": !params.password.is_empty(),
+                "configuration": configuration,
+            }),
+        );
+    }
+    let document = json!({ "folders": {}, "connections": Value::Object(connections) });
+    Ok(serde_json::to_string_pretty(&document)? + "\n")
+}
+
+/// DataGrip driver id for an engine.
+fn datagrip_driver(engine: Engine) -> &'static str {
+    match engine {
+        Engine::Postgres => "postgresql",
+        Engine::CockroachDb => "cockroach",
+        Engine::Redshift => "redshift",
+        Engine::ClickHouse => "clickhouse",
+        Engine::Snowflake => "snowflake",
+        Engine::Oracle => "oracle",
+        Engine::Trino => "trino",
+        Engine::Sqlite => "sqlite.xerial",
+        Engine::DuckDb => "duckdb",
+        Engine::Cassandra => "cassandra",
+    }
+}
+
+fn datagrip(databases: &[(&str, ConnectionParams)]) -> String {
+    let mut text = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<project version=\"4\">\n  \
+         <component name=\"DataSourceManagerImpl\" format=\"xml\" multifile-model=\"true\">\n",
+    );
+    for (name, params) in databases {
+        // DataGrip keeps credentials out of this file, so the user (and with
+        // secrets, the password) travel in the JDBC URL instead
+        let Some(url) = connection_string::jdbc(params, true) else {
+            eprintln!("Skipping {}: DataGrip has no JDBC driver for it", name);
+            continue;
+        };
+        let _ = write!(
+            text,
+            "    <data-source source=\"LOCAL\" name=\"{}\" uuid=\"{}\">\n      \
+             <driver-ref>{}</driver-ref>\n      <synchronize>true</synchronize>\n      \
+             <jdbc-url>{}</jdbc-url>\n      <working-dir>$ProjectFileDir$</working-dir>\n    \
+             </data-source>\n",
+            escape_xml(name),
+            uuid(name),
+            datagrip_driver(params.engine),
+            escape_xml(&url)
+        );
+    }
+    text.push_str("  </component>\n</project>\n");
+    text
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A stable UUID per name, so reexporting updates data sources in place.
+fn uuid(name: &str) -> String {
+    let mut hash = Sha256::default();
+    hash.update(format!("connect-db:{}", name).as_bytes());
+    let hex = hash.finish();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
@@ -0,0 +1,142 @@
+//! Privileges on schemas, tables, views and sequences, for security
+//! reviews, with over-broad grants called out.
+
+use crate::{
+    config::Profile,
+    engines::{self, ConnectionParams, Engine, postgres::quote_literal},
+    report,
+};
+use anyhow::Result;
+
+/// Objects outside the system schemas with their owner and ACL; a NULL ACL
+/// means the built-in defaults.
+const OBJECTS: &str = "\
+objects as ( \
+select n.nspname || '.' || c.relname as object, \
+case c.relkind when 'S' then 'sequence' when 'v' then 'view' when 'm' then 'materialized view' \
+when 'f' then 'foreign table' else 'table' end as kind, \
+c.relowner as owner, \
+coalesce(c.relacl, acldefault(case c.relkind when 'S' then 's' else 'r' end::\"char\", c.relowner)) as acl \
+from pg_class c join pg_namespace n on n.oid = c.relnamespace \
+where c.relkind in ('r', 'p', 'v', 'm', 'S', 'f') \
+and n.nspname not in ('pg_catalog', 'information_schema') and n.nspname not like 'pg\\_%' {table} \
+union all \
+select nspname, 'schema', nspowner, coalesce(nspacl, acldefault('n', nspowner)) \
+from pg_namespace \
+where nspname not in ('pg_catalog', 'information_schema') and nspname not like 'pg\\_%' {schema} \
+), \
+grants as ( \
+select o.object, o.kind, o.owner, a.grantee, a.privilege_type, a.is_grantable \
+from objects o, aclexplode(o.acl) a \
+)";
+
+/// Print who holds which privileges, limited to `role` (including what it
+/// inherits from the roles it belongs to and from PUBLIC) and `table` when
+/// given, then the grants worth a second look.
+pub fn report(
+    params: &ConnectionParams,
+    profile: &Profile,
+    role: Option<&str>,
+    table: Option<&str>,
+) -> Result<()> {
+    if params.engine != Engine::Postgres {
+        return Err(anyhow::anyhow!("grants needs a PostgreSQL database"));
+    }
+    let query = |sql: &str| -> Result<Vec<Vec<String>>> {
+        Ok(report::rows(&engines::run_query(params, profile, sql)?))
+    };
+    let objects = match table {
+        // A bare name matches the table in any schema
+        Some(table) => OBJECTS
+            .replace(
+                "{table}",
+                &format!(
+                    "and (c.relname = {0} or n.nspname || '.' || c.relname = {0})",
+                    quote_literal(table)
+                ),
+            )
+            .replace("{schema}", "and false"),
+        None => OBJECTS.replace("{table}", "").replace("{schema}", ""),
+    };
+    let privileges = "string_agg(g.privilege_type || case when g.is_grantable then '*' else '' end, \
+                      ',' order by g.privilege_type)";
+
+    let rows = match role {
+        Some(role) => {
+            let found = query(&format!(
+                "select rolsuper from pg_roles where rolname = {}",
+                quote_literal(role)
+            ))?;
+            match found.first().map(|row| row[0].as_str()) {
+                None => return Err(anyhow::anyhow!("No role named {}", role)),
+                Some("t") => println!(
+                    "{} is a superuser and bypasses every privilege check.\n",
+                    role
+                ),
+                Some(_) => {}
+            }
+            query(&format!(
+                "with recursive member_of(oid) as ( \
+                 select oid from pg_roles where rolname = {role} \
+                 union \
+                 select m.roleid from pg_auth_members m join member_of on m.member = member_of.oid \
+                 ), {objects} \
+                 select {role}, g.object, g.kind, {privileges}, \
+                 case when g.grantee = 0 then 'PUBLIC' \
+                 when g.grantee <> (select oid from pg_roles where rolname = {role}) \
+                 then 'member of ' || pg_get_userbyid(g.grantee) \
+                 when g.grantee = g.owner then 'owner' else 'grant' end \
+                 from grants g \
+                 where g.grantee = 0 or g.grantee in (select oid from member_of) \
+                 group by g.grantee, g.owner, g.object, g.kind order by 2, 5",
+                role = quote_literal(role),
+            ))?
+        }
+        None => query(&format!(
+            "with {objects} \
+             select case when g.grantee = 0 then 'PUBLIC' else pg_get_userbyid(g.grantee) end, \
+             g.object, g.kind, {privileges}, \
+             case when g.grantee = 0 then 'PUBLIC' when g.grantee = g.owner then 'owner' else 'grant' end \
+             from grants g \
+             group by g.grantee, g.owner, g.object, g.kind order by 2, 1"
+        ))?,
+    };
+    section(
+        "Privileges (* = with grant option)",
+        &["ROLE", "OBJECT", "KIND", "PRIVILEGES", "VIA"],
+        &rows,
+    );
+
+    let findings = query(&format!(
+        "with {objects} \
+         select 'granted to PUBLIC', g.object, string_agg(g.privilege_type, ',' order by g.privilege_type) \
+         from grants g \
+         where g.grantee = 0 and not (g.kind = 'schema' and g.privilege_type = 'USAGE') \
+         group by g.object \
+         union all \
+         select 'owned by superuser ' || r.rolname, o.object, o.kind \
+         from objects o join pg_roles r on r.oid = o.owner where r.rolsuper \
+         union all \
+         select 'grant option held by ' || pg_get_userbyid(g.grantee), g.object, \
+         string_agg(g.privilege_type, ',' order by g.privilege_type) \
+         from grants g where g.is_grantable and g.grantee not in (0, g.owner) \
+         group by g.grantee, g.object \
+         order by 2, 1"
+    ))?;
+    section(
+        "Over-broad grants (PUBLIC access, superuser-owned objects, grant options)",
+        &["FINDING", "OBJECT", "DETAIL"],
+        &findings,
+    );
+    Ok(())
+}
+
+fn section(title: &str, headers: &[&str], rows: &[Vec<String>]) {
+    println!("{}", title);
+    if rows.is_empty() {
+        println!("  none\n");
+        return;
+    }
+    report::print_table(headers, rows);
+    println!();
+}
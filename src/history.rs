@@ -0,0 +1,61 @@
+//! Local log of interactive sessions started by connect-db.
+
+use crate::{config, time};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Session {
+    pub name: String,
+    pub environment: Option<String>,
+    /// Unix time the session started
+    pub started_at: u64,
+}
+
+fn history_path() -> Result<PathBuf> {
+    Ok(config::config_dir()?.join("history.jsonl"))
+}
+
+/// Append a session to the history file.
+pub fn record(name: &str) -> Result<()> {
+    let session = Session {
+        name: name.to_string(),
+        environment: config::selected_environment().map(str::to_string),
+        started_at: time::unix_now(),
+    };
+    let path = history_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(&session)?)?;
+    Ok(())
+}
+
+/// The latest `limit` sessions for `name`, newest first.
+pub fn recent(name: &str, limit: usize) -> Result<Vec<Session>> {
+    let path = history_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    // Skip lines that don't parse rather than losing the whole history
+    let mut sessions: Vec<Session> = content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Session>(line).ok())
+        .filter(|session| session.name == name)
+        .collect();
+    sessions.reverse();
+    sessions.truncate(limit);
+    Ok(sessions)
+}
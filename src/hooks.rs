@@ -0,0 +1,203 @@
+//! Hook scripts around interactive sessions: executables named `pre-connect`,
+//! `post-connect` and `post-disconnect` in the `hooks` directory of the
+//! connect-db config directory, written in any language.
+//!
+//! Each gets the session context as JSON on stdin, without the password or
+//! secret-looking URL options. A failing `pre-connect` hook refuses the
+//! connection, so it can check a ticket or set up the environment first;
+//! failures of the other two are only reported.
+//!
+//! Profiles can also list plain shell commands in `pre_connect` and
+//! `post_disconnect`, for prerequisites like a VPN.
+
+use crate::{
+    config::{self, Profile},
+    dry_run,
+    engines::{ConnectionParams, client},
+    progress, time,
+};
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::{
+    env,
+    io::Write,
+    path::PathBuf,
+    process::{Command, ExitStatus, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Hooks still running after this long are killed.
+const HOOK_TIMEOUT: Duration = Duration::from_secs(60);
+
+const DEFAULT_COMMAND_TIMEOUT: &str = "30s";
+
+#[derive(Debug, Clone, Copy)]
+pub enum Event {
+    PreConnect,
+    PostConnect,
+    PostDisconnect,
+}
+
+impl Event {
+    fn name(self) -> &'static str {
+        match self {
+            Event::PreConnect => "pre-connect",
+            Event::PostConnect => "post-connect",
+            Event::PostDisconnect => "post-disconnect",
+        }
+    }
+}
+
+fn script(event: Event) -> Option<PathBuf> {
+    let path = config::config_dir().ok()?.join("hooks").join(event.name());
+    client::locate(&path.display().to_string())
+}
+
+/// Whether a script is installed for `event`.
+pub fn exists(event: Event) -> bool {
+    script(event).is_some()
+}
+
+/// What hooks are told about a session, with secrets left out.
+pub fn context(name: &str, params: &ConnectionParams, profile: &Profile) -> Value {
+    const SENSITIVE: [&str; 4] = ["password", "secret", "token", "key"];
+    let options: serde_json::Map<String, Value> = params
+        .options
+        .iter()
+        .filter(|(key, _)| !SENSITIVE.iter().any(|word| key.contains(word)))
+        .map(|(key, value)| (key.clone(), value.clone().into()))
+        .collect();
+    json!({
+        "database": name,
+        "engine": format!("{:?}", params.engine).to_lowercase(),
+        "host": params.host,
+        "port": params.port,
+        "username": params.username,
+        "dbname": params.database,
+        "options": options,
+        "production": profile.production,
+        "read_only": profile.read_only,
+        "user": env::var("USER").unwrap_or_default(),
+    })
+}
+
+/// Run the script for `event`, if there is one, with `context` plus the event
+/// name and time on stdin.
+pub fn run(event: Event, mut context: Value) -> Result<()> {
+    let Some(path) = script(event) else {
+        return Ok(());
+    };
+    context["event"] = event.name().into();
+    context["time"] = time::now_rfc3339().into();
+
+    let mut command = Command::new(&path);
+    command.env("CONNECT_DB_EVENT", event.name());
+    if let Some(database) = context["database"].as_str() {
+        command.env("CONNECT_DB_DATABASE", database);
+    }
+    let status = run_with_timeout(&mut command, &context.to_string(), HOOK_TIMEOUT)
+        .with_context(|| format!("{} hook failed", event.name()))?;
+    if !status.success() {
+        let err = anyhow::anyhow!(
+            "{} hook {} exited with {}",
+            event.name(),
+            path.display(),
+            status
+        );
+        return match event {
+            Event::PreConnect => Err(err.context("Connection refused by the pre-connect hook")),
+            _ => {
+                eprintln!("Warning: {}", err);
+                Ok(())
+            }
+        };
+    }
+    Ok(())
+}
+
+/// Run the profile's `pre_connect` commands in order, stopping at the first
+/// that fails.
+pub fn pre_connect(profile: &Profile) -> Result<()> {
+    let timeout = command_timeout(profile)?;
+    for line in &profile.pre_connect {
+        if dry_run::enabled() {
+            dry_run::report(format!("would run pre_connect command: {}", line));
+            continue;
+        }
+        let status = run_with_timeout(Command::new("sh").args(["-c", line]), "", timeout)
+            .with_context(|| format!("pre_connect command failed: {}", line))?;
+        if !status.success() {
+            return Err(anyhow::anyhow!(
+                "pre_connect command exited with {}: {}",
+                status,
+                line
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Run the profile's `post_disconnect` commands, reporting failures.
+pub fn post_disconnect(profile: &Profile) {
+    let timeout = match command_timeout(profile) {
+        Ok(timeout) => timeout,
+        Err(err) => {
+            eprintln!("Warning: {:#}", err);
+            return;
+        }
+    };
+    for line in &profile.post_disconnect {
+        match run_with_timeout(Command::new("sh").args(["-c", line]), "", timeout) {
+            Ok(status) if status.success() => {}
+            Ok(status) => eprintln!(
+                "Warning: post_disconnect command exited with {}: {}",
+                status, line
+            ),
+            Err(err) => eprintln!(
+                "Warning: post_disconnect command failed: {}: {:#}",
+                line, err
+            ),
+        }
+    }
+}
+
+fn command_timeout(profile: &Profile) -> Result<Duration> {
+    let timeout = profile
+        .command_timeout
+        .as_deref()
+        .unwrap_or(DEFAULT_COMMAND_TIMEOUT);
+    let seconds = time::parse_duration(timeout).context("Invalid command_timeout")?;
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Run `command` with `input` on stdin, killing it after `timeout`.
+pub fn run_with_timeout(
+    command: &mut Command,
+    input: &str,
+    timeout: Duration,
+) -> Result<ExitStatus> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("Failed to start")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that doesn't read its input is fine
+        let _ = stdin.write_all(input.as_bytes());
+    }
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow::anyhow!(
+                "timed out after {}",
+                progress::format_duration(timeout.as_secs())
+            ));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
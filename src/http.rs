@@ -0,0 +1,168 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Response of an HTTP request made through `curl`.
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn json(&self) -> Result<Value> {
+        serde_json::from_str(&self.body).context("Response is not valid JSON")
+    }
+}
+
+pub fn get(url: &str, headers: &[(&str, &str)]) -> Result<Response> {
+    send("GET", url, headers, None, None)
+}
+
+pub fn post(url: &str, headers: &[(&str, &str)], body: &str) -> Result<Response> {
+    send("POST", url, headers, Some(body), None)
+}
+
+/// Background POST: gives up after `seconds` and keeps curl's errors off
+/// the terminal.
+pub fn post_with_timeout(
+    url: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+    seconds: u64,
+) -> Result<Response> {
+    send("POST", url, headers, Some(body), Some(seconds))
+}
+
+/// Build an `application/x-www-form-urlencoded` body.
+pub fn form(fields: &[(&str, &str)]) -> String {
+    fields
+        .iter()
+        .map(|(key, value)| format!("{}={}", url_encode(key), url_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+pub fn url_encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Run curl with the whole request passed as a config file on stdin, so
+/// tokens and request bodies never show up in the process list.
+fn send(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<&str>,
+    timeout: Option<u64>,
+) -> Result<Response> {
+    // Reads help resolve secrets; anything else may change something
+    if crate::dry_run::enabled() && method != "GET" {
+        crate::dry_run::report(format!("would send {} {}", method, url));
+        return Err(anyhow::anyhow!("{} {} not sent (--dry-run)", method, url));
+    }
+    crate::dry_run::report(format!("{} {}", method, url));
+    let mut config = format!("url = {}\nrequest = {}\n", quote(url), quote(method));
+    if let Some(seconds) = timeout {
+        config.push_str(&format!("max-time = {}\n", seconds));
+    }
+    for (name, value) in headers {
+        config.push_str(&format!(
+            "header = {}\n",
+            quote(&format!("{}: {}", name, value))
+        ));
+    }
+    if let Some(body) = body {
+        config.push_str(&format!("data-binary = {}\n", quote(body)));
+    }
+
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--location", "--config", "-"])
+        .args(["--write-out", "\n%{http_code}"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(if timeout.is_some() {
+            Stdio::null()
+        } else {
+            Stdio::inherit()
+        })
+        .spawn()
+        .context("Failed to run curl")?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(config.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("Request to {} failed", url));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (body, status) = stdout.rsplit_once('\n').unwrap_or(("", &stdout));
+    Ok(Response {
+        status: status.trim().parse().unwrap_or(0),
+        body: body.to_string(),
+    })
+}
+
+/// Save the body of a GET request to `url` in `path`, failing on HTTP errors.
+pub fn download(url: &str, path: &Path) -> Result<()> {
+    if crate::dry_run::enabled() {
+        crate::dry_run::stop(&format!("download {} to {}", url, path.display()));
+    }
+    let config = format!(
+        "url = {}\noutput = {}\n",
+        quote(url),
+        quote(&path.display().to_string())
+    );
+    let mut child = Command::new("curl")
+        .args([
+            "--silent",
+            "--show-error",
+            "--location",
+            "--fail",
+            "--config",
+            "-",
+        ])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .context("Failed to run curl")?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(config.as_bytes())?;
+    if !child.wait()?.success() {
+        return Err(anyhow::anyhow!("Download of {} failed", url));
+    }
+    Ok(())
+}
+
+/// Quote a value for curl's config file syntax.
+fn quote(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t");
+    format!("\"{}\"", escaped)
+}
@@ -0,0 +1,303 @@
+//! Turn `~/.pgpass` and `pg_service.conf` entries into profiles.
+//!
+//! Passwords go to the encrypted profile store, or to the OS keychain with
+//! `--keychain`. Entries without a password become plain URLs, leaving
+//! libpq to find the password as it did before.
+
+use crate::{
+    config, dry_run, http, keychain, libpq,
+    profile_store::{ProfileStore, StoredProfile},
+};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde_json::{Map, Value, json};
+use std::{env, fs, path::PathBuf};
+
+/// Where `import` reads connection definitions from.
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum Source {
+    /// `~/.pgpass` (or `$PGPASSFILE`)
+    Pgpass,
+    /// `~/.pg_service.conf` (or `$PGSERVICEFILE`)
+    PgService,
+}
+
+struct Entry {
+    name: String,
+    host: String,
+    port: String,
+    database: String,
+    username: String,
+    password: Option<String>,
+    /// libpq options such as `sslmode`, kept as URL options
+    options: Vec<(String, String)>,
+}
+
+/// Import every entry of `source` (read from `file` or its default location)
+/// that doesn't clash with an existing profile.
+pub fn run(
+    source: Source,
+    file: Option<PathBuf>,
+    use_keychain: bool,
+    store: &ProfileStore,
+) -> Result<()> {
+    let path = match file {
+        Some(path) => path,
+        None => default_path(source)?,
+    };
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let entries = match source {
+        Source::Pgpass => parse_pgpass(&content),
+        Source::PgService => parse_pg_service(&content)?,
+    };
+    if entries.is_empty() {
+        println!("No importable entries in {}", path.display());
+        return Ok(());
+    }
+
+    let config_path = config::config_dir()?.join("config.json");
+    let mut config = if config_path.exists() {
+        config::read_config_file(&config_path)?
+    } else {
+        json!({ "version": config::CONFIG_VERSION, "profiles": {} })
+    };
+    let profiles = config
+        .as_object_mut()
+        .context("config.json must contain a JSON object")?
+        .entry("profiles")
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .context("'profiles' must be an object in config.json")?;
+    let existing = config::profile_names()?;
+    // Only touch the store (which may ask for a passphrase) when needed
+    let uses_store = !use_keychain && entries.iter().any(|entry| entry.password.is_some());
+    let mut stored = if uses_store {
+        store.load()?
+    } else {
+        Default::default()
+    };
+    let stored_before = stored.len();
+
+    let mut imported = 0;
+    for entry in entries {
+        if profiles.contains_key(&entry.name) || existing.contains(&entry.name) {
+            println!("{}: a profile with this name exists; skipping", entry.name);
+            continue;
+        }
+        let mut profile = json!({
+            "description": format!("Imported from {}", path.display()),
+        });
+        match &entry.password {
+            None => profile["db_url"] = url(&entry, None).into(),
+            Some(password) if use_keychain => {
+                keychain::set(&format!("{}:{}", entry.name, entry.username), password)
+                    .with_context(|| format!("Failed to store the password of {}", entry.name))?;
+                profile["db_url"] = url(&entry, Some("{{password}}")).into();
+                profile["username"] = entry.username.clone().into();
+                profile["password_in_keychain"] = true.into();
+            }
+            Some(password) => {
+                stored.insert(
+                    entry.name.clone(),
+                    StoredProfile {
+                        db_url: url(&entry, Some("{{password}}")),
+                        username: entry.username.clone(),
+                        password: password.clone(),
+                    },
+                );
+            }
+        }
+        println!(
+            "{}: {}@{}:{}/{}",
+            entry.name, entry.username, entry.host, entry.port, entry.database
+        );
+        profiles.insert(entry.name, profile);
+        imported += 1;
+    }
+
+    dry_run::stop_before_writing(&config_path);
+    if stored.len() != stored_before {
+        store.save(&stored)?;
+    }
+    if let Some(dir) = config_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&config_path, serde_json::to_string_pretty(&config)?)
+        .with_context(|| format!("Failed to write {}", config_path.display()))?;
+    println!(
+        "Imported {} profile{} into {}",
+        imported,
+        if imported == 1 { "" } else { "s" },
+        config_path.display()
+    );
+    Ok(())
+}
+
+fn default_path(source: Source) -> Result<PathBuf> {
+    match source {
+        Source::Pgpass => match env::var_os("PGPASSFILE") {
+            Some(path) if !path.is_empty() => Ok(PathBuf::from(path)),
+            _ => Ok(config::home_dir()?.join(".pgpass")),
+        },
+        Source::PgService => libpq::service_file(),
+    }
+}
+
+/// Connection URL for `entry`, with `password` (or a placeholder) in it.
+fn url(entry: &Entry, password: Option<&str>) -> String {
+    let mut url = String::from("postgresql://");
+    if !entry.username.is_empty() {
+        let user = if password.is_some() {
+            "{{username}}".to_string()
+        } else {
+            http::url_encode(&entry.username)
+        };
+        url.push_str(&user);
+        if let Some(password) = password {
+            url.push(':');
+            url.push_str(password);
+        }
+        url.push('@');
+    }
+    if entry.host.contains(':') {
+        url.push_str(&format!("[{}]", entry.host));
+    } else {
+        url.push_str(&entry.host);
+    }
+    url.push_str(&format!(
+        ":{}/{}",
+        entry.port,
+        http::url_encode(&entry.database)
+    ));
+    if !entry.options.is_empty() {
+        let options: Vec<(&str, &str)> = entry
+            .options
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        url.push('?');
+        url.push_str(&http::form(&options));
+    }
+    url
+}
+
+/// `hostname:port:database:username:password` lines, with `\:` and `\\`
+/// escapes. Wildcard hosts and databases match many databases and can't be
+/// turned into a profile, so they are skipped.
+fn parse_pgpass(content: &str) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = split_pgpass_line(line);
+        let [host, port, database, username, password] = fields.as_slice() else {
+            eprintln!("Skipping malformed line {}", number + 1);
+            continue;
+        };
+        if host == "*" || database == "*" || username == "*" {
+            eprintln!(
+                "Skipping wildcard entry {}:{}:{}:{}",
+                host, port, database, username
+            );
+            continue;
+        }
+        if host.starts_with('/') {
+            eprintln!("Skipping socket directory entry for {}", database);
+            continue;
+        }
+        let host = host.clone();
+        let port = if port == "*" { "5432" } else { port }.to_string();
+        // The first entry for a database gets the short name
+        let mut name = profile_name(database);
+        if entries.iter().any(|entry| entry.name == name) {
+            name = profile_name(&format!(
+                "{}-{}",
+                database,
+                host.split('.').next().unwrap_or(&host)
+            ));
+        }
+        if entries.iter().any(|entry| entry.name == name) {
+            name = profile_name(&format!("{}-{}-{}", database, host, username));
+        }
+        entries.push(Entry {
+            name,
+            host,
+            port,
+            database: database.clone(),
+            username: username.clone(),
+            password: Some(password.clone()).filter(|password| !password.is_empty()),
+            options: Vec::new(),
+        });
+    }
+    entries
+}
+
+fn split_pgpass_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    fields.last_mut().unwrap().push(escaped);
+                }
+            }
+            ':' => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/// `[service]` sections of `pg_service.conf`.
+fn parse_pg_service(content: &str) -> Result<Vec<Entry>> {
+    let sections = libpq::parse_service_file(content)?;
+    let mut entries = Vec::new();
+    for (service, keywords) in sections {
+        let mut entry = Entry {
+            name: profile_name(&service),
+            host: "localhost".to_string(),
+            port: "5432".to_string(),
+            database: String::new(),
+            username: String::new(),
+            password: None,
+            options: Vec::new(),
+        };
+        for (key, value) in keywords {
+            match key.as_str() {
+                "host" | "hostaddr" => entry.host = value,
+                "port" => entry.port = value,
+                "dbname" => entry.database = value,
+                "user" => entry.username = value,
+                "password" => entry.password = Some(value).filter(|value| !value.is_empty()),
+                _ => entry.options.push((key, value)),
+            }
+        }
+        if entry.host.starts_with('/') {
+            eprintln!("Skipping {}: socket directories aren't supported", service);
+            continue;
+        }
+        if entry.database.is_empty() {
+            entry.database = entry.username.clone();
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// A name usable on the command line and in secrets file names.
+fn profile_name(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
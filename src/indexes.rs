@@ -0,0 +1,107 @@
+use crate::{
+    config::Profile,
+    engines::{self, ConnectionParams, Engine, postgres},
+    report,
+};
+use anyhow::Result;
+
+/// Tables smaller than this are cheap to scan and never get a hint
+const MIN_ROWS_FOR_HINT: u32 = 10_000;
+
+const INVALID: &str = "\
+select c.oid::regclass, t.oid::regclass, pg_size_pretty(pg_relation_size(c.oid)) \
+from pg_index i join pg_class c on c.oid = i.indexrelid join pg_class t on t.oid = i.indrelid \
+where not i.indisvalid order by 1";
+
+const DUPLICATES: &str = "\
+select min(indrelid::regclass::text), string_agg(indexrelid::regclass::text, ', ' order by indexrelid::regclass::text), \
+pg_size_pretty(sum(pg_relation_size(indexrelid))::bigint) \
+from pg_index \
+group by indrelid, indclass::text, indkey::text, coalesce(indexprs::text, ''), coalesce(indpred::text, '') \
+having count(*) > 1 order by 1";
+
+const UNUSED: &str = "\
+select s.schemaname || '.' || s.indexrelname, s.schemaname || '.' || s.relname, \
+pg_size_pretty(pg_relation_size(s.indexrelid)) \
+from pg_stat_user_indexes s join pg_index i using (indexrelid) \
+where s.idx_scan = 0 and not i.indisunique \
+order by pg_relation_size(s.indexrelid) desc";
+
+/// Report invalid, duplicate and unused indexes plus tables that look like
+/// they are missing one.
+pub fn report(params: &ConnectionParams, profile: &Profile) -> Result<()> {
+    if params.engine != Engine::Postgres {
+        return Err(anyhow::anyhow!("indexes needs a PostgreSQL database"));
+    }
+    let query = |sql: &str| -> Result<Vec<Vec<String>>> {
+        Ok(report::rows(&engines::run_query(params, profile, sql)?))
+    };
+
+    section(
+        "Invalid indexes (failed CREATE INDEX CONCURRENTLY; rebuild or drop)",
+        &["INDEX", "TABLE", "SIZE"],
+        &query(INVALID)?,
+    );
+    section(
+        "Duplicate indexes (same table, columns and predicate)",
+        &["TABLE", "INDEXES", "TOTAL SIZE"],
+        &query(DUPLICATES)?,
+    );
+    section(
+        "Unused indexes (no scans since statistics were reset; unique indexes excluded)",
+        &["INDEX", "TABLE", "SIZE"],
+        &query(UNUSED)?,
+    );
+
+    let candidates = query(&format!(
+        "select schemaname || '.' || relname, seq_scan, coalesce(idx_scan, 0), n_live_tup \
+         from pg_stat_user_tables \
+         where seq_scan > coalesce(idx_scan, 0) and n_live_tup > {} \
+         order by seq_tup_read desc limit 10",
+        MIN_ROWS_FOR_HINT
+    ))?;
+    section(
+        "Missing-index hints (large tables read mostly by sequential scans)",
+        &["TABLE", "SEQ SCANS", "INDEX SCANS", "ROWS"],
+        &candidates,
+    );
+
+    let has_statements = engines::run_query(
+        params,
+        profile,
+        "select count(*) from pg_extension where extname = 'pg_stat_statements'",
+    )? == "1";
+    if !has_statements {
+        if !candidates.is_empty() {
+            println!("Install pg_stat_statements to see the queries behind these scans.");
+        }
+        return Ok(());
+    }
+    for candidate in &candidates {
+        let table = &candidate[0];
+        let relname = table.rsplit('.').next().unwrap_or(table);
+        let statements = query(&format!(
+            "select calls, round(mean_exec_time::numeric, 1), \
+             left(regexp_replace(query, '\\s+', ' ', 'g'), 100) \
+             from pg_stat_statements where query ilike {} \
+             order by total_exec_time desc limit 3",
+            postgres::quote_literal(&format!("%{}%", relname))
+        ))?;
+        section(
+            &format!("Top queries on {}", table),
+            &["CALLS", "MEAN MS", "QUERY"],
+            &statements,
+        );
+    }
+    Ok(())
+}
+
+fn section(title: &str, headers: &[&str], rows: &[Vec<String>]) {
+    println!("{}", title);
+    if rows.is_empty() {
+        println!("  none\n");
+        return;
+    }
+    report::print_table(headers, rows);
+    println!();
+}
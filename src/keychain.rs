@@ -0,0 +1,115 @@
+use anyhow::{Context, Result};
+use std::{
+    io::Write,
+    process::{Command, ExitStatus, Stdio},
+};
+
+const SERVICE: &str = "connect-db";
+
+/// Look up a cached secret in the OS keychain (macOS `security`, or
+/// libsecret's `secret-tool` elsewhere). Missing tools count as a miss.
+pub fn get(account: &str) -> Option<String> {
+    let output = if cfg!(target_os = "macos") {
+        Command::new("security")
+            .args(["find-generic-password", "-s", SERVICE, "-a", account, "-w"])
+            .stderr(Stdio::null())
+            .output()
+    } else {
+        Command::new("secret-tool")
+            .args(["lookup", "service", SERVICE, "account", account])
+            .stderr(Stdio::null())
+            .output()
+    }
+    .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+    let secret = String::from_utf8(output.stdout).ok()?;
+    let secret = secret.trim_end_matches('\n');
+    (!secret.is_empty()).then(|| secret.to_string())
+}
+
+pub fn set(account: &str, secret: &str) -> Result<()> {
+    let status = if cfg!(target_os = "macos") {
+        // `security -i` reads the command from stdin, which keeps the secret
+        // out of the argument list other users can see in `ps`
+        if secret.contains(['\n', '\r']) {
+            return Err(anyhow::anyhow!(
+                "The macOS keychain can't store secrets with line breaks"
+            ));
+        }
+        let command = format!(
+            "add-generic-password -U -s {} -a {} -w {}\n",
+            security_quote(SERVICE),
+            security_quote(account),
+            security_quote(secret)
+        );
+        let status = write_to(Command::new("security").arg("-i"), &command)
+            .context("Failed to run security")?;
+        // Interactive mode can exit cleanly after a failed command, so read
+        // the secret back to know it was stored
+        if status.success() && get(account).as_deref() != Some(secret) {
+            return Err(anyhow::anyhow!("Failed to store secret in the keychain"));
+        }
+        status
+    } else {
+        write_to(
+            Command::new("secret-tool")
+                .arg("store")
+                .arg(format!("--label=connect-db {}", account))
+                .args(["service", SERVICE, "account", account]),
+            secret,
+        )
+        .context("Failed to run secret-tool")?
+    };
+
+    if !status.success() {
+        return Err(anyhow::anyhow!("Failed to store secret in the keychain"));
+    }
+    Ok(())
+}
+
+/// Run `command` with `input` on its stdin.
+fn write_to(command: &mut Command, input: &str) -> std::io::Result<ExitStatus> {
+    let mut child = command.stdin(Stdio::piped()).spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input.as_bytes())?;
+    child.wait()
+}
+
+/// A double-quoted word for a `security -i` command line.
+fn security_quote(word: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in word.chars() {
+        if matches!(c, '"' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+pub fn delete(account: &str) -> Result<()> {
+    let status = if cfg!(target_os = "macos") {
+        Command::new("security")
+            .args(["delete-generic-password", "-s", SERVICE, "-a", account])
+            .stdout(Stdio::null())
+            .status()
+            .context("Failed to run security")?
+    } else {
+        Command::new("secret-tool")
+            .args(["clear", "service", SERVICE, "account", account])
+            .status()
+            .context("Failed to run secret-tool")?
+    };
+
+    if !status.success() {
+        return Err(anyhow::anyhow!("Failed to remove secret from the keychain"));
+    }
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use crate::{
+    config::Profile,
+    engines::{self, ConnectionParams, Engine, postgres},
+    prompt,
+};
+use anyhow::Result;
+
+pub struct KillOptions {
+    pub pids: Vec<u32>,
+    /// Only idle sessions whose state is older than this many seconds
+    pub idle_longer_than: Option<u64>,
+    /// `ILIKE` pattern for the session's current or last query
+    pub query_matching: Option<String>,
+    /// `pg_terminate_backend` instead of `pg_cancel_backend`
+    pub terminate: bool,
+    /// List the matching sessions without touching them
+    pub list_only: bool,
+    pub yes: bool,
+}
+
+/// Cancel or terminate the client sessions matching every given filter.
+pub fn kill(
+    name: &str,
+    params: &ConnectionParams,
+    profile: &Profile,
+    options: &KillOptions,
+) -> Result<()> {
+    if !matches!(params.engine, Engine::Postgres | Engine::CockroachDb) {
+        return Err(anyhow::anyhow!("kill needs a PostgreSQL database"));
+    }
+
+    let mut conditions = vec![
+        "pid <> pg_backend_pid()".to_string(),
+        "backend_type = 'client backend'".to_string(),
+        "datname = current_database()".to_string(),
+    ];
+    if !options.pids.is_empty() {
+        let pids: Vec<String> = options.pids.iter().map(u32::to_string).collect();
+        conditions.push(format!("pid in ({})", pids.join(", ")));
+    }
+    if let Some(seconds) = options.idle_longer_than {
+        conditions.push(format!(
+            "state like 'idle%' and state_change < now() - interval '{} seconds'",
+            seconds
+        ));
+    }
+    if let Some(pattern) = &options.query_matching {
+        conditions.push(format!("query ilike {}", postgres::quote_literal(pattern)));
+    }
+    if conditions.len() == 3 {
+        return Err(anyhow::anyhow!(
+            "Pass --pid, --idle-longer-than or --query-matching to choose sessions"
+        ));
+    }
+    let filter = conditions.join(" and ");
+
+    let sessions = engines::run_query(
+        params,
+        profile,
+        &format!(
+            "select pid, usename, coalesce(state, ''), \
+             coalesce(date_trunc('second', now() - state_change)::text, ''), \
+             left(regexp_replace(query, '\\s+', ' ', 'g'), 60) \
+             from pg_stat_activity where {} order by pid",
+            filter
+        ),
+    )?;
+    // Anything but a session row (a notice, a blank line) is dropped, so only
+    // numeric pids ever reach the terminate query
+    let rows: Vec<(u32, Vec<&str>)> = sessions
+        .lines()
+        .map(|line| line.splitn(5, '|').collect::<Vec<&str>>())
+        .filter_map(|row| Some((row[0].trim().parse().ok()?, row)))
+        .collect();
+    if rows.is_empty() {
+        println!("No matching sessions in {}", name);
+        return Ok(());
+    }
+
+    println!(
+        "{:<8} {:<16} {:<20} {:<10} QUERY",
+        "PID", "USER", "STATE", "FOR"
+    );
+    for (_, row) in &rows {
+        let column = |index: usize| row.get(index).copied().unwrap_or_default();
+        println!(
+            "{:<8} {:<16} {:<20} {:<10} {}",
+            column(0),
+            column(1),
+            column(2),
+            column(3),
+            column(4)
+        );
+    }
+
+    let action = if options.terminate {
+        "Terminate"
+    } else {
+        "Cancel the queries of"
+    };
+    if options.list_only {
+        println!("Would {} {} session(s)", action.to_lowercase(), rows.len());
+        return Ok(());
+    }
+    let question = format!("{} {} session(s) in {}?", action, rows.len(), name);
+    if !options.yes && !prompt::confirm(&question)? {
+        return Err(anyhow::anyhow!("Aborted"));
+    }
+
+    // Only the listed pids, even if new sessions started matching meanwhile
+    let pids: Vec<String> = rows.iter().map(|(pid, _)| pid.to_string()).collect();
+    let function = if options.terminate {
+        "pg_terminate_backend"
+    } else {
+        "pg_cancel_backend"
+    };
+    let results = engines::run_query(
+        params,
+        profile,
+        &format!(
+            "select pid, {}(pid) from pg_stat_activity where {} and pid in ({})",
+            function,
+            filter,
+            pids.join(", ")
+        ),
+    )?;
+    let done = results.lines().filter(|line| line.ends_with("|t")).count();
+    println!("{}: {} of {} session(s)", function, done, rows.len());
+    Ok(())
+}
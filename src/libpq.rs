@@ -0,0 +1,273 @@
+//! The libpq environment (`PGHOST`, `PGUSER`, `PGSERVICE`, ...) as a layer
+//! over what the secret backend resolved, and `pg_service.conf` lookups.
+//!
+//! With `libpq_env = "merge"` the environment fills in what the secret left
+//! out; with `"prefer"` it replaces it. A password only ever fills a gap, so
+//! credentials from the secret backend are never swapped for a stray
+//! `PGPASSWORD`.
+
+use crate::{
+    config::{self, Profile},
+    dry_run,
+    engines::{ConnectionParams, Engine},
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{env, fs, path::PathBuf};
+
+/// How the libpq environment combines with the resolved parameters.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvLayer {
+    /// Leave the resolved parameters as they are
+    #[default]
+    Ignore,
+    /// Fill in fields the secret left empty
+    Merge,
+    /// Replace the resolved fields with the environment's
+    Prefer,
+}
+
+/// Environment variables and the connection keywords they stand for.
+pub const VARIABLES: [(&str, &str); 16] = [
+    ("PGHOST", "host"),
+    ("PGHOSTADDR", "hostaddr"),
+    ("PGPORT", "port"),
+    ("PGUSER", "user"),
+    ("PGDATABASE", "dbname"),
+    ("PGPASSWORD", "password"),
+    ("PGSSLMODE", "sslmode"),
+    ("PGSSLROOTCERT", "sslrootcert"),
+    ("PGSSLCERT", "sslcert"),
+    ("PGSSLKEY", "sslkey"),
+    ("PGAPPNAME", "application_name"),
+    ("PGCONNECT_TIMEOUT", "connect_timeout"),
+    ("PGTARGETSESSIONATTRS", "target_session_attrs"),
+    ("PGGSSENCMODE", "gssencmode"),
+    ("PGOPTIONS", "options"),
+    ("PGCHANNELBINDING", "channel_binding"),
+];
+
+/// Apply the libpq environment to `params` as the profile (or the
+/// `libpq_env` setting) asks. Only engines speaking the PostgreSQL protocol
+/// are affected.
+pub fn apply_env(params: &mut ConnectionParams, profile: &Profile) -> Result<()> {
+    let mode = match profile.libpq_env {
+        Some(mode) => mode,
+        None => config::settings()?.libpq_env.unwrap_or_default(),
+    };
+    if mode == EnvLayer::Ignore
+        || !matches!(
+            params.engine,
+            Engine::Postgres | Engine::Redshift | Engine::CockroachDb
+        )
+    {
+        return Ok(());
+    }
+    let keywords = env_keywords()?;
+    apply(
+        params,
+        &keywords,
+        mode == EnvLayer::Prefer,
+        "the environment",
+    );
+    Ok(())
+}
+
+/// The connection keywords the environment sets. Those of `PGSERVICE` come
+/// first and win, as they do in libpq.
+fn env_keywords() -> Result<Keywords> {
+    let mut keywords = match env::var("PGSERVICE") {
+        Ok(service) if !service.is_empty() => self::service(&service)?,
+        _ => Vec::new(),
+    };
+    for (variable, keyword) in VARIABLES {
+        if let Ok(value) = env::var(variable)
+            && !value.is_empty()
+            && !keywords.iter().any(|(key, _)| key == keyword)
+        {
+            keywords.push((keyword.to_string(), value));
+        }
+    }
+    Ok(keywords)
+}
+
+/// Layer libpq `keywords` over `params`, replacing what is set when
+/// `replace` and otherwise only filling gaps. `source` names where they came
+/// from in dry-run reports.
+pub fn apply(
+    params: &mut ConnectionParams,
+    keywords: &[(String, String)],
+    replace: bool,
+    source: &str,
+) {
+    // Hosts come first so ports can pair up with them
+    let mut keywords: Vec<_> = keywords.iter().collect();
+    keywords.sort_by_key(|(keyword, _)| keyword != "host");
+    for (keyword, value) in keywords {
+        let field = match keyword.as_str() {
+            "host" => {
+                if replace || params.host.is_empty() {
+                    set_hosts(params, value);
+                    dry_run::report(format!("using {}={} from {}", keyword, value, source));
+                }
+                continue;
+            }
+            "port" => {
+                if replace || params.port.is_empty() {
+                    set_ports(params, value);
+                    dry_run::report(format!("using port={} from {}", value, source));
+                }
+                continue;
+            }
+            "user" => &mut params.username,
+            "dbname" => &mut params.database,
+            "password" => {
+                if params.password.is_empty() {
+                    params.password = value.clone();
+                    dry_run::report(format!("using the password from {}", source));
+                }
+                continue;
+            }
+            "service" => continue,
+            _ => {
+                let present = params.option(keyword).is_some();
+                if replace || !present {
+                    params.options.retain(|(key, _)| key != keyword);
+                    params.options.push((keyword.clone(), value.clone()));
+                    dry_run::report(format!("using {}={} from {}", keyword, value, source));
+                }
+                continue;
+            }
+        };
+        if replace || field.is_empty() {
+            *field = value.clone();
+            dry_run::report(format!("using {}={} from {}", keyword, value, source));
+        }
+    }
+}
+
+/// `host` may list several hosts, like `PGHOST=a,b`, tried in order.
+fn set_hosts(params: &mut ConnectionParams, value: &str) {
+    let mut hosts = value.split(',').map(str::trim);
+    params.host = hosts.next().unwrap_or_default().to_string();
+    params.failover_hosts = hosts
+        .map(|host| (host.to_string(), params.port.clone()))
+        .collect();
+}
+
+/// One port for every host, or one per host.
+fn set_ports(params: &mut ConnectionParams, value: &str) {
+    let ports: Vec<&str> = value.split(',').map(str::trim).collect();
+    params.port = ports[0].to_string();
+    for (index, (_, port)) in params.failover_hosts.iter_mut().enumerate() {
+        *port = ports.get(index + 1).unwrap_or(&ports[0]).to_string();
+    }
+}
+
+/// Take the network parameters of `pg_service.conf`'s `service` (host,
+/// port, database, TLS settings and so on) over the resolved ones. The
+/// credentials stay those of the secret backend; a service's `user` is only
+/// used when the secret has none, and its `password` never.
+pub fn apply_service(params: &mut ConnectionParams, service: &str) -> Result<()> {
+    let (users, network): (Keywords, Keywords) = self::service(service)?
+        .into_iter()
+        .filter(|(keyword, _)| keyword != "password")
+        .partition(|(keyword, _)| keyword == "user");
+    let source = format!("service {}", service);
+    apply(params, &network, true, &source);
+    apply(params, &users, false, &source);
+    Ok(())
+}
+
+/// The profile's TCP keepalive settings as libpq keywords, which replace
+/// any given in the URL.
+pub fn apply_tcp_settings(params: &mut ConnectionParams, profile: &Profile) {
+    if !matches!(
+        params.engine,
+        Engine::Postgres | Engine::Redshift | Engine::CockroachDb
+    ) {
+        return;
+    }
+    let settings = [
+        ("keepalives_idle", profile.keepalives_idle),
+        ("keepalives_interval", profile.keepalives_interval),
+        ("keepalives_count", profile.keepalives_count),
+        ("tcp_user_timeout", profile.tcp_user_timeout),
+    ];
+    for (keyword, value) in settings {
+        if let Some(value) = value {
+            params.options.retain(|(key, _)| key != keyword);
+            params
+                .options
+                .push((keyword.to_string(), value.to_string()));
+        }
+    }
+}
+
+/// `$PGSERVICEFILE`, or `~/.pg_service.conf`.
+pub fn service_file() -> Result<PathBuf> {
+    match env::var_os("PGSERVICEFILE") {
+        Some(path) if !path.is_empty() => Ok(PathBuf::from(path)),
+        _ => Ok(config::home_dir()?.join(".pg_service.conf")),
+    }
+}
+
+/// The keywords of `service` in the user's service file, or in the system
+/// one under `$PGSYSCONFDIR`.
+pub fn service(service: &str) -> Result<Keywords> {
+    let mut files = vec![service_file()?];
+    if let Some(dir) = env::var_os("PGSYSCONFDIR").filter(|dir| !dir.is_empty()) {
+        files.push(PathBuf::from(dir).join("pg_service.conf"));
+    }
+    for path in &files {
+        if !path.exists() {
+            continue;
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let sections =
+            parse_service_file(&content).with_context(|| format!("In {}", path.display()))?;
+        if let Some((_, keywords)) = sections.into_iter().find(|(name, _)| name == service) {
+            return Ok(keywords);
+        }
+    }
+    Err(anyhow::anyhow!(
+        "No service '{}' in {}",
+        service,
+        files
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(" or ")
+    ))
+}
+
+/// libpq connection keywords and their values, in file order.
+pub type Keywords = Vec<(String, String)>;
+
+/// INI-style `[service]` sections of libpq keywords.
+pub fn parse_service_file(content: &str) -> Result<Vec<(String, Keywords)>> {
+    let mut sections: Vec<(String, Keywords)> = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            sections.push((name.trim().to_string(), Vec::new()));
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .with_context(|| format!("Line {}: expected keyword=value", number + 1))?;
+        let (_, keywords) = sections
+            .last_mut()
+            .with_context(|| format!("Line {}: keyword outside a [service]", number + 1))?;
+        keywords.push((key.trim().to_string(), value.trim().to_string()));
+    }
+    Ok(sections)
+}
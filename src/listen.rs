@@ -0,0 +1,144 @@
+use crate::{
+    config::Profile,
+    dry_run,
+    engines::{ConnectionParams, Engine, client, postgres},
+    notify, time,
+};
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+/// How often psql is prodded so it reports pending notifications
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+struct Notification {
+    channel: String,
+    payload: String,
+    pid: u32,
+}
+
+/// Subscribe to `channels` and print notifications until interrupted,
+/// reconnecting with backoff when the connection drops.
+///
+/// psql only reports notifications after running a command, so it is fed a
+/// no-op query every second.
+pub fn listen(
+    params: &ConnectionParams,
+    profile: &Profile,
+    channels: &[String],
+    as_json: bool,
+) -> Result<()> {
+    if !matches!(params.engine, Engine::Postgres | Engine::CockroachDb) {
+        return Err(anyhow::anyhow!("LISTEN/NOTIFY needs a PostgreSQL database"));
+    }
+    let engine = params.engine.implementation();
+    let psql = client::ensure(engine, "psql")?;
+    let listen_sql: String = channels
+        .iter()
+        .map(|channel| format!("LISTEN \"{}\";\n", channel.replace('"', "\"\"")))
+        .collect();
+
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        eprintln!(
+            "Listening on {} in {}",
+            channels.join(", "),
+            params.database
+        );
+        let mut cmd = Command::new(&psql);
+        cmd.args(postgres::psql_args(params))
+            .args(["-X", "-q", "-A", "-t", "-v", "ON_ERROR_STOP=1"])
+            .envs(engine.client_env(params, profile))
+            .env("PGCONNECT_TIMEOUT", "5");
+        dry_run::stop_before(&cmd);
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Failed to run psql")?;
+
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let listen_sql = listen_sql.clone();
+        thread::spawn(move || {
+            if stdin.write_all(listen_sql.as_bytes()).is_err() {
+                return;
+            }
+            while stdin.write_all(b"SELECT 1 WHERE false;\n").is_ok() {
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+
+        let stdout = child.stdout.take().expect("stdout is piped");
+        for line in BufReader::new(stdout).lines() {
+            let Some(notification) = parse_notification(&line?) else {
+                continue;
+            };
+            // A delivered notification means the connection is healthy
+            backoff = Duration::from_secs(1);
+            let timestamp = time::now_rfc3339();
+            if as_json {
+                println!(
+                    "{}",
+                    json!({
+                        "time": timestamp,
+                        "channel": notification.channel,
+                        "payload": notification.payload,
+                        "pid": notification.pid,
+                    })
+                );
+            } else {
+                println!(
+                    "{} {} {}",
+                    timestamp, notification.channel, notification.payload
+                );
+            }
+        }
+
+        let status = child.wait()?;
+        // Only the first failure after a healthy stretch is worth a popup
+        if backoff == Duration::from_secs(1) {
+            notify::send(
+                &format!("Lost connection to {}", params.database),
+                "Reconnecting with backoff",
+            );
+        }
+        eprintln!(
+            "Connection lost ({}); reconnecting in {}s",
+            status,
+            backoff.as_secs()
+        );
+        thread::sleep(backoff);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Parse psql's `Asynchronous notification "chan" [with payload "x"]
+/// received from server process with PID 123.` line.
+fn parse_notification(line: &str) -> Option<Notification> {
+    let rest = line.strip_prefix("Asynchronous notification \"")?;
+    let (channel, rest) = rest.split_once('"')?;
+    let (payload, rest) = match rest.strip_prefix(" with payload \"") {
+        Some(rest) => {
+            let (payload, rest) = rest.rsplit_once("\" received")?;
+            (payload.to_string(), rest)
+        }
+        None => (String::new(), rest),
+    };
+    let pid = rest
+        .rsplit_once("PID ")?
+        .1
+        .trim_end_matches('.')
+        .parse()
+        .ok()?;
+    Some(Notification {
+        channel: channel.to_string(),
+        payload,
+        pid,
+    })
+}
@@ -0,0 +1,124 @@
+use crate::{
+    config::Profile,
+    dry_run,
+    engines::{self, ConnectionParams, Engine, client, postgres},
+    prompt,
+};
+use anyhow::{Context, Result};
+use std::{
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+pub struct MaintainOptions {
+    pub tables: Vec<String>,
+    pub analyze_only: bool,
+    pub yes: bool,
+}
+
+/// Run `VACUUM (VERBOSE, ANALYZE)` or `ANALYZE VERBOSE`, printing progress
+/// from `pg_stat_progress_vacuum` / `pg_stat_progress_analyze` meanwhile.
+pub fn maintain(
+    name: &str,
+    params: &ConnectionParams,
+    profile: &Profile,
+    options: &MaintainOptions,
+) -> Result<()> {
+    if params.engine != Engine::Postgres {
+        return Err(anyhow::anyhow!("maintain needs a PostgreSQL database"));
+    }
+    if profile.read_only {
+        return Err(anyhow::anyhow!(
+            "{} is read-only; maintenance is not allowed",
+            name
+        ));
+    }
+    for table in &options.tables {
+        if !table
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '"'))
+        {
+            return Err(anyhow::anyhow!("Invalid table name: {}", table));
+        }
+    }
+
+    let (command, progress_view) = if options.analyze_only {
+        ("ANALYZE VERBOSE", "pg_stat_progress_analyze")
+    } else {
+        ("VACUUM (VERBOSE, ANALYZE)", "pg_stat_progress_vacuum")
+    };
+    let sql = format!("{} {}", command, options.tables.join(", "));
+    let target = if options.tables.is_empty() {
+        "all tables".to_string()
+    } else {
+        options.tables.join(", ")
+    };
+    if profile.production
+        && !options.yes
+        && !prompt::confirm(&format!(
+            "{} is a production database. Run {} on {}?",
+            name, command, target
+        ))?
+    {
+        return Err(anyhow::anyhow!("Aborted"));
+    }
+
+    let engine = params.engine.implementation();
+    let psql = client::ensure(engine, "psql")?;
+    let mut psql = Command::new(psql);
+    psql.args(postgres::psql_query_args(params, &sql))
+        .envs(engine.client_env(params, profile))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null());
+    dry_run::stop_before(&psql);
+    eprintln!("Running {} on {} in {}", command, target, name);
+    // VERBOSE output arrives on stderr as INFO messages
+    let mut child = psql.spawn().context("Failed to run psql")?;
+
+    let progress_sql = progress_query(progress_view);
+    let mut last = String::new();
+    while child.try_wait()?.is_none() {
+        thread::sleep(PROGRESS_INTERVAL);
+        // Progress is best effort; the view may be empty between tables
+        let Ok(progress) = engines::run_query(params, profile, &progress_sql) else {
+            continue;
+        };
+        if !progress.is_empty() && progress != last {
+            for line in progress.lines() {
+                let fields: Vec<&str> = line.split('|').collect();
+                if let [table, phase, done, total] = fields[..] {
+                    eprintln!("progress: {} {} {}", table, phase, percent(done, total));
+                }
+            }
+            last = progress;
+        }
+    }
+
+    if !child.wait()?.success() {
+        return Err(anyhow::anyhow!("{} failed", command));
+    }
+    eprintln!("{} finished", command);
+    Ok(())
+}
+
+fn progress_query(view: &str) -> String {
+    let (done, total) = if view == "pg_stat_progress_analyze" {
+        ("sample_blks_scanned", "sample_blks_total")
+    } else {
+        ("heap_blks_scanned", "heap_blks_total")
+    };
+    format!(
+        "select relid::regclass, phase, {}, {} from {} where datname = current_database()",
+        done, total, view
+    )
+}
+
+fn percent(done: &str, total: &str) -> String {
+    match (done.parse::<f64>(), total.parse::<f64>()) {
+        (Ok(done), Ok(total)) if total > 0.0 => format!("{:.0}%", done / total * 100.0),
+        _ => String::new(),
+    }
+}
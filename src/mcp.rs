@@ -0,0 +1,226 @@
+//! `connect-db mcp`: a Model Context Protocol server on stdin and stdout, so
+//! AI assistants can look at development databases through connect-db.
+//!
+//! The tools only read, with the limits of [`crate::sandbox`], and only on
+//! profiles that opt in with `"mcp": true`.
+
+use crate::{
+    config::{self, Profile},
+    engines::{ConnectionParams, Engine},
+    sandbox,
+    sql_params::{Binding, Kind},
+};
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::{
+    fs::File,
+    io::{self, BufRead, Write},
+    os::fd::FromRawFd,
+};
+
+const DEFAULT_LIMIT: usize = 100;
+
+/// Resolves a database name to its profile and connection parameters.
+pub type Resolver<'a> = dyn Fn(&str) -> Result<(Profile, ConnectionParams)> + 'a;
+
+/// Answer JSON-RPC requests on stdin about the databases in `names` until
+/// stdin closes.
+pub fn run(names: &[String], resolve: &Resolver) -> Result<()> {
+    // Whatever else prints to stdout (tunnel notices, say) must not end up
+    // in the protocol stream, so stdout becomes stderr for everyone else
+    let mut output = unsafe {
+        let fd = libc::dup(libc::STDOUT_FILENO);
+        if fd < 0 || libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            return Err(io::Error::last_os_error()).context("Failed to set up stdout");
+        }
+        File::from_raw_fd(fd)
+    };
+    let names: Vec<&String> = names
+        .iter()
+        .filter(|name| config::load_profile(name).is_ok_and(|profile| sandbox::exposed(&profile)))
+        .collect();
+    eprintln!("connect-db MCP server for {} database(s)", names.len());
+
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => handle(&request, &names, resolve),
+            Err(err) => Some(error(Value::Null, -32700, &format!("Parse error: {}", err))),
+        };
+        if let Some(response) = response {
+            writeln!(output, "{}", response)?;
+            output.flush()?;
+        }
+    }
+    Ok(())
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+/// The response to `request`; none for notifications.
+fn handle(request: &Value, names: &[&String], resolve: &Resolver) -> Option<Value> {
+    let id = request.get("id")?.clone();
+    let method = request["method"].as_str().unwrap_or_default();
+    let result = match method {
+        "initialize" => json!({
+            "protocolVersion": request["params"]["protocolVersion"]
+                .as_str()
+                .unwrap_or("2024-11-05"),
+            "capabilities": {"tools": {}},
+            "serverInfo": {"name": "connect-db", "version": env!("CARGO_PKG_VERSION")},
+        }),
+        "ping" => json!({}),
+        "tools/list" => json!({"tools": tools()}),
+        "tools/call" => {
+            let tool = request["params"]["name"].as_str().unwrap_or_default();
+            let arguments = &request["params"]["arguments"];
+            match call(tool, arguments, names, resolve) {
+                Ok(text) => json!({"content": [{"type": "text", "text": text}], "isError": false}),
+                Err(err) => json!({
+                    "content": [{"type": "text", "text": format!("{:#}", err)}],
+                    "isError": true,
+                }),
+            }
+        }
+        _ => return Some(error(id, -32601, &format!("Unknown method {}", method))),
+    };
+    Some(json!({"jsonrpc": "2.0", "id": id, "result": result}))
+}
+
+fn tools() -> Value {
+    let max_rows = sandbox::max_rows();
+    json!([
+        {
+            "name": "list_databases",
+            "description": "List the databases that can be queried, with their engine and description.",
+            "inputSchema": {"type": "object", "properties": {}},
+        },
+        {
+            "name": "query",
+            "description": format!(
+                "Run one read-only SQL statement (SELECT, WITH, SHOW, EXPLAIN, VALUES) and return \
+                 up to `limit` rows as JSON (default {}, at most {}).",
+                DEFAULT_LIMIT, max_rows
+            ),
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {"type": "string"},
+                    "sql": {"type": "string"},
+                    "limit": {"type": "integer", "minimum": 1, "maximum": max_rows},
+                },
+                "required": ["database", "sql"],
+            },
+        },
+        {
+            "name": "describe_schema",
+            "description": "List the tables of a database, or the columns of one table.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "database": {"type": "string"},
+                    "table": {"type": "string", "description": "Table name, optionally schema-qualified"},
+                },
+                "required": ["database"],
+            },
+        },
+    ])
+}
+
+fn call(tool: &str, arguments: &Value, names: &[&String], resolve: &Resolver) -> Result<String> {
+    if tool == "list_databases" {
+        let databases: Vec<Value> = names
+            .iter()
+            .map(|name| {
+                let profile = config::load_profile(name).unwrap_or_default();
+                json!({
+                    "name": name,
+                    "engine": profile.engine,
+                    "environment": profile.environment,
+                    "description": profile.description,
+                })
+            })
+            .collect();
+        return Ok(serde_json::to_string_pretty(&databases)?);
+    }
+    if !matches!(tool, "query" | "describe_schema") {
+        return Err(anyhow::anyhow!("Unknown tool {}", tool));
+    }
+
+    let database = arguments["database"]
+        .as_str()
+        .context("`database` is required")?;
+    if !names.iter().any(|name| *name == database) {
+        return Err(anyhow::anyhow!(
+            "{} is not one of the databases list_databases shows",
+            database
+        ));
+    }
+    let (profile, params) = resolve(database)?;
+    let (sql, bindings, limit) = match tool {
+        "describe_schema" => {
+            let (sql, bindings) = schema_query(&params, arguments["table"].as_str());
+            (sql, bindings, usize::MAX)
+        }
+        _ => {
+            let sql = arguments["sql"].as_str().context("`sql` is required")?;
+            let limit = arguments["limit"]
+                .as_u64()
+                .map_or(DEFAULT_LIMIT, |limit| limit as usize);
+            (sql.to_string(), Vec::new(), limit)
+        }
+    };
+    let result = sandbox::run("mcp", database, &profile, &params, &sql, &bindings, limit)?;
+    Ok(serde_json::to_string_pretty(&sandbox::rows_json(&result))?)
+}
+
+/// Tables, or the columns of `table`, from `information_schema` (or the
+/// SQLite catalog).
+fn schema_query(params: &ConnectionParams, table: Option<&str>) -> (String, Vec<Binding>) {
+    let binding = |name: &str, value: &str| Binding {
+        name: name.to_string(),
+        kind: Kind::Text,
+        value: value.to_string(),
+    };
+    let Some(table) = table else {
+        let sql = match params.engine {
+            Engine::Sqlite => {
+                "select name as table_name, type as table_type from sqlite_master \
+                 where type in ('table', 'view') and name not like 'sqlite_%' order by name"
+            }
+            _ => {
+                "select table_schema, table_name, table_type from information_schema.tables \
+                 where table_schema not in ('pg_catalog', 'information_schema', 'crdb_internal', \
+                 'pg_extension', 'system') order by table_schema, table_name"
+            }
+        };
+        return (sql.to_string(), Vec::new());
+    };
+    if params.engine == Engine::Sqlite {
+        return (
+            "select name as column_name, type as data_type, \"notnull\" = 0 as is_nullable \
+             from pragma_table_info(:table) order by cid"
+                .to_string(),
+            vec![binding("table", table)],
+        );
+    }
+    let (schema, name) = match table.split_once('.') {
+        Some((schema, name)) => (Some(schema), name),
+        None => (None, table),
+    };
+    let mut sql = "select column_name, data_type, is_nullable from information_schema.columns \
+                   where table_name = :table"
+        .to_string();
+    let mut bindings = vec![binding("table", name)];
+    if let Some(schema) = schema {
+        sql.push_str(" and table_schema = :schema");
+        bindings.push(binding("schema", schema));
+    }
+    sql.push_str(" order by table_schema, ordinal_position");
+    (sql, bindings)
+}
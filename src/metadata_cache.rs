@@ -0,0 +1,111 @@
+//! Non-secret connection metadata remembered from the last successful
+//! resolution, so listings keep working when the secret backend is down.
+
+use crate::{
+    config,
+    engines::{ConnectionParams, Engine},
+    time,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf, sync::Mutex};
+
+/// URL options that may carry credentials and are never cached.
+const SENSITIVE_OPTIONS: [&str; 4] = ["password", "secret", "token", "key"];
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CachedMetadata {
+    pub engine: Engine,
+    pub host: String,
+    pub port: String,
+    pub username: String,
+    pub database: String,
+    pub options: Vec<(String, String)>,
+    /// Unix time of the resolution this came from
+    pub cached_at: u64,
+}
+
+impl CachedMetadata {
+    /// Connection parameters without any secrets.
+    pub fn params(&self) -> ConnectionParams {
+        ConnectionParams {
+            engine: self.engine,
+            host: self.host.clone(),
+            port: self.port.clone(),
+            username: self.username.clone(),
+            password: String::new(),
+            database: self.database.clone(),
+            options: self.options.clone(),
+            failover_hosts: Vec::new(),
+            extra: BTreeMap::new(),
+        }
+    }
+}
+
+/// Serializes read-modify-write cycles of concurrent health checks.
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+fn cache_path() -> Result<PathBuf> {
+    Ok(config::config_dir()?.join("metadata-cache.json"))
+}
+
+/// Entries for a selected environment are kept apart as `name@env`.
+fn cache_key(name: &str) -> String {
+    match config::selected_environment() {
+        Some(environment) => format!("{}@{}", name, environment),
+        None => name.to_string(),
+    }
+}
+
+/// Every cached entry, keyed by database name (`name@env` per environment).
+pub fn load_all() -> Result<BTreeMap<String, CachedMetadata>> {
+    let path = cache_path()?;
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+pub fn load(name: &str) -> Result<Option<CachedMetadata>> {
+    Ok(load_all()?.remove(&cache_key(name)))
+}
+
+/// Remember the non-secret parts of freshly resolved parameters.
+pub fn store(name: &str, params: &ConnectionParams) -> Result<()> {
+    let options = params
+        .options
+        .iter()
+        .filter(|(key, _)| {
+            let key = key.to_lowercase();
+            !SENSITIVE_OPTIONS.iter().any(|word| key.contains(word))
+        })
+        .cloned()
+        .collect();
+    let entry = CachedMetadata {
+        engine: params.engine,
+        host: params.host.clone(),
+        port: params.port.clone(),
+        username: params.username.clone(),
+        database: params.database.clone(),
+        options,
+        cached_at: time::unix_now(),
+    };
+
+    let _guard = WRITE_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut entries = load_all()?;
+    entries.insert(cache_key(name), entry);
+
+    // Write and rename so readers in other processes never see half a file
+    let path = cache_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temporary = path.with_extension(format!("json.{}", std::process::id()));
+    fs::write(&temporary, serde_json::to_string_pretty(&entries)?)
+        .with_context(|| format!("Failed to write {}", temporary.display()))?;
+    fs::rename(&temporary, &path).with_context(|| format!("Failed to write {}", path.display()))
+}
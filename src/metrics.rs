@@ -0,0 +1,152 @@
+use crate::{
+    auth::AuthMode,
+    config::Profile,
+    engines::{self, ConnectionParams, Engine},
+    report,
+};
+use anyhow::{Context, Result};
+use std::{
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    time::Instant,
+};
+
+const POSTGRES_STATS: &str = "\
+select (select count(*) from pg_stat_activity where backend_type = 'client backend'), \
+current_setting('max_connections'), pg_database_size(current_database()), \
+case when pg_is_in_recovery() then coalesce(extract(epoch from now() - pg_last_xact_replay_timestamp()), 0) else 0 end";
+
+/// Resolves a database name to its profile and connection parameters.
+pub type Resolver<'a> = dyn Fn(&str) -> Result<(Profile, ConnectionParams)> + 'a;
+
+/// Serve Prometheus metrics about `names` on `listen` (e.g. `:9187`).
+/// Every scrape resolves and checks each database afresh.
+pub fn serve(listen: &str, names: &[String], resolve: &Resolver) -> Result<()> {
+    let address = match listen.strip_prefix(':') {
+        Some(port) => format!("0.0.0.0:{}", port),
+        None => listen.to_string(),
+    };
+    let listener =
+        TcpListener::bind(&address).with_context(|| format!("Failed to listen on {}", address))?;
+    eprintln!(
+        "Serving metrics for {} database(s) on http://{}/metrics",
+        names.len(),
+        address
+    );
+
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        if let Err(err) = handle(stream, names, resolve) {
+            eprintln!("metrics request failed: {}", err);
+        }
+    }
+    Ok(())
+}
+
+fn handle(mut stream: TcpStream, names: &[String], resolve: &Resolver) -> Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+
+    let (status, body) = if path == "/metrics" {
+        ("200 OK", scrape(names, resolve))
+    } else {
+        (
+            "404 Not Found",
+            "Metrics are served on /metrics\n".to_string(),
+        )
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+fn scrape(names: &[String], resolve: &Resolver) -> String {
+    let mut up = String::from(
+        "# HELP connect_db_up Whether the database answered its health check.\n# TYPE connect_db_up gauge\n",
+    );
+    let mut duration = String::from(
+        "# HELP connect_db_check_duration_seconds Time taken by the health check.\n# TYPE connect_db_check_duration_seconds gauge\n",
+    );
+    let mut connections = String::from(
+        "# HELP connect_db_connections Client connections in use.\n# TYPE connect_db_connections gauge\n",
+    );
+    let mut max_connections = String::from(
+        "# HELP connect_db_max_connections Configured max_connections.\n# TYPE connect_db_max_connections gauge\n",
+    );
+    let mut size = String::from(
+        "# HELP connect_db_database_size_bytes Size of the database.\n# TYPE connect_db_database_size_bytes gauge\n",
+    );
+    let mut lag = String::from(
+        "# HELP connect_db_replication_lag_seconds Replay lag on standbys (0 on primaries).\n# TYPE connect_db_replication_lag_seconds gauge\n",
+    );
+
+    for name in names {
+        let label = format!("{{database=\"{}\"}}", name.replace('"', "\\\""));
+        let started = Instant::now();
+        let result = check(name, resolve);
+        let _ = writeln!(
+            duration,
+            "connect_db_check_duration_seconds{} {:.3}",
+            label,
+            started.elapsed().as_secs_f64()
+        );
+        match result {
+            Ok(stats) => {
+                let _ = writeln!(up, "connect_db_up{} 1", label);
+                if let Some([used, max, bytes, seconds]) = stats.as_deref() {
+                    let _ = writeln!(connections, "connect_db_connections{} {}", label, used);
+                    let _ = writeln!(
+                        max_connections,
+                        "connect_db_max_connections{} {}",
+                        label, max
+                    );
+                    let _ = writeln!(size, "connect_db_database_size_bytes{} {}", label, bytes);
+                    let _ = writeln!(
+                        lag,
+                        "connect_db_replication_lag_seconds{} {}",
+                        label, seconds
+                    );
+                }
+            }
+            Err(_) => {
+                let _ = writeln!(up, "connect_db_up{} 0", label);
+            }
+        }
+    }
+    [up, duration, connections, max_connections, size, lag].concat()
+}
+
+/// Health check, plus connection/size/lag stats for PostgreSQL.
+fn check(name: &str, resolve: &Resolver) -> Result<Option<Vec<String>>> {
+    let (profile, params) = resolve(name)?;
+    if matches!(profile.auth, AuthMode::Ldap | AuthMode::Oidc) {
+        return Err(anyhow::anyhow!("interactive authentication"));
+    }
+    if params.engine == Engine::Postgres {
+        let stats = report::rows(&engines::run_query(&params, &profile, POSTGRES_STATS)?);
+        return Ok(stats.into_iter().next());
+    }
+
+    let engine = params.engine.implementation();
+    match engine.health_check_query() {
+        Some(sql) if engine.query_args(&params, &profile, sql).is_some() => {
+            engines::run_query(&params, &profile, sql)?;
+        }
+        _ => {
+            let address = format!("{}:{}", params.host, params.port);
+            if !engines::is_reachable(&address) {
+                return Err(anyhow::anyhow!("{} unreachable", address));
+            }
+        }
+    }
+    Ok(None)
+}
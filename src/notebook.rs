@@ -0,0 +1,139 @@
+//! Session notebooks: each query run with `--notebook` is appended to a
+//! Markdown or HTML file along with its result, for sharing an
+//! investigation.
+
+use crate::time;
+use anyhow::{Context, Result};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+/// One recorded query.
+pub struct Cell<'a> {
+    /// Database, or databases, the query ran on
+    pub database: &'a str,
+    pub sql: &'a str,
+    pub columns: &'a [String],
+    pub rows: &'a [Vec<String>],
+    /// Error message when the query failed
+    pub error: Option<String>,
+    pub truncated: bool,
+}
+
+/// Append `cell` to the notebook at `path`, creating it when missing. Files
+/// ending in `.html` or `.htm` are HTML, anything else Markdown.
+pub fn record(path: &Path, cell: &Cell) -> Result<()> {
+    let html = path.extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("html") || extension.eq_ignore_ascii_case("htm")
+    });
+    let new = fs::metadata(path).map_or(true, |metadata| metadata.len() == 0);
+    let mut text = String::new();
+    if new && html {
+        text.push_str(HTML_HEADER);
+    } else if new {
+        text.push_str("# connect-db session\n");
+    }
+    text.push_str(&if html {
+        html_cell(cell)
+    } else {
+        markdown_cell(cell)
+    });
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    file.write_all(text.as_bytes())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn summary(cell: &Cell) -> String {
+    let rows = if cell.rows.len() == 1 {
+        "1 row".to_string()
+    } else {
+        format!("{} rows", cell.rows.len())
+    };
+    if cell.truncated {
+        format!("{}, stopped at the row limit", rows)
+    } else {
+        rows
+    }
+}
+
+fn markdown_cell(cell: &Cell) -> String {
+    let mut text = format!(
+        "\n## {} at {}\n\n```sql\n{}\n```\n\n",
+        cell.database,
+        time::now_rfc3339(),
+        cell.sql.trim()
+    );
+    if let Some(error) = &cell.error {
+        text.push_str(&format!("```\n{}\n```\n", error));
+        return text;
+    }
+    if cell.columns.is_empty() {
+        text.push_str("No result.\n");
+        return text;
+    }
+    let escape = |cell: &String| cell.replace('|', "\\|").replace('\n', "<br>");
+    let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+    text.push_str(&line(cell.columns.iter().map(escape).collect()));
+    text.push_str(&line(vec!["---".to_string(); cell.columns.len()]));
+    for row in cell.rows {
+        text.push_str(&line(row.iter().map(escape).collect()));
+    }
+    text.push_str(&format!("\n({})\n", summary(cell)));
+    text
+}
+
+const HTML_HEADER: &str = "<!doctype html>\n<meta charset=\"utf-8\">\n<title>connect-db session</title>\n\
+<style>body{font-family:sans-serif;margin:2em}pre{background:#f4f4f4;padding:.6em}\
+table{border-collapse:collapse}td,th{border:1px solid #ccc;padding:.2em .5em;text-align:left}\
+.error{color:#b00}</style>\n<h1>connect-db session</h1>\n";
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html_cell(cell: &Cell) -> String {
+    let mut text = format!(
+        "<section>\n<h2>{} at {}</h2>\n<pre><code>{}</code></pre>\n",
+        escape_html(cell.database),
+        time::now_rfc3339(),
+        escape_html(cell.sql.trim())
+    );
+    if let Some(error) = &cell.error {
+        text.push_str(&format!(
+            "<pre class=\"error\">{}</pre>\n</section>\n",
+            escape_html(error)
+        ));
+        return text;
+    }
+    if cell.columns.is_empty() {
+        text.push_str("<p>No result.</p>\n</section>\n");
+        return text;
+    }
+    let line = |tag: &str, cells: &[String]| {
+        let cells: Vec<String> = cells
+            .iter()
+            .map(|cell| format!("<{0}>{1}</{0}>", tag, escape_html(cell)))
+            .collect();
+        format!("<tr>{}</tr>\n", cells.concat())
+    };
+    text.push_str("<table>\n");
+    text.push_str(&line("th", cell.columns));
+    for row in cell.rows {
+        text.push_str(&line("td", row));
+    }
+    text.push_str(&format!(
+        "</table>\n<p>({})</p>\n</section>\n",
+        summary(cell)
+    ));
+    text
+}
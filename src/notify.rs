@@ -0,0 +1,42 @@
+//! Desktop notifications, sent when `"notifications": true` is set in
+//! `config.json`.
+//!
+//! They go through `notify-send` (libnotify) or `osascript` rather than the
+//! notify-rust crate, so no D-Bus client is linked in and a machine without
+//! either tool just gets no notifications.
+
+use crate::config;
+use anyhow::Result;
+use std::process::{Command, Stdio};
+
+/// Show a desktop notification if enabled. Failures are ignored: a missing
+/// notification daemon must never fail the operation itself.
+pub fn send(summary: &str, body: &str) {
+    if !config::settings().is_ok_and(|settings| settings.notifications) {
+        return;
+    }
+    let mut command = if cfg!(target_os = "macos") {
+        let script = format!(
+            "display notification \"{}\" with title \"{}\"",
+            body.replace('\\', "\\\\").replace('"', "\\\""),
+            summary.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+        let mut command = Command::new("osascript");
+        command.args(["-e", &script]);
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.args(["--app-name", "connect-db", summary, body]);
+        command
+    };
+    let _ = command.stdout(Stdio::null()).stderr(Stdio::null()).status();
+}
+
+/// Notify that `operation` finished or failed, passing the result through.
+pub fn finished<T>(operation: &str, result: Result<T>) -> Result<T> {
+    match &result {
+        Ok(_) => send(&format!("{} finished", operation), ""),
+        Err(err) => send(&format!("{} failed", operation), &err.to_string()),
+    }
+    result
+}
@@ -0,0 +1,222 @@
+//! S3, Google Cloud Storage and Azure Blob locations, driven through the
+//! providers' CLIs so their credential chains and multipart uploads apply.
+
+use anyhow::{Context, Result};
+use std::{
+    env, fmt,
+    process::{Child, Command, Stdio},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Provider {
+    /// `s3://bucket/key` via the aws CLI
+    S3,
+    /// `gs://bucket/object` via gcloud storage
+    Gcs,
+    /// `az://account/container/blob` via azcopy
+    Azure,
+}
+
+#[derive(Debug, Clone)]
+pub struct Location {
+    provider: Provider,
+    /// Provider URL; `https://<account>.blob.core.windows.net/...` for Azure
+    url: String,
+}
+
+/// An object directly below a listed prefix.
+pub struct Object {
+    pub name: String,
+    pub size: u64,
+}
+
+/// Parse an object storage URL; `None` for anything else (local paths).
+pub fn parse(value: &str) -> Option<Location> {
+    let (provider, url) = if value.starts_with("s3://") {
+        (Provider::S3, value.to_string())
+    } else if value.starts_with("gs://") {
+        (Provider::Gcs, value.to_string())
+    } else if let Some(rest) = value.strip_prefix("az://") {
+        let (account, path) = rest.split_once('/').unwrap_or((rest, ""));
+        (
+            Provider::Azure,
+            format!("https://{}.blob.core.windows.net/{}", account, path),
+        )
+    } else if value.starts_with("https://") && value.contains(".blob.core.windows.net/") {
+        (Provider::Azure, value.to_string())
+    } else {
+        return None;
+    };
+    Some(Location { provider, url })
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.url)
+    }
+}
+
+impl Location {
+    /// The object `name` below this location, treated as a prefix.
+    pub fn join(&self, name: &str) -> Location {
+        Location {
+            provider: self.provider,
+            url: format!("{}/{}", self.url.trim_end_matches('/'), name),
+        }
+    }
+
+    pub fn is_prefix(&self) -> bool {
+        self.url.ends_with('/')
+    }
+
+    /// Start an upload that reads the object from its stdin.
+    pub fn upload(&self) -> Result<Child> {
+        let mut cmd = match self.provider {
+            Provider::S3 => self.cli(&["s3", "cp", "-", &self.url]),
+            Provider::Gcs => self.cli(&["storage", "cp", "-", &self.url]),
+            Provider::Azure => self.cli(&["copy", &self.azure_url(), "--from-to", "PipeBlob"]),
+        };
+        cmd.stdin(Stdio::piped()).stdout(Stdio::null());
+        self.spawn(cmd)
+    }
+
+    /// Start a download that writes the object to its stdout.
+    pub fn download(&self) -> Result<Child> {
+        let mut cmd = match self.provider {
+            Provider::S3 => self.cli(&["s3", "cp", &self.url, "-"]),
+            Provider::Gcs => self.cli(&["storage", "cp", &self.url, "-"]),
+            Provider::Azure => self.cli(&["copy", &self.azure_url(), "--from-to", "BlobPipe"]),
+        };
+        cmd.stdout(Stdio::piped());
+        self.spawn(cmd)
+    }
+
+    /// Objects directly below this location, treated as a prefix.
+    pub fn list(&self) -> Result<Vec<Object>> {
+        let prefix = self.join("");
+        let mut cmd = match self.provider {
+            Provider::S3 => self.cli(&["s3", "ls", &prefix.url]),
+            Provider::Gcs => self.cli(&["storage", "ls", "-l", &prefix.url]),
+            Provider::Azure => self.cli(&["list", &prefix.azure_url(), "--machine-readable"]),
+        };
+        let output = cmd
+            .output()
+            .with_context(|| format!("Failed to run {}", self.program()))?;
+        // An empty S3 prefix makes `aws s3 ls` exit 1 without any output
+        let empty = output.stdout.is_empty() && output.stderr.is_empty();
+        if !output.status.success() && !empty {
+            return Err(anyhow::anyhow!(
+                "Failed to list {}: {}",
+                prefix,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(stdout
+            .lines()
+            .filter_map(|line| parse_listing(self.provider, line))
+            .collect())
+    }
+
+    /// Size of this object, if the provider lists it.
+    pub fn size(&self) -> Result<Option<u64>> {
+        let (parent, name) = self.url.rsplit_once('/').unwrap_or(("", &self.url));
+        let parent = Location {
+            provider: self.provider,
+            url: parent.to_string(),
+        };
+        Ok(parent
+            .list()?
+            .into_iter()
+            .find(|object| object.name == name)
+            .map(|object| object.size))
+    }
+
+    pub fn delete(&self) -> Result<()> {
+        let mut cmd = match self.provider {
+            Provider::S3 => self.cli(&["s3", "rm", &self.url]),
+            Provider::Gcs => self.cli(&["storage", "rm", &self.url]),
+            Provider::Azure => self.cli(&["remove", &self.azure_url()]),
+        };
+        let status = cmd
+            .stdout(Stdio::null())
+            .status()
+            .with_context(|| format!("Failed to run {}", self.program()))?;
+        if !status.success() {
+            return Err(anyhow::anyhow!("Failed to delete {}", self));
+        }
+        Ok(())
+    }
+
+    fn program(&self) -> &'static str {
+        match self.provider {
+            Provider::S3 => "aws",
+            Provider::Gcs => "gcloud",
+            Provider::Azure => "azcopy",
+        }
+    }
+
+    fn cli(&self, args: &[&str]) -> Command {
+        let mut cmd = Command::new(self.program());
+        cmd.args(args);
+        cmd
+    }
+
+    fn spawn(&self, mut cmd: Command) -> Result<Child> {
+        cmd.spawn().with_context(|| {
+            format!(
+                "Failed to run {}; it is needed for {} locations",
+                self.program(),
+                self.url.split("://").next().unwrap_or_default()
+            )
+        })
+    }
+
+    /// The blob URL with `AZURE_STORAGE_SAS_TOKEN` appended when set;
+    /// otherwise azcopy uses its own login.
+    fn azure_url(&self) -> String {
+        match env::var("AZURE_STORAGE_SAS_TOKEN") {
+            Ok(token) if !token.is_empty() => {
+                format!("{}?{}", self.url, token.trim_start_matches('?'))
+            }
+            _ => self.url.clone(),
+        }
+    }
+}
+
+/// One listing line; directories and summary lines are skipped.
+fn parse_listing(provider: Provider, line: &str) -> Option<Object> {
+    match provider {
+        // `2026-10-14 13:00:34    1234 orders.dump`, or `PRE dir/`
+        Provider::S3 => {
+            let mut words = line.split_whitespace();
+            let (_date, _time) = (words.next()?, words.next()?);
+            let size = words.next()?.parse().ok()?;
+            let name = words.collect::<Vec<_>>().join(" ");
+            Some(Object { name, size })
+        }
+        // `      1234  2026-10-14T13:00:34Z  gs://bucket/orders.dump`
+        Provider::Gcs => {
+            let mut words = line.split_whitespace();
+            let size = words.next()?.parse().ok()?;
+            let url = words.nth(1)?;
+            let name = url.rsplit('/').next()?.to_string();
+            Some(Object { name, size })
+        }
+        // `INFO: orders.dump;  Content Length: 1234`
+        Provider::Azure => {
+            let rest = line.strip_prefix("INFO: ")?;
+            let (name, length) = rest.split_once(';')?;
+            let size = length
+                .trim()
+                .strip_prefix("Content Length:")?
+                .trim()
+                .parse()
+                .ok()?;
+            Some(Object {
+                name: name.to_string(),
+                size,
+            })
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use crate::http;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// OIDC settings for `auth = "oidc"` profiles.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OidcConfig {
+    /// Issuer URL; endpoints are discovered from its openid-configuration
+    pub issuer: Option<String>,
+    pub device_authorization_endpoint: Option<String>,
+    pub token_endpoint: Option<String>,
+    pub client_id: String,
+    #[serde(default = "default_scope")]
+    pub scope: String,
+    /// Endpoint exchanging the access token for a database credential. When
+    /// unset, the access token itself is used as the password.
+    pub exchange_url: Option<String>,
+    /// Open the verification URL in a browser
+    #[serde(default = "default_true")]
+    pub open_browser: bool,
+}
+
+fn default_scope() -> String {
+    "openid".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Credential obtained through the token exchange.
+pub struct DatabaseCredential {
+    pub username: Option<String>,
+    pub password: String,
+}
+
+/// Run the device-code flow and turn the resulting token into a database
+/// credential for `profile_name`.
+pub fn database_credential(profile_name: &str, oidc: &OidcConfig) -> Result<DatabaseCredential> {
+    let (device_endpoint, token_endpoint) = endpoints(oidc)?;
+    let access_token = device_code_flow(oidc, &device_endpoint, &token_endpoint)?;
+
+    let Some(exchange_url) = &oidc.exchange_url else {
+        return Ok(DatabaseCredential {
+            username: None,
+            password: access_token,
+        });
+    };
+
+    let body = json!({ "profile": profile_name }).to_string();
+    let response = http::post(
+        exchange_url,
+        &[
+            ("Authorization", &format!("Bearer {}", access_token)),
+            ("Content-Type", "application/json"),
+        ],
+        &body,
+    )?;
+    if !response.is_success() {
+        return Err(anyhow::anyhow!(
+            "Token exchange failed with HTTP {}: {}",
+            response.status,
+            response.body.trim()
+        ));
+    }
+
+    let credential = response.json()?;
+    Ok(DatabaseCredential {
+        username: credential
+            .get("username")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        password: credential
+            .get("password")
+            .and_then(Value::as_str)
+            .context("Token exchange response has no 'password'")?
+            .to_string(),
+    })
+}
+
+fn endpoints(oidc: &OidcConfig) -> Result<(String, String)> {
+    if let (Some(device), Some(token)) = (&oidc.device_authorization_endpoint, &oidc.token_endpoint)
+    {
+        return Ok((device.clone(), token.clone()));
+    }
+
+    let issuer = oidc
+        .issuer
+        .as_deref()
+        .context("OIDC profile needs an issuer or explicit endpoints")?;
+    let discovery_url = format!(
+        "{}/.well-known/openid-configuration",
+        issuer.trim_end_matches('/')
+    );
+    let response = http::get(&discovery_url, &[])?;
+    if !response.is_success() {
+        return Err(anyhow::anyhow!(
+            "OIDC discovery failed with HTTP {}: {}",
+            response.status,
+            discovery_url
+        ));
+    }
+    let discovery = response.json()?;
+    let endpoint = |configured: &Option<String>, key: &str| -> Result<String> {
+        match configured {
+            Some(url) => Ok(url.clone()),
+            None => discovery
+                .get(key)
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .with_context(|| format!("Issuer does not advertise {}", key)),
+        }
+    };
+
+    Ok((
+        endpoint(
+            &oidc.device_authorization_endpoint,
+            "device_authorization_endpoint",
+        )?,
+        endpoint(&oidc.token_endpoint, "token_endpoint")?,
+    ))
+}
+
+/// RFC 8628 device authorization grant; returns the access token.
+fn device_code_flow(
+    oidc: &OidcConfig,
+    device_endpoint: &str,
+    token_endpoint: &str,
+) -> Result<String> {
+    let form_header = [("Content-Type", "application/x-www-form-urlencoded")];
+    let response = http::post(
+        device_endpoint,
+        &form_header,
+        &http::form(&[("client_id", &oidc.client_id), ("scope", &oidc.scope)]),
+    )?;
+    if !response.is_success() {
+        return Err(anyhow::anyhow!(
+            "Device authorization failed with HTTP {}: {}",
+            response.status,
+            response.body.trim()
+        ));
+    }
+    let authorization = response.json()?;
+    let field = |key: &str| authorization.get(key).and_then(Value::as_str);
+
+    let device_code = field("device_code").context("Missing device_code in response")?;
+    let verification_uri = field("verification_uri_complete")
+        .or_else(|| field("verification_uri"))
+        .context("Missing verification_uri in response")?;
+    let mut interval = authorization
+        .get("interval")
+        .and_then(Value::as_u64)
+        .unwrap_or(5);
+    let expires_in = authorization
+        .get("expires_in")
+        .and_then(Value::as_u64)
+        .unwrap_or(600);
+
+    println!("To sign in, open {}", verification_uri);
+    if let Some(user_code) = field("user_code") {
+        println!("and enter the code: {}", user_code);
+    }
+    if oidc.open_browser {
+        open_browser(verification_uri);
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(expires_in);
+    while Instant::now() < deadline {
+        thread::sleep(Duration::from_secs(interval));
+
+        let response = http::post(
+            token_endpoint,
+            &form_header,
+            &http::form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                ("device_code", device_code),
+                ("client_id", &oidc.client_id),
+            ]),
+        )?;
+        let token = response.json()?;
+        if response.is_success() {
+            return token
+                .get("access_token")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .context("Token response has no access_token");
+        }
+
+        match token.get("error").and_then(Value::as_str) {
+            Some("authorization_pending") => {}
+            Some("slow_down") => interval += 5,
+            Some(error) => {
+                let description = token
+                    .get("error_description")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                return Err(anyhow::anyhow!("Sign-in failed: {} {}", error, description));
+            }
+            None => {
+                return Err(anyhow::anyhow!(
+                    "Token endpoint returned HTTP {}",
+                    response.status
+                ));
+            }
+        }
+    }
+
+    Err(anyhow::anyhow!("Sign-in timed out"))
+}
+
+fn open_browser(url: &str) {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    let _ = Command::new(opener)
+        .arg(url)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+}
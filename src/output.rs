@@ -0,0 +1,281 @@
+//! Writing query results to files, or to stdout for `-`, as CSV, JSON Lines,
+//! JSON or Parquet (the last through the DuckDB CLI, and only to files).
+//!
+//! Parquet is not written with the arrow and parquet crates: DuckDB converts a
+//! staged CSV instead and infers the column types, which the text rows from
+//! the clients don't carry.
+
+use crate::{
+    csv,
+    engines::{Engine, client},
+};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Csv,
+    /// One JSON object per line
+    Jsonl,
+    /// A JSON array of objects
+    Json,
+    Parquet,
+}
+
+impl OutputFormat {
+    /// The format named by the file extension; CSV for stdout.
+    pub fn from_path(path: &Path) -> Result<OutputFormat> {
+        if is_stdout(path) {
+            return Ok(OutputFormat::Csv);
+        }
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("csv") => Ok(OutputFormat::Csv),
+            Some("jsonl" | "ndjson") => Ok(OutputFormat::Jsonl),
+            Some("json") => Ok(OutputFormat::Json),
+            Some("parquet") => Ok(OutputFormat::Parquet),
+            _ => Err(anyhow::anyhow!(
+                "Can't tell the output format of {}; use a .csv, .jsonl, .json or .parquet name or pass --output-format",
+                path.display()
+            )),
+        }
+    }
+}
+
+/// Whether `path` is `-`, for stdout.
+pub fn is_stdout(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Write `rows` to `path` as they arrive, returning how many were written.
+/// Only one row is held at a time, and the client waits while the output
+/// can't keep up. A failed write leaves no partial file behind.
+pub fn write(
+    columns: &[String],
+    rows: impl Iterator<Item = io::Result<Vec<String>>>,
+    path: &Path,
+    format: OutputFormat,
+) -> Result<u64> {
+    if is_stdout(path) {
+        return match format {
+            OutputFormat::Parquet => Err(anyhow::anyhow!("Parquet can only be written to a file")),
+            _ => write_text(columns, rows, path, format),
+        };
+    }
+    let result = match format {
+        OutputFormat::Parquet => write_parquet(columns, rows, path),
+        _ => write_text(columns, rows, path, format),
+    };
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+fn write_text(
+    columns: &[String],
+    rows: impl Iterator<Item = io::Result<Vec<String>>>,
+    path: &Path,
+    format: OutputFormat,
+) -> Result<u64> {
+    let file: Box<dyn Write> = match is_stdout(path) {
+        true => Box::new(io::stdout().lock()),
+        false => Box::new(
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?,
+        ),
+    };
+    let mut output = BufWriter::new(file);
+    let written = write_rows(columns, rows, &mut output, format)?;
+    output
+        .flush()
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(written)
+}
+
+/// Write the header (for CSV) and each row to `output` as it arrives.
+fn write_rows(
+    columns: &[String],
+    rows: impl Iterator<Item = io::Result<Vec<String>>>,
+    output: &mut impl Write,
+    format: OutputFormat,
+) -> Result<u64> {
+    let object = |row: Vec<String>| -> Value {
+        let fields: Map<String, Value> = columns
+            .iter()
+            .cloned()
+            .zip(row.into_iter().map(Value::String))
+            .collect();
+        Value::Object(fields)
+    };
+
+    match format {
+        OutputFormat::Csv => csv::write_record(output, columns)?,
+        OutputFormat::Json => output.write_all(b"[")?,
+        _ => {}
+    }
+    let mut written = 0u64;
+    for row in rows {
+        let row = row?;
+        match format {
+            OutputFormat::Csv => csv::write_record(output, &row)?,
+            OutputFormat::Json => {
+                if written > 0 {
+                    output.write_all(b",")?;
+                }
+                output.write_all(b"\n  ")?;
+                serde_json::to_writer(&mut *output, &object(row))?;
+            }
+            _ => {
+                serde_json::to_writer(&mut *output, &object(row))?;
+                output.write_all(b"\n")?;
+            }
+        }
+        written += 1;
+    }
+    if format == OutputFormat::Json {
+        output.write_all(b"\n]\n")?;
+    }
+    Ok(written)
+}
+
+/// Stage the rows as CSV next to `path` and let DuckDB convert them, which
+/// also infers column types.
+fn write_parquet(
+    columns: &[String],
+    rows: impl Iterator<Item = io::Result<Vec<String>>>,
+    path: &Path,
+) -> Result<u64> {
+    let duckdb = client::ensure(Engine::DuckDb.implementation(), "duckdb")?;
+    let mut staged = PathBuf::from(path);
+    staged.set_extension("parquet.csv.partial");
+    let rows = write_text(columns, rows, &staged, OutputFormat::Csv);
+    let converted = rows.and_then(|rows| {
+        let sql = format!(
+            "copy (select * from read_csv({}, header = true)) to {} (format parquet)",
+            sql_string(&staged),
+            sql_string(path)
+        );
+        let output = Command::new(&duckdb)
+            .args(["-c", &sql])
+            .output()
+            .context("Failed to run duckdb")?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "duckdb failed to write {}: {}",
+                path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(rows)
+    });
+    let _ = fs::remove_file(&staged);
+    converted
+}
+
+fn sql_string(path: &Path) -> String {
+    format!("'{}'", path.display().to_string().replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        cell::Cell,
+        io::{BufReader, Read},
+        rc::Rc,
+    };
+
+    /// Rows the client has produced and rows that reached the output, and
+    /// the furthest the first ever got ahead of the second.
+    #[derive(Default)]
+    struct Progress {
+        produced: Cell<usize>,
+        written: Cell<usize>,
+        most_ahead: Cell<usize>,
+    }
+
+    /// CSV text for `rows` rows, generated as it is read like the output of
+    /// a client.
+    struct Client {
+        rows: usize,
+        line: Vec<u8>,
+        offset: usize,
+        progress: Rc<Progress>,
+    }
+
+    impl Read for Client {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            if self.offset == self.line.len() {
+                if self.progress.produced.get() == self.rows {
+                    return Ok(0);
+                }
+                let row = self.progress.produced.get() + 1;
+                self.progress.produced.set(row);
+                let ahead = row.saturating_sub(self.progress.written.get());
+                if ahead > self.progress.most_ahead.get() {
+                    self.progress.most_ahead.set(ahead);
+                }
+                self.line =
+                    format!("{},\"customer, {}\",{}\n", row, row, "x".repeat(80)).into_bytes();
+                self.offset = 0;
+            }
+            let count = buffer.len().min(self.line.len() - self.offset);
+            buffer[..count].copy_from_slice(&self.line[self.offset..self.offset + count]);
+            self.offset += count;
+            Ok(count)
+        }
+    }
+
+    /// Output that counts the rows reaching it, one per line.
+    struct Sink(Rc<Progress>);
+
+    impl Write for Sink {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            let lines = buffer.iter().filter(|byte| **byte == b'\n').count();
+            self.0.written.set(self.0.written.get() + lines);
+            Ok(buffer.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rows_are_written_while_the_client_still_produces_them() {
+        let columns = ["id", "name", "padding"].map(str::to_string);
+        for format in [OutputFormat::Csv, OutputFormat::Jsonl, OutputFormat::Json] {
+            let rows = 20_000;
+            let progress = Rc::new(Progress::default());
+            let client = Client {
+                rows,
+                line: Vec::new(),
+                offset: 0,
+                progress: progress.clone(),
+            };
+            // The reader and writer buffers the exec path uses
+            let stream = csv::Reader::new(BufReader::new(client));
+            let mut output = BufWriter::new(Sink(progress.clone()));
+            let written = write_rows(&columns, stream, &mut output, format).unwrap();
+            output.flush().unwrap();
+            assert_eq!(written, rows as u64);
+            assert!(progress.written.get() >= rows);
+            // Only what fits in the two buffers may be in flight, never the result
+            assert!(
+                progress.most_ahead.get() < 500,
+                "{:?}: the client got {} rows ahead of the output",
+                format,
+                progress.most_ahead.get()
+            );
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Plugins: `connect-db-<name>` executables on PATH run as extra subcommands,
+//! like git's, and `connect-db-secret-<name>` executables resolve secrets for
+//! `auth = "plugin"` profiles.
+//!
+//! Secret plugins get one JSON request on stdin:
+//! `{"version": 1, "database": "...", "secret": "...", "username": "...", "db_url": "..."}`
+//! (`username` and `db_url` only when the profile sets them) and answer on
+//! stdout with `{"db_url": "...", "username": "...", "password": "...", "extra": {...}}`,
+//! every field optional, or `{"error": "..."}`.
+
+use crate::{engines::client, error::ConnectDbError};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+const PREFIX: &str = "connect-db-";
+const SECRET_PREFIX: &str = "connect-db-secret-";
+
+/// Version of the secret plugin handshake sent in each request.
+pub const PROTOCOL_VERSION: u64 = 1;
+
+/// The `connect-db-<name>` executable for a subcommand, if one is on PATH.
+pub fn find(name: &str) -> Option<PathBuf> {
+    if name.is_empty() || name.contains('/') {
+        return None;
+    }
+    client::locate(&format!("{}{}", PREFIX, name))
+}
+
+/// Every plugin on PATH by name, the first one found winning, secret
+/// plugins as `secret-<name>`.
+pub fn discover() -> BTreeMap<String, PathBuf> {
+    let mut plugins = BTreeMap::new();
+    let Some(path) = env::var_os("PATH") else {
+        return plugins;
+    };
+    for dir in env::split_paths(&path) {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if let Some(name) = file_name.strip_prefix(PREFIX)
+                && !plugins.contains_key(name)
+                && let Some(path) = client::locate(&dir.join(&file_name).display().to_string())
+            {
+                plugins.insert(name.to_string(), path);
+            }
+        }
+    }
+    plugins
+}
+
+/// Replace this process with the plugin, passing `args` through. The plugin
+/// finds this executable in `$CONNECT_DB` to call back into it.
+pub fn exec(path: &PathBuf, args: &[String]) -> Result<()> {
+    if let Ok(current) = env::current_exe() {
+        unsafe {
+            env::set_var("CONNECT_DB", current);
+        }
+    }
+    let err = exec::Command::new(path).args(args).exec();
+    Err(anyhow::anyhow!(
+        "Failed to exec {}: {}",
+        path.display(),
+        err
+    ))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct SecretResponse {
+    pub db_url: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub extra: BTreeMap<String, String>,
+    error: Option<String>,
+}
+
+/// Ask the `connect-db-secret-<backend>` plugin for the secrets of `database`.
+pub fn resolve_secret(
+    backend: &str,
+    database: &str,
+    secret: &str,
+    username: Option<&str>,
+    db_url: Option<&str>,
+) -> Result<SecretResponse> {
+    let binary = format!("{}{}", SECRET_PREFIX, backend);
+    let path = client::locate(&binary).ok_or_else(|| {
+        ConnectDbError::SecretNotFound(format!("Secret plugin {} was not found on PATH", binary))
+    })?;
+    let mut request = json!({
+        "version": PROTOCOL_VERSION,
+        "database": database,
+        "secret": secret,
+    });
+    if let Some(username) = username {
+        request["username"] = username.into();
+    }
+    if let Some(db_url) = db_url {
+        request["db_url"] = db_url.into();
+    }
+
+    let mut child = Command::new(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", binary))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(request.to_string().as_bytes())?;
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("Failed to run {}", binary))?;
+
+    let response: SecretResponse = serde_json::from_slice(&output.stdout).with_context(|| {
+        ConnectDbError::SecretParse(format!("{} answered with invalid JSON", binary))
+    })?;
+    if let Some(error) = response.error {
+        return Err(ConnectDbError::SecretNotFound(format!("{}: {}", binary, error)).into());
+    }
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("{} failed with {}", binary, output.status));
+    }
+    Ok(response)
+}
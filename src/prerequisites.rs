@@ -0,0 +1,111 @@
+//! Network prerequisites of a profile, like a VPN being up, checked before
+//! connecting so a missing VPN is reported as such instead of as a timeout.
+
+use crate::{engines, error::ConnectDbError};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{fs, net::Ipv4Addr, process::Command};
+
+/// One entry of a profile's `prerequisites`; set one of the checks.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Prerequisite {
+    /// IPv4 network like `10.0.0.0/8` that must have a route other than the
+    /// default one
+    pub route: Option<String>,
+    /// Network interface like `wg0` that must exist and be up
+    pub interface: Option<String>,
+    /// `host:port` that must accept TCP connections
+    pub reachable: Option<String>,
+    /// What to tell the user when the check fails, e.g. "run `corpvpn connect`"
+    pub hint: Option<String>,
+}
+
+/// Check every prerequisite, failing on the first that isn't met.
+pub fn check(name: &str, prerequisites: &[Prerequisite]) -> Result<()> {
+    for prerequisite in prerequisites {
+        let problem = if let Some(network) = &prerequisite.route {
+            (!has_route(network)?).then(|| format!("no route to {}", network))
+        } else if let Some(interface) = &prerequisite.interface {
+            (!interface_up(interface)).then(|| format!("network interface {} is not up", interface))
+        } else if let Some(address) = &prerequisite.reachable {
+            (!engines::is_reachable(address)).then(|| format!("{} is unreachable", address))
+        } else {
+            return Err(anyhow::anyhow!(
+                "Prerequisites of {} need one of route, interface or reachable",
+                name
+            ));
+        };
+        if let Some(problem) = problem {
+            let mut message = format!("Can't connect to {}: {}", name, problem);
+            if let Some(hint) = &prerequisite.hint {
+                message.push_str(&format!("\nTo fix it, {}", hint));
+            }
+            return Err(ConnectDbError::Unreachable(message).into());
+        }
+    }
+    Ok(())
+}
+
+fn parse_network(network: &str) -> Result<(u32, u32)> {
+    let (address, length) = network.split_once('/').unwrap_or((network, "32"));
+    let address: Ipv4Addr = address
+        .parse()
+        .with_context(|| format!("Invalid network in prerequisites: {}", network))?;
+    let length: u32 = length
+        .parse()
+        .ok()
+        .filter(|length| *length <= 32)
+        .with_context(|| format!("Invalid prefix length in prerequisites: {}", network))?;
+    Ok((u32::from(address), length))
+}
+
+fn mask(length: u32) -> u32 {
+    u32::MAX.checked_shl(32 - length).unwrap_or(0)
+}
+
+/// Whether some route narrower than the default one covers `network`.
+fn has_route(network: &str) -> Result<bool> {
+    let (address, length) = parse_network(network)?;
+    if let Ok(table) = fs::read_to_string("/proc/net/route") {
+        // Destination and mask are hex in host byte order of network-order bytes
+        let parse = |field: &str| {
+            u32::from_str_radix(field, 16)
+                .ok()
+                .map(|value| u32::from(Ipv4Addr::from(value.to_le_bytes())))
+        };
+        return Ok(table.lines().skip(1).any(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (Some(destination), Some(route_mask)) = (
+                fields.get(1).and_then(|field| parse(field)),
+                fields.get(7).and_then(|field| parse(field)),
+            ) else {
+                return false;
+            };
+            let route_length = route_mask.count_ones();
+            route_length > 0
+                && route_length <= length
+                && address & mask(route_length) == destination & mask(route_length)
+        }));
+    }
+    // BSD and macOS: ask the routing table about the network's first address
+    let output = Command::new("route")
+        .args(["-n", "get", &Ipv4Addr::from(address).to_string()])
+        .output()
+        .context("Failed to run route")?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    Ok(output.status.success() && !text.contains("destination: default"))
+}
+
+fn interface_up(interface: &str) -> bool {
+    if let Ok(state) = fs::read_to_string(format!("/sys/class/net/{}/operstate", interface)) {
+        // Point-to-point tunnels like WireGuard report "unknown" while up
+        return state.trim() != "down";
+    }
+    Command::new("ifconfig")
+        .arg(interface)
+        .output()
+        .is_ok_and(|output| {
+            output.status.success() && String::from_utf8_lossy(&output.stdout).contains("UP")
+        })
+}
@@ -0,0 +1,168 @@
+use crate::{
+    config::{config_dir, home_dir},
+    dry_run,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// A connection profile kept in the encrypted local store. Mirrors the
+/// contents of the `.db.json` / `.db-role.json` pair in `.vault/secrets`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredProfile {
+    pub db_url: String,
+    pub username: String,
+    pub password: String,
+}
+
+/// Age-encrypted profile store at `~/.config/connect-db/profiles.age`.
+///
+/// Encryption and decryption are delegated to the `age` binary. With an
+/// identity (an age key file or an SSH private key) the store is encrypted to
+/// the matching recipient; without one, `age` prompts for a passphrase.
+///
+/// Nothing is looked up until the store is used, so commands that never
+/// touch it also work without a home directory or an SSH key.
+pub struct ProfileStore {
+    identity: Option<PathBuf>,
+}
+
+impl ProfileStore {
+    pub fn open(identity: Option<PathBuf>) -> Self {
+        ProfileStore { identity }
+    }
+
+    fn path(&self) -> Result<PathBuf> {
+        Ok(config_dir()?.join("profiles.age"))
+    }
+
+    fn identity(&self) -> Option<PathBuf> {
+        self.identity.clone().or_else(default_identity)
+    }
+
+    /// Whether the store file exists; without a config directory there is
+    /// no store to find.
+    pub fn exists(&self) -> bool {
+        self.path().is_ok_and(|path| path.exists())
+    }
+
+    pub fn load(&self) -> Result<BTreeMap<String, StoredProfile>> {
+        if !self.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let path = self.path()?;
+
+        let mut cmd = Command::new("age");
+        cmd.arg("--decrypt");
+        if let Some(identity) = self.identity() {
+            cmd.arg("--identity").arg(identity);
+        }
+        cmd.arg(&path).stderr(Stdio::inherit());
+
+        let output = cmd.output().context("Failed to run age")?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "Failed to decrypt profile store: {}",
+                path.display()
+            ));
+        }
+
+        serde_json::from_slice(&output.stdout)
+            .with_context(|| format!("Failed to parse profile store: {}", path.display()))
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<StoredProfile>> {
+        Ok(self.load()?.remove(name))
+    }
+
+    pub fn save(&self, profiles: &BTreeMap<String, StoredProfile>) -> Result<()> {
+        let path = self.path()?;
+        if dry_run::enabled() {
+            dry_run::stop(&format!(
+                "write {} with profiles: {}",
+                path.display(),
+                profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            ));
+        }
+        let dir = path.parent().expect("store path has a parent");
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+
+        let tmp_path = path.with_extension("age.tmp");
+        let mut cmd = Command::new("age");
+        cmd.arg("--encrypt");
+        match self.identity() {
+            Some(identity) => {
+                cmd.arg("--recipient").arg(recipient_for(&identity)?);
+            }
+            None => {
+                cmd.arg("--passphrase");
+            }
+        }
+        cmd.arg("--output")
+            .arg(&tmp_path)
+            .stdin(Stdio::piped())
+            .stderr(Stdio::inherit());
+
+        let mut child = cmd.spawn().context("Failed to run age")?;
+        let plaintext = serde_json::to_vec_pretty(profiles)?;
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(&plaintext)?;
+        if !child.wait()?.success() {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(anyhow::anyhow!("Failed to encrypt profile store"));
+        }
+
+        fs::set_permissions(&tmp_path, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("Failed to write profile store: {}", path.display()))
+    }
+}
+
+/// Prefer an SSH key so most users never have to manage a separate age key.
+fn default_identity() -> Option<PathBuf> {
+    let ssh_dir = home_dir().ok()?.join(".ssh");
+    ["id_ed25519", "id_rsa"]
+        .iter()
+        .map(|key| ssh_dir.join(key))
+        .find(|key| key.exists() && key.with_extension("pub").exists())
+}
+
+/// The public recipient matching an identity: `<key>.pub` for SSH keys, or the
+/// output of `age-keygen -y` for native age identities.
+fn recipient_for(identity: &Path) -> Result<String> {
+    let mut public_key = identity.as_os_str().to_owned();
+    public_key.push(".pub");
+    let public_key = PathBuf::from(public_key);
+    if public_key.exists() {
+        return Ok(fs::read_to_string(&public_key)
+            .with_context(|| format!("Failed to read public key: {}", public_key.display()))?
+            .trim()
+            .to_string());
+    }
+
+    let output = Command::new("age-keygen")
+        .arg("-y")
+        .arg(identity)
+        .stderr(Stdio::inherit())
+        .output()
+        .context("Failed to run age-keygen")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to derive recipient from identity: {}",
+            identity.display()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,143 @@
+//! Single-line progress bar on stderr for long-running transfers.
+//!
+//! A small stand-in for indicatif's `ProgressBar`: one bar at a time, redrawn
+//! at most every 100ms and only when stderr is a terminal.
+
+use crate::storage;
+use std::{
+    io::{IsTerminal, Write},
+    time::{Duration, Instant},
+};
+
+const WIDTH: usize = 30;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy)]
+pub enum Unit {
+    Bytes,
+    Tables,
+    Rows,
+    Chunks,
+}
+
+pub struct Progress {
+    label: String,
+    unit: Unit,
+    total: u64,
+    done: u64,
+    detail: String,
+    started: Instant,
+    last_draw: Option<Instant>,
+    /// Last 10% step reported when stderr is not a terminal
+    last_step: u64,
+    interactive: bool,
+}
+
+impl Progress {
+    pub fn new(label: &str, total: u64, unit: Unit) -> Self {
+        Progress {
+            label: label.to_string(),
+            unit,
+            total,
+            done: 0,
+            detail: String::new(),
+            started: Instant::now(),
+            last_draw: None,
+            last_step: 0,
+            interactive: std::io::stderr().is_terminal(),
+        }
+    }
+
+    /// Update the amount done (capped at the total) and the item in progress.
+    pub fn set(&mut self, done: u64, detail: &str) {
+        self.done = done.min(self.total);
+        self.detail = detail.to_string();
+        self.draw(false);
+    }
+
+    /// Print a line (e.g. a warning) without garbling the bar.
+    pub fn message(&mut self, line: &str) {
+        if self.interactive {
+            eprint!("\r\x1b[2K");
+        }
+        eprintln!("{}", line);
+        self.draw(true);
+    }
+
+    pub fn finish(&mut self) {
+        self.done = self.total;
+        self.detail.clear();
+        self.draw(true);
+        if self.interactive {
+            eprintln!();
+        }
+    }
+
+    fn format(&self, amount: u64) -> String {
+        match self.unit {
+            Unit::Bytes => storage::format_size(amount),
+            Unit::Tables | Unit::Rows | Unit::Chunks => amount.to_string(),
+        }
+    }
+
+    fn draw(&mut self, force: bool) {
+        let fraction = if self.total == 0 {
+            1.0
+        } else {
+            self.done as f64 / self.total as f64
+        };
+        if !self.interactive {
+            let step = (fraction * 10.0) as u64;
+            if step > self.last_step {
+                self.last_step = step;
+                eprintln!("{}: {}%", self.label, (fraction * 100.0).floor());
+            }
+            return;
+        }
+        if !force
+            && self
+                .last_draw
+                .is_some_and(|last| last.elapsed() < REDRAW_INTERVAL)
+        {
+            return;
+        }
+        self.last_draw = Some(Instant::now());
+
+        let filled = (fraction * WIDTH as f64) as usize;
+        let eta = match self.started.elapsed().as_secs_f64() {
+            elapsed if fraction > 0.0 && fraction < 1.0 => {
+                let remaining = elapsed / fraction - elapsed;
+                format!(" ETA {}", format_duration(remaining as u64))
+            }
+            _ => String::new(),
+        };
+        let unit = match self.unit {
+            Unit::Bytes => "",
+            Unit::Tables => " tables",
+            Unit::Rows => " rows",
+            Unit::Chunks => " chunks",
+        };
+        eprint!(
+            "\r\x1b[2K{} [{}{}] {:>3.0}% {}/{}{}{}  {}",
+            self.label,
+            "#".repeat(filled),
+            " ".repeat(WIDTH - filled),
+            fraction * 100.0,
+            self.format(self.done),
+            self.format(self.total),
+            unit,
+            eta,
+            self.detail
+        );
+        let _ = std::io::stderr().flush();
+    }
+}
+
+/// `42s`, `3m05s` or `1h07m`.
+pub fn format_duration(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow::{Context, Result};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    process::Command,
+};
+
+/// Read a line from the controlling terminal without echoing it.
+pub fn hidden(prompt: &str) -> Result<String> {
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .context("No terminal available to prompt for input")?;
+    write!(tty, "{}", prompt)?;
+    tty.flush()?;
+
+    let echo_disabled = set_echo(false);
+    let mut line = String::new();
+    let result = BufReader::new(&tty).read_line(&mut line);
+    if echo_disabled {
+        set_echo(true);
+    }
+    writeln!(tty)?;
+    result.context("Failed to read from terminal")?;
+
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn set_echo(enabled: bool) -> bool {
+    let Ok(tty) = File::open("/dev/tty") else {
+        return false;
+    };
+    Command::new("stty")
+        .arg(if enabled { "echo" } else { "-echo" })
+        .stdin(tty)
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// Ask a yes/no question on the controlling terminal; anything but y/yes is no.
+pub fn confirm(question: &str) -> Result<bool> {
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .context("No terminal available to confirm; pass --yes to proceed")?;
+    write!(tty, "{} [y/N] ", question)?;
+    tty.flush()?;
+
+    let mut line = String::new();
+    BufReader::new(&tty)
+        .read_line(&mut line)
+        .context("Failed to read from terminal")?;
+    Ok(matches!(line.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Ask for a line of text on the controlling terminal, with a default for
+/// an empty answer.
+pub fn line(question: &str, default: Option<&str>) -> Result<String> {
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .context("No terminal available to prompt for input")?;
+    match default {
+        Some(default) => write!(tty, "{} [{}]: ", question, default)?,
+        None => write!(tty, "{}: ", question)?,
+    }
+    tty.flush()?;
+
+    let mut line = String::new();
+    BufReader::new(&tty)
+        .read_line(&mut line)
+        .context("Failed to read from terminal")?;
+    let line = line.trim();
+    match default {
+        Some(default) if line.is_empty() => Ok(default.to_string()),
+        _ => Ok(line.to_string()),
+    }
+}
@@ -0,0 +1,478 @@
+//! Database connections through a SOCKS5 or HTTP CONNECT proxy, for networks
+//! whose only egress is a proxy.
+//!
+//! Clients can't speak to proxies themselves, so a background connect-db
+//! process listens on a local port and forwards each connection through the
+//! proxy (`connect-db proxy`). SSH tunnels use the same command as their
+//! `ProxyCommand`.
+
+use crate::{
+    config::Profile,
+    engines::{self, ConnectionParams},
+    url,
+};
+use anyhow::{Context, Result, bail};
+use std::{
+    env,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{IpAddr, Shutdown, TcpListener, TcpStream, ToSocketAddrs},
+    os::fd::AsRawFd,
+    process::{Command, Stdio},
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long the forwarder waits for a connection before exiting, at the
+/// start and after the last one closed.
+const IDLE_SECONDS: u64 = 10;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Carries the proxy URL to forwarder processes.
+pub const PROXY_VARIABLE: &str = "CONNECT_DB_PROXY";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// Names resolved locally
+    Socks5,
+    /// Names resolved by the proxy
+    Socks5h,
+    Http,
+}
+
+#[derive(Debug, Clone)]
+pub struct Proxy {
+    kind: Kind,
+    host: String,
+    port: u16,
+    username: String,
+    password: String,
+    keepalives: Keepalives,
+}
+
+/// TCP keepalive settings for the connection from the forwarder to the
+/// proxy, which the client's own settings don't reach.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Keepalives {
+    pub idle: Option<u32>,
+    pub interval: Option<u32>,
+    pub count: Option<u32>,
+    /// Milliseconds
+    pub user_timeout: Option<u32>,
+}
+
+impl Keepalives {
+    /// The libpq keepalive options of `params`.
+    pub fn of(params: &ConnectionParams) -> Keepalives {
+        let option = |name| params.option(name).and_then(|value| value.parse().ok());
+        Keepalives {
+            idle: option("keepalives_idle"),
+            interval: option("keepalives_interval"),
+            count: option("keepalives_count"),
+            user_timeout: option("tcp_user_timeout"),
+        }
+    }
+
+    fn settings(&self) -> [(&'static str, Option<u32>); 4] {
+        [
+            ("--keepalives-idle", self.idle),
+            ("--keepalives-interval", self.interval),
+            ("--keepalives-count", self.count),
+            ("--tcp-user-timeout", self.user_timeout),
+        ]
+    }
+
+    fn args(&self) -> Vec<String> {
+        self.settings()
+            .into_iter()
+            .filter_map(|(flag, value)| value.map(|value| format!("{}={}", flag, value)))
+            .collect()
+    }
+
+    fn apply(&self, stream: &TcpStream) {
+        if self.settings().iter().all(|(_, value)| value.is_none()) {
+            return;
+        }
+        let fd = stream.as_raw_fd();
+        let set = |level, name, value: u32| unsafe {
+            let value = value as libc::c_int;
+            libc::setsockopt(
+                fd,
+                level,
+                name,
+                &value as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            );
+        };
+        set(libc::SOL_SOCKET, libc::SO_KEEPALIVE, 1);
+        #[cfg(target_os = "linux")]
+        {
+            let options = [
+                (libc::TCP_KEEPIDLE, self.idle),
+                (libc::TCP_KEEPINTVL, self.interval),
+                (libc::TCP_KEEPCNT, self.count),
+                (libc::TCP_USER_TIMEOUT, self.user_timeout),
+            ];
+            for (name, value) in options {
+                if let Some(value) = value {
+                    set(libc::IPPROTO_TCP, name, value);
+                }
+            }
+        }
+    }
+}
+
+/// `socks5://`, `socks5h://` or `http://` proxy URLs, with optional
+/// `user:password@`.
+pub fn parse(url: &str) -> Result<Proxy> {
+    let scheme = url::scheme(url)
+        .with_context(|| format!("Proxy URL {} has no scheme, like socks5://host:1080", url))?;
+    let (kind, default_port) = match scheme {
+        "socks5" => (Kind::Socks5, 1080),
+        "socks5h" => (Kind::Socks5h, 1080),
+        "http" => (Kind::Http, 8080),
+        _ => bail!(
+            "Unsupported proxy scheme {}; use socks5, socks5h or http",
+            scheme
+        ),
+    };
+    let parts = url::split_url(url)?;
+    if parts.host.is_empty() {
+        bail!("Proxy URL {} has no host", url);
+    }
+    let port = match parts.port {
+        Some(port) => port
+            .parse()
+            .with_context(|| format!("Invalid port in proxy URL {}", url))?,
+        None => default_port,
+    };
+    let username = parts.username.unwrap_or_default();
+    let password = parts.password.unwrap_or_default();
+    // SOCKS5 sends each with a one-byte length
+    if kind != Kind::Http && (username.len() > 255 || password.len() > 255) {
+        bail!(
+            "The username and password of proxy {}:{} must be at most 255 bytes each for SOCKS5",
+            parts.host,
+            port
+        );
+    }
+    Ok(Proxy {
+        kind,
+        host: parts.host,
+        port,
+        username,
+        password,
+        keepalives: Keepalives::default(),
+    })
+}
+
+/// `host:port` of the proxy, for messages that mustn't show its password.
+pub fn address(url: &str) -> Result<String> {
+    let proxy = parse(url)?;
+    Ok(format!("{}:{}", proxy.host, proxy.port))
+}
+
+static CLI_PROXY: OnceLock<Option<String>> = OnceLock::new();
+
+/// The proxy given with `--proxy` (or `CONNECT_DB_PROXY`), which wins over
+/// the profile's.
+pub fn set_cli_proxy(url: Option<String>) {
+    let _ = CLI_PROXY.set(url);
+}
+
+/// The proxy to reach `profile`'s database through, if any.
+pub fn for_profile(profile: &Profile) -> Option<String> {
+    CLI_PROXY
+        .get()
+        .cloned()
+        .flatten()
+        .or_else(|| profile.proxy.clone())
+}
+
+/// Start the background forwarder to `host:port` through `url` and return
+/// the local port to connect to instead. The URL goes through the
+/// environment, so its password doesn't show in `ps`.
+pub fn open(url: &str, host: &str, port: &str, keepalives: Keepalives) -> Result<u16> {
+    parse(url)?;
+    let program = env::current_exe().context("Failed to locate the connect-db binary")?;
+    let mut child = Command::new(program)
+        .args(["proxy", host, port])
+        .args(keepalives.args())
+        .env(PROXY_VARIABLE, url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to start the proxy forwarder")?;
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().expect("stdout is piped")).read_line(&mut line)?;
+    let line = line.trim();
+    match line.parse() {
+        Ok(port) => Ok(port),
+        Err(_) if line.is_empty() => Err(anyhow::anyhow!("The proxy forwarder didn't start")),
+        Err(_) => Err(anyhow::anyhow!("{}", line)),
+    }
+}
+
+/// `ssh -o ProxyCommand=...` reaching the SSH host through the proxy in
+/// [`PROXY_VARIABLE`].
+pub fn ssh_proxy_command() -> Result<String> {
+    let program = env::current_exe().context("Failed to locate the connect-db binary")?;
+    Ok(format!(
+        "{} proxy --stdio %h %p",
+        engines::shell_quote(&program.to_string_lossy())
+    ))
+}
+
+/// `connect-db proxy`: forward connections to `host:port` through the
+/// proxy, from stdin/stdout with `stdio` and otherwise from a local port
+/// printed on stdout.
+pub fn run(url: &str, host: &str, port: &str, stdio: bool, keepalives: Keepalives) -> Result<()> {
+    let mut proxy = parse(url)?;
+    proxy.keepalives = keepalives;
+    let port: u16 = port
+        .parse()
+        .with_context(|| format!("Invalid port {}", port))?;
+    if stdio {
+        let upstream = proxy.connect(host, port)?;
+        let mut reader = upstream.try_clone()?;
+        let mut writer = upstream;
+        let inbound = thread::spawn(move || {
+            let _ = io::copy(&mut io::stdin(), &mut writer);
+            let _ = writer.shutdown(Shutdown::Write);
+        });
+        io::copy(&mut reader, &mut io::stdout())?;
+        let _ = inbound.join();
+        return Ok(());
+    }
+
+    // Connect once up front so proxy errors reach the user, and keep that
+    // connection for the first client
+    let mut first = match proxy.connect(host, port) {
+        Ok(upstream) => Some(upstream),
+        Err(err) => {
+            println!("{:#}", err);
+            return Ok(());
+        }
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").context("Failed to find a free local port")?;
+    println!("{}", listener.local_addr()?.port());
+    io::stdout().flush()?;
+    listener.set_nonblocking(true)?;
+
+    let active = Arc::new(AtomicUsize::new(0));
+    let mut idle_since = Instant::now();
+    loop {
+        match listener.accept() {
+            Ok((client, _)) => {
+                client.set_nonblocking(false)?;
+                active.fetch_add(1, Ordering::SeqCst);
+                let upstream = first.take();
+                let (proxy, host, active) = (proxy.clone(), host.to_string(), active.clone());
+                thread::spawn(move || {
+                    if let Some(upstream) = upstream.or_else(|| proxy.connect(&host, port).ok()) {
+                        pipe(client, upstream);
+                    }
+                    active.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                if active.load(Ordering::SeqCst) > 0 {
+                    idle_since = Instant::now();
+                } else if idle_since.elapsed() > Duration::from_secs(IDLE_SECONDS) {
+                    return Ok(());
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Copy bytes both ways until either side closes.
+fn pipe(client: TcpStream, upstream: TcpStream) {
+    let (Ok(mut client_reader), Ok(mut upstream_writer)) =
+        (client.try_clone(), upstream.try_clone())
+    else {
+        return;
+    };
+    let (mut upstream_reader, mut client_writer) = (upstream, client);
+    let inbound = thread::spawn(move || {
+        let _ = io::copy(&mut client_reader, &mut upstream_writer);
+        let _ = upstream_writer.shutdown(Shutdown::Write);
+    });
+    let _ = io::copy(&mut upstream_reader, &mut client_writer);
+    let _ = client_writer.shutdown(Shutdown::Write);
+    let _ = inbound.join();
+}
+
+impl Proxy {
+    /// A connection to `host:port` through the proxy.
+    fn connect(&self, host: &str, port: u16) -> Result<TcpStream> {
+        let address = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve proxy {}", self.host))?
+            .next()
+            .with_context(|| format!("Failed to resolve proxy {}", self.host))?;
+        let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)
+            .with_context(|| format!("Failed to reach proxy {}:{}", self.host, self.port))?;
+        self.keepalives.apply(&stream);
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        match self.kind {
+            Kind::Http => self.http_connect(&mut stream, host, port)?,
+            Kind::Socks5 | Kind::Socks5h => self.socks5_connect(&mut stream, host, port)?,
+        }
+        stream.set_read_timeout(None)?;
+        Ok(stream)
+    }
+
+    fn http_connect(&self, stream: &mut TcpStream, host: &str, port: u16) -> Result<()> {
+        let target = if host.contains(':') {
+            format!("[{}]:{}", host, port)
+        } else {
+            format!("{}:{}", host, port)
+        };
+        let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", target, target);
+        if !self.username.is_empty() {
+            let credentials = format!("{}:{}", self.username, self.password);
+            request.push_str(&format!(
+                "Proxy-Authorization: Basic {}\r\n",
+                base64(credentials.as_bytes())
+            ));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+
+        // Read the response head byte by byte, so nothing after it is lost
+        let mut head = Vec::new();
+        let mut byte = [0u8; 1];
+        while !head.ends_with(b"\r\n\r\n") {
+            stream
+                .read_exact(&mut byte)
+                .context("The proxy closed the connection")?;
+            head.push(byte[0]);
+            if head.len() > 16 * 1024 {
+                bail!("The proxy sent an oversized response");
+            }
+        }
+        let head = String::from_utf8_lossy(&head);
+        let status = head.lines().next().unwrap_or_default();
+        if status.split_whitespace().nth(1) != Some("200") {
+            bail!("The proxy refused CONNECT {}: {}", target, status);
+        }
+        Ok(())
+    }
+
+    fn socks5_connect(&self, stream: &mut TcpStream, host: &str, port: u16) -> Result<()> {
+        let method = if self.username.is_empty() { 0x00 } else { 0x02 };
+        stream.write_all(&[0x05, 0x01, method])?;
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply)?;
+        match reply {
+            [0x05, 0x00] => {}
+            [0x05, 0x02] => {
+                let mut auth = vec![0x01, self.username.len() as u8];
+                auth.extend(self.username.as_bytes());
+                auth.push(self.password.len() as u8);
+                auth.extend(self.password.as_bytes());
+                stream.write_all(&auth)?;
+                stream.read_exact(&mut reply)?;
+                if reply[1] != 0x00 {
+                    bail!("The SOCKS proxy rejected the username and password");
+                }
+            }
+            _ => bail!("The SOCKS proxy offers no usable authentication method"),
+        }
+
+        let mut request = vec![0x05, 0x01, 0x00];
+        let address = match host.parse::<IpAddr>() {
+            Ok(address) => Some(address),
+            Err(_) if self.kind == Kind::Socks5 => (host, port)
+                .to_socket_addrs()
+                .with_context(|| format!("Failed to resolve {}", host))?
+                .next()
+                .map(|address| address.ip()),
+            Err(_) => None,
+        };
+        match address {
+            Some(IpAddr::V4(address)) => {
+                request.push(0x01);
+                request.extend(address.octets());
+            }
+            Some(IpAddr::V6(address)) => {
+                request.push(0x04);
+                request.extend(address.octets());
+            }
+            None if host.len() > 255 => {
+                bail!("{} is too long a host name for a SOCKS proxy", host)
+            }
+            None => {
+                request.push(0x03);
+                request.push(host.len() as u8);
+                request.extend(host.as_bytes());
+            }
+        }
+        request.extend(port.to_be_bytes());
+        stream.write_all(&request)?;
+
+        let mut head = [0u8; 4];
+        stream.read_exact(&mut head)?;
+        if head[1] != 0x00 {
+            bail!(
+                "The SOCKS proxy couldn't connect to {}:{}: {}",
+                host,
+                port,
+                socks_error(head[1])
+            );
+        }
+        // Skip the bound address
+        let length = match head[3] {
+            0x01 => 4,
+            0x04 => 16,
+            _ => {
+                let mut length = [0u8; 1];
+                stream.read_exact(&mut length)?;
+                length[0] as usize
+            }
+        };
+        let mut bound = vec![0u8; length + 2];
+        stream.read_exact(&mut bound)?;
+        Ok(())
+    }
+}
+
+fn socks_error(code: u8) -> &'static str {
+    match code {
+        0x01 => "general failure",
+        0x02 => "not allowed by the proxy's rules",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let value = chunk.iter().enumerate().fold(0u32, |value, (index, byte)| {
+            value | (*byte as u32) << (16 - 8 * index)
+        });
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(value >> (18 - 6 * index) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
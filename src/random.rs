@@ -0,0 +1,37 @@
+use anyhow::{Context, Result};
+use std::{fs::File, io::Read};
+
+/// `count` random bytes from the OS.
+pub fn bytes(count: usize) -> Result<Vec<u8>> {
+    let mut buffer = vec![0u8; count];
+    File::open("/dev/urandom")
+        .and_then(|mut random| random.read_exact(&mut buffer))
+        .context("Failed to read random bytes")?;
+    Ok(buffer)
+}
+
+/// `bytes` random bytes from the OS, hex encoded.
+pub fn hex(bytes: usize) -> Result<String> {
+    Ok(self::bytes(bytes)?
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// A password of `length` letters and digits, safe to put in a URL.
+pub fn password(length: usize) -> Result<String> {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+    let mut random = File::open("/dev/urandom").context("Failed to read random bytes")?;
+    let mut password = String::with_capacity(length);
+    let mut byte = [0u8; 1];
+    while password.len() < length {
+        random
+            .read_exact(&mut byte)
+            .context("Failed to read random bytes")?;
+        // Skip the bytes that would make the first letters more likely
+        if usize::from(byte[0]) < 256 - 256 % ALPHABET.len() {
+            password.push(char::from(ALPHABET[usize::from(byte[0]) % ALPHABET.len()]));
+        }
+    }
+    Ok(password)
+}
@@ -0,0 +1,54 @@
+pub fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    for line in table_lines(headers, rows, |_, _| false) {
+        println!("{}", line);
+    }
+}
+
+/// The lines of a table, with the cells where `highlight(row, column)` is
+/// true shown in reverse video.
+pub fn table_lines(
+    headers: &[&str],
+    rows: &[Vec<String>],
+    highlight: impl Fn(usize, usize) -> bool,
+) -> Vec<String> {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: Vec<&str>, row: Option<usize>| {
+        let last = cells.len().saturating_sub(1);
+        let text: Vec<String> = cells
+            .iter()
+            .enumerate()
+            .map(|(index, cell)| {
+                let padded = if index == last {
+                    cell.to_string()
+                } else {
+                    format!("{:<width$}", cell, width = widths[index])
+                };
+                match row {
+                    Some(row) if highlight(row, index) => format!("\x1b[7m{}\x1b[0m", padded),
+                    _ => padded,
+                }
+            })
+            .collect();
+        text.join("  ")
+    };
+    let mut lines = vec![line(headers.to_vec(), None)];
+    for (index, row) in rows.iter().enumerate() {
+        lines.push(line(row.iter().map(String::as_str).collect(), Some(index)));
+    }
+    lines
+}
+
+/// Split psql's unaligned `a|b|c` output into rows.
+pub fn rows(output: &str) -> Vec<Vec<String>> {
+    output
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.split('|').map(str::to_string).collect())
+        .collect()
+}
@@ -0,0 +1,272 @@
+//! `--resilient` sessions: psql runs on a pseudo-terminal that connect-db
+//! relays, so when the connection drops for good (psql's own reset failed),
+//! connect-db quits it the way the user would, which keeps its readline
+//! history, resolves the credentials again and starts a new session.
+
+use crate::{
+    config::Profile,
+    engines::{self, ConnectionParams, Engine},
+};
+use anyhow::{Context, Result, bail};
+use std::{
+    ffi::CStr,
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    os::{fd::AsRawFd, fd::FromRawFd, unix::process::CommandExt},
+    process::{ExitStatus, Stdio},
+    sync::{
+        Mutex, Once,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// What psql prints once the connection is gone and could not be reset.
+const LOST: [&str; 2] = [
+    "Attempting reset: Failed.",
+    "You are currently not connected to a database.",
+];
+
+/// Reconnection attempts before giving up, spaced 1, 2, 4 ... 30 seconds.
+const ATTEMPTS: u32 = 8;
+
+/// The pty master psql currently reads from, for the stdin relay.
+static MASTER: Mutex<Option<File>> = Mutex::new(None);
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+pub fn supported(params: &ConnectionParams) -> bool {
+    matches!(
+        params.engine,
+        Engine::Postgres | Engine::Redshift | Engine::CockroachDb
+    )
+}
+
+/// Run sessions on `name` until one ends without losing its connection.
+/// `reconnect` resolves fresh parameters; `connected` runs once the first
+/// client is up.
+pub fn run(
+    name: &str,
+    params: &ConnectionParams,
+    profile: &Profile,
+    reconnect: &dyn Fn() -> Result<ConnectionParams>,
+    connected: &mut dyn FnMut(),
+) -> Result<ExitStatus> {
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+        bail!("--resilient needs a terminal");
+    }
+    let mut params = params.clone();
+    let mut connected = Some(connected);
+    let mut failures = 0;
+    loop {
+        let started = Instant::now();
+        let (status, lost) = supervise(&params, profile, &mut connected)?;
+        // A restarted psql that can't connect exits with 2
+        let failed = failures > 0 && status.code() == Some(2);
+        if !lost && !failed {
+            return Ok(status);
+        }
+        if lost {
+            if started.elapsed() > Duration::from_secs(60) {
+                failures = 0;
+            }
+            eprintln!();
+            eprintln!("=== Connection to {} lost ===", name);
+        }
+        params = loop {
+            failures += 1;
+            if failures > ATTEMPTS {
+                bail!(
+                    "Gave up reconnecting to {} after {} attempts",
+                    name,
+                    ATTEMPTS
+                );
+            }
+            thread::sleep(Duration::from_secs((1 << (failures - 1)).min(30)));
+            eprintln!(
+                "=== Reconnecting to {} (attempt {} of {}) ===",
+                name, failures, ATTEMPTS
+            );
+            match reconnect() {
+                Ok(params) => break params,
+                Err(err) => eprintln!("{:#}", err),
+            }
+        };
+    }
+}
+
+/// One client session on a pty. Returns its exit status and whether it was
+/// quit because the connection was lost.
+fn supervise(
+    params: &ConnectionParams,
+    profile: &Profile,
+    connected: &mut Option<&mut dyn FnMut()>,
+) -> Result<(ExitStatus, bool)> {
+    let (master, slave) = open_pty()?;
+    copy_window_size(&master);
+    let mut command = engines::session_command(params, profile)?;
+    command
+        .stdin(Stdio::from(slave.try_clone()?))
+        .stdout(Stdio::from(slave.try_clone()?))
+        .stderr(Stdio::from(slave));
+    unsafe {
+        // The client gets the pty as its controlling terminal
+        command.pre_exec(|| {
+            if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn().context("Failed to start the client")?;
+    // Only the child may hold the slave, so reads fail once it exits
+    drop(command);
+    if let Some(connected) = connected.take() {
+        connected();
+    }
+
+    let raw = RawMode::enter()?;
+    *MASTER.lock().unwrap() = Some(master.try_clone()?);
+    start_input_relay();
+    let lost = relay_output(&master, child.id());
+    *MASTER.lock().unwrap() = None;
+    let status = child.wait()?;
+    drop(raw);
+    Ok((status, lost))
+}
+
+fn open_pty() -> Result<(File, File)> {
+    unsafe {
+        let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).context("Failed to open a pseudo-terminal");
+        }
+        let master = File::from_raw_fd(fd);
+        if libc::grantpt(fd) != 0 || libc::unlockpt(fd) != 0 {
+            return Err(io::Error::last_os_error()).context("Failed to set up a pseudo-terminal");
+        }
+        let name = libc::ptsname(fd);
+        if name.is_null() {
+            return Err(io::Error::last_os_error()).context("Failed to name a pseudo-terminal");
+        }
+        let path = CStr::from_ptr(name).to_string_lossy().to_string();
+        let slave = OpenOptions::new().read(true).write(true).open(&path)?;
+        Ok((master, slave))
+    }
+}
+
+fn copy_window_size(master: &File) {
+    unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(libc::STDIN_FILENO, libc::TIOCGWINSZ, &mut size) == 0 {
+            libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &size);
+        }
+    }
+}
+
+extern "C" fn on_resize(_: libc::c_int) {
+    RESIZED.store(true, Ordering::Relaxed);
+}
+
+/// Keystrokes go to whichever client is running; the thread outlives
+/// sessions since a blocked read can't be taken back.
+fn start_input_relay() {
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| {
+        unsafe {
+            libc::signal(libc::SIGWINCH, on_resize as *const () as libc::sighandler_t);
+        }
+        thread::spawn(|| {
+            let mut stdin = io::stdin().lock();
+            let mut buffer = [0; 1024];
+            while let Ok(count) = stdin.read(&mut buffer) {
+                if count == 0 {
+                    break;
+                }
+                if let Some(master) = MASTER.lock().unwrap().as_mut() {
+                    let _ = master.write_all(&buffer[..count]);
+                }
+            }
+        });
+    });
+}
+
+/// Copy the client's output to ours until it exits. When it reports the
+/// connection lost, quit it like the user would and return true.
+fn relay_output(master: &File, pid: u32) -> bool {
+    let mut stdout = io::stdout().lock();
+    let mut reader = master;
+    let mut buffer = [0; 4096];
+    let mut tail = String::new();
+    let mut quit_at: Option<Instant> = None;
+    loop {
+        if RESIZED.swap(false, Ordering::Relaxed) {
+            copy_window_size(master);
+        }
+        if let Some(at) = quit_at
+            && at.elapsed() > Duration::from_secs(5)
+        {
+            unsafe {
+                libc::kill(pid as libc::pid_t, libc::SIGTERM);
+            }
+        }
+        let mut poll = libc::pollfd {
+            fd: master.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut poll, 1, 200) } <= 0 {
+            continue;
+        }
+        let count = match reader.read(&mut buffer) {
+            Ok(0) | Err(_) => return quit_at.is_some(),
+            Ok(count) => count,
+        };
+        let _ = stdout.write_all(&buffer[..count]);
+        let _ = stdout.flush();
+        if quit_at.is_some() {
+            continue;
+        }
+        tail.push_str(&String::from_utf8_lossy(&buffer[..count]));
+        if LOST.iter().any(|message| tail.contains(message)) {
+            // Clear the line being typed, then \q so psql saves its history
+            if let Some(master) = MASTER.lock().unwrap().as_mut() {
+                let _ = master.write_all(b"\x15\\q\r");
+            }
+            quit_at = Some(Instant::now());
+        }
+        if tail.len() > 256 {
+            let mut cut = tail.len() - 128;
+            while !tail.is_char_boundary(cut) {
+                cut += 1;
+            }
+            tail.drain(..cut);
+        }
+    }
+}
+
+/// Our terminal in raw mode while the client's pty does the line editing.
+struct RawMode(libc::termios);
+
+impl RawMode {
+    fn enter() -> Result<Self> {
+        unsafe {
+            let mut original: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error()).context("Failed to read terminal settings");
+            }
+            let mut raw = original;
+            libc::cfmakeraw(&mut raw);
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw);
+            Ok(RawMode(original))
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Results of read-only `exec` queries kept for `--cache`, so dashboards and
+//! repeated CI checks don't send the same query over and over.
+//!
+//! Entries are keyed by the database's endpoint and user, the normalized
+//! SQL, the bound parameters and the row limit, and live in
+//! `result-cache/` in the data directory, readable only by the user.
+
+use crate::{
+    clone_schema::Side, config, engines::ResultSet, sha256::Sha256, sql_lint, sql_params::Binding,
+    time,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{fs, io::Write, os::unix::fs::OpenOptionsExt, path::PathBuf};
+
+#[derive(Deserialize, Serialize)]
+struct Entry {
+    /// Unix time the query ran
+    cached_at: u64,
+    result: ResultSet,
+}
+
+/// The cache key of running `sql` on `side`; only read-only queries are
+/// cached.
+pub fn key(side: &Side, sql: &str, bindings: &[Binding], limit: Option<usize>) -> Result<String> {
+    if let Some(reason) = sql_lint::read_only_violation(sql) {
+        return Err(anyhow::anyhow!(
+            "Only read-only queries can be cached: {}",
+            reason
+        ));
+    }
+    let params = side.params;
+    let bindings: Vec<String> = bindings
+        .iter()
+        .map(|binding| format!("{}:{:?}={}", binding.name, binding.kind, binding.value))
+        .collect();
+    let key = serde_json::json!([
+        side.name,
+        config::selected_environment(),
+        params.engine,
+        params.host,
+        params.port,
+        params.username,
+        params.database,
+        sql_lint::normalize(sql),
+        bindings,
+        limit,
+    ]);
+    let mut hasher = Sha256::default();
+    hasher.update(key.to_string().as_bytes());
+    Ok(hasher.finish())
+}
+
+fn entry_path(key: &str) -> Result<PathBuf> {
+    Ok(config::data_dir()?
+        .join("result-cache")
+        .join(format!("{}.json", key)))
+}
+
+/// The cached result for `key` and when it was fetched, unless older than
+/// `ttl` seconds.
+pub fn load(key: &str, ttl: u64) -> Result<Option<(u64, ResultSet)>> {
+    let path = entry_path(key)?;
+    let Ok(content) = fs::read_to_string(&path) else {
+        return Ok(None);
+    };
+    let entry: Entry = match serde_json::from_str(&content) {
+        Ok(entry) => entry,
+        // A half-written entry is as good as none
+        Err(_) => return Ok(None),
+    };
+    if time::unix_now().saturating_sub(entry.cached_at) >= ttl {
+        let _ = fs::remove_file(&path);
+        return Ok(None);
+    }
+    Ok(Some((entry.cached_at, entry.result)))
+}
+
+pub fn store(key: &str, result: &ResultSet) -> Result<()> {
+    let path = entry_path(key)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let entry = Entry {
+        cached_at: time::unix_now(),
+        result: result.clone(),
+    };
+    // Written aside and renamed so concurrent readers never see a partial entry
+    let partial = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&partial)
+        .and_then(|mut file| file.write_all(serde_json::to_string(&entry)?.as_bytes()))
+        .and_then(|_| fs::rename(&partial, &path))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
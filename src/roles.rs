@@ -0,0 +1,236 @@
+//! Creating and dropping PostgreSQL roles and rotating their passwords.
+
+use crate::{
+    config::Profile,
+    engines::{
+        self, ConnectionParams, Engine,
+        postgres::{self, quote_ident, quote_literal},
+    },
+    prompt,
+    proxy::base64,
+    random,
+    sha256::{self, Sha256},
+    time,
+};
+use anyhow::Result;
+
+/// Length of generated passwords, in letters and digits.
+const PASSWORD_LENGTH: usize = 32;
+
+/// PostgreSQL's default `scram_iterations`.
+const SCRAM_ITERATIONS: u32 = 4096;
+
+pub struct CreateOptions {
+    /// Allow logging in, with a generated password
+    pub login: bool,
+    /// Seconds until the password expires, for temporary accounts
+    pub valid_for: Option<u64>,
+    /// Roles to grant membership of
+    pub member_of: Vec<String>,
+    /// Don't ask for confirmation on production databases
+    pub yes: bool,
+}
+
+fn check(name: &str, params: &ConnectionParams, profile: &Profile) -> Result<()> {
+    if params.engine != Engine::Postgres {
+        return Err(anyhow::anyhow!("role needs a PostgreSQL database"));
+    }
+    if profile.read_only {
+        return Err(anyhow::anyhow!(
+            "{} is read-only; managing roles is not allowed",
+            name
+        ));
+    }
+    Ok(())
+}
+
+fn valid_until(valid_for: Option<u64>) -> String {
+    match valid_for {
+        Some(seconds) => format!(
+            " valid until {}",
+            quote_literal(&time::rfc3339(time::unix_now() + seconds))
+        ),
+        None => String::new(),
+    }
+}
+
+/// The SCRAM-SHA-256 verifier PostgreSQL stores for `password`. Sending it
+/// instead of the password keeps the password out of server logs and
+/// pg_stat_statements. Generated passwords are ASCII letters and digits,
+/// which SASLprep leaves unchanged.
+fn scram_verifier(password: &str) -> Result<String> {
+    Ok(verifier_with_salt(password, &random::bytes(16)?))
+}
+
+fn verifier_with_salt(password: &str, salt: &[u8]) -> String {
+    let salted = sha256::pbkdf2(password.as_bytes(), salt, SCRAM_ITERATIONS);
+    let mut stored_key = Sha256::default();
+    stored_key.update(&sha256::hmac(&salted, b"Client Key"));
+    format!(
+        "SCRAM-SHA-256${}:{}${}:{}",
+        SCRAM_ITERATIONS,
+        base64(salt),
+        base64(&stored_key.digest()),
+        base64(&sha256::hmac(&salted, b"Server Key"))
+    )
+}
+
+/// Create `role`, returning its generated password when it can log in.
+/// Roles are NOLOGIN unless `options.login` is set.
+pub fn create(
+    name: &str,
+    params: &ConnectionParams,
+    profile: &Profile,
+    role: &str,
+    options: &CreateOptions,
+) -> Result<Option<String>> {
+    check(name, params, profile)?;
+    if profile.production
+        && !options.yes
+        && !prompt::confirm(&format!(
+            "{} is a production database. Create role {}?",
+            name, role
+        ))?
+    {
+        return Err(anyhow::anyhow!("Aborted"));
+    }
+    let password = if options.login {
+        Some(random::password(PASSWORD_LENGTH)?)
+    } else {
+        None
+    };
+    let mut script = match &password {
+        Some(password) => format!(
+            "create role {} login password {}{};\n",
+            quote_ident(role),
+            quote_literal(&scram_verifier(password)?),
+            valid_until(options.valid_for)
+        ),
+        None => format!(
+            "create role {} nologin{};\n",
+            quote_ident(role),
+            valid_until(options.valid_for)
+        ),
+    };
+    for group in &options.member_of {
+        script.push_str(&format!(
+            "grant {} to {};\n",
+            quote_ident(group),
+            quote_ident(role)
+        ));
+    }
+    // Piped on stdin, so not even the verifier shows up in a process list
+    postgres::run_script(params, profile, &script)?;
+    eprintln!(
+        "Created role {} on {}{}",
+        role,
+        name,
+        if options.login { "" } else { " (NOLOGIN)" }
+    );
+    Ok(password)
+}
+
+/// Drop `role`, first handing what it owns to `reassign_to` when given.
+pub fn drop(
+    name: &str,
+    params: &ConnectionParams,
+    profile: &Profile,
+    role: &str,
+    reassign_to: Option<&str>,
+    yes: bool,
+) -> Result<()> {
+    check(name, params, profile)?;
+    if profile.production
+        && !yes
+        && !prompt::confirm(&format!(
+            "{} is a production database. Drop role {}?",
+            name, role
+        ))?
+    {
+        return Err(anyhow::anyhow!("Aborted"));
+    }
+    let mut script = String::new();
+    if let Some(owner) = reassign_to {
+        script.push_str(&format!(
+            "reassign owned by {0} to {1};\ndrop owned by {0};\n",
+            quote_ident(role),
+            quote_ident(owner)
+        ));
+    }
+    script.push_str(&format!("drop role {};\n", quote_ident(role)));
+    postgres::run_script(params, profile, &script)?;
+    eprintln!("Dropped role {} on {}", role, name);
+    Ok(())
+}
+
+/// Give `role` a new generated password and return it.
+pub fn passwd(
+    name: &str,
+    params: &ConnectionParams,
+    profile: &Profile,
+    role: &str,
+    valid_for: Option<u64>,
+    yes: bool,
+) -> Result<String> {
+    check(name, params, profile)?;
+    let exists = engines::run_query(
+        params,
+        profile,
+        &format!(
+            "select count(*) from pg_roles where rolname = {}",
+            quote_literal(role)
+        ),
+    )?;
+    if exists != "1" {
+        return Err(anyhow::anyhow!("No role named {} on {}", role, name));
+    }
+    if profile.production
+        && !yes
+        && !prompt::confirm(&format!(
+            "{} is a production database. Change the password of {}?",
+            name, role
+        ))?
+    {
+        return Err(anyhow::anyhow!("Aborted"));
+    }
+    let password = random::password(PASSWORD_LENGTH)?;
+    postgres::run_script(
+        params,
+        profile,
+        &format!(
+            "alter role {} password {}{};\n",
+            quote_ident(role),
+            quote_literal(&scram_verifier(&password)?),
+            valid_until(valid_for)
+        ),
+    )?;
+    eprintln!("Changed the password of {} on {}", role, name);
+    Ok(password)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifier_matches_postgres() {
+        // rolpassword after `create role ... password 'pencil'` on PostgreSQL
+        let salt = [
+            0x86, 0x88, 0xa2, 0x70, 0x9b, 0x6f, 0xac, 0x9e, 0x7e, 0xb5, 0x83, 0x99, 0x23, 0x27,
+            0xd1, 0x74,
+        ];
+        assert_eq!(
+            verifier_with_salt("pencil", &salt),
+            "SCRAM-SHA-256$4096:hoiicJtvrJ5+tYOZIyfRdA==$\
+             RJokocsdhncys+SDHIBj6nIpJlMN+HesOB5dP4fSZAs=:\
+             I6/mZ9NTlZitJoayXJUJt5V+60QJmD2DKb/c0H9GQtM="
+        );
+    }
+
+    #[test]
+    fn verifiers_are_salted() {
+        let first = scram_verifier("pencil").unwrap();
+        assert!(first.starts_with("SCRAM-SHA-256$4096:"));
+        assert_ne!(first, scram_verifier("pencil").unwrap());
+    }
+}
@@ -0,0 +1,155 @@
+//! Server-side limits for SQL that `mcp` and the HTTP API run for others.
+//!
+//! Whatever the database would allow the profile's user, statements must be
+//! a single read-only one, run in a read-only session where the engine has
+//! one, return a bounded number of rows and stop after a timeout. Every
+//! statement is recorded in the audit log, refused ones included.
+
+use crate::{
+    audit,
+    auth::AuthMode,
+    config::{self, Profile},
+    engines::{self, ConnectionParams, Engine, ResultSet},
+    sql_lint,
+    sql_params::Binding,
+    time,
+};
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::time::{Duration, Instant};
+
+const MAX_ROWS: usize = 1000;
+const TIMEOUT: &str = "30s";
+
+/// Whether queries may be run against `profile` at all: only profiles with
+/// `"mcp": true` are, and never production ones (by flag or environment name)
+/// or those authenticating interactively.
+pub fn exposed(profile: &Profile) -> bool {
+    profile.mcp
+        && !config::is_production(profile)
+        && !matches!(profile.auth, AuthMode::Ldap | AuthMode::Oidc)
+}
+
+/// The most rows a query returns, from `sandbox_max_rows`.
+pub fn max_rows() -> usize {
+    config::settings()
+        .ok()
+        .and_then(|settings| settings.sandbox_max_rows)
+        .unwrap_or(MAX_ROWS)
+}
+
+/// Run `sql` for `caller` (`mcp` or `api`), reading at most `limit` rows.
+pub fn run(
+    caller: &str,
+    name: &str,
+    profile: &Profile,
+    params: &ConnectionParams,
+    sql: &str,
+    bindings: &[Binding],
+    limit: usize,
+) -> Result<ResultSet> {
+    let settings = config::settings()?;
+    let timeout = settings.sandbox_timeout.as_deref().unwrap_or(TIMEOUT);
+    let timeout =
+        Duration::from_secs(time::parse_duration(timeout).context("Invalid sandbox_timeout")?);
+    let limit = limit.clamp(1, settings.sandbox_max_rows.unwrap_or(MAX_ROWS));
+    let log = |outcome: &str, details: Value| {
+        let mut entry = json!({"caller": caller, "sql": sql, "outcome": outcome});
+        if let (Some(entry), Value::Object(details)) = (entry.as_object_mut(), details) {
+            entry.extend(details);
+        }
+        if let Err(err) = audit::record("sandbox_query", name, entry) {
+            eprintln!("Warning: {:#}", err);
+        }
+    };
+
+    let refusal = match exposed(profile) {
+        true => sql_lint::read_only_violation(sql),
+        false => Some(format!("{} is not exposed to {}", name, caller)),
+    };
+    if let Some(reason) = refusal {
+        log("refused", json!({"reason": reason}));
+        return Err(anyhow::anyhow!("Refusing to run this query: {}", reason));
+    }
+
+    let mut params = params.clone();
+    read_only_session(&mut params, timeout);
+    let started = Instant::now();
+    let result =
+        engines::run_result_query_within(&params, profile, sql, bindings, Some(limit), timeout);
+    let duration_ms = started.elapsed().as_millis() as u64;
+    match &result {
+        Ok(result) => log(
+            "ok",
+            json!({
+                "rows": result.rows.len(),
+                "truncated": result.truncated,
+                "duration_ms": duration_ms,
+            }),
+        ),
+        Err(err) => log(
+            "error",
+            json!({"reason": format!("{:#}", err), "duration_ms": duration_ms}),
+        ),
+    }
+    result
+}
+
+/// Rows as objects keyed by column name, and whether the limit cut them off.
+pub fn rows_json(result: &ResultSet) -> Value {
+    let rows: Vec<Value> = result
+        .rows
+        .iter()
+        .map(|row| {
+            result
+                .columns
+                .iter()
+                .zip(row)
+                .map(|(column, value)| (column.clone(), Value::from(value.as_str())))
+                .collect::<serde_json::Map<_, _>>()
+                .into()
+        })
+        .collect();
+    json!({"rows": rows, "truncated": result.truncated})
+}
+
+/// Make PostgreSQL-protocol sessions read-only and bounded in time on the
+/// server too, past the statement check.
+fn read_only_session(params: &mut ConnectionParams, timeout: Duration) {
+    if !matches!(
+        params.engine,
+        Engine::Postgres | Engine::Redshift | Engine::CockroachDb
+    ) {
+        return;
+    }
+    let mut options = params.option("options").unwrap_or_default().to_string();
+    options.push_str(&format!(
+        " -c default_transaction_read_only=on -c statement_timeout={}",
+        timeout.as_millis()
+    ));
+    params.options.retain(|(key, _)| key != "options");
+    params
+        .options
+        .push(("options".to_string(), options.trim().to_string()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exposure_is_opt_in() {
+        let mut profile = Profile::default();
+        assert!(!exposed(&profile));
+        profile.mcp = true;
+        assert!(exposed(&profile));
+        profile.environment = Some("Prod".to_string());
+        assert!(!exposed(&profile));
+        profile.environment = None;
+        profile.production = true;
+        assert!(!exposed(&profile));
+        profile.production = false;
+        profile.auth = AuthMode::Oidc;
+        assert!(!exposed(&profile));
+    }
+}
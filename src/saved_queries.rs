@@ -0,0 +1,190 @@
+//! Named, parameterized queries: built-in diagnostics, ones shared through
+//! the team catalog, and the user's own in `~/.config/connect-db/queries.json`.
+
+use crate::{config, dry_run, engines::Engine, report, sql_params};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Variant key used for engines without their own SQL.
+const DEFAULT_VARIANT: &str = "default";
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct SavedQuery {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// SQL by engine name (`postgres`, `clickhouse`, ...) or `default`
+    pub sql: BTreeMap<String, String>,
+}
+
+const BUILTIN: &[(&str, &str, &str)] = &[
+    (
+        "blocked-queries",
+        "Sessions waiting on locks and the sessions blocking them",
+        "select blocked.pid, blocked.usename, now() - blocked.query_start as waiting, \
+         blocking.pid as blocking_pid, blocking.usename as blocking_user, \
+         left(blocked.query, 60) as query \
+         from pg_stat_activity blocked \
+         join pg_stat_activity blocking on blocking.pid = any(pg_blocking_pids(blocked.pid)) \
+         order by waiting desc",
+    ),
+    (
+        "table-sizes",
+        "Largest tables with their data and index sizes",
+        "select schemaname || '.' || relname as table, \
+         pg_size_pretty(pg_total_relation_size(relid)) as total, \
+         pg_size_pretty(pg_relation_size(relid)) as data, \
+         pg_size_pretty(pg_indexes_size(relid)) as indexes, n_live_tup as rows \
+         from pg_stat_user_tables order by pg_total_relation_size(relid) desc limit 20",
+    ),
+    (
+        "long-running",
+        "Statements running longer than :seconds seconds",
+        "select pid, usename, now() - query_start as running, state, left(query, 80) as query \
+         from pg_stat_activity where state <> 'idle' \
+         and now() - query_start > make_interval(secs => :seconds::int) \
+         order by running desc",
+    ),
+];
+
+fn queries_path() -> Result<PathBuf> {
+    Ok(config::config_dir()?.join("queries.json"))
+}
+
+fn read_file(path: &Path) -> Result<BTreeMap<String, SavedQuery>> {
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Queries from the synced team catalog's `queries` object.
+fn catalog_queries() -> Result<BTreeMap<String, SavedQuery>> {
+    let path = config::config_dir()?.join("catalog.json");
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let catalog = config::read_config_file(&path)?;
+    match catalog.get("queries") {
+        Some(queries) => serde_json::from_value(queries.clone())
+            .with_context(|| format!("Invalid queries in {}", path.display())),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+/// Every known query with where it comes from; user queries override the
+/// catalog's, which override the built-in ones.
+pub fn load() -> Result<BTreeMap<String, (SavedQuery, &'static str)>> {
+    let mut queries = BTreeMap::new();
+    for (name, description, sql) in BUILTIN {
+        let query = SavedQuery {
+            description: description.to_string(),
+            sql: BTreeMap::from([("postgres".to_string(), sql.to_string())]),
+        };
+        queries.insert(name.to_string(), (query, "built-in"));
+    }
+    for (name, query) in catalog_queries()? {
+        queries.insert(name, (query, "catalog"));
+    }
+    for (name, query) in read_file(&queries_path()?)? {
+        queries.insert(name, (query, "user"));
+    }
+    Ok(queries)
+}
+
+fn engine_key(engine: Engine) -> String {
+    match serde_json::to_value(engine) {
+        Ok(Value::String(name)) => name,
+        _ => format!("{:?}", engine).to_lowercase(),
+    }
+}
+
+/// Store `sql` as the variant of `name` for `engine` (or the default one),
+/// keeping its other variants.
+pub fn save(
+    name: &str,
+    sql: &str,
+    engine: Option<&str>,
+    description: Option<String>,
+) -> Result<()> {
+    let variant = match engine {
+        Some(engine) => {
+            let engine: Engine = serde_json::from_value(Value::String(engine.to_string()))
+                .map_err(|_| anyhow::anyhow!("Unknown engine: {}", engine))?;
+            engine_key(engine)
+        }
+        None => DEFAULT_VARIANT.to_string(),
+    };
+    let path = queries_path()?;
+    let mut queries = read_file(&path)?;
+    let query = queries.entry(name.to_string()).or_default();
+    query.sql.insert(variant.clone(), sql.to_string());
+    if let Some(description) = description {
+        query.description = description;
+    }
+    dry_run::stop_before_writing(&path);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(&queries)?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Saved {} ({}) to {}", name, variant, path.display());
+    Ok(())
+}
+
+pub fn list() -> Result<()> {
+    let rows: Vec<Vec<String>> = load()?
+        .into_iter()
+        .map(|(name, (query, source))| {
+            let mut params: Vec<String> = Vec::new();
+            for sql in query.sql.values() {
+                for param in sql_params::names(sql) {
+                    if !params.contains(&param) {
+                        params.push(param);
+                    }
+                }
+            }
+            let engines: Vec<&str> = query.sql.keys().map(String::as_str).collect();
+            vec![
+                name,
+                engines.join(","),
+                params.join(","),
+                source.to_string(),
+                query.description,
+            ]
+        })
+        .collect();
+    report::print_table(
+        &["NAME", "ENGINES", "PARAMS", "SOURCE", "DESCRIPTION"],
+        &rows,
+    );
+    Ok(())
+}
+
+/// The SQL of `name` for `engine`.
+pub fn sql_for(name: &str, engine: Engine) -> Result<String> {
+    let queries = load()?;
+    let (query, _) = queries
+        .get(name)
+        .with_context(|| format!("No saved query named {}", name))?;
+    query
+        .sql
+        .get(&engine_key(engine))
+        .or_else(|| query.sql.get(DEFAULT_VARIANT))
+        .cloned()
+        .with_context(|| {
+            format!(
+                "{} has no variant for {} (it has {})",
+                name,
+                engine_key(engine),
+                query.sql.keys().cloned().collect::<Vec<_>>().join(", ")
+            )
+        })
+}
@@ -0,0 +1,213 @@
+//! Caching and rate limiting in front of the secret backends, so commands
+//! resolving many profiles (`status`, `list`) don't flood Vault or AWS.
+//!
+//! Resolved parameters are kept in memory for `secret_cache_ttl`. Temporary
+//! AWS credentials are also kept on disk, readable only by the user, because
+//! every request mints new ones; they are dropped a minute before they expire.
+
+use crate::{
+    config,
+    engines::{ConnectionParams, redshift::TemporaryCredentials},
+    time,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, OpenOptions},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+const DEFAULT_TTL: &str = "5m";
+
+/// Temporary credentials are refreshed this long before they expire.
+const EXPIRY_MARGIN: u64 = 60;
+
+struct Limits {
+    /// Seconds a resolution stays cached; 0 turns caching off
+    ttl: u64,
+    /// Minimum time between two backend requests
+    interval: Option<Duration>,
+}
+
+fn limits() -> &'static Limits {
+    static LIMITS: OnceLock<Limits> = OnceLock::new();
+    LIMITS.get_or_init(|| {
+        let settings = config::settings().unwrap_or_default();
+        let ttl = settings.secret_cache_ttl.as_deref().unwrap_or(DEFAULT_TTL);
+        Limits {
+            ttl: time::parse_duration(ttl).unwrap_or_else(|err| {
+                eprintln!("Warning: invalid secret_cache_ttl: {}", err);
+                0
+            }),
+            interval: settings
+                .secret_requests_per_second
+                .filter(|rate| *rate > 0.0)
+                .map(|rate| Duration::from_secs_f64(1.0 / rate)),
+        }
+    })
+}
+
+fn memory() -> &'static Mutex<HashMap<String, (Instant, ConnectionParams)>> {
+    static MEMORY: OnceLock<Mutex<HashMap<String, (Instant, ConnectionParams)>>> = OnceLock::new();
+    MEMORY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Results for a selected environment are kept apart as `name@env`.
+fn cache_key(name: &str) -> String {
+    match config::selected_environment() {
+        Some(environment) => format!("{}@{}", name, environment),
+        None => name.to_string(),
+    }
+}
+
+/// The parameters `fetch` resolves for `name`, reused while they are fresh;
+/// `refresh` skips the cache.
+pub fn resolve(
+    name: &str,
+    refresh: bool,
+    fetch: impl FnOnce() -> Result<ConnectionParams>,
+) -> Result<ConnectionParams> {
+    let ttl = Duration::from_secs(limits().ttl);
+    let key = cache_key(name);
+    if !refresh
+        && let Some((fetched, params)) = memory().lock().unwrap().get(&key)
+        && fetched.elapsed() < ttl
+    {
+        return Ok(params.clone());
+    }
+    throttle();
+    let params = fetch()?;
+    if !ttl.is_zero() {
+        memory()
+            .lock()
+            .unwrap()
+            .insert(key, (Instant::now(), params.clone()));
+    }
+    Ok(params)
+}
+
+/// Drop what is remembered for `name`, so the next resolution asks the
+/// backend again.
+pub fn forget(name: &str) {
+    memory().lock().unwrap().remove(&cache_key(name));
+}
+
+/// Wait until the next backend request is allowed.
+fn throttle() {
+    static NEXT: Mutex<Option<Instant>> = Mutex::new(None);
+    let Some(interval) = limits().interval else {
+        return;
+    };
+    // Holding the lock while sleeping queues concurrent requests in turn
+    let mut next = NEXT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let now = Instant::now();
+    if let Some(at) = *next
+        && at > now
+    {
+        thread::sleep(at - now);
+    }
+    *next = Some(Instant::now() + interval);
+}
+
+#[derive(Deserialize, Serialize)]
+struct StoredCredentials {
+    credentials: TemporaryCredentials,
+    /// Unix time after which the entry is not used
+    valid_until: u64,
+}
+
+/// Serializes read-modify-write cycles of concurrent resolutions.
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+fn credentials_path() -> Result<PathBuf> {
+    Ok(config::data_dir()?.join("secret-cache.json"))
+}
+
+fn load_credentials() -> BTreeMap<String, StoredCredentials> {
+    credentials_path()
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Temporary credentials for `key`, minted by `fetch` only when none cached on
+/// disk are still valid (or when `refresh` is set).
+pub fn temporary_credentials(
+    key: &str,
+    refresh: bool,
+    fetch: impl FnOnce() -> Result<TemporaryCredentials>,
+) -> Result<TemporaryCredentials> {
+    let ttl = limits().ttl;
+    let now = time::unix_now();
+    if ttl > 0
+        && !refresh
+        && let Some(stored) = load_credentials().remove(key)
+        && stored.valid_until > now
+    {
+        return Ok(stored.credentials);
+    }
+    let credentials = fetch()?;
+    if ttl == 0 {
+        return Ok(credentials);
+    }
+    let expires = credentials
+        .expiration
+        .as_deref()
+        .and_then(parse_expiration)
+        .unwrap_or(u64::MAX);
+    let valid_until = (now + ttl).min(expires.saturating_sub(EXPIRY_MARGIN));
+    let stored = StoredCredentials {
+        credentials,
+        valid_until,
+    };
+    if let Err(err) = store_credentials(key, &stored) {
+        eprintln!("Warning: failed to cache temporary credentials: {}", err);
+    }
+    Ok(stored.credentials)
+}
+
+fn store_credentials(key: &str, stored: &StoredCredentials) -> Result<()> {
+    let _guard = WRITE_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let now = time::unix_now();
+    let mut entries = load_credentials();
+    entries.retain(|_, entry| entry.valid_until > now);
+    entries.insert(
+        key.to_string(),
+        StoredCredentials {
+            credentials: stored.credentials.clone(),
+            valid_until: stored.valid_until,
+        },
+    );
+
+    // Write and rename so readers in other processes never see half a file
+    let path = credentials_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temporary = path.with_extension(format!("json.{}", std::process::id()));
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&temporary)
+        .with_context(|| format!("Failed to write {}", temporary.display()))?;
+    file.write_all(serde_json::to_string_pretty(&entries)?.as_bytes())
+        .with_context(|| format!("Failed to write {}", temporary.display()))?;
+    fs::rename(&temporary, &path).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// AWS writes expirations like `2024-05-01T12:00:00Z` or
+/// `2024-05-01T12:00:00.123000+00:00`, always in UTC.
+fn parse_expiration(value: &str) -> Option<u64> {
+    time::parse_rfc3339(&format!("{}Z", value.get(..19)?))
+}
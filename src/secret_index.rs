@@ -0,0 +1,108 @@
+//! An on-disk index of the `.db.json` files under the secrets directory, so
+//! `list`, completion and name suggestions stay fast with hundreds of secrets.
+//!
+//! The index remembers the modification time of every directory it read and
+//! is rebuilt as soon as one of them changes, which happens whenever a file
+//! is added, removed or renamed in it.
+
+use crate::config;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+#[derive(Deserialize, Serialize, Default)]
+struct Index {
+    /// The secrets directory the index describes
+    root: PathBuf,
+    /// Modification times in nanoseconds, by path relative to `root`; none
+    /// for a missing secrets directory
+    dirs: BTreeMap<String, Option<u128>>,
+    /// `.db.json` files, relative to `root`
+    files: Vec<String>,
+}
+
+fn index_path() -> Option<PathBuf> {
+    Some(config::data_dir().ok()?.join("secret-index.json"))
+}
+
+fn mtime(path: &Path) -> Option<u128> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos())
+}
+
+/// Paths of the `.db.json` files below `root`, relative to it and separated
+/// by `/`. Hidden entries such as Vault agent sinks and editor files are
+/// skipped.
+pub fn files(root: &Path, suffix: &str) -> Vec<String> {
+    // Relative secrets directories differ per project
+    let absolute = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+    let path = index_path();
+    if let Some(index) = path.as_deref().and_then(load)
+        && index.root == absolute
+        && index
+            .dirs
+            .iter()
+            .all(|(dir, time)| mtime(&root.join(dir)) == *time)
+    {
+        return index.files;
+    }
+
+    let mut index = Index {
+        root: absolute,
+        ..Index::default()
+    };
+    scan(root, "", suffix, &mut index);
+    index.files.sort();
+    if let Some(path) = path {
+        save(&path, &index);
+    }
+    index.files
+}
+
+fn load(path: &Path) -> Option<Index> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+/// Best effort: without a writable data directory every call scans.
+fn save(path: &Path, index: &Index) {
+    let Ok(content) = serde_json::to_string(index) else {
+        return;
+    };
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    // Write and rename so concurrent readers never see half a file
+    let temporary = path.with_extension(format!("json.{}", std::process::id()));
+    if fs::write(&temporary, content).is_ok() && fs::rename(&temporary, path).is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+}
+
+fn scan(dir: &Path, prefix: &str, suffix: &str, index: &mut Index) {
+    // Take the time before reading, so changes made during the scan show
+    index
+        .dirs
+        .insert(prefix.trim_end_matches('/').to_string(), mtime(dir));
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if file_name.starts_with('.') {
+            continue;
+        }
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = format!("{}{}", prefix, file_name);
+        if file_type.is_dir() {
+            scan(&entry.path(), &format!("{}/", path), suffix, index);
+        } else if file_name.ends_with(suffix) {
+            index.files.push(path);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Where secrets files live in the secrets directory: `<name>.db.json`, or
+//! the layout the `secret_path` setting describes, like
+//! `{team}/{env}/{name}.db.json`.
+//!
+//! Template values come from `--secret-var`, the profile's `secret_vars`, the
+//! `secret_vars` setting and, for `{env}`, the profile's or the selected
+//! environment.
+
+use crate::{
+    config::{self, Profile},
+    secret_index,
+};
+use anyhow::{Result, bail};
+use std::{collections::BTreeMap, path::PathBuf, sync::OnceLock};
+
+const SUFFIX: &str = ".db.json";
+const ROLE_SUFFIX: &str = ".db-role.json";
+
+static CLI_VARS: OnceLock<BTreeMap<String, String>> = OnceLock::new();
+
+/// Values given with `--secret-var`, which win over configured ones.
+pub fn set_cli_vars(vars: Vec<(String, String)>) {
+    let _ = CLI_VARS.set(vars.into_iter().collect());
+}
+
+/// `NAME=VALUE` for `--secret-var`.
+pub fn parse_var(argument: &str) -> Result<(String, String), String> {
+    argument
+        .split_once('=')
+        .map(|(name, value)| (name.trim().to_string(), value.to_string()))
+        .filter(|(name, _)| !name.is_empty())
+        .ok_or_else(|| format!("expected NAME=VALUE, got '{}'", argument))
+}
+
+fn template() -> Result<Option<String>> {
+    let Some(template) = config::settings()?.secret_path else {
+        return Ok(None);
+    };
+    if !template.ends_with(SUFFIX) || !template.contains("{name}") {
+        bail!(
+            "secret_path must contain {{name}} and end in {}, like {{team}}/{{env}}/{{name}}{}",
+            SUFFIX,
+            SUFFIX
+        );
+    }
+    Ok(Some(template))
+}
+
+/// Template values for `profile` (or for any secret): configured ones, then
+/// `{env}` from the profile's or the selected environment, then the
+/// profile's own, then `--secret-var`.
+fn vars(profile: Option<&Profile>) -> BTreeMap<String, String> {
+    let mut vars = config::settings().unwrap_or_default().secret_vars;
+    let environment = profile
+        .and_then(|profile| profile.environment.clone())
+        .or_else(|| config::selected_environment().map(str::to_string));
+    match environment {
+        Some(environment) => {
+            vars.insert("env".to_string(), environment);
+        }
+        None => {
+            if !vars.contains_key("env")
+                && let Some((environment, _)) = config::active_environment()
+            {
+                vars.insert("env".to_string(), environment);
+            }
+        }
+    }
+    if let Some(profile) = profile {
+        vars.extend(profile.secret_vars.clone());
+    }
+    if let Some(cli) = CLI_VARS.get() {
+        vars.extend(cli.clone());
+    }
+    vars
+}
+
+/// The `.db.json` and `.db-role.json` files of the secret `name`, with the
+/// template values of `profile` when it is known.
+pub fn files(name: &str, profile: Option<&Profile>) -> Result<(PathBuf, PathBuf)> {
+    let dir = config::secrets_dir()?;
+    let Some(template) = template()? else {
+        return Ok((
+            dir.join(format!("{}{}", name, SUFFIX)),
+            dir.join(format!("{}{}", name, ROLE_SUFFIX)),
+        ));
+    };
+
+    let mut vars = vars(profile);
+    vars.insert("name".to_string(), name.to_string());
+
+    let path = render(&template, &vars).map_err(|var| {
+        anyhow::anyhow!(
+            "secret_path {} needs {{{}}} for '{}'; pass --secret-var {}=VALUE or set it in secret_vars",
+            template,
+            var,
+            name,
+            var
+        )
+    })?;
+    let role = format!("{}{}", &path[..path.len() - SUFFIX.len()], ROLE_SUFFIX);
+    Ok((dir.join(path), dir.join(role)))
+}
+
+/// Whether the secrets of an untagged `profile` sit in the active
+/// environment's directory of an `{env}` layout, which makes it part of
+/// that environment.
+pub fn in_active_environment(name: &str, profile: &Profile) -> bool {
+    let Ok(Some(template)) = template() else {
+        return false;
+    };
+    let secret = profile.secret.as_deref().unwrap_or(name);
+    template.contains("{env}")
+        && profile.environment.is_none()
+        && config::active_environment().is_some()
+        && files(secret, Some(profile)).is_ok_and(|(path, _)| path.exists())
+}
+
+/// `template` with `{var}` replaced, or the first variable without a value.
+fn render(template: &str, vars: &BTreeMap<String, String>) -> Result<String, String> {
+    let mut result = String::new();
+    for piece in pieces(template) {
+        match piece {
+            Piece::Literal(text) => result.push_str(text),
+            Piece::Var(var) => result.push_str(vars.get(var).ok_or_else(|| var.to_string())?),
+        }
+    }
+    Ok(result)
+}
+
+enum Piece<'a> {
+    Literal(&'a str),
+    Var(&'a str),
+}
+
+fn pieces(template: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(length) = rest[start + 1..].find('}') else {
+            break;
+        };
+        if start > 0 {
+            pieces.push(Piece::Literal(&rest[..start]));
+        }
+        pieces.push(Piece::Var(&rest[start + 1..start + 1 + length]));
+        rest = &rest[start + 2 + length..];
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Literal(rest));
+    }
+    pieces
+}
+
+/// Names of the secrets in the secrets directory. Without a template,
+/// subdirectories are searched too and name their secrets by path, like
+/// `payments/staging/orders`. With one, only files matching it count,
+/// restricted to the configured and `--secret-var` values (so
+/// `--secret-var team=payments` lists the payments team's).
+pub fn names() -> Result<Vec<String>> {
+    let files = secret_index::files(&config::secrets_dir()?, SUFFIX);
+    let Some(template) = template()? else {
+        return Ok(files
+            .iter()
+            .filter_map(|file| file.strip_suffix(SUFFIX).map(str::to_string))
+            .collect());
+    };
+    let segments: Vec<Vec<Piece>> = template.split('/').map(pieces).collect();
+    let vars = vars(None);
+    let mut names = Vec::new();
+    for file in &files {
+        let parts: Vec<&str> = file.split('/').collect();
+        if parts.len() != segments.len() {
+            continue;
+        }
+        let mut bound = vars.clone();
+        if segments
+            .iter()
+            .zip(&parts)
+            .all(|(pattern, part)| matches(pattern, part, &mut bound))
+            && let Some(name) = bound.remove("name")
+        {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+/// Whether `text` fits `pattern`, binding its unbound variables.
+fn matches(pattern: &[Piece], text: &str, vars: &mut BTreeMap<String, String>) -> bool {
+    let Some((piece, rest)) = pattern.split_first() else {
+        return text.is_empty();
+    };
+    match piece {
+        Piece::Literal(literal) => text
+            .strip_prefix(literal)
+            .is_some_and(|text| matches(rest, text, vars)),
+        Piece::Var(var) => {
+            if let Some(value) = vars.get(*var).cloned() {
+                return text
+                    .strip_prefix(value.as_str())
+                    .is_some_and(|text| matches(rest, text, vars));
+            }
+            for (end, _) in text.char_indices().skip(1).chain([(text.len(), ' ')]) {
+                vars.insert(var.to_string(), text[..end].to_string());
+                if matches(rest, &text[end..], vars) {
+                    return true;
+                }
+            }
+            vars.remove(*var);
+            false
+        }
+    }
+}
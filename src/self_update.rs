@@ -0,0 +1,173 @@
+//! `self-update`: replace this binary with the latest release from the
+//! team's release endpoint, after checking its minisign signature.
+//!
+//! `update_url` answers with
+//! `{"version": "1.4.0", "assets": {"linux-x86_64": {"url": "...", "signature_url": "..."}}}`,
+//! keyed by `<os>-<arch>`. `signature_url` defaults to the asset URL plus
+//! `.minisig`.
+//!
+//! That answer isn't signed, so the version is taken from the signature's
+//! trusted comment (`minisign -S -t "connect-db 1.4.0"`) instead; otherwise a
+//! tampered endpoint could pass an old signed build off as a new one.
+
+use crate::{config, dry_run, http};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(Deserialize)]
+struct Release {
+    version: String,
+    assets: BTreeMap<String, Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    url: String,
+    signature_url: Option<String>,
+}
+
+pub fn run(check_only: bool, reinstall: bool) -> Result<()> {
+    let settings = config::settings()?;
+    let url = settings
+        .update_url
+        .context("Set update_url in config.json to the release endpoint")?;
+    let public_key = settings
+        .update_public_key
+        .context("Set update_public_key in config.json to the release minisign public key")?;
+
+    let response = http::get(&url, &[])?;
+    if !response.is_success() {
+        return Err(anyhow::anyhow!(
+            "Release endpoint {} answered {}",
+            url,
+            response.status
+        ));
+    }
+    let release: Release = serde_json::from_str(&response.body)
+        .with_context(|| format!("Unexpected answer from {}", url))?;
+    let current = env!("CARGO_PKG_VERSION");
+    let newer = parse_version(&release.version) > parse_version(current);
+    if !newer && !reinstall {
+        println!("connect-db {} is up to date", current);
+        return Ok(());
+    }
+    if check_only {
+        println!(
+            "connect-db {} is available (installed: {})",
+            release.version, current
+        );
+        return Ok(());
+    }
+
+    let platform = format!("{}-{}", env::consts::OS, env::consts::ARCH);
+    let asset = release
+        .assets
+        .get(&platform)
+        .with_context(|| format!("Release {} has no build for {}", release.version, platform))?;
+    let target = env::current_exe()
+        .and_then(fs::canonicalize)
+        .context("Failed to locate the running binary")?;
+
+    if dry_run::enabled() {
+        dry_run::stop(&format!(
+            "download {} and replace {}",
+            asset.url,
+            target.display()
+        ));
+    }
+    // Download next to the binary so the final rename is atomic
+    let staged = sibling(&target, "update")?;
+    let signature = sibling(&target, "update.minisig")?;
+    let result = (|| {
+        http::download(&asset.url, &staged)?;
+        let signature_url = asset
+            .signature_url
+            .clone()
+            .unwrap_or_else(|| format!("{}.minisig", asset.url));
+        http::download(&signature_url, &signature)?;
+        let trusted_comment = verify(&staged, &signature, &public_key)?;
+        if !names_version(&trusted_comment, &release.version) {
+            return Err(anyhow::anyhow!(
+                "The signature of release {} is for \"{}\", not updating",
+                release.version,
+                trusted_comment
+            ));
+        }
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+        fs::rename(&staged, &target)
+            .with_context(|| format!("Failed to replace {}", target.display()))
+    })();
+    let _ = fs::remove_file(&staged);
+    let _ = fs::remove_file(&signature);
+    result?;
+    println!("Updated connect-db {} -> {}", current, release.version);
+    Ok(())
+}
+
+fn sibling(target: &Path, suffix: &str) -> Result<PathBuf> {
+    let name = target
+        .file_name()
+        .context("The running binary has no file name")?
+        .to_string_lossy();
+    Ok(target.with_file_name(format!(".{}.{}.{}", name, std::process::id(), suffix)))
+}
+
+/// Check `signature` of `file`, returning its trusted comment.
+fn verify(file: &Path, signature: &Path, public_key: &str) -> Result<String> {
+    let output = Command::new("minisign")
+        .arg("-V")
+        .arg("-Q")
+        .arg("-m")
+        .arg(file)
+        .arg("-x")
+        .arg(signature)
+        .args(["-P", public_key])
+        .output()
+        .context("Failed to run minisign; install it to verify updates")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Signature check failed, not updating: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Whether a trusted comment like `connect-db 1.4.0` names `version`.
+fn names_version(trusted_comment: &str, version: &str) -> bool {
+    trusted_comment
+        .split_whitespace()
+        .any(|word| word.trim_start_matches('v') == version.trim_start_matches('v'))
+}
+
+fn parse_version(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trusted_comments_must_name_the_version() {
+        assert!(names_version("connect-db 1.4.0", "1.4.0"));
+        assert!(names_version("connect-db v1.4.0", "1.4.0"));
+        assert!(!names_version("connect-db 1.3.2", "1.4.0"));
+        assert!(!names_version("connect-db 1.4.0-rc1", "1.4.0"));
+        assert!(!names_version(
+            "timestamp:1700000000\tfile:connect-db",
+            "1.4.0"
+        ));
+    }
+}
@@ -0,0 +1,99 @@
+//! Interactive sessions, announced to the `session_webhook` when the
+//! profile is a production one and to the user's hook scripts.
+
+use crate::{
+    config::{self, Profile},
+    engines::{self, ConnectionParams},
+    hooks::{self, Event},
+    progress, resilient, time, usage, webhook,
+};
+use anyhow::Result;
+use serde_json::json;
+use std::env;
+
+/// Open the client on `name`, after its `pre-connect` hook. Without a webhook
+/// or later hooks, the client simply replaces this process; otherwise it runs
+/// as a child so the end of the session can be reported too. With
+/// `reconnect`, dropped sessions are restarted on the parameters it resolves.
+pub fn connect(
+    name: &str,
+    params: &ConnectionParams,
+    profile: &Profile,
+    reconnect: Option<&dyn Fn() -> Result<ConnectionParams>>,
+) -> Result<()> {
+    let context = hooks::context(name, params, profile);
+    hooks::run(Event::PreConnect, context.clone())?;
+    let webhook = config::settings()?
+        .session_webhook
+        .filter(|_| profile.production);
+    if reconnect.is_none()
+        && webhook.is_none()
+        && profile.post_disconnect.is_empty()
+        && !hooks::exists(Event::PostConnect)
+        && !hooks::exists(Event::PostDisconnect)
+    {
+        return engines::connect(params, profile);
+    }
+    let user = env::var("USER").unwrap_or_default();
+    if let Some(webhook) = &webhook {
+        webhook::post(
+            webhook,
+            &json!({
+                "text": format!("{} opened a session on production database {}", user, name),
+                "event": "session_start",
+                "database": name,
+                "user": user,
+                "time": time::now_rfc3339(),
+            }),
+        );
+    }
+
+    let started = time::unix_now();
+    let mut connected = || {
+        if let Err(err) = hooks::run(Event::PostConnect, context.clone()) {
+            eprintln!("Warning: {:#}", err);
+        }
+    };
+    let status = match reconnect {
+        Some(reconnect) => resilient::run(name, params, profile, reconnect, &mut connected),
+        None => engines::spawn_session(params, profile).and_then(|mut child| {
+            connected();
+            engines::wait_session(&mut child)
+        }),
+    };
+    let seconds = time::unix_now() - started;
+    if let Some(webhook) = &webhook {
+        webhook::post(
+            webhook,
+            &json!({
+                "text": format!(
+                    "{} closed their session on production database {} after {}",
+                    user,
+                    name,
+                    progress::format_duration(seconds)
+                ),
+                "event": "session_end",
+                "database": name,
+                "user": user,
+                "duration_seconds": seconds,
+                "time": time::now_rfc3339(),
+            }),
+        );
+    }
+    let mut context = context;
+    context["duration_seconds"] = seconds.into();
+    if let Ok(status) = &status {
+        context["exit_code"] = status.code().into();
+    }
+    if let Err(err) = hooks::run(Event::PostDisconnect, context) {
+        eprintln!("Warning: {:#}", err);
+    }
+    hooks::post_disconnect(profile);
+    let status = status?;
+    if !status.success() {
+        let code = status.code().unwrap_or(1);
+        usage::finish(false, code as u8);
+        std::process::exit(code);
+    }
+    Ok(())
+}
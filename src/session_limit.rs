@@ -0,0 +1,100 @@
+//! Per-profile limits on concurrent sessions (`max_sessions`), enforced with
+//! `flock` on one lock file per allowed session.
+//!
+//! A lock lasts as long as the process holding it, and the interactive client
+//! inherits it, so a crashed or killed session never leaves a stale lock.
+
+use crate::{config, dry_run, time};
+use anyhow::{Context, Result};
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, Write},
+    os::fd::AsRawFd,
+    sync::{Mutex, OnceLock},
+};
+
+static FORCE: OnceLock<bool> = OnceLock::new();
+
+/// Lock files held by this process, kept open until it exits.
+static HELD: Mutex<Vec<File>> = Mutex::new(Vec::new());
+
+fn held_names() -> &'static Mutex<HashSet<String>> {
+    static NAMES: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+    NAMES.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+/// Connect even when every session of a profile is in use (`--force`).
+pub fn ignore_limits() {
+    let _ = FORCE.set(true);
+}
+
+/// Take one of the `max_sessions` slots of `name`, failing when all of them
+/// are held by other processes.
+pub fn acquire(name: &str, profile: &config::Profile) -> Result<()> {
+    let Some(max) = profile.max_sessions else {
+        return Ok(());
+    };
+    if FORCE.get().copied().unwrap_or(false) || held_names().lock().unwrap().contains(name) {
+        return Ok(());
+    }
+    if dry_run::enabled() {
+        dry_run::report(format!(
+            "would take one of {}'s {} session slots",
+            name, max
+        ));
+        return Ok(());
+    }
+    let dir = config::data_dir()?.join("locks");
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+
+    let mut holders = Vec::new();
+    for slot in 0..max.max(1) {
+        let path = dir.join(format!("{}.{}.lock", name.replace('/', "_"), slot));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let mut holder = String::new();
+            let _ = file.read_to_string(&mut holder);
+            holders.push(holder.trim().to_string());
+            continue;
+        }
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(
+            file,
+            "pid {} since {}",
+            std::process::id(),
+            time::now_rfc3339()
+        )?;
+        HELD.lock().unwrap().push(file);
+        held_names().lock().unwrap().insert(name.to_string());
+        return Ok(());
+    }
+    Err(anyhow::anyhow!(
+        "{} allows {} concurrent session{} and all are in use ({}); pass --force to connect anyway",
+        name,
+        max,
+        if max == 1 { "" } else { "s" },
+        holders.join("; ")
+    ))
+}
+
+/// Let the client about to replace this process inherit the held locks.
+pub fn hand_over() {
+    for file in HELD.lock().unwrap().iter() {
+        let fd = file.as_raw_fd();
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFD);
+            if flags >= 0 {
+                libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC);
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use crate::{
+    config::Profile,
+    engines::{self, ConnectionParams, Engine},
+    prompt, report,
+};
+use anyhow::Result;
+use clap::ValueEnum;
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum Order {
+    TotalTime,
+    MeanTime,
+    Calls,
+    Rows,
+}
+
+/// Print the top statements from pg_stat_statements.
+pub fn report(params: &ConnectionParams, profile: &Profile, top: u32, order: Order) -> Result<()> {
+    check_extension(params, profile)?;
+
+    // PostgreSQL 13 renamed the timing columns
+    let version: u32 = engines::run_query(
+        params,
+        profile,
+        "select current_setting('server_version_num')",
+    )?
+    .parse()
+    .unwrap_or(0);
+    let (total, mean) = if version >= 130000 {
+        ("total_exec_time", "mean_exec_time")
+    } else {
+        ("total_time", "mean_time")
+    };
+    let order_by = match order {
+        Order::TotalTime => total,
+        Order::MeanTime => mean,
+        Order::Calls => "calls",
+        Order::Rows => "rows",
+    };
+
+    let rows = report::rows(&engines::run_query(
+        params,
+        profile,
+        &format!(
+            "select calls, round({total}::numeric, 1), round({mean}::numeric, 2), rows, \
+             left(regexp_replace(query, '\\s+', ' ', 'g'), 120) \
+             from pg_stat_statements where dbid = (select oid from pg_database where datname = current_database()) \
+             order by {order_by} desc limit {top}",
+        ),
+    )?);
+    if rows.is_empty() {
+        println!("No statements recorded");
+        return Ok(());
+    }
+    report::print_table(&["CALLS", "TOTAL MS", "MEAN MS", "ROWS", "QUERY"], &rows);
+    Ok(())
+}
+
+/// Clear the collected statistics.
+pub fn reset(name: &str, params: &ConnectionParams, profile: &Profile, yes: bool) -> Result<()> {
+    check_extension(params, profile)?;
+    if profile.production
+        && !yes
+        && !prompt::confirm(&format!(
+            "{} is a production database. Reset pg_stat_statements?",
+            name
+        ))?
+    {
+        return Err(anyhow::anyhow!("Aborted"));
+    }
+    engines::run_query(params, profile, "select pg_stat_statements_reset()")?;
+    println!("Reset pg_stat_statements on {}", name);
+    Ok(())
+}
+
+fn check_extension(params: &ConnectionParams, profile: &Profile) -> Result<()> {
+    if params.engine != Engine::Postgres {
+        return Err(anyhow::anyhow!("slow-queries needs a PostgreSQL database"));
+    }
+    let installed = engines::run_query(
+        params,
+        profile,
+        "select count(*) from pg_extension where extname = 'pg_stat_statements'",
+    )?;
+    if installed != "1" {
+        return Err(anyhow::anyhow!(
+            "pg_stat_statements is not installed; add it to shared_preload_libraries and run CREATE EXTENSION pg_stat_statements"
+        ));
+    }
+    Ok(())
+}
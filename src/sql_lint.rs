@@ -0,0 +1,528 @@
+//! Detection of destructive statements in SQL about to be executed.
+//!
+//! The SQL is only tokenized, not parsed: comments, string literals, quoted
+//! identifiers and dollar-quoted bodies are skipped, statements are split on
+//! top-level semicolons and classified by their leading keywords. Unlike a
+//! full parser such as sqlparser-rs, this also copes with every engine's
+//! dialect and with scripts no parser accepts, at the cost of judging
+//! statements by their shape only.
+
+use crate::config::{self, Profile};
+use anyhow::Result;
+
+/// A destructive statement, numbered from 1 in script order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub statement: usize,
+    pub reason: &'static str,
+    pub snippet: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Open,
+    Close,
+    Semicolon,
+    Other,
+}
+
+/// Destructive statements in `sql`.
+pub fn check(sql: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (index, (tokens, text)) in statements(sql).into_iter().enumerate() {
+        if let Some(reason) = classify(&tokens) {
+            findings.push(Finding {
+                statement: index + 1,
+                reason,
+                snippet: snippet(&text),
+            });
+        }
+    }
+    findings
+}
+
+/// Refuse destructive SQL on production profiles unless `allow_destructive`,
+/// and warn about it elsewhere.
+pub fn guard(name: &str, profile: &Profile, sql: &str, allow_destructive: bool) -> Result<()> {
+    let findings = check(sql);
+    if findings.is_empty() || allow_destructive {
+        return Ok(());
+    }
+    for finding in &findings {
+        eprintln!(
+            "{}: statement {}: {}: {}",
+            name, finding.statement, finding.reason, finding.snippet
+        );
+    }
+    if config::is_production(profile) {
+        return Err(anyhow::anyhow!(
+            "{} is a production database; pass --allow-destructive to run destructive statements",
+            name
+        ));
+    }
+    eprintln!("Warning: running destructive statements on {}", name);
+    Ok(())
+}
+
+/// Why `sql` is not a single statement that only reads, if it isn't.
+pub fn read_only_violation(sql: &str) -> Option<String> {
+    if let Some(reason) = meta_command(sql) {
+        return Some(reason.to_string());
+    }
+    let statements = statements(sql);
+    let [(tokens, _)] = statements.as_slice() else {
+        return Some(format!("expected one statement, got {}", statements.len()));
+    };
+    let words = top_level_words(tokens);
+    let mut verb = *words.first()?;
+    if verb == "with" {
+        verb = words
+            .iter()
+            .find(|word| matches!(**word, "select" | "insert" | "update" | "delete" | "merge"))
+            .copied()
+            .unwrap_or(verb);
+    }
+    let reads = match verb {
+        // select ... into creates a table
+        "select" => !words.contains(&"into"),
+        // explain analyze runs the statement
+        "explain" => !words.contains(&"analyze") && !words.contains(&"analyse"),
+        "show" | "values" | "table" | "describe" | "desc" => true,
+        _ => false,
+    };
+    if !reads {
+        return Some(format!(
+            "{} statements are not read-only",
+            verb.to_uppercase()
+        ));
+    }
+    // Writes also hide in CTEs and locking clauses, side effects in functions
+    tokens.iter().find_map(|(token, _)| match token {
+        Token::Word(word) if WRITE_WORDS.contains(&word.as_str()) => Some(format!(
+            "{} is not allowed in a read-only query",
+            word.to_uppercase()
+        )),
+        Token::Word(word) if SIDE_EFFECT_FUNCTIONS.contains(&word.as_str()) => {
+            Some(format!("{}() has side effects", word))
+        }
+        _ => None,
+    })
+}
+
+/// Words of writes and row locks, wherever they appear in a statement.
+const WRITE_WORDS: [&str; 13] = [
+    "insert", "update", "delete", "merge", "truncate", "create", "drop", "alter", "grant",
+    "revoke", "copy", "lock", "share",
+];
+
+/// PostgreSQL functions that change state or reach outside the database.
+const SIDE_EFFECT_FUNCTIONS: [&str; 16] = [
+    "nextval",
+    "setval",
+    "set_config",
+    "pg_terminate_backend",
+    "pg_cancel_backend",
+    "pg_reload_conf",
+    "pg_rotate_logfile",
+    "pg_advisory_lock",
+    "pg_advisory_xact_lock",
+    "pg_read_file",
+    "pg_read_binary_file",
+    "pg_ls_dir",
+    "lo_import",
+    "lo_export",
+    "dblink",
+    "dblink_exec",
+];
+
+/// Words outside parentheses, which decide the kind of statement.
+fn top_level_words(tokens: &[(Token, usize)]) -> Vec<&str> {
+    let mut depth = 0usize;
+    let mut words = Vec::new();
+    for (token, _) in tokens {
+        match token {
+            Token::Open => depth += 1,
+            Token::Close => depth = depth.saturating_sub(1),
+            Token::Word(word) if depth == 0 => words.push(word.as_str()),
+            _ => {}
+        }
+    }
+    words
+}
+
+fn classify(tokens: &[(Token, usize)]) -> Option<&'static str> {
+    let words = top_level_words(tokens);
+    let mut verb = *words.first()?;
+    if verb == "with" {
+        // with x as (...) delete from ...
+        verb = *words
+            .iter()
+            .find(|word| matches!(**word, "select" | "insert" | "update" | "delete" | "merge"))?;
+    }
+    let has_where = words.contains(&"where");
+    match verb {
+        "drop" => Some("DROP"),
+        "truncate" => Some("TRUNCATE"),
+        "alter" => Some("ALTER"),
+        "delete" if !has_where => Some("DELETE without WHERE"),
+        "update" if !has_where => Some("UPDATE without WHERE"),
+        _ => None,
+    }
+}
+
+/// A statement on one line, shortened for messages.
+fn snippet(text: &str) -> String {
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if line.chars().count() > 60 {
+        format!("{}...", line.chars().take(57).collect::<String>())
+    } else {
+        line
+    }
+}
+
+/// Tokens and source text of each non-empty statement.
+fn statements(sql: &str) -> Vec<(Vec<(Token, usize)>, String)> {
+    let mut statements = Vec::new();
+    let mut current = Vec::new();
+    let mut start = 0;
+    for (token, offset) in tokenize(sql) {
+        if token == Token::Semicolon {
+            if !current.is_empty() {
+                statements.push((
+                    std::mem::take(&mut current),
+                    sql[start..offset].trim().to_string(),
+                ));
+            }
+            start = offset + 1;
+        } else {
+            if current.is_empty() {
+                start = offset;
+            }
+            current.push((token, offset));
+        }
+    }
+    if !current.is_empty() {
+        statements.push((current, sql[start..].trim().to_string()));
+    }
+    statements
+}
+
+/// Tokens with their byte offsets; words are lowercased.
+fn tokenize(sql: &str) -> Vec<(Token, usize)> {
+    let bytes = sql.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let start = i;
+        match c {
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = skip_block_comment(bytes, i);
+                continue;
+            }
+            b'\'' | b'"' | b'`' => {
+                i = skip_quoted(bytes, i, c, false);
+                tokens.push((Token::Other, start));
+                continue;
+            }
+            b'$' => {
+                // A lone `$` is a parameter like $1
+                i = skip_dollar_quoted(bytes, i).unwrap_or(i + 1);
+                tokens.push((Token::Other, start));
+                continue;
+            }
+            b'(' => tokens.push((Token::Open, start)),
+            b')' => tokens.push((Token::Close, start)),
+            b';' => tokens.push((Token::Semicolon, start)),
+            _ if c.is_ascii_alphabetic() || c == b'_' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                // E'...' and friends are string literals, not words
+                if i < bytes.len() && bytes[i] == b'\'' && i - start == 1 {
+                    let escapes = c.eq_ignore_ascii_case(&b'e');
+                    i = skip_quoted(bytes, i, b'\'', escapes);
+                    tokens.push((Token::Other, start));
+                } else {
+                    tokens.push((Token::Word(sql[start..i].to_ascii_lowercase()), start));
+                }
+                continue;
+            }
+            _ if c.is_ascii_whitespace() => {}
+            _ => tokens.push((Token::Other, start)),
+        }
+        i += 1;
+    }
+    tokens
+}
+
+/// `sql` without comments, with whitespace outside literals collapsed and
+/// no trailing semicolon, so differently formatted queries compare equal.
+pub fn normalize(sql: &str) -> String {
+    let bytes = sql.as_bytes();
+    let mut normalized = Vec::with_capacity(bytes.len());
+    let mut space = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let start = i;
+        i = match c {
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                space = true;
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = skip_block_comment(bytes, i);
+                space = true;
+                continue;
+            }
+            _ if c.is_ascii_whitespace() => {
+                space = true;
+                i += 1;
+                continue;
+            }
+            b'\'' => {
+                // E'...' strings have backslash escapes
+                let escapes = i > 0
+                    && bytes[i - 1].eq_ignore_ascii_case(&b'e')
+                    && (i == 1 || !(bytes[i - 2].is_ascii_alphanumeric() || bytes[i - 2] == b'_'));
+                skip_quoted(bytes, i, c, escapes)
+            }
+            b'"' | b'`' => skip_quoted(bytes, i, c, false),
+            b'$' => skip_dollar_quoted(bytes, i).unwrap_or(i + 1),
+            _ => i + 1,
+        }
+        .min(bytes.len());
+        if space && !normalized.is_empty() {
+            normalized.push(b' ');
+        }
+        space = false;
+        normalized.extend_from_slice(&bytes[start..i]);
+    }
+    while normalized.last() == Some(&b';') {
+        normalized.pop();
+        while normalized.last() == Some(&b' ') {
+            normalized.pop();
+        }
+    }
+    // Only whole characters were copied, with spaces between them
+    String::from_utf8_lossy(&normalized).into_owned()
+}
+
+/// Why `sql` might hold a psql meta-command like `\!` or `\o`, which psql
+/// runs itself when reading a script.
+///
+/// Backslashes are only allowed where psql and the server agree they are
+/// data: in `E'...'` strings, dollar quotes and quoted identifiers. Plain
+/// strings may not contain them, since where those end depends on
+/// `standard_conforming_strings`; backticks don't quote anything for psql.
+fn meta_command(sql: &str) -> Option<&'static str> {
+    let bytes = sql.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let start = i;
+        i = match c {
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                if bytes[start..i].contains(&b'\\') {
+                    return Some("backslashes are not allowed in comments");
+                }
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = skip_block_comment(bytes, i);
+                if bytes[start..i.min(bytes.len())].contains(&b'\\') {
+                    return Some("backslashes are not allowed in comments");
+                }
+                continue;
+            }
+            b'\'' => {
+                let escapes = i > 0
+                    && bytes[i - 1].eq_ignore_ascii_case(&b'e')
+                    && (i == 1 || !(bytes[i - 2].is_ascii_alphanumeric() || bytes[i - 2] == b'_'));
+                let end = skip_quoted(bytes, i, c, escapes).min(bytes.len());
+                if !escapes && bytes[start..end].contains(&b'\\') {
+                    return Some("backslashes are only allowed in E'...' strings");
+                }
+                end
+            }
+            b'"' => skip_quoted(bytes, i, c, false),
+            b'$' => skip_dollar_quoted(bytes, i).unwrap_or(i + 1),
+            b'\\' => return Some("psql meta-commands are not allowed"),
+            _ => i + 1,
+        };
+    }
+    None
+}
+
+/// Index after the block comment at `start`; they nest in PostgreSQL.
+fn skip_block_comment(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"/*") {
+            depth += 1;
+            i += 2;
+        } else if bytes[i..].starts_with(b"*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                break;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    i
+}
+
+/// Index after the literal opened by `quote` at `start`; doubled quotes
+/// (and backslash escapes in `E'...'` strings) stay inside it.
+fn skip_quoted(bytes: &[u8], start: usize, quote: u8, escapes: bool) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        if escapes && bytes[i] == b'\\' {
+            i += 2;
+            continue;
+        }
+        if bytes[i] == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+/// Index after a `$tag$ ... $tag$` body starting at `start`, if one does.
+fn skip_dollar_quoted(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+        i += 1;
+    }
+    if bytes.get(i) != Some(&b'$') || bytes.get(start + 1).is_some_and(u8::is_ascii_digit) {
+        return None;
+    }
+    let tag = &bytes[start..=i];
+    let body = i + 1;
+    let end = bytes[body..]
+        .windows(tag.len())
+        .position(|window| window == tag)
+        .map(|position| body + position + tag.len())
+        .unwrap_or(bytes.len());
+    Some(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_only_queries_pass() {
+        for sql in [
+            "select * from orders",
+            "select 1;",
+            "with recent as (select * from orders) select count(*) from recent",
+            "explain select * from orders",
+            "show search_path",
+            "values (1), (2)",
+            "select 'drop table x', \"delete\" from t",
+            "select E'it\\'s\\n', $$a \\ b$$, \"odd\\name\" from t",
+            "select 1 -- a comment",
+        ] {
+            assert_eq!(read_only_violation(sql), None, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn writes_are_refused() {
+        for sql in [
+            "delete from orders",
+            "select 1; select 2",
+            "select * into copy from orders",
+            "explain analyze delete from orders",
+            "with gone as (delete from orders returning *) select * from gone",
+            "select * from orders for update",
+            "select * from orders for share",
+            "select nextval('orders_id_seq')",
+            "select pg_catalog.set_config('role', 'admin', false)",
+            "create table t (id int)",
+        ] {
+            assert!(read_only_violation(sql).is_some(), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn psql_meta_commands_are_refused() {
+        for sql in [
+            "\\! rm -rf ~",
+            "select 1 \\! id",
+            "\\o /tmp/out",
+            "select 1 \\g /tmp/out",
+            "select 1 \\gexec",
+            "\\copy orders to '/tmp/orders.csv'",
+            "\\set ON_ERROR_STOP 0",
+            "select 1; -- \\! id",
+            "select 1 /* \\! id */",
+            "select `x \\! id`",
+            // Ends early when standard_conforming_strings is off
+            "select 'a\\' \\! id --'",
+        ] {
+            assert!(read_only_violation(sql).is_some(), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn classifies_destructive_statements() {
+        let reasons = |sql: &str| -> Vec<&'static str> {
+            check(sql)
+                .into_iter()
+                .map(|finding| finding.reason)
+                .collect()
+        };
+        assert_eq!(reasons("drop table orders"), ["DROP"]);
+        assert_eq!(reasons("truncate orders"), ["TRUNCATE"]);
+        assert_eq!(reasons("delete from orders"), ["DELETE without WHERE"]);
+        assert!(reasons("delete from orders where id = 1").is_empty());
+        assert!(reasons("select 'drop table x' -- drop table y").is_empty());
+        assert!(reasons("create function f() returns void as $$ drop table x $$").is_empty());
+        assert_eq!(check("select 1; drop table orders")[0].statement, 2,);
+    }
+
+    #[test]
+    fn production_environments_refuse_destructive_statements() {
+        let mut profile = Profile {
+            environment: Some("Production".to_string()),
+            ..Profile::default()
+        };
+        assert!(guard("orders", &profile, "drop table orders", false).is_err());
+        assert!(guard("orders", &profile, "drop table orders", true).is_ok());
+        profile.environment = Some("staging".to_string());
+        assert!(guard("orders", &profile, "drop table orders", false).is_ok());
+    }
+
+    #[test]
+    fn normalizes_whitespace_and_comments_only() {
+        assert_eq!(
+            normalize("select  *\n  from t -- note\n where a = 'x  y' ;"),
+            "select * from t where a = 'x  y'"
+        );
+        assert_ne!(normalize("select 'a b'"), normalize("select 'a  b'"));
+    }
+}
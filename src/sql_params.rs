@@ -0,0 +1,314 @@
+//! `:name` placeholders in SQL, outside literals, comments and `::` casts.
+
+use crate::time;
+use anyhow::Result;
+use std::ops::Range;
+
+/// A placeholder and the byte range of `:name` in the SQL.
+#[derive(Debug, Clone)]
+pub struct Placeholder {
+    pub name: String,
+    pub range: Range<usize>,
+}
+
+/// Every placeholder in `sql`, in order.
+pub fn placeholders(sql: &str) -> Vec<Placeholder> {
+    let bytes = sql.as_bytes();
+    let mut found = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = sql[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + end + 4);
+            }
+            quote @ (b'\'' | b'"') => {
+                i += 1;
+                while i < bytes.len() {
+                    if bytes[i] == quote {
+                        if bytes.get(i + 1) != Some(&quote) {
+                            break;
+                        }
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            b'$' => {
+                // $tag$ ... $tag$ bodies
+                let tag_end = sql[i + 1..]
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .map(|end| i + 1 + end);
+                match tag_end {
+                    Some(end) if bytes[end] == b'$' && !bytes[i + 1].is_ascii_digit() => {
+                        let tag = &sql[i..=end];
+                        i = sql[end + 1..]
+                            .find(tag)
+                            .map_or(bytes.len(), |close| end + 1 + close + tag.len());
+                    }
+                    _ => i += 1,
+                }
+            }
+            b':' if bytes.get(i + 1) == Some(&b':') => i += 2,
+            b':' if bytes
+                .get(i + 1)
+                .is_some_and(|c| c.is_ascii_alphabetic() || *c == b'_') =>
+            {
+                let start = i;
+                i += 1;
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                found.push(Placeholder {
+                    name: sql[start + 1..i].to_string(),
+                    range: start..i,
+                });
+            }
+            _ => i += 1,
+        }
+    }
+    found
+}
+
+/// Distinct placeholder names in order of first use.
+pub fn names(sql: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for placeholder in placeholders(sql) {
+        if !names.contains(&placeholder.name) {
+            names.push(placeholder.name);
+        }
+    }
+    names
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Text,
+    Int,
+    Json,
+    Date,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Text => "text",
+            Kind::Int => "int",
+            Kind::Json => "json",
+            Kind::Date => "date",
+        }
+    }
+
+    /// PostgreSQL type the value is cast to.
+    pub fn pg_type(self) -> Option<&'static str> {
+        match self {
+            Kind::Text => None,
+            Kind::Int => Some("bigint"),
+            Kind::Json => Some("json"),
+            Kind::Date => Some("date"),
+        }
+    }
+}
+
+/// A value for a placeholder, from `--param name[:type]=value`.
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub name: String,
+    pub kind: Kind,
+    pub value: String,
+}
+
+/// Parser for `--param name=value` and `--param name:int=value`; the type
+/// is one of text (the default), int, json or date.
+pub fn parse_binding(argument: &str) -> Result<Binding, String> {
+    let (target, value) = argument
+        .split_once('=')
+        .ok_or_else(|| format!("expected name=value, got {}", argument))?;
+    let (name, kind) = match target.split_once(':') {
+        Some((name, kind)) => (name, kind),
+        None => (target, "text"),
+    };
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("invalid parameter name: {}", name));
+    }
+    let kind = match kind {
+        "text" => Kind::Text,
+        "int" => Kind::Int,
+        "json" => Kind::Json,
+        "date" => Kind::Date,
+        other => {
+            return Err(format!(
+                "unknown parameter type {} (expected text, int, json or date)",
+                other
+            ));
+        }
+    };
+    let valid = match kind {
+        Kind::Text => true,
+        Kind::Int => value.parse::<i64>().is_ok(),
+        Kind::Json => serde_json::from_str::<serde_json::Value>(value).is_ok(),
+        Kind::Date => time::is_valid_date(value),
+    };
+    if !valid {
+        return Err(format!(
+            "{} is not a valid {} value: {}",
+            name,
+            kind.name(),
+            value
+        ));
+    }
+    Ok(Binding {
+        name: name.to_string(),
+        kind,
+        value: value.to_string(),
+    })
+}
+
+/// Fail on placeholders without a binding and on bindings nothing uses.
+pub fn check(sql: &str, bindings: &[Binding]) -> Result<()> {
+    let names = names(sql);
+    let missing: Vec<&str> = names
+        .iter()
+        .filter(|name| !bindings.iter().any(|binding| &binding.name == *name))
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Missing --param for {}",
+            missing.join(", ")
+        ));
+    }
+    if let Some(unused) = bindings
+        .iter()
+        .find(|binding| !names.contains(&binding.name))
+    {
+        return Err(anyhow::anyhow!("The SQL has no parameter :{}", unused.name));
+    }
+    Ok(())
+}
+
+/// `sql` with every placeholder replaced by `render(binding)`; call
+/// [`check`] first.
+pub fn substitute(sql: &str, bindings: &[Binding], render: impl Fn(&Binding) -> String) -> String {
+    let mut bound = String::new();
+    let mut last = 0;
+    for placeholder in placeholders(sql) {
+        bound.push_str(&sql[last..placeholder.range.start]);
+        if let Some(binding) = bindings
+            .iter()
+            .find(|binding| binding.name == placeholder.name)
+        {
+            bound.push_str(&render(binding));
+        } else {
+            bound.push_str(&sql[placeholder.range.clone()]);
+        }
+        last = placeholder.range.end;
+    }
+    bound.push_str(&sql[last..]);
+    bound
+}
+
+/// The value as a SQL literal, for clients that can't bind parameters.
+pub fn literal(binding: &Binding) -> String {
+    match binding.kind {
+        // Validated as an integer when parsed
+        Kind::Int => binding.value.clone(),
+        _ => format!("'{}'", binding.value.replace('\'', "''")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(argument: &str) -> Binding {
+        parse_binding(argument).unwrap()
+    }
+
+    #[test]
+    fn finds_placeholders() {
+        let sql = "select * from orders where id = :id and status = :status_1 or id = :id";
+        assert_eq!(names(sql), ["id", "status_1"]);
+        let found = placeholders(sql);
+        assert_eq!(found.len(), 3);
+        assert_eq!(&sql[found[0].range.clone()], ":id");
+    }
+
+    #[test]
+    fn skips_literals_comments_and_casts() {
+        let sql = "select ':no', \"a:b\", 'it''s :no', x::text, $$ :no $$, $fn$ :no $fn$, \
+                   $1, :yes -- :no\n /* :no */ from t where y = :also";
+        assert_eq!(names(sql), ["yes", "also"]);
+        assert!(names("select :1, a:=b").is_empty());
+        assert!(names("select $").is_empty());
+        assert_eq!(names("select 1 /* open :no"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn parses_bindings() {
+        let parsed = binding("limit:int=10");
+        assert_eq!((parsed.name.as_str(), parsed.kind), ("limit", Kind::Int));
+        assert_eq!(binding("name=a=b").value, "a=b");
+        assert_eq!(binding("doc:json={\"a\": 1}").kind, Kind::Json);
+        assert_eq!(binding("day:date=2024-02-29").kind, Kind::Date);
+        for bad in [
+            "novalue",
+            "=1",
+            "bad-name=1",
+            "n:float=1",
+            "n:int=1.5",
+            "n:json={",
+            "n:date=2023-02-29",
+        ] {
+            assert!(parse_binding(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn checks_bindings_against_the_sql() {
+        let sql = "select :a, :b";
+        assert!(check(sql, &[binding("a=1"), binding("b=2")]).is_ok());
+        assert_eq!(
+            check(sql, &[binding("a=1")]).unwrap_err().to_string(),
+            "Missing --param for b"
+        );
+        assert_eq!(
+            check(sql, &[binding("a=1"), binding("b=2"), binding("c=3")])
+                .unwrap_err()
+                .to_string(),
+            "The SQL has no parameter :c"
+        );
+    }
+
+    #[test]
+    fn substitutes_literals() {
+        let bindings = [binding("name=O'Brien"), binding("limit:int=5")];
+        assert_eq!(
+            substitute(
+                "select ':name', :name::text from t limit :limit",
+                &bindings,
+                literal
+            ),
+            "select ':name', 'O''Brien'::text from t limit 5"
+        );
+        // Placeholders without a binding are left in place
+        assert_eq!(
+            substitute("select :other", &bindings, literal),
+            "select :other"
+        );
+        assert_eq!(
+            substitute("select :name, :limit", &bindings, |binding| format!(
+                "${}",
+                binding.name
+            )),
+            "select $name, $limit"
+        );
+    }
+}
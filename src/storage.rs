@@ -0,0 +1,136 @@
+use crate::{
+    config::{self, Profile},
+    engines::{self, ConnectionParams, Engine},
+    report, time,
+};
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::fs;
+
+const TABLESPACES: &str = "\
+select spcname, pg_size_pretty(pg_tablespace_size(oid)), pg_tablespace_size(oid) \
+from pg_tablespace order by 3 desc";
+
+const DATABASES: &str = "\
+select datname, pg_size_pretty(pg_database_size(datname)), temp_files, pg_size_pretty(temp_bytes) \
+from pg_stat_database join pg_database using (datname) \
+where not datistemplate order by pg_database_size(datname) desc";
+
+const WAL: &str = "select count(*), coalesce(sum(size), 0) from pg_ls_waldir()";
+
+/// Report tablespace, database, temp file and WAL usage; with a profile
+/// `storage_limit`, estimate when the disk fills up from the growth since
+/// the previous run.
+pub fn report(name: &str, params: &ConnectionParams, profile: &Profile) -> Result<()> {
+    if params.engine != Engine::Postgres {
+        return Err(anyhow::anyhow!("storage needs a PostgreSQL database"));
+    }
+    let query = |sql: &str| -> Result<Vec<Vec<String>>> {
+        Ok(report::rows(&engines::run_query(params, profile, sql)?))
+    };
+
+    let tablespaces = query(TABLESPACES)?;
+    let mut used: u64 = tablespaces
+        .iter()
+        .filter_map(|row| row.get(2)?.parse::<u64>().ok())
+        .sum();
+    println!("Tablespaces");
+    let rows: Vec<Vec<String>> = tablespaces.iter().map(|row| row[..2].to_vec()).collect();
+    report::print_table(&["TABLESPACE", "SIZE"], &rows);
+    println!();
+
+    println!("Databases");
+    report::print_table(
+        &["DATABASE", "SIZE", "TEMP FILES", "TEMP BYTES"],
+        &query(DATABASES)?,
+    );
+    println!();
+
+    // pg_ls_waldir needs superuser or pg_monitor
+    match query(WAL) {
+        Ok(wal) => {
+            if let Some([segments, bytes]) = wal.first().map(Vec::as_slice) {
+                let bytes: u64 = bytes.parse().unwrap_or(0);
+                used += bytes;
+                println!("WAL: {} segments, {}", segments, format_size(bytes));
+            }
+        }
+        Err(err) => println!("WAL: unavailable ({})", err),
+    }
+
+    let Some(limit) = &profile.storage_limit else {
+        return Ok(());
+    };
+    let limit = parse_size(limit)?;
+    println!(
+        "Used {} of {} ({:.1}%)",
+        format_size(used),
+        format_size(limit),
+        used as f64 / limit as f64 * 100.0
+    );
+    match growth_per_second(name, used)? {
+        Some(rate) if rate > 0.0 && used < limit => {
+            let seconds = (limit - used) as f64 / rate;
+            println!(
+                "Growing {}/day; full in about {:.1} days",
+                format_size((rate * 86_400.0) as u64),
+                seconds / 86_400.0
+            );
+        }
+        Some(_) => println!("Not growing since the last run"),
+        None => println!("Run again later to estimate time until full"),
+    }
+    Ok(())
+}
+
+/// Compare with the sample saved by the previous run, then save this one.
+fn growth_per_second(name: &str, used: u64) -> Result<Option<f64>> {
+    let dir = config::config_dir()?.join("storage");
+    let path = dir.join(format!("{}.json", name.replace('/', "_")));
+    let now = time::unix_now();
+
+    let previous = fs::read_to_string(&path)
+        .ok()
+        .and_then(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok())
+        .and_then(|sample| Some((sample["time"].as_u64()?, sample["used"].as_u64()?)));
+
+    fs::create_dir_all(&dir)?;
+    fs::write(&path, json!({ "time": now, "used": used }).to_string())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(previous
+        .filter(|(time, _)| now > *time)
+        .map(|(time, bytes)| (used as f64 - bytes as f64) / (now - time) as f64))
+}
+
+/// Parse sizes like `500GB`, `1.5T` or `1048576`.
+fn parse_size(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .with_context(|| format!("Invalid size: {}", value))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(anyhow::anyhow!("Invalid size unit in {}", value)),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+pub fn format_size(bytes: u64) -> String {
+    let units = ["B", "kB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, units[unit])
+}
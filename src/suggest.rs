@@ -0,0 +1,64 @@
+//! "Did you mean" suggestions for mistyped names, and glob patterns
+//! selecting several.
+
+/// Up to three of `candidates` close to `name`, closest first.
+pub fn closest<'a>(name: &str, candidates: &'a [String]) -> Vec<&'a str> {
+    let limit = (name.chars().count() / 3).max(2);
+    let mut scored: Vec<(usize, &str)> = candidates
+        .iter()
+        .filter(|candidate| candidate.as_str() != name)
+        .filter_map(|candidate| {
+            let distance = levenshtein(&name.to_lowercase(), &candidate.to_lowercase());
+            // A typed prefix like `ana` for `analytics` counts as close too
+            let prefix = candidate.starts_with(name) && name.len() >= 3;
+            (distance <= limit || prefix).then_some((distance, candidate.as_str()))
+        })
+        .collect();
+    scored.sort();
+    scored.into_iter().take(3).map(|(_, name)| name).collect()
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Whether `name` matches the shell-style `pattern`: `*` matches any run of
+/// characters, including `/`, and `?` any single one.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was and the name position it is retried from
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Whether `text` is a pattern rather than a plain name.
+pub fn is_glob(text: &str) -> bool {
+    text.contains(['*', '?'])
+}
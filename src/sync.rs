@@ -0,0 +1,298 @@
+//! Copy rows of a table between PostgreSQL databases over the COPY
+//! protocol, in batches committed one at a time.
+
+use crate::{
+    clone_schema::Side,
+    engines::{self, Engine, client, postgres},
+    progress::{Progress, Unit},
+    prompt,
+};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+};
+
+/// Temporary table the upsert mode stages each batch in.
+const STAGING_TABLE: &str = "connect_db_sync_stage";
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    /// Insert rows; existing rows cause an error on unique keys
+    Append,
+    /// Empty the target table first
+    Truncate,
+    /// Insert new rows and update existing ones by key
+    Upsert,
+}
+
+pub struct SyncOptions {
+    pub table: String,
+    /// SQL condition selecting the source rows
+    pub filter: Option<String>,
+    pub conflict: Conflict,
+    /// Upsert key (defaults to the target's primary key)
+    pub keys: Vec<String>,
+    /// Rows per transaction on the target
+    pub batch_size: usize,
+    pub yes: bool,
+}
+
+/// Stream the selected rows of `options.table` from `source` into `target`.
+pub fn sync(source: &Side, target: &Side, options: &SyncOptions) -> Result<()> {
+    for side in [source, target] {
+        if side.params.engine != Engine::Postgres {
+            return Err(anyhow::anyhow!(
+                "{} is not a PostgreSQL database; sync uses COPY",
+                side.name
+            ));
+        }
+    }
+    if target.profile.read_only {
+        return Err(anyhow::anyhow!(
+            "{} is read-only; syncing into it is not allowed",
+            target.name
+        ));
+    }
+    if target.profile.production
+        && !options.yes
+        && !prompt::confirm(&format!(
+            "{} is a production database. Write {} rows into it?",
+            target.name, options.table
+        ))?
+    {
+        return Err(anyhow::anyhow!("Aborted"));
+    }
+
+    let columns = target_columns(target, &options.table)?;
+    let statements = batch_statements(target, options, &columns)?;
+    let filter = options
+        .filter
+        .as_deref()
+        .map(|filter| format!(" where {}", filter))
+        .unwrap_or_default();
+    let total = engines::run_query(
+        source.params,
+        source.profile,
+        &format!("select count(*) from {}{}", options.table, filter),
+    )
+    .with_context(|| format!("Failed to count rows in {}", source.name))?
+    .parse()
+    .unwrap_or(0);
+
+    let copy_out = format!(
+        "copy (select {} from {}{}) to stdout",
+        columns.join(", "),
+        options.table,
+        filter
+    );
+    let mut reader = psql(source, &["-c", &copy_out])?
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to run psql")?;
+    let stdout = BufReader::new(reader.stdout.take().expect("stdout is piped"));
+
+    eprintln!(
+        "Syncing {} from {} into {} ({:?})",
+        options.table, source.name, target.name, options.conflict
+    );
+    let mut progress = Progress::new("sync", total, Unit::Rows);
+    let mut batch: Option<Batch> = None;
+    let mut batches = 0;
+    let mut rows = 0u64;
+    // COPY's text format escapes newlines inside values, so rows are lines
+    for line in stdout.split(b'\n') {
+        let line = line?;
+        if batch.is_none() {
+            batch = Some(Batch::start(target, &statements, batches == 0)?);
+        }
+        let current = batch.as_mut().expect("batch was just started");
+        current.write(&line)?;
+        rows += 1;
+        progress.set(rows, "");
+        if current.rows == options.batch_size.max(1) {
+            batch.take().expect("batch is open").commit(target)?;
+            batches += 1;
+        }
+    }
+    // Truncating an empty selection still empties the target
+    if batch.is_none() && batches == 0 && options.conflict == Conflict::Truncate {
+        batch = Some(Batch::start(target, &statements, true)?);
+    }
+
+    let read = reader.wait()?.success();
+    match batch {
+        Some(batch) if read => {
+            batch.commit(target)?;
+            batches += 1;
+        }
+        Some(mut batch) => batch.abort(),
+        None => {}
+    }
+    if !read {
+        return Err(anyhow::anyhow!(
+            "Reading {} from {} failed after {} committed batches",
+            options.table,
+            source.name,
+            batches
+        ));
+    }
+    progress.finish();
+    eprintln!(
+        "Synced {} rows of {} into {} in {} batches",
+        rows, options.table, target.name, batches
+    );
+    Ok(())
+}
+
+/// Quoted names of the target's writable columns, in table order.
+fn target_columns(target: &Side, table: &str) -> Result<Vec<String>> {
+    let sql = format!(
+        "select quote_ident(attname) from pg_attribute \
+         where attrelid = {}::regclass and attnum > 0 and not attisdropped \
+         and attgenerated = '' order by attnum",
+        postgres::quote_literal(table)
+    );
+    let output = engines::run_query(target.params, target.profile, &sql)
+        .with_context(|| format!("Failed to read the columns of {} in {}", table, target.name))?;
+    Ok(output.lines().map(str::to_string).collect())
+}
+
+fn primary_key(target: &Side, table: &str) -> Result<Vec<String>> {
+    let sql = format!(
+        "select quote_ident(a.attname) from pg_index i \
+         join pg_attribute a on a.attrelid = i.indrelid and a.attnum = any(i.indkey) \
+         where i.indrelid = {}::regclass and i.indisprimary order by a.attnum",
+        postgres::quote_literal(table)
+    );
+    let output = engines::run_query(target.params, target.profile, &sql)?;
+    Ok(output.lines().map(str::to_string).collect())
+}
+
+/// Statements of a batch as `(first batch, later batches)`; the COPY reads
+/// the batch's rows from stdin.
+fn batch_statements(
+    target: &Side,
+    options: &SyncOptions,
+    columns: &[String],
+) -> Result<(Vec<String>, Vec<String>)> {
+    let columns = columns.join(", ");
+    let table = &options.table;
+    let copy_into = |table: &str| format!("copy {} ({}) from stdin", table, columns);
+    Ok(match options.conflict {
+        Conflict::Append => (vec![copy_into(table)], vec![copy_into(table)]),
+        Conflict::Truncate => (
+            vec![format!("truncate {}", table), copy_into(table)],
+            vec![copy_into(table)],
+        ),
+        Conflict::Upsert => {
+            let keys = if options.keys.is_empty() {
+                primary_key(target, table)?
+            } else {
+                options.keys.clone()
+            };
+            if keys.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "{} has no primary key in {}; pass --key for upserts",
+                    table,
+                    target.name
+                ));
+            }
+            let updates: Vec<String> = columns
+                .split(", ")
+                .filter(|column| !keys.iter().any(|key| key == column))
+                .map(|column| format!("{} = excluded.{}", column, column))
+                .collect();
+            let action = if updates.is_empty() {
+                "do nothing".to_string()
+            } else {
+                format!("do update set {}", updates.join(", "))
+            };
+            let statements = vec![
+                format!(
+                    "create temp table {} (like {} including defaults) on commit drop",
+                    STAGING_TABLE, table
+                ),
+                copy_into(STAGING_TABLE),
+                format!(
+                    "insert into {} ({}) select {} from {} on conflict ({}) {}",
+                    table,
+                    columns,
+                    columns,
+                    STAGING_TABLE,
+                    keys.join(", "),
+                    action
+                ),
+            ];
+            (statements.clone(), statements)
+        }
+    })
+}
+
+/// psql on `side` with `args`, stopping at the first error.
+fn psql(side: &Side, args: &[&str]) -> Result<Command> {
+    let engine = side.params.engine.implementation();
+    let mut cmd = Command::new(client::ensure(engine, "psql")?);
+    cmd.args(postgres::psql_args(side.params))
+        .args(["-X", "-q", "-v", "ON_ERROR_STOP=1"])
+        .args(args)
+        .envs(engine.client_env(side.params, side.profile));
+    Ok(cmd)
+}
+
+/// One transaction on the target, fed rows on stdin.
+struct Batch {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    rows: usize,
+}
+
+impl Batch {
+    fn start(target: &Side, statements: &(Vec<String>, Vec<String>), first: bool) -> Result<Self> {
+        let statements = if first { &statements.0 } else { &statements.1 };
+        let mut args = vec!["--single-transaction"];
+        for statement in statements {
+            args.extend(["-c", statement.as_str()]);
+        }
+        let mut child = psql(target, &args)?
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .context("Failed to run psql")?;
+        let stdin = child.stdin.take();
+        Ok(Batch {
+            child,
+            stdin,
+            rows: 0,
+        })
+    }
+
+    fn write(&mut self, line: &[u8]) -> Result<()> {
+        let stdin = self.stdin.as_mut().expect("stdin is open until commit");
+        // A failed write means psql stopped on an error, reported at commit
+        if stdin.write_all(line).is_ok() {
+            let _ = stdin.write_all(b"\n");
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn commit(mut self, target: &Side) -> Result<()> {
+        drop(self.stdin.take());
+        if !self.child.wait()?.success() {
+            return Err(anyhow::anyhow!(
+                "Writing a batch of {} rows into {} failed; earlier batches were committed",
+                self.rows,
+                target.name
+            ));
+        }
+        Ok(())
+    }
+
+    /// Roll back by killing psql before its input ends.
+    fn abort(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
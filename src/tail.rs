@@ -0,0 +1,115 @@
+use crate::{
+    config::Profile,
+    dry_run,
+    engines::{ConnectionParams, Engine, client, postgres},
+};
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Command, Stdio},
+};
+
+pub struct TailOptions {
+    pub slot: String,
+    /// Output plugin, e.g. `wal2json` or `test_decoding`
+    pub plugin: String,
+    /// Plugin options passed as `-o name=value`
+    pub plugin_options: Vec<String>,
+    /// Drop the slot when the tail stops
+    pub drop_slot: bool,
+}
+
+/// Stream logical decoding output from a replication slot as NDJSON.
+///
+/// Uses `pg_recvlogical`, creating the slot when it does not exist yet.
+/// wal2json (format version 2) already emits one JSON object per change;
+/// other plugins' text lines are wrapped as `{"slot": ..., "change": ...}`.
+pub fn tail(params: &ConnectionParams, profile: &Profile, options: &TailOptions) -> Result<()> {
+    if params.engine != Engine::Postgres {
+        return Err(anyhow::anyhow!("tail needs PostgreSQL logical replication"));
+    }
+    let engine = params.engine.implementation();
+    let pg_recvlogical = client::ensure(engine, "pg_recvlogical")?;
+    let env = engine.client_env(params, profile);
+    let wal2json = options.plugin == "wal2json";
+
+    let mut create = Command::new(&pg_recvlogical);
+    create
+        .args(postgres::psql_args(params))
+        .args(["--slot", &options.slot, "--plugin", &options.plugin])
+        .args(["--create-slot", "--if-not-exists"])
+        .envs(env.clone());
+    dry_run::stop_before(&create);
+    let status = create.status().context("Failed to run pg_recvlogical")?;
+    if !status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to create replication slot {}",
+            options.slot
+        ));
+    }
+
+    let mut cmd = Command::new(&pg_recvlogical);
+    cmd.args(postgres::psql_args(params))
+        .args(["--slot", &options.slot, "--start", "--file", "-"])
+        .envs(env.clone());
+    if wal2json
+        && !options
+            .plugin_options
+            .iter()
+            .any(|option| option.starts_with("format-version="))
+    {
+        cmd.args(["-o", "format-version=2"]);
+    }
+    for option in &options.plugin_options {
+        cmd.arg("-o").arg(option);
+    }
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to run pg_recvlogical")?;
+
+    // Let Ctrl-C stop pg_recvlogical while we stay around to drop the slot
+    if options.drop_slot {
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_IGN);
+        }
+    }
+
+    eprintln!(
+        "Tailing slot {} on {} (Ctrl-C to stop)",
+        options.slot, params.database
+    );
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut out = std::io::stdout().lock();
+    for line in BufReader::new(stdout).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        if wal2json {
+            writeln!(out, "{}", line)?;
+        } else {
+            writeln!(out, "{}", json!({ "slot": options.slot, "change": line }))?;
+        }
+        out.flush()?;
+    }
+    child.wait()?;
+
+    if options.drop_slot {
+        let status = Command::new(&pg_recvlogical)
+            .args(postgres::psql_args(params))
+            .args(["--slot", &options.slot, "--drop-slot"])
+            .envs(env)
+            .status()
+            .context("Failed to run pg_recvlogical")?;
+        if !status.success() {
+            return Err(anyhow::anyhow!(
+                "Failed to drop replication slot {}",
+                options.slot
+            ));
+        }
+        eprintln!("Dropped slot {}", options.slot);
+    }
+    Ok(())
+}
@@ -0,0 +1,218 @@
+//! OpenTelemetry spans for connect-db's own operations, exported as OTLP/HTTP
+//! JSON when `OTEL_EXPORTER_OTLP_ENDPOINT` (or `..._TRACES_ENDPOINT`) is set.
+
+use crate::{http, random};
+use serde_json::{Value, json};
+use std::{
+    env,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+struct OpenSpan {
+    id: String,
+    parent: Option<String>,
+    name: String,
+    start: u128,
+    attributes: Vec<(String, String)>,
+    error: Option<String>,
+}
+
+struct Trace {
+    trace_id: String,
+    open: Vec<OpenSpan>,
+    finished: Vec<Value>,
+}
+
+static TRACE: Mutex<Option<Trace>> = Mutex::new(None);
+
+/// Ends the span when dropped.
+pub struct Span {
+    id: Option<String>,
+}
+
+fn now_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default()
+}
+
+fn traces_endpoint() -> Option<String> {
+    if let Ok(endpoint) = env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT")
+        && !endpoint.is_empty()
+    {
+        return Some(endpoint);
+    }
+    env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .filter(|endpoint| !endpoint.is_empty())
+        .map(|endpoint| format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+}
+
+/// Start a span, nested under the innermost open one. A no-op unless an
+/// OTLP endpoint is configured.
+pub fn span(name: &str) -> Span {
+    let mut trace = TRACE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if trace.is_none() {
+        if traces_endpoint().is_none() {
+            return Span { id: None };
+        }
+        let Ok(trace_id) = random::hex(16) else {
+            return Span { id: None };
+        };
+        *trace = Some(Trace {
+            trace_id,
+            open: Vec::new(),
+            finished: Vec::new(),
+        });
+    }
+    let trace = trace.as_mut().expect("trace is initialized");
+    let Ok(id) = random::hex(8) else {
+        return Span { id: None };
+    };
+    trace.open.push(OpenSpan {
+        id: id.clone(),
+        parent: trace.open.last().map(|span| span.id.clone()),
+        name: name.to_string(),
+        start: now_nanos(),
+        attributes: Vec::new(),
+        error: None,
+    });
+    Span { id: Some(id) }
+}
+
+impl Span {
+    pub fn attribute(&self, key: &str, value: impl ToString) {
+        self.with_open(|span| span.attributes.push((key.to_string(), value.to_string())));
+    }
+
+    /// Mark the span as failed.
+    pub fn error(&self, message: impl ToString) {
+        self.with_open(|span| span.error = Some(message.to_string()));
+    }
+
+    /// Mark the span as failed when `result` is an error, passing it through.
+    pub fn record<T>(&self, result: anyhow::Result<T>) -> anyhow::Result<T> {
+        if let Err(err) = &result {
+            self.error(err);
+        }
+        result
+    }
+
+    fn with_open(&self, update: impl FnOnce(&mut OpenSpan)) {
+        let Some(id) = &self.id else {
+            return;
+        };
+        let mut trace = TRACE
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(span) = trace
+            .as_mut()
+            .and_then(|trace| trace.open.iter_mut().find(|span| &span.id == id))
+        {
+            update(span);
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let Some(id) = &self.id else {
+            return;
+        };
+        let mut trace = TRACE
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(trace) = trace.as_mut()
+            && let Some(index) = trace.open.iter().position(|span| &span.id == id)
+        {
+            let span = trace.open.remove(index);
+            let finished = finish(&trace.trace_id, span);
+            trace.finished.push(finished);
+        }
+    }
+}
+
+fn finish(trace_id: &str, span: OpenSpan) -> Value {
+    let attributes: Vec<Value> = span
+        .attributes
+        .iter()
+        .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } }))
+        .collect();
+    let status = match &span.error {
+        Some(message) => json!({ "code": 2, "message": message }),
+        None => json!({ "code": 1 }),
+    };
+    let mut value = json!({
+        "traceId": trace_id,
+        "spanId": span.id,
+        "name": span.name,
+        "kind": 1,
+        "startTimeUnixNano": span.start.to_string(),
+        "endTimeUnixNano": now_nanos().to_string(),
+        "attributes": attributes,
+        "status": status,
+    });
+    if let Some(parent) = span.parent {
+        value["parentSpanId"] = json!(parent);
+    }
+    value
+}
+
+/// End any spans still open and send everything recorded so far. Called
+/// before exec'ing the client and when the command finishes.
+pub fn flush() {
+    let spans = {
+        let mut trace = TRACE
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Some(trace) = trace.as_mut() else {
+            return;
+        };
+        while let Some(span) = trace.open.pop() {
+            let finished = finish(&trace.trace_id, span);
+            trace.finished.push(finished);
+        }
+        std::mem::take(&mut trace.finished)
+    };
+    let Some(endpoint) = traces_endpoint() else {
+        return;
+    };
+    if spans.is_empty() {
+        return;
+    }
+
+    let service = env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "connect-db".to_string());
+    let body = json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [{ "key": "service.name", "value": { "stringValue": service } }]
+            },
+            "scopeSpans": [{
+                "scope": { "name": "connect-db", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }]
+        }]
+    });
+
+    // OTEL_EXPORTER_OTLP_HEADERS is a comma-separated list of key=value pairs
+    let extra_headers = env::var("OTEL_EXPORTER_OTLP_HEADERS").unwrap_or_default();
+    let mut headers: Vec<(&str, &str)> = extra_headers
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim()))
+        .collect();
+    headers.push(("Content-Type", "application/json"));
+
+    // Tracing must never break the actual command
+    match http::post(&endpoint, &headers, &body.to_string()) {
+        Ok(response) if !response.is_success() => {
+            eprintln!("Trace export failed with HTTP {}", response.status)
+        }
+        Err(err) => eprintln!("Trace export failed: {}", err),
+        Ok(_) => {}
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{
+    config::Profile,
+    engines::{self, ConnectionParams, Engine},
+    report,
+};
+use anyhow::{Context, Result};
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Phases of getting a connection, printed by `--timings`.
+#[derive(Default)]
+pub struct Timings {
+    phases: Vec<(String, Duration, String)>,
+}
+
+impl Timings {
+    /// Run `step`, recording how long it took under `phase`.
+    pub fn time<T>(&mut self, phase: &str, step: impl FnOnce() -> Result<T>) -> Result<T> {
+        let started = Instant::now();
+        let result = step();
+        let note = match &result {
+            Ok(_) => String::new(),
+            Err(err) => format!("failed: {}", err),
+        };
+        self.phases
+            .push((phase.to_string(), started.elapsed(), note));
+        result
+    }
+
+    fn record(&mut self, phase: &str, elapsed: Duration, note: impl Into<String>) {
+        self.phases.push((phase.to_string(), elapsed, note.into()));
+    }
+
+    /// Time the network steps to the server on their own, then a full login
+    /// through the client. Failures are recorded rather than returned so the
+    /// report shows how far the connection got.
+    pub fn probe(&mut self, params: &ConnectionParams, profile: &Profile) {
+        // Local file engines have nothing to resolve or dial
+        if params.host.is_empty() {
+            return;
+        }
+
+        let address = format!("{}:{}", params.host, params.port);
+        let started = Instant::now();
+        let addrs: Vec<SocketAddr> = match address.to_socket_addrs() {
+            Ok(addrs) => addrs.collect(),
+            Err(err) => {
+                self.record("dns", started.elapsed(), format!("failed: {}", err));
+                return;
+            }
+        };
+        let resolved: Vec<String> = addrs.iter().map(|addr| addr.ip().to_string()).collect();
+        self.record("dns", started.elapsed(), resolved.join(", "));
+
+        let started = Instant::now();
+        let mut stream = match connect_any(&addrs) {
+            Ok(stream) => stream,
+            Err(err) => {
+                self.record("tcp connect", started.elapsed(), format!("failed: {}", err));
+                return;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_default();
+        self.record("tcp connect", started.elapsed(), peer);
+
+        if matches!(
+            params.engine,
+            Engine::Postgres | Engine::Redshift | Engine::CockroachDb
+        ) {
+            let started = Instant::now();
+            let note = match ssl_request(&mut stream) {
+                Ok(true) => "server accepts TLS".to_string(),
+                Ok(false) => "server refused TLS".to_string(),
+                Err(err) => format!("failed: {}", err),
+            };
+            self.record("tls negotiation", started.elapsed(), note);
+        }
+        drop(stream);
+
+        let engine = params.engine.implementation();
+        let Some(sql) = engine.health_check_query() else {
+            return;
+        };
+        if engine.query_args(params, profile, sql).is_none() {
+            return;
+        }
+        let started = Instant::now();
+        let note = match engines::run_query(params, profile, sql) {
+            Ok(_) => "TLS handshake, authentication and first query".to_string(),
+            Err(err) => format!("failed: {}", err.to_string().lines().next().unwrap_or("")),
+        };
+        self.record("client login", started.elapsed(), note);
+    }
+
+    pub fn print(&self) {
+        let rows: Vec<Vec<String>> = self
+            .phases
+            .iter()
+            .map(|(phase, elapsed, note)| {
+                vec![phase.clone(), format_millis(*elapsed), note.clone()]
+            })
+            .collect();
+        report::print_table(&["PHASE", "TIME", "DETAIL"], &rows);
+        let total: Duration = self.phases.iter().map(|(_, elapsed, _)| *elapsed).sum();
+        println!("total {}\n", format_millis(total));
+    }
+}
+
+fn format_millis(elapsed: Duration) -> String {
+    format!("{:.1}ms", elapsed.as_secs_f64() * 1000.0)
+}
+
+fn connect_any(addrs: &[SocketAddr]) -> Result<TcpStream> {
+    let mut last_error = None;
+    for addr in addrs {
+        match TcpStream::connect_timeout(addr, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_error = Some(err),
+        }
+    }
+    match last_error {
+        Some(err) => Err(err.into()),
+        None => Err(anyhow::anyhow!("No addresses to connect to")),
+    }
+}
+
+/// Send PostgreSQL's SSLRequest and report whether the server is willing to
+/// switch to TLS.
+fn ssl_request(stream: &mut TcpStream) -> Result<bool> {
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    // Length 8, then the magic SSLRequest code 1234.5679
+    stream.write_all(&[0, 0, 0, 8, 0x04, 0xd2, 0x16, 0x2f])?;
+    let mut answer = [0u8; 1];
+    stream
+        .read_exact(&mut answer)
+        .context("No answer to SSLRequest")?;
+    Ok(answer[0] == b'S')
+}
@@ -0,0 +1,546 @@
+//! The subset of TOML used by catalogs and project files: `[table]` and
+//! `[[array]]` headers, bare, quoted and dotted keys, basic and literal
+//! strings (also multi-line), integers, floats, booleans, arrays and inline
+//! tables. Dates and times are kept as strings.
+
+use anyhow::{Result, bail};
+use serde_json::{Map, Value};
+
+/// Parse `text` into the JSON object it describes.
+pub fn parse(text: &str) -> Result<Value> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+    let mut root = Map::new();
+    // Where `key = value` lines go, set by the last header
+    let mut current = Vec::new();
+    let mut defined: Vec<Vec<String>> = Vec::new();
+    loop {
+        parser.skip_blank();
+        let Some(c) = parser.peek() else {
+            break;
+        };
+        let line = parser.line;
+        if c == '[' {
+            let array = parser.peek_at(1) == Some('[');
+            parser.advance(if array { 2 } else { 1 });
+            let path = parser.key()?;
+            parser.expect(']')?;
+            if array {
+                parser.expect(']')?;
+            }
+            parser.end_of_line()?;
+            let (last, parent) = path.split_last().expect("keys are never empty");
+            let parent = table_at(&mut root, parent, line)?;
+            if array {
+                let items = parent
+                    .entry(last.clone())
+                    .or_insert_with(|| Value::Array(Vec::new()));
+                let Value::Array(items) = items else {
+                    bail!(
+                        "Line {}: {} is not an array of tables",
+                        line,
+                        path.join(".")
+                    );
+                };
+                items.push(Value::Object(Map::new()));
+            } else {
+                if defined.contains(&path) {
+                    bail!("Line {}: table [{}] is defined twice", line, path.join("."));
+                }
+                defined.push(path.clone());
+                table_at(parent, std::slice::from_ref(last), line)?;
+            }
+            current = path;
+        } else {
+            let (key, value) = parser.pair()?;
+            parser.end_of_line()?;
+            insert(table_at(&mut root, &current, line)?, &key, value, line)?;
+        }
+    }
+    Ok(Value::Object(root))
+}
+
+/// The table at `path` under `table`, created as needed; an array of tables
+/// stands for its last entry.
+fn table_at<'a>(
+    mut table: &'a mut Map<String, Value>,
+    path: &[String],
+    line: usize,
+) -> Result<&'a mut Map<String, Value>> {
+    for key in path {
+        let value = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+        let value = match value {
+            Value::Array(items) => match items.last_mut() {
+                Some(last) => last,
+                None => bail!("Line {}: {} is not a table", line, key),
+            },
+            value => value,
+        };
+        let Value::Object(next) = value else {
+            bail!("Line {}: {} is not a table", line, key);
+        };
+        table = next;
+    }
+    Ok(table)
+}
+
+fn insert(table: &mut Map<String, Value>, key: &[String], value: Value, line: usize) -> Result<()> {
+    let (last, parents) = key.split_last().expect("keys are never empty");
+    let table = table_at(table, parents, line)?;
+    if table.contains_key(last) {
+        bail!("Line {}: duplicate key '{}'", line, key.join("."));
+    }
+    table.insert(last.clone(), value);
+    Ok(())
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        text.chars()
+            .enumerate()
+            .all(|(offset, c)| self.peek_at(offset) == Some(c))
+    }
+
+    fn advance(&mut self, count: usize) {
+        for _ in 0..count {
+            if self.peek() == Some('\n') {
+                self.line += 1;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.advance(1);
+        c
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_spaces();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.advance(1);
+                Ok(())
+            }
+            Some('\r' | '\n') | None => bail!("Line {}: expected '{}'", self.line, expected),
+            Some(c) => bail!("Line {}: expected '{}', found '{}'", self.line, expected, c),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.advance(1);
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while self.peek().is_some_and(|c| c != '\n') {
+                self.advance(1);
+            }
+        }
+    }
+
+    /// Skip whitespace, newlines and comments.
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n' | '\r') => self.advance(1),
+                _ => break,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<()> {
+        self.skip_spaces();
+        self.skip_comment();
+        if self.starts_with("\r\n") {
+            self.advance(2);
+        }
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.advance(1);
+                Ok(())
+            }
+            Some(c) => bail!("Line {}: unexpected '{}' after the value", self.line, c),
+        }
+    }
+
+    /// A `key = value` pair.
+    fn pair(&mut self) -> Result<(Vec<String>, Value)> {
+        let key = self.key()?;
+        self.expect('=')?;
+        self.skip_spaces();
+        Ok((key, self.value()?))
+    }
+
+    /// A dotted key, as its parts.
+    fn key(&mut self) -> Result<Vec<String>> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_spaces();
+            let part = match self.peek() {
+                Some('"') => {
+                    self.advance(1);
+                    self.basic_string()?
+                }
+                Some('\'') => {
+                    self.advance(1);
+                    self.literal_string()?
+                }
+                _ => {
+                    let mut part = String::new();
+                    while let Some(c) = self.peek()
+                        && (c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                    {
+                        part.push(c);
+                        self.advance(1);
+                    }
+                    if part.is_empty() {
+                        bail!("Line {}: expected a key", self.line);
+                    }
+                    part
+                }
+            };
+            parts.push(part);
+            self.skip_spaces();
+            if self.peek() != Some('.') {
+                return Ok(parts);
+            }
+            self.advance(1);
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        let line = self.line;
+        match self.peek() {
+            Some('"') if self.starts_with("\"\"\"") => {
+                self.advance(3);
+                self.multiline_string(true).map(Value::String)
+            }
+            Some('\'') if self.starts_with("'''") => {
+                self.advance(3);
+                self.multiline_string(false).map(Value::String)
+            }
+            Some('"') => {
+                self.advance(1);
+                self.basic_string().map(Value::String)
+            }
+            Some('\'') => {
+                self.advance(1);
+                self.literal_string().map(Value::String)
+            }
+            Some('[') => {
+                self.advance(1);
+                let mut items = Vec::new();
+                loop {
+                    self.skip_blank();
+                    if self.peek() == Some(']') {
+                        break;
+                    }
+                    items.push(self.value()?);
+                    self.skip_blank();
+                    match self.next() {
+                        Some(',') => {}
+                        Some(']') => return Ok(Value::Array(items)),
+                        _ => bail!("Line {}: unterminated array", line),
+                    }
+                }
+                self.advance(1);
+                Ok(Value::Array(items))
+            }
+            Some('{') => {
+                self.advance(1);
+                let mut table = Map::new();
+                self.skip_spaces();
+                if self.peek() == Some('}') {
+                    self.advance(1);
+                    return Ok(Value::Object(table));
+                }
+                loop {
+                    let (key, value) = self.pair()?;
+                    insert(&mut table, &key, value, line)?;
+                    self.skip_spaces();
+                    match self.next() {
+                        Some(',') => {}
+                        Some('}') => return Ok(Value::Object(table)),
+                        _ => bail!("Line {}: unterminated inline table", line),
+                    }
+                }
+            }
+            _ => {
+                let mut token = String::new();
+                while let Some(c) = self.peek()
+                    && !matches!(c, ' ' | '\t' | '\r' | '\n' | ',' | ']' | '}' | '#')
+                {
+                    token.push(c);
+                    self.advance(1);
+                }
+                scalar(&token, line)
+            }
+        }
+    }
+
+    /// The rest of a `"` string, with its escapes.
+    fn basic_string(&mut self) -> Result<String> {
+        let line = self.line;
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(text),
+                Some('\\') => text.push(self.escape()?),
+                Some('\n') | None => bail!("Line {}: unterminated string", line),
+                Some(c) => text.push(c),
+            }
+        }
+    }
+
+    /// The rest of a `'` string, taken as it is.
+    fn literal_string(&mut self) -> Result<String> {
+        let line = self.line;
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('\'') => return Ok(text),
+                Some('\n') | None => bail!("Line {}: unterminated string", line),
+                Some(c) => text.push(c),
+            }
+        }
+    }
+
+    /// The rest of a `"""` (`basic`) or `'''` string. A newline right after
+    /// the opening quotes is dropped, and in basic strings a `\` at the end
+    /// of a line joins it to the next non-blank text.
+    fn multiline_string(&mut self, basic: bool) -> Result<String> {
+        let line = self.line;
+        let quote = if basic { '"' } else { '\'' };
+        let delimiter: String = [quote; 3].iter().collect();
+        if self.starts_with("\r\n") {
+            self.advance(2);
+        } else if self.peek() == Some('\n') {
+            self.advance(1);
+        }
+        let mut text = String::new();
+        loop {
+            if self.starts_with(&delimiter) {
+                self.advance(3);
+                // Up to two quotes may end the content right before the delimiter
+                for _ in 0..2 {
+                    if self.peek() == Some(quote) {
+                        text.push(quote);
+                        self.advance(1);
+                    }
+                }
+                return Ok(text);
+            }
+            match self.next() {
+                Some('\\') if basic => {
+                    let mut ahead = 0;
+                    while matches!(self.peek_at(ahead), Some(' ' | '\t' | '\r')) {
+                        ahead += 1;
+                    }
+                    if self.peek_at(ahead) == Some('\n') {
+                        while self.peek().is_some_and(char::is_whitespace) {
+                            self.advance(1);
+                        }
+                    } else {
+                        text.push(self.escape()?);
+                    }
+                }
+                Some(c) => text.push(c),
+                None => bail!("Line {}: unterminated string", line),
+            }
+        }
+    }
+
+    /// The character a `\` escape stands for.
+    fn escape(&mut self) -> Result<char> {
+        Ok(match self.next() {
+            Some('b') => '\u{8}',
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('f') => '\u{c}',
+            Some('r') => '\r',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some(kind @ ('u' | 'U')) => {
+                let digits = if kind == 'u' { 4 } else { 8 };
+                let hex: String = (0..digits).filter_map(|_| self.next()).collect();
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Line {}: invalid escape \\{}{}", self.line, kind, hex)
+                    })?
+            }
+            Some(c) => bail!("Line {}: invalid escape \\{}", self.line, c),
+            None => bail!("Line {}: unterminated string", self.line),
+        })
+    }
+}
+
+fn scalar(token: &str, line: usize) -> Result<Value> {
+    match token {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        "" => bail!("Line {}: expected a value", line),
+        _ => {}
+    }
+    let digits = token.replace('_', "");
+    let (sign, unsigned) = match digits.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, digits.strip_prefix('+').unwrap_or(&digits)),
+    };
+    let radix = [("0x", 16), ("0o", 8), ("0b", 2)]
+        .into_iter()
+        .find_map(|(prefix, radix)| Some((unsigned.strip_prefix(prefix)?, radix)));
+    if let Some((number, radix)) = radix
+        && let Ok(number) = i64::from_str_radix(number, radix)
+    {
+        return Ok(Value::from(sign * number));
+    }
+    if let Ok(number) = digits.parse::<i64>() {
+        return Ok(Value::from(number));
+    }
+    if token.starts_with(|c: char| c.is_ascii_digit() || c == '+' || c == '-')
+        && let Ok(number) = digits.parse::<f64>()
+        && let Some(number) = serde_json::Number::from_f64(number)
+    {
+        return Ok(Value::Number(number));
+    }
+    // Dates and times, like 2024-05-27 or 2024-05-27T07:32:00Z
+    if token.len() >= 8
+        && token.starts_with(|c: char| c.is_ascii_digit())
+        && token.contains([':', '-'])
+    {
+        return Ok(Value::String(token.to_string()));
+    }
+    bail!("Line {}: invalid value '{}'", line, token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_tables_and_dotted_keys() {
+        let value = parse(
+            r#"# team catalog
+version = 1
+
+[profiles.orders]
+secret = "orders-prod"
+tunnel.ssh = "me@bastion"   # a dotted key
+"description" = 'Orders primary'
+
+[profiles."base postgres".environments.prod]
+production = true
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "version": 1,
+                "profiles": {
+                    "orders": {
+                        "secret": "orders-prod",
+                        "tunnel": {"ssh": "me@bastion"},
+                        "description": "Orders primary"
+                    },
+                    "base postgres": {"environments": {"prod": {"production": true}}}
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn parses_values() {
+        let value = parse(
+            "a = -42\nb = 1_000\nc = 0xff\nd = 3.5\ne = 1e3\nf = false\n\
+             g = 2024-05-27T07:32:00Z\nh = [1, 'two', [3]]\n\
+             i = { ssh = \"me@bastion\", port.local = 6543 }\nj = []\nk = {}\n\
+             l = \"tab\\tquote\\\" \\u00e9\"\nm = 'C:\\path'\n",
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "a": -42, "b": 1000, "c": 255, "d": 3.5, "e": 1000.0, "f": false,
+                "g": "2024-05-27T07:32:00Z", "h": [1, "two", [3]],
+                "i": {"ssh": "me@bastion", "port": {"local": 6543}}, "j": [], "k": {},
+                "l": "tab\tquote\" é", "m": "C:\\path"
+            })
+        );
+    }
+
+    #[test]
+    fn parses_multiline_values() {
+        let value = parse(
+            "sql = \"\"\"\nselect 1;\nselect 2;\"\"\"\n\
+             joined = \"\"\"one \\\n    two\"\"\"\n\
+             raw = '''\n\\d+ ''quoted'''''\n\
+             hosts = [\n  \"a\",  # first\n  \"b\",\n]\n",
+        )
+        .unwrap();
+        assert_eq!(value["sql"], "select 1;\nselect 2;");
+        assert_eq!(value["joined"], "one two");
+        assert_eq!(value["raw"], "\\d+ ''quoted''");
+        assert_eq!(value["hosts"], json!(["a", "b"]));
+    }
+
+    #[test]
+    fn parses_arrays_of_tables() {
+        let value = parse(
+            "[[queries]]\nname = \"top\"\n[queries.params]\nlimit = 10\n\n[[queries]]\nname = \"slow\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            json!({"queries": [{"name": "top", "params": {"limit": 10}}, {"name": "slow"}]})
+        );
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        for (text, message) in [
+            ("a = 1\na = 2\n", "Line 2: duplicate key 'a'"),
+            ("[t]\n[t]\n", "Line 2: table [t] is defined twice"),
+            ("a = 1\n[a.b]\n", "Line 2: a is not a table"),
+            ("a = \"open\n", "Line 1: unterminated string"),
+            ("a = [1, 2\n", "Line 1: unterminated array"),
+            ("a = 1 2\n", "Line 1: unexpected '2' after the value"),
+            ("a = nope\n", "Line 1: invalid value 'nope'"),
+            ("= 1\n", "Line 1: expected a key"),
+            ("a\n", "Line 1: expected '='"),
+            ("a b = 1\n", "Line 1: expected '=', found 'b'"),
+            ("a = \"\\q\"\n", "Line 1: invalid escape \\q"),
+        ] {
+            assert_eq!(parse(text).unwrap_err().to_string(), message, "{:?}", text);
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Full-screen database picker drawn with plain ANSI escapes.
+//!
+//! Not built on ratatui: one scrolling list and a status line only need raw
+//! mode (through libc) and a handful of escape sequences, which keeps the
+//! picker within the crates the tool already depends on.
+
+use crate::{config, history, parallel, time};
+use anyhow::{Context, Result};
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    process::{Command, Stdio},
+};
+
+/// Clipboard commands tried in order by the copy action.
+const CLIPBOARD_TOOLS: [(&str, &[&str]); 4] = [
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("pbcopy", &[]),
+];
+
+/// What the picker can do with a database, supplied by the caller.
+pub struct Actions<'a> {
+    /// Health check returning a state such as `ok` and a detail line
+    pub check: &'a (dyn Fn(&str) -> Result<(&'static str, String)> + Sync),
+    /// Full connection URL
+    pub url: &'a dyn Fn(&str) -> Result<String>,
+    /// Open the profile's SSH tunnel, returning a description of it
+    pub tunnel: &'a dyn Fn(&str) -> Result<String>,
+}
+
+struct Entry {
+    name: String,
+    group: &'static str,
+    state: String,
+    detail: String,
+}
+
+/// Show the picker; returns the database to connect to, if one was chosen.
+pub fn run(names: Vec<String>, actions: &Actions) -> Result<Option<String>> {
+    let mut entries = Vec::new();
+    for name in names {
+        let group = match config::load_profile(&name) {
+            Ok(profile) if profile.production => "production",
+            _ => "other",
+        };
+        entries.push(Entry {
+            name,
+            group,
+            state: "...".to_string(),
+            detail: String::new(),
+        });
+    }
+    // Production databases first, keeping names sorted within a group
+    entries.sort_by_key(|entry| (entry.group != "production", entry.name.clone()));
+    if entries.is_empty() {
+        return Err(anyhow::anyhow!("No databases found"));
+    }
+
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .context("The TUI needs a terminal")?;
+    let mut terminal = Terminal::enter()?;
+    refresh_health(&mut entries, actions, &mut tty)?;
+
+    let mut selected = 0;
+    let mut status = String::new();
+    loop {
+        draw(&mut tty, &entries, selected, &status)?;
+        status.clear();
+        let name = entries[selected].name.clone();
+        match read_key(&mut tty)? {
+            Key::Up => selected = selected.saturating_sub(1),
+            Key::Down => selected = (selected + 1).min(entries.len() - 1),
+            Key::Enter => return Ok(Some(name)),
+            Key::Char('q') | Key::Escape => return Ok(None),
+            Key::Char('r') => refresh_health(&mut entries, actions, &mut tty)?,
+            Key::Char('c') => {
+                status = match (actions.url)(&name).and_then(|url| copy_to_clipboard(&url)) {
+                    Ok(tool) => format!("Copied URL of {} with {}", name, tool),
+                    Err(err) => format!("Copy failed: {}", first_line(&err)),
+                };
+            }
+            Key::Char('t') => {
+                // ssh may ask for a passphrase, so hand the terminal back
+                terminal.leave();
+                let result = (actions.tunnel)(&name);
+                terminal = Terminal::enter()?;
+                status = match result {
+                    Ok(message) => message,
+                    Err(err) => format!("Tunnel failed: {}", first_line(&err)),
+                };
+            }
+            Key::Char('s') => {
+                show_sessions(&mut tty, &name)?;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn first_line(err: &anyhow::Error) -> String {
+    err.to_string()
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Check every database in parallel, redrawing once all are done.
+fn refresh_health(entries: &mut [Entry], actions: &Actions, tty: &mut File) -> Result<()> {
+    write!(tty, "\x1b[H\x1b[2JChecking {} databases...", entries.len())?;
+    tty.flush()?;
+    let check = actions.check;
+    let results = parallel::map(entries, parallel::DEFAULT_JOBS, |entry| check(&entry.name));
+    for (entry, result) in entries.iter_mut().zip(results) {
+        (entry.state, entry.detail) = match result {
+            Ok((state, detail)) => (state.to_string(), detail),
+            Err(err) => ("error".to_string(), first_line(&err)),
+        };
+    }
+    Ok(())
+}
+
+fn draw(tty: &mut File, entries: &[Entry], selected: usize, status: &str) -> Result<()> {
+    let (rows, columns) = terminal_size();
+    // Title, footer and status take three lines; group headers take more
+    let visible = rows.saturating_sub(5).max(1);
+    let offset = selected.saturating_sub(visible - 1);
+
+    let mut screen = String::from("\x1b[H\x1b[2J\x1b[1mconnect-db\x1b[0m\r\n");
+    let mut group = "";
+    for (index, entry) in entries.iter().enumerate().skip(offset).take(visible) {
+        if entry.group != group {
+            group = entry.group;
+            screen.push_str(&format!("\x1b[2m{}\x1b[0m\r\n", group));
+        }
+        let color = match entry.state.as_str() {
+            "ok" => "32",
+            "skipped" | "missing" | "..." => "33",
+            _ => "31",
+        };
+        let marker = if index == selected { "\x1b[7m>" } else { " " };
+        let line = format!("{:<24} {:<8} {}", entry.name, entry.state, entry.detail);
+        let line: String = line.chars().take(columns.saturating_sub(3)).collect();
+        screen.push_str(&format!("{} \x1b[{}m{}\x1b[0m\r\n", marker, color, line));
+    }
+    screen.push_str(&format!("\r\n{}\r\n", status));
+    screen.push_str(
+        "\x1b[2m↑/↓ move  enter connect  c copy URL  t tunnel  s sessions  r refresh  q quit\x1b[0m",
+    );
+    tty.write_all(screen.as_bytes())?;
+    tty.flush()?;
+    Ok(())
+}
+
+fn show_sessions(tty: &mut File, name: &str) -> Result<()> {
+    let sessions = history::recent(name, 20)?;
+    let mut screen = format!(
+        "\x1b[H\x1b[2J\x1b[1mRecent sessions: {}\x1b[0m\r\n\r\n",
+        name
+    );
+    if sessions.is_empty() {
+        screen.push_str("No sessions recorded yet\r\n");
+    }
+    for session in sessions {
+        screen.push_str(&format!(
+            "{}  {}\r\n",
+            time::rfc3339(session.started_at),
+            session.environment.unwrap_or_default()
+        ));
+    }
+    screen.push_str("\r\n\x1b[2mpress any key\x1b[0m");
+    tty.write_all(screen.as_bytes())?;
+    tty.flush()?;
+    read_key(tty)?;
+    Ok(())
+}
+
+fn copy_to_clipboard(text: &str) -> Result<&'static str> {
+    for (tool, args) in CLIPBOARD_TOOLS {
+        let Ok(mut child) = Command::new(tool)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            continue;
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        if child.wait()?.success() {
+            return Ok(tool);
+        }
+    }
+    Err(anyhow::anyhow!(
+        "No clipboard tool found (install wl-copy, xclip, xsel or pbcopy)"
+    ))
+}
+
+enum Key {
+    Up,
+    Down,
+    Enter,
+    Escape,
+    Char(char),
+    Other,
+}
+
+fn read_key(tty: &mut File) -> Result<Key> {
+    let mut byte = [0u8; 1];
+    tty.read_exact(&mut byte)?;
+    Ok(match byte[0] {
+        b'\r' | b'\n' => Key::Enter,
+        b'k' => Key::Up,
+        b'j' => Key::Down,
+        // Ctrl-C, since raw mode turns off signals
+        3 => Key::Escape,
+        0x1b => {
+            let mut sequence = [0u8; 2];
+            if tty.read_exact(&mut sequence).is_err() || sequence[0] != b'[' {
+                return Ok(Key::Escape);
+            }
+            match sequence[1] {
+                b'A' => Key::Up,
+                b'B' => Key::Down,
+                _ => Key::Other,
+            }
+        }
+        byte if byte.is_ascii_graphic() => Key::Char(byte as char),
+        _ => Key::Other,
+    })
+}
+
+fn terminal_size() -> (usize, usize) {
+    let output = File::open("/dev/tty")
+        .ok()
+        .and_then(|tty| Command::new("stty").arg("size").stdin(tty).output().ok());
+    let size = output.map(|output| String::from_utf8_lossy(&output.stdout).to_string());
+    let mut numbers = size
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|number| number.parse().ok());
+    match (numbers.next(), numbers.next()) {
+        (Some(rows), Some(columns)) if rows > 0 && columns > 0 => (rows, columns),
+        _ => (24, 80),
+    }
+}
+
+fn stty(args: &[&str]) -> Option<String> {
+    let tty = File::open("/dev/tty").ok()?;
+    let output = Command::new("stty").args(args).stdin(tty).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn write_tty(text: &str) {
+    if let Ok(mut tty) = OpenOptions::new().write(true).open("/dev/tty") {
+        let _ = tty.write_all(text.as_bytes());
+    }
+}
+
+/// Raw mode on the alternate screen, restored when dropped.
+struct Terminal {
+    saved: Option<String>,
+}
+
+impl Terminal {
+    fn enter() -> Result<Self> {
+        let saved = stty(&["-g"]).context("Failed to read terminal settings")?;
+        stty(&["raw", "-echo"]).context("Failed to switch the terminal to raw mode")?;
+        write_tty("\x1b[?1049h\x1b[?25l");
+        Ok(Terminal { saved: Some(saved) })
+    }
+
+    fn leave(&mut self) {
+        if let Some(saved) = self.saved.take() {
+            write_tty("\x1b[?25h\x1b[?1049l");
+            stty(&[&saved]);
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        self.leave();
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{config::TunnelConfig, engines, proxy};
+use anyhow::{Context, Result};
+use std::{
+    net::TcpListener,
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long the backgrounded ssh waits for the first connection. Once psql
+/// is connected the forward stays up until psql disconnects.
+const TUNNEL_GRACE_SECONDS: u32 = 10;
+
+/// Start an SSH port forward to `host:port` through the configured jump host
+/// and return the local port to connect to instead.
+pub fn open_ssh_tunnel(
+    tunnel: &TunnelConfig,
+    host: &str,
+    port: &str,
+    proxy: Option<&str>,
+) -> Result<u16> {
+    let local_port = match tunnel.local_port {
+        Some(port) => port,
+        None => free_local_port()?,
+    };
+
+    println!(
+        "Opening SSH tunnel via {} (localhost:{} -> {}:{})",
+        tunnel.ssh, local_port, host, port
+    );
+    let status = ssh_command(local_port, host, port, proxy)?
+        .arg("-f")
+        .arg(&tunnel.ssh)
+        .arg(format!("sleep {}", TUNNEL_GRACE_SECONDS))
+        .status()
+        .context("Failed to run ssh")?;
+    if !status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to open SSH tunnel via {}",
+            tunnel.ssh
+        ));
+    }
+
+    Ok(local_port)
+}
+
+/// A forward like [`open_ssh_tunnel`]'s that stays up until the returned
+/// ssh process is killed, for `connect-db daemon`.
+pub fn open_persistent_tunnel(
+    tunnel: &TunnelConfig,
+    host: &str,
+    port: &str,
+    proxy: Option<&str>,
+) -> Result<(Child, u16)> {
+    let local_port = free_local_port()?;
+    let mut child = ssh_command(local_port, host, port, proxy)?
+        .arg("-N")
+        .arg(&tunnel.ssh)
+        .stdin(Stdio::null())
+        .spawn()
+        .context("Failed to run ssh")?;
+    let started = Instant::now();
+    while !engines::is_reachable(&format!("127.0.0.1:{}", local_port)) {
+        if child.try_wait()?.is_some() || started.elapsed() > Duration::from_secs(30) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow::anyhow!(
+                "Failed to open SSH tunnel via {}",
+                tunnel.ssh
+            ));
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    Ok((child, local_port))
+}
+
+fn ssh_command(local_port: u16, host: &str, port: &str, proxy: Option<&str>) -> Result<Command> {
+    let mut command = Command::new("ssh");
+    // The jump host itself may only be reachable through the proxy
+    if let Some(url) = proxy {
+        command
+            .args([
+                "-o",
+                &format!("ProxyCommand={}", proxy::ssh_proxy_command()?),
+            ])
+            .env(proxy::PROXY_VARIABLE, url);
+    }
+    command
+        .args(["-o", "ExitOnForwardFailure=yes"])
+        .arg("-L")
+        .arg(format!("{}:{}:{}", local_port, host, port));
+    Ok(command)
+}
+
+pub fn free_local_port() -> Result<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0").context("Failed to find a free local port")?;
+    Ok(listener.local_addr()?.port())
+}
@@ -0,0 +1,111 @@
+//! Opt-in usage statistics: with `usage_stats_url` set, each run sends one
+//! event naming the command, engine, duration and outcome to that endpoint.
+//!
+//! Events carry no database names, hosts, users or arguments; runs are only
+//! tied together by a random id kept in the data directory.
+
+use crate::{config, engines::Engine, http, random};
+use serde_json::json;
+use std::{
+    fs,
+    sync::{Mutex, OnceLock},
+    time::Instant,
+};
+
+struct Run {
+    command: String,
+    /// `None` until a database is resolved, `Some(None)` once databases of
+    /// different engines were
+    engine: Option<Option<Engine>>,
+    started: Instant,
+}
+
+static RUN: Mutex<Option<Run>> = Mutex::new(None);
+
+/// How long sending an event may delay the command.
+const SEND_TIMEOUT_SECONDS: u64 = 2;
+
+fn endpoint() -> Option<&'static str> {
+    static ENDPOINT: OnceLock<Option<String>> = OnceLock::new();
+    ENDPOINT
+        .get_or_init(|| {
+            config::settings()
+                .ok()
+                .and_then(|settings| settings.usage_stats_url)
+                .filter(|url| !url.is_empty())
+        })
+        .as_deref()
+}
+
+/// Start timing a run of `command` (like `exec` or `connect`).
+pub fn start(command: &str) {
+    *RUN.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Run {
+        command: command.to_string(),
+        engine: None,
+        started: Instant::now(),
+    });
+}
+
+/// Note the engine of a database the run resolved. Runs across several
+/// engines (like `status`) are reported without one.
+pub fn set_engine(engine: Engine) {
+    if let Some(run) = RUN
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .as_mut()
+    {
+        run.engine = match run.engine {
+            None => Some(Some(engine)),
+            Some(seen) if seen == Some(engine) => Some(seen),
+            Some(_) => Some(None),
+        };
+    }
+}
+
+/// Send the event for the current run, once. Called when the command
+/// finishes and before this process is replaced by a client.
+pub fn finish(success: bool, exit_code: u8) {
+    let Some(run) = RUN
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .take()
+    else {
+        return;
+    };
+    let Some(url) = endpoint() else {
+        return;
+    };
+    let event = json!({
+        "install_id": install_id(),
+        "version": env!("CARGO_PKG_VERSION"),
+        "os": std::env::consts::OS,
+        "command": run.command,
+        "engine": run.engine.flatten().map(|engine| format!("{:?}", engine).to_lowercase()),
+        "duration_ms": run.started.elapsed().as_millis() as u64,
+        "success": success,
+        "exit_code": exit_code,
+    });
+    // Statistics must never get in the way, so failures are silent
+    let _ = http::post_with_timeout(
+        url,
+        &[("Content-Type", "application/json")],
+        &event.to_string(),
+        SEND_TIMEOUT_SECONDS,
+    );
+}
+
+/// Random id of this installation, created on first use.
+fn install_id() -> String {
+    let Ok(path) = config::data_dir().map(|dir| dir.join("usage-id")) else {
+        return String::new();
+    };
+    if let Ok(id) = fs::read_to_string(&path) {
+        return id.trim().to_string();
+    }
+    let id = random::hex(16).unwrap_or_default();
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let _ = fs::write(&path, &id);
+    id
+}
@@ -0,0 +1,102 @@
+//! Row counts and checksums of whole tables, to check a restore or a
+//! migration against its source.
+
+use crate::{
+    clone_schema::Side,
+    engines::{self, Engine},
+    report,
+};
+use anyhow::{Context, Result};
+use std::thread;
+
+/// Row count and checksum of one table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Summary {
+    rows: String,
+    checksum: String,
+}
+
+/// Print the row count and checksum of every table in `side`, side by side
+/// with those in `against` when given. Fails when any table differs.
+pub fn verify(side: &Side, against: Option<&Side>, tables: &[String]) -> Result<()> {
+    for side in std::iter::once(side).chain(against) {
+        if side.params.engine != Engine::Postgres {
+            return Err(anyhow::anyhow!(
+                "{} is not a PostgreSQL database; verify only supports PostgreSQL",
+                side.name
+            ));
+        }
+    }
+
+    let mut rows = Vec::new();
+    let mut different = Vec::new();
+    for table in tables {
+        let (summary, other) = thread::scope(|scope| {
+            let other = against.map(|against| scope.spawn(|| summarize(against, table)));
+            let summary = summarize(side, table);
+            let other = other.map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("query panicked")))
+            });
+            (summary, other)
+        });
+        let summary = summary?;
+        let mut row = vec![
+            table.clone(),
+            summary.rows.clone(),
+            summary.checksum.clone(),
+        ];
+        if let Some(other) = other {
+            let other = other?;
+            let matches = other == summary;
+            if !matches {
+                different.push(table.as_str());
+            }
+            row.extend([
+                other.rows,
+                other.checksum,
+                if matches { "yes" } else { "NO" }.to_string(),
+            ]);
+        }
+        rows.push(row);
+    }
+
+    match against {
+        Some(against) => {
+            let rows_a = format!("ROWS ({})", side.name);
+            let rows_b = format!("ROWS ({})", against.name);
+            report::print_table(
+                &["TABLE", &rows_a, "CHECKSUM", &rows_b, "CHECKSUM", "MATCH"],
+                &rows,
+            );
+        }
+        None => report::print_table(&["TABLE", "ROWS", "CHECKSUM"], &rows),
+    }
+    if !different.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} of {} tables differ: {}",
+            different.len(),
+            tables.len(),
+            different.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Count the rows of `table` and checksum them in an order that doesn't
+/// depend on how they are stored.
+fn summarize(side: &Side, table: &str) -> Result<Summary> {
+    let sql = format!(
+        "select count(*), coalesce(md5(string_agg(row_hash, '' order by row_hash)), '') \
+         from (select md5(verify_row::text) as row_hash from {} verify_row) hashes",
+        table
+    );
+    let output = engines::run_query(side.params, side.profile, &sql)
+        .with_context(|| format!("Failed to checksum {} on {}", table, side.name))?;
+    let (rows, checksum) = output.split_once('|').unwrap_or((&output, ""));
+    Ok(Summary {
+        rows: rows.to_string(),
+        checksum: checksum.to_string(),
+    })
+}
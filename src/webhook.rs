@@ -0,0 +1,18 @@
+//! JSON notifications to chat webhooks (Slack-compatible `text` payloads).
+
+use crate::http;
+use serde_json::Value;
+
+/// POST `body` to `url`. Failures are printed rather than returned, since a
+/// missed notification shouldn't stop the work it reports on.
+pub fn post(url: &str, body: &Value) {
+    match http::post(
+        url,
+        &[("Content-Type", "application/json")],
+        &body.to_string(),
+    ) {
+        Ok(response) if response.is_success() => {}
+        Ok(response) => eprintln!("Warning: webhook returned HTTP {}", response.status),
+        Err(err) => eprintln!("Warning: webhook failed: {}", err),
+    }
+}
@@ -0,0 +1,194 @@
+//! Ephemeral WireGuard interfaces for profiles with a `wireguard` section.
+//!
+//! The profile's wg-quick config has its `{{field}}` placeholders (keys, in
+//! practice) filled from the credentials' extra fields, is written to a
+//! private file and brought up with `wg-quick`. A detached
+//! `connect-db wireguard-down` waits for the session's process to exit and
+//! tears the interface down once no other session uses it.
+
+use crate::{config, dry_run, engines::ConnectionParams, sha256::Sha256};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    os::{fd::AsRawFd, unix::fs::DirBuilderExt, unix::fs::OpenOptionsExt},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct WireguardConfig {
+    /// wg-quick config file, with placeholders like `{{wireguard_private_key}}`
+    pub config: String,
+}
+
+/// Bring up the interface for `wireguard` (or join it when another session
+/// already did) and keep it up while this process runs.
+pub fn up(wireguard: &WireguardConfig, params: &ConnectionParams) -> Result<()> {
+    let path = PathBuf::from(config::expand_env(&wireguard.config)?);
+    let template = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read WireGuard config {}", path.display()))?;
+    let rendered = render(&template, params)
+        .with_context(|| format!("In WireGuard config {}", path.display()))?;
+    // One interface per config file; names are at most 15 characters
+    let mut hasher = Sha256::default();
+    hasher.update(fs::canonicalize(&path)?.to_string_lossy().as_bytes());
+    let interface = format!("cdb{}", &hasher.finish()[..8]);
+    if dry_run::enabled() {
+        dry_run::report(format!(
+            "would bring up WireGuard interface {} from {}",
+            interface,
+            path.display()
+        ));
+        return Ok(());
+    }
+
+    let dir = state_dir(&interface)?;
+    let _lock = lock(&dir)?;
+    let conf = dir.join(format!("{}.conf", interface));
+    if !Path::new("/sys/class/net").join(&interface).exists() {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&conf)
+            .with_context(|| format!("Failed to write {}", conf.display()))?;
+        file.write_all(rendered.as_bytes())?;
+        eprintln!("Bringing up WireGuard interface {}", interface);
+        if let Err(err) = wg_quick("up", &conf) {
+            let _ = fs::remove_file(&conf);
+            return Err(err);
+        }
+    }
+    let pid = std::process::id();
+    fs::write(dir.join(format!("{}.pid", pid)), "")?;
+
+    let program = env::current_exe().context("Failed to locate the connect-db binary")?;
+    Command::new(program)
+        .args(["wireguard-down", &interface, &pid.to_string()])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to start the WireGuard watcher")?;
+    Ok(())
+}
+
+/// `connect-db wireguard-down`: wait for `pid` to exit, then take
+/// `interface` down unless other live sessions still use it.
+pub fn down_after(interface: &str, pid: u32) -> Result<()> {
+    // The watcher must outlive the terminal the session ran in
+    unsafe {
+        libc::setsid();
+    }
+    while alive(pid) {
+        thread::sleep(Duration::from_secs(1));
+    }
+    let dir = state_dir(interface)?;
+    let _lock = lock(&dir)?;
+    let _ = fs::remove_file(dir.join(format!("{}.pid", pid)));
+    let mut others = false;
+    for entry in fs::read_dir(&dir)?.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(other) = name.strip_suffix(".pid").and_then(|pid| pid.parse().ok()) else {
+            continue;
+        };
+        if alive(other) {
+            others = true;
+        } else {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+    if others {
+        return Ok(());
+    }
+    let conf = dir.join(format!("{}.conf", interface));
+    // Another watcher of the same process may have been first
+    if !conf.exists() {
+        return Ok(());
+    }
+    let result = wg_quick("down", &conf);
+    let _ = fs::remove_file(&conf);
+    result
+}
+
+/// `template` with `{{field}}` replaced by the credentials' extra fields.
+fn render(template: &str, params: &ConnectionParams) -> Result<String> {
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let field = rest[start + 2..start + 2 + length].trim();
+        let value = params.extra.get(field).with_context(|| {
+            format!(
+                "{{{{{}}}}} needs a '{}' field in the credentials file",
+                field, field
+            )
+        })?;
+        result.push_str(&rest[..start]);
+        result.push_str(value);
+        rest = &rest[start + 4 + length..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn wg_quick(action: &str, conf: &Path) -> Result<()> {
+    // wg-quick needs root. sudo may ask for a password when bringing the
+    // interface up; the watcher has no terminal to ask on
+    let mut command = if unsafe { libc::geteuid() } == 0 {
+        Command::new("wg-quick")
+    } else {
+        let mut command = Command::new("sudo");
+        if action == "down" {
+            command.arg("-n");
+        }
+        command.arg("wg-quick");
+        command
+    };
+    let status = command
+        .arg(action)
+        .arg(conf)
+        .stdout(Stdio::null())
+        .status()
+        .context("Failed to run wg-quick (install wireguard-tools)")?;
+    if !status.success() {
+        bail!(
+            "wg-quick {} {} failed with {}",
+            action,
+            conf.display(),
+            status
+        );
+    }
+    Ok(())
+}
+
+fn state_dir(interface: &str) -> Result<PathBuf> {
+    let dir = config::data_dir()?.join("wireguard").join(interface);
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)
+        .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+    Ok(dir)
+}
+
+/// Serializes bringing the interface up and down across processes.
+fn lock(dir: &Path) -> Result<File> {
+    let file = File::create(dir.join("lock"))?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        bail!("Failed to lock {}", dir.display());
+    }
+    Ok(file)
+}
+
+fn alive(pid: u32) -> bool {
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
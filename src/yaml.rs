@@ -0,0 +1,422 @@
+//! The block-style subset of YAML used by plan files: nested mappings and
+//! `-` sequences, plain and quoted scalars, `|`/`>` block scalars, `#`
+//! comments and one-line `[a, b]` flow sequences. Anchors, tags and
+//! multi-document files are not supported.
+
+use anyhow::{Result, bail};
+use serde_json::{Map, Value};
+
+/// Parse `text` into the JSON value it describes.
+pub fn parse(text: &str) -> Result<Value> {
+    let mut parser = Parser {
+        lines: text.lines().map(str::to_string).collect(),
+        pos: 0,
+    };
+    parser.skip_blank();
+    if parser.pos == parser.lines.len() {
+        return Ok(Value::Null);
+    }
+    let value = parser.node(0)?;
+    parser.skip_blank();
+    if parser.pos < parser.lines.len() {
+        bail!("Line {}: unexpected indentation", parser.pos + 1);
+    }
+    Ok(value)
+}
+
+struct Parser {
+    lines: Vec<String>,
+    pos: usize,
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_blank(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+fn is_sequence_item(content: &str) -> bool {
+    content == "-" || content.starts_with("- ")
+}
+
+impl Parser {
+    fn skip_blank(&mut self) {
+        while self.pos < self.lines.len() && is_blank(&self.lines[self.pos]) {
+            self.pos += 1;
+        }
+    }
+
+    /// The indentation and content of the next non-blank line.
+    fn peek(&mut self) -> Option<(usize, String)> {
+        self.skip_blank();
+        let line = self.lines.get(self.pos)?;
+        Some((indent(line), line.trim_start_matches(' ').to_string()))
+    }
+
+    /// The mapping, sequence or scalar starting on the next line, which is
+    /// indented by at least `min_indent`.
+    fn node(&mut self, min_indent: usize) -> Result<Value> {
+        let Some((indent, content)) = self.peek() else {
+            return Ok(Value::Null);
+        };
+        if indent < min_indent {
+            return Ok(Value::Null);
+        }
+        if content.starts_with('\t') {
+            bail!("Line {}: tabs can't be used for indentation", self.pos + 1);
+        }
+        if is_sequence_item(&content) {
+            self.sequence(indent)
+        } else if split_key(&content).is_some() {
+            self.mapping(indent)
+        } else {
+            self.pos += 1;
+            scalar(&content, self.pos)
+        }
+    }
+
+    fn sequence(&mut self, indent: usize) -> Result<Value> {
+        let mut items = Vec::new();
+        while let Some((line_indent, content)) = self.peek()
+            && line_indent == indent
+            && is_sequence_item(&content)
+        {
+            let rest = content[1..].trim_start_matches(' ');
+            if rest.is_empty() || rest.starts_with('#') {
+                self.pos += 1;
+                items.push(self.node(indent + 1)?);
+                continue;
+            }
+            // Parse what follows the dash as if it started its own line, so
+            // `- key: value` opens a mapping indented past the dash
+            let offset = indent + content.len() - rest.len();
+            self.lines[self.pos] = format!("{}{}", " ".repeat(offset), rest);
+            if is_sequence_item(rest) || split_key(rest).is_some() {
+                items.push(self.node(offset)?);
+            } else {
+                items.push(self.value(rest.to_string(), indent)?);
+            }
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn mapping(&mut self, indent: usize) -> Result<Value> {
+        let mut map = Map::new();
+        while let Some((line_indent, content)) = self.peek()
+            && line_indent >= indent
+        {
+            if line_indent > indent {
+                bail!("Line {}: unexpected indentation", self.pos + 1);
+            }
+            if is_sequence_item(&content) {
+                break;
+            }
+            let Some((key, rest)) = split_key(&content) else {
+                bail!("Line {}: expected `key: value`", self.pos + 1);
+            };
+            let key = match scalar(&key, self.pos + 1)? {
+                Value::String(key) => key,
+                other => other.to_string(),
+            };
+            if map.contains_key(&key) {
+                bail!("Line {}: duplicate key '{}'", self.pos + 1, key);
+            }
+            let value = if strip_comment(&rest).is_empty() {
+                self.pos += 1;
+                match self.peek() {
+                    // A sequence may sit at the same indentation as its key
+                    Some((next, content)) if next == indent && is_sequence_item(&content) => {
+                        self.sequence(indent)?
+                    }
+                    Some((next, _)) if next > indent => self.node(indent + 1)?,
+                    _ => Value::Null,
+                }
+            } else {
+                self.value(rest, indent)?
+            };
+            map.insert(key, value);
+        }
+        Ok(Value::Object(map))
+    }
+
+    /// The inline value `text` on the current line, which may open a block
+    /// scalar continuing on lines indented past `parent`.
+    fn value(&mut self, text: String, parent: usize) -> Result<Value> {
+        let line = self.pos + 1;
+        self.pos += 1;
+        let text = strip_comment(&text);
+        let Some(style) = text.chars().next().filter(|c| *c == '|' || *c == '>') else {
+            return scalar(&text, line);
+        };
+        let chomp = &text[1..];
+        if !matches!(chomp, "" | "-" | "+") {
+            bail!("Line {}: unsupported block scalar header '{}'", line, text);
+        }
+
+        let mut block = Vec::new();
+        let mut block_indent = None;
+        while let Some(raw) = self.lines.get(self.pos) {
+            if raw.trim().is_empty() {
+                block.push(String::new());
+                self.pos += 1;
+                continue;
+            }
+            let raw_indent = indent(raw);
+            if raw_indent <= parent {
+                break;
+            }
+            let block_indent = *block_indent.get_or_insert(raw_indent);
+            if raw_indent < block_indent {
+                break;
+            }
+            block.push(raw[block_indent..].to_string());
+            self.pos += 1;
+        }
+        // Trailing blank lines belong to what follows
+        let mut trailing = 0;
+        while block.last().is_some_and(String::is_empty) {
+            block.pop();
+            trailing += 1;
+        }
+        self.pos -= trailing;
+
+        let mut result = if style == '|' {
+            block.join("\n")
+        } else {
+            fold(&block)
+        };
+        match chomp {
+            "-" => {}
+            "+" => result.push_str(&"\n".repeat(trailing + 1)),
+            _ if !block.is_empty() => result.push('\n'),
+            _ => {}
+        }
+        Ok(Value::String(result))
+    }
+}
+
+/// `>` blocks: lines join with spaces, blank lines become newlines.
+fn fold(lines: &[String]) -> String {
+    let mut result = String::new();
+    for line in lines {
+        if line.is_empty() {
+            result.push('\n');
+        } else {
+            if !result.is_empty() && !result.ends_with('\n') {
+                result.push(' ');
+            }
+            result.push_str(line);
+        }
+    }
+    result
+}
+
+/// `key: rest` (or `key:`), with `:` outside quotes and followed by a space.
+fn split_key(content: &str) -> Option<(String, String)> {
+    let mut quote = None;
+    for (index, c) in content.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') if index == 0 => quote = Some(c),
+            (Some(open), c) if c == open => quote = None,
+            (None, '#') if index == 0 => return None,
+            (None, ':') => {
+                let rest = &content[index + 1..];
+                if rest.is_empty() || rest.starts_with(' ') {
+                    return Some((content[..index].trim().to_string(), rest.trim().to_string()));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `text` without a trailing ` # comment` outside quotes.
+fn strip_comment(text: &str) -> String {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (index, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), c) if c == open => quote = None,
+            (None, '#') if previous == ' ' => return text[..index].trim_end().to_string(),
+            _ => {}
+        }
+        previous = c;
+    }
+    text.trim_end().to_string()
+}
+
+fn scalar(text: &str, line: usize) -> Result<Value> {
+    let text = strip_comment(text);
+    let text = text.trim();
+    if let Some(inner) = text.strip_prefix('\'') {
+        let Some(inner) = inner.strip_suffix('\'') else {
+            bail!("Line {}: unterminated quoted string", line);
+        };
+        return Ok(Value::String(inner.replace("''", "'")));
+    }
+    if text.starts_with('"') {
+        return serde_json::from_str(text)
+            .map_err(|err| anyhow::anyhow!("Line {}: invalid quoted string: {}", line, err));
+    }
+    if let Some(inner) = text.strip_prefix('[') {
+        let Some(inner) = inner.strip_suffix(']') else {
+            bail!("Line {}: unterminated flow sequence", line);
+        };
+        if let Ok(value) = serde_json::from_str(text) {
+            return Ok(value);
+        }
+        if inner.trim().is_empty() {
+            return Ok(Value::Array(Vec::new()));
+        }
+        return inner
+            .split(',')
+            .map(|item| scalar(item, line))
+            .collect::<Result<_>>()
+            .map(Value::Array);
+    }
+    if text.starts_with('{') {
+        return serde_json::from_str(text)
+            .map_err(|err| anyhow::anyhow!("Line {}: invalid flow mapping: {}", line, err));
+    }
+    Ok(match text {
+        "" | "~" | "null" => Value::Null,
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => text
+            .parse::<i64>()
+            .map(Value::from)
+            .ok()
+            .or_else(|| {
+                text.parse::<f64>()
+                    .ok()
+                    .filter(|number| number.is_finite())
+                    .and_then(|number| serde_json::Number::from_f64(number).map(Value::Number))
+            })
+            .unwrap_or_else(|| Value::String(text.to_string())),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_a_plan() {
+        let plan = parse(
+            "# nightly refresh
+name: refresh
+steps:
+  - exec: select 1  # inline comment
+    database: orders
+  - database: 'reporting'
+    continue_on_error: true
+    timeout: 30
+  -
+    shell: ./notify.sh
+tags: [nightly, 'ops team']
+",
+        )
+        .unwrap();
+        assert_eq!(
+            plan,
+            json!({
+                "name": "refresh",
+                "steps": [
+                    {"exec": "select 1", "database": "orders"},
+                    {"database": "reporting", "continue_on_error": true, "timeout": 30},
+                    {"shell": "./notify.sh"}
+                ],
+                "tags": ["nightly", "ops team"]
+            })
+        );
+    }
+
+    #[test]
+    fn sequences_may_sit_at_their_keys_indentation() {
+        assert_eq!(
+            parse("steps:\n- a\n- b\nafter: c\n").unwrap(),
+            json!({"steps": ["a", "b"], "after": "c"})
+        );
+        assert_eq!(
+            parse("- - 1\n  - 2\n- [3, 4]\n").unwrap(),
+            json!([[1, 2], [3, 4]])
+        );
+    }
+
+    #[test]
+    fn parses_scalars() {
+        assert_eq!(
+            parse(
+                "a: ~\nb: null\nc:\nd: false\ne: -12\nf: 1.5\ng: 1e999\nh: '#1 it''s'\n\
+                 i: \"tab\\there\"\nj: plain text: with colon\nk: []\nl: {\"x\": 1}\nm: a#b\n"
+            )
+            .unwrap(),
+            json!({
+                "a": null, "b": null, "c": null, "d": false, "e": -12, "f": 1.5,
+                "g": "1e999", "h": "#1 it's", "i": "tab\there",
+                "j": "plain text: with colon", "k": [], "l": {"x": 1}, "m": "a#b"
+            })
+        );
+        assert_eq!(parse("").unwrap(), Value::Null);
+        assert_eq!(parse("# only a comment\n").unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn parses_block_scalars() {
+        let value = parse(
+            "literal: |
+  select 1;
+
+  select 2;
+folded: >
+  one
+  two
+
+  three
+stripped: |-
+  no newline
+kept: |+
+  trailing
+
+next: x
+",
+        )
+        .unwrap();
+        assert_eq!(value["literal"], "select 1;\n\nselect 2;\n");
+        assert_eq!(value["folded"], "one two\nthree\n");
+        assert_eq!(value["stripped"], "no newline");
+        assert_eq!(value["kept"], "trailing\n\n");
+        assert_eq!(value["next"], "x");
+    }
+
+    #[test]
+    fn block_scalars_in_sequences() {
+        assert_eq!(
+            parse("- exec: |\n    select 1;\n  database: app\n").unwrap(),
+            json!([{"exec": "select 1;\n", "database": "app"}])
+        );
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        for (text, message) in [
+            ("a: 1\na: 2\n", "Line 2: duplicate key 'a'"),
+            ("a: 1\n  b: 2\n", "Line 2: unexpected indentation"),
+            ("a: 'open\n", "Line 1: unterminated quoted string"),
+            ("a: [1, 2\n", "Line 1: unterminated flow sequence"),
+            (
+                "a: |2\n  x\n",
+                "Line 1: unsupported block scalar header '|2'",
+            ),
+            ("a:\n  b: 1\n  oops\n", "Line 3: expected `key: value`"),
+        ] {
+            assert_eq!(parse(text).unwrap_err().to_string(), message, "{}", text);
+        }
+    }
+}